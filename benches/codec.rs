@@ -0,0 +1,148 @@
+// Timings of the codec's hot paths, so a change that slows them down shows
+// up in review: cargo bench, or cargo bench -- varint for some of them.
+// Every benchmark works through a batch per iteration, which criterion
+// reports as throughput per item.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mchat::{memory_pipe, Connection, Packet, PacketReader};
+use std::{hint::black_box, io::Write};
+
+const BATCH: usize = 1024;
+
+// Prepares a benchmark, the closure then runs one batch
+type Setup = fn() -> Box<dyn FnMut()>;
+
+fn bench_group(c: &mut Criterion, group: &str, benchmarks: &[(&str, Setup)]) {
+    let mut group = c.benchmark_group(group);
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for (name, setup) in benchmarks {
+        let mut batch = setup();
+        group.bench_function(*name, |b| b.iter(&mut batch));
+    }
+    group.finish();
+}
+
+fn varint(c: &mut Criterion) {
+    bench_group(
+        c,
+        "varint",
+        &[("read", read_varints), ("write", write_varints)],
+    );
+}
+
+fn string(c: &mut Criterion) {
+    bench_group(c, "string", &[("read", read_strings)]);
+}
+
+fn frame(c: &mut Criterion) {
+    bench_group(
+        c,
+        "frame",
+        &[
+            ("read", || read_frames(None)),
+            ("read_compressed", || read_frames(Some(256))),
+            ("write", write_frames),
+        ],
+    );
+}
+
+criterion_group!(benches, varint, string, frame);
+criterion_main!(benches);
+
+// Spread like in real traffic: mostly ids and short lengths, some
+// counts, the odd negative that takes all five bytes
+fn varints() -> Vec<i32> {
+    (0..BATCH as i32)
+        .map(|index| match index % 8 {
+            0..=4 => index % 128,
+            5 => 300 + index,
+            6 => 70000 + index * 31,
+            _ => -index,
+        })
+        .collect()
+}
+
+fn read_varints() -> Box<dyn FnMut()> {
+    let mut packet = Packet::new();
+    for value in varints() {
+        packet.write_varint(value).unwrap();
+    }
+    Box::new(move || {
+        let mut reader = PacketReader::new(&packet.buffer);
+        for _ in 0..BATCH {
+            black_box(reader.read_varint().unwrap());
+        }
+    })
+}
+
+fn write_varints() -> Box<dyn FnMut()> {
+    let values = varints();
+    let mut packet = Packet::new();
+    Box::new(move || {
+        packet.clear();
+        for value in &values {
+            packet.write_varint(black_box(*value)).unwrap();
+        }
+        black_box(&packet);
+    })
+}
+
+fn read_strings() -> Box<dyn FnMut()> {
+    let mut bytes = Packet::new();
+    for index in 0..BATCH {
+        let text = "<alice> hello there ".repeat(1 + index % 4);
+        bytes.write_varint(text.len() as i32).unwrap();
+        bytes.buffer.extend_from_slice(text.as_bytes());
+    }
+    Box::new(move || {
+        let mut reader = PacketReader::new(&bytes.buffer);
+        for _ in 0..BATCH {
+            black_box(reader.read_str().unwrap());
+        }
+    })
+}
+
+// A system chat message, what a chat bot mostly reads
+fn chat_packet(index: usize) -> Packet {
+    let mut packet = Packet::new();
+    packet.write_varint(0x5F).unwrap();
+    let json = format!(
+        r#"{{"text":"Message number {} {}"}}"#,
+        index,
+        "x".repeat(index % 300)
+    );
+    packet.write_varint(json.len() as i32).unwrap();
+    packet.buffer.extend_from_slice(json.as_bytes());
+    packet.write_varint(1).unwrap();
+    packet
+}
+
+// Writing the frames into the pipe is part of every batch, it's a copy
+// next to the decoding
+fn read_frames(compression: Option<usize>) -> Box<dyn FnMut()> {
+    let frames: Vec<u8> = (0..BATCH)
+        .flat_map(|index| chat_packet(index).to_frame(compression))
+        .collect();
+    let (mut server, client) = memory_pipe();
+    let mut connection = Connection::new(Box::new(client)).unwrap();
+    connection.set_compression(compression);
+    let mut packet = Packet::new();
+    Box::new(move || {
+        server.write_all(&frames).unwrap();
+        for _ in 0..BATCH {
+            connection.read_packet_into(&mut packet).unwrap();
+            black_box(packet.get_protocol_id());
+        }
+    })
+}
+
+fn write_frames() -> Box<dyn FnMut()> {
+    let packets: Vec<Packet> = (0..BATCH).map(chat_packet).collect();
+    let mut out = Vec::new();
+    Box::new(move || {
+        out.clear();
+        for packet in &packets {
+            packet.write_frame_to(&mut out, None, 6).unwrap();
+        }
+        black_box(&out);
+    })
+}
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mchat::{Frame, Handshake};
+
+// The first byte picks the compression threshold, 0 leaves it off
+fuzz_target!(|data: &[u8]| {
+    let (compression, bytes) = match data.split_first() {
+        Some((0, bytes)) => (None, bytes),
+        Some((threshold, bytes)) => (Some(*threshold as usize), bytes),
+        None => return,
+    };
+
+    if let Ok(Some(mut frame)) = Frame::parse(bytes, compression) {
+        assert!(frame.size <= bytes.len());
+        let _ = Handshake::from_packet(&mut frame.packet);
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mchat::{PacketReader, Tag};
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = PacketReader::new(data);
+    if let Ok(Some((name, tag))) = Tag::read_named(&mut reader) {
+        // Whatever was read writes back to something that reads the same
+        let mut written = Vec::new();
+        tag.write_named(&name, &mut written).unwrap();
+        let reread = Tag::read_named(&mut PacketReader::new(&written)).unwrap();
+        assert_eq!(reread, Some((name, tag)));
+    }
+
+    let _ = Tag::read_network(&mut PacketReader::new(data));
+});
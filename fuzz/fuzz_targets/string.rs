@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mchat::PacketReader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = PacketReader::new(data);
+    let _ = reader.read_str();
+
+    let mut reader = PacketReader::new(data);
+    if let Ok(lossy) = reader.read_str_lossy() {
+        // The strict read only differs in rejecting invalid UTF-8
+        let mut strict = PacketReader::new(data);
+        if let Ok(text) = strict.read_str() {
+            assert_eq!(text, lossy);
+        }
+    }
+});
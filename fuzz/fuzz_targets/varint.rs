@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mchat::PacketReader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = PacketReader::new(data);
+    while reader.read_varint().is_ok() {}
+
+    let mut reader = PacketReader::new(data);
+    while reader.read_varlong().is_ok() {}
+});
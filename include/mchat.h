@@ -0,0 +1,68 @@
+/*
+ * C API of mchat, available when the library is built with the ffi feature:
+ *
+ *   cargo build --release --features ffi
+ *
+ * which produces libmchat.so (libmchat.dylib, mchat.dll). Functions returning
+ * int give 0 on success and -1 on failure, mchat_last_error then describes the
+ * failure. Strings returned through `out` parameters belong to the caller and
+ * must be released with mchat_string_free. A client must only be used from one
+ * thread at a time.
+ */
+#ifndef MCHAT_H
+#define MCHAT_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MchatClient MchatClient;
+
+/* Message of the last failure on the calling thread, NULL if there was none.
+ * Valid until the next mchat call on the same thread. */
+const char *mchat_last_error(void);
+
+void mchat_string_free(char *string);
+
+/* Opens a connection, NULL on failure. `username` may be NULL. */
+MchatClient *mchat_connect(const char *host, uint16_t port, const char *username);
+void mchat_client_free(MchatClient *client);
+
+/* Offline mode login */
+int mchat_login(MchatClient *client);
+
+/* Status JSON as the server sent it */
+int mchat_status(MchatClient *client, char **out);
+
+int mchat_send_chat(MchatClient *client, const char *message);
+
+/* Without the leading slash */
+int mchat_send_command(MchatClient *client, const char *command);
+
+/* Waits up to timeout_ms for the next event and stores it in `out` as a JSON
+ * object with a "type" field, e.g. chat, system, player_joined, player_left,
+ * died, title or packet, and a "schema_version" field. Returns 1 for an event,
+ * 0 on timeout and -1 on failure. Keep alives are answered along the way.
+ *
+ * Within a schema version fields and event types are only ever added, so
+ * unknown ones must be ignored. Renames and removals bump the version. */
+int mchat_poll_event(MchatClient *client, uint32_t timeout_ms, char **out);
+
+/* Schema version of the events from mchat_poll_event */
+uint32_t mchat_event_schema_version(void);
+
+#define MCHAT_FORMAT_PLAIN 0
+#define MCHAT_FORMAT_ANSI 1
+#define MCHAT_FORMAT_HTML 2
+#define MCHAT_FORMAT_MARKDOWN 3
+
+/* Renders a JSON chat component in one of the MCHAT_FORMAT_ formats */
+int mchat_render_component(const char *json, int format, char **out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
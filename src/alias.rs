@@ -0,0 +1,108 @@
+use crate::config::AliasConfig;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+// Client-side shortcuts typed in the chat UI, see config::AliasConfig. A line
+// whose first word is an alias is swapped for its expansion before sending,
+// with $1 to $9 standing for the words after it, $* for all of them and $$
+// for a dollar sign.
+#[derive(Debug, Clone, Default)]
+pub struct Aliases {
+    aliases: HashMap<String, Alias>,
+}
+
+#[derive(Debug, Clone)]
+struct Alias {
+    lines: Vec<String>,
+    // The highest $N used, that many words have to follow the alias
+    arguments: usize,
+}
+
+impl Aliases {
+    pub fn new(config: &HashMap<String, AliasConfig>) -> Result<Aliases> {
+        let mut aliases = HashMap::new();
+        for (name, alias) in config {
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(anyhow!("Alias {:?} has to be a single word", name));
+            }
+            let lines = match alias {
+                AliasConfig::Line(line) => vec![line.clone()],
+                AliasConfig::Lines(lines) => lines.clone(),
+            };
+            if lines.iter().all(|line| line.trim().is_empty()) {
+                return Err(anyhow!("Alias {} expands to nothing", name));
+            }
+            let arguments = lines
+                .iter()
+                .map(|line| highest_argument(line))
+                .max()
+                .unwrap_or(0);
+            aliases.insert(name.clone(), Alias { lines, arguments });
+        }
+
+        Ok(Aliases { aliases })
+    }
+
+    // The lines to send instead of `line`, None when it isn't an alias
+    pub fn expand(&self, line: &str) -> Option<Result<Vec<String>>> {
+        let mut words = line.split_whitespace();
+        let alias = self.aliases.get(words.next()?)?;
+        let arguments: Vec<&str> = words.collect();
+        if arguments.len() < alias.arguments {
+            return Some(Err(anyhow!(
+                "{} needs {} argument{}",
+                line.split_whitespace().next()?,
+                alias.arguments,
+                if alias.arguments == 1 { "" } else { "s" }
+            )));
+        }
+
+        let lines = alias
+            .lines
+            .iter()
+            .map(|template| substitute(template, &arguments))
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        Some(Ok(lines))
+    }
+}
+
+fn highest_argument(template: &str) -> usize {
+    let mut highest = 0;
+    let mut characters = template.chars();
+    while let Some(character) = characters.next() {
+        if character != '$' {
+            continue;
+        }
+        if let Some(digit) = characters.next().and_then(|next| next.to_digit(10)) {
+            highest = highest.max(digit as usize);
+        }
+    }
+    highest
+}
+
+fn substitute(template: &str, arguments: &[&str]) -> String {
+    let mut line = String::with_capacity(template.len());
+    let mut characters = template.chars().peekable();
+    while let Some(character) = characters.next() {
+        if character != '$' {
+            line.push(character);
+            continue;
+        }
+        match characters.peek().copied() {
+            Some('$') => line.push('$'),
+            Some('*') => line.push_str(&arguments.join(" ")),
+            // $0 is the alias itself, which isn't worth supporting
+            Some(digit @ '1'..='9') => {
+                let index = digit as usize - '1' as usize;
+                line.push_str(arguments.get(index).copied().unwrap_or_default());
+            }
+            _ => {
+                line.push('$');
+                continue;
+            }
+        }
+        characters.next();
+    }
+    line
+}
@@ -0,0 +1,352 @@
+// Mojang's session server, the part of online mode that happens outside the
+// Minecraft connection: the client joins with its access token, the server
+// then checks that it did. Getting that access token takes a Microsoft
+// account, signed in to Xbox Live and from there to Minecraft.
+use crate::{HttpClient, Profile, ProfileProperty, ShutdownToken};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+pub const SESSION_SERVER: &str = "https://sessionserver.mojang.com/session/minecraft";
+// Personal Microsoft accounts only, which is what Minecraft accounts are
+pub const MICROSOFT_OAUTH: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0";
+const MICROSOFT_SCOPE: &str = "XboxLive.signin offline_access";
+const XBOX_USER_AUTH: &str = "https://user.auth.xboxlive.com/user/authenticate";
+const XBOX_XSTS_AUTH: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
+pub const MINECRAFT_SERVICES: &str = "https://api.minecraftservices.com";
+
+// SHA-1 printed the way Java's BigInteger does it: the digest read as a
+// signed number in hex, so with a minus sign instead of the top bit and no
+// leading zeros
+pub fn minecraft_hex_digest(data: &[u8]) -> String {
+    let mut digest: [u8; 20] = Sha1::digest(data).into();
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        // Two's complement, to print the magnitude
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            (*byte, carry) = (!*byte).overflowing_add(carry as u8);
+        }
+    }
+
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hex = match hex.trim_start_matches('0') {
+        "" => "0",
+        trimmed => trimmed,
+    };
+
+    match negative {
+        true => format!("-{}", hex),
+        false => String::from(hex),
+    }
+}
+
+// The "server id" both sides send to the session server, hashed from the
+// Encryption Request's server id and public key plus the shared secret
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut data = Vec::with_capacity(server_id.len() + shared_secret.len() + public_key.len());
+    data.extend_from_slice(server_id.as_bytes());
+    data.extend_from_slice(shared_secret);
+    data.extend_from_slice(public_key);
+    minecraft_hex_digest(&data)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JoinRequest<'a> {
+    access_token: &'a str,
+    selected_profile: String,
+    server_id: &'a str,
+}
+
+// Client side: tells the session server we're about to join the server
+// that sent us `server_hash`. Has to happen before Encryption Response.
+pub fn join_server(
+    http: &HttpClient,
+    access_token: &str,
+    profile_id: Uuid,
+    server_hash: &str,
+) -> Result<()> {
+    let request = JoinRequest {
+        access_token,
+        selected_profile: profile_id.simple().to_string(),
+        server_id: server_hash,
+    };
+    http.post_json(&format!("{}/join", SESSION_SERVER), &request)
+        .context("The session server refused the join")?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct JoinedProfile {
+    id: Uuid,
+    name: String,
+    #[serde(default)]
+    properties: Vec<ProfileProperty>,
+}
+
+// Server side: whether `username` joined with `server_hash`, and if so
+// their profile. `ip` makes the session server also check the address the
+// player joined from.
+pub fn has_joined(
+    http: &HttpClient,
+    username: &str,
+    server_hash: &str,
+    ip: Option<IpAddr>,
+) -> Result<Option<Profile>> {
+    let ip = ip.map(|ip| ip.to_string());
+    let mut query = vec![("username", username), ("serverId", server_hash)];
+    if let Some(ip) = &ip {
+        query.push(("ip", ip));
+    }
+
+    let url = format!("{}/hasJoined", SESSION_SERVER);
+    let mut response = http.get_with_query(&url, &query)?;
+    if response.status() == 204 {
+        return Ok(None);
+    }
+
+    let body = response.body_mut().read_to_string()?;
+    let joined: JoinedProfile =
+        serde_json::from_str(&body).context("Invalid profile from the session server")?;
+
+    Ok(Some(Profile {
+        uuid: joined.id,
+        name: joined.name,
+        properties: joined.properties,
+    }))
+}
+
+// Seconds since the epoch, what token expiry is kept in
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// What to show the user so they sign in on another device, see
+// request_device_code
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceCode {
+    pub user_code: String,
+    pub verification_uri: String,
+    // Microsoft's own instructions, with the code and the link
+    pub message: String,
+    device_code: String,
+    // Seconds
+    expires_in: u64,
+    #[serde(default = "default_poll_interval")]
+    interval: u64,
+}
+
+fn default_poll_interval() -> u64 {
+    5
+}
+
+// A signed in Microsoft account. The refresh token lasts about 90 days and
+// is replaced on every refresh, the access token only about an hour.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MicrosoftTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthResponse {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+// Starts a sign in for `client_id`, an Azure app registered for personal
+// accounts with the Xbox Live scope. Show the code, then poll_device_code.
+pub fn request_device_code(http: &HttpClient, client_id: &str) -> Result<DeviceCode> {
+    let mut response = http.post_form(
+        &format!("{}/devicecode", MICROSOFT_OAUTH),
+        &[("client_id", client_id), ("scope", MICROSOFT_SCOPE)],
+    )?;
+    let body = response.body_mut().read_to_string()?;
+    if !response.status().is_success() {
+        return Err(oauth_error(&body));
+    }
+
+    serde_json::from_str(&body).context("Invalid device code from Microsoft")
+}
+
+// Waits for the user to enter `code`, or for it to expire or `token` to be
+// cancelled
+pub fn poll_device_code(
+    http: &HttpClient,
+    client_id: &str,
+    code: &DeviceCode,
+    token: &ShutdownToken,
+) -> Result<MicrosoftTokens> {
+    let deadline = unix_time() + code.expires_in;
+    let mut interval = Duration::from_secs(code.interval.max(1));
+    loop {
+        if token.wait_timeout(interval) {
+            return Err(anyhow!("Cancelled while waiting for the sign in"));
+        }
+        if unix_time() >= deadline {
+            return Err(anyhow!("The code expired before anyone signed in with it"));
+        }
+
+        let form = [
+            ("client_id", client_id),
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ("device_code", code.device_code.as_str()),
+        ];
+        match oauth_tokens(http, &form) {
+            Err(error) if error.to_string().contains("authorization_pending") => continue,
+            Err(error) if error.to_string().contains("slow_down") => {
+                interval += Duration::from_secs(5);
+            }
+            result => return result,
+        }
+    }
+}
+
+// Trades the refresh token for new tokens, no user needed
+pub fn refresh_microsoft(
+    http: &HttpClient,
+    client_id: &str,
+    refresh_token: &str,
+) -> Result<MicrosoftTokens> {
+    oauth_tokens(
+        http,
+        &[
+            ("client_id", client_id),
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("scope", MICROSOFT_SCOPE),
+        ],
+    )
+    .context("Failed to refresh the Microsoft sign in, log in again")
+}
+
+fn oauth_tokens(http: &HttpClient, form: &[(&str, &str)]) -> Result<MicrosoftTokens> {
+    let mut response = http.post_form(&format!("{}/token", MICROSOFT_OAUTH), form)?;
+    let body = response.body_mut().read_to_string()?;
+    let tokens: OAuthResponse = serde_json::from_str(&body)
+        .with_context(|| format!("Invalid answer from Microsoft: {}", body))?;
+    match (tokens.access_token, tokens.refresh_token) {
+        (Some(access_token), Some(refresh_token)) => Ok(MicrosoftTokens {
+            access_token,
+            refresh_token,
+        }),
+        _ => Err(oauth_error(&body)),
+    }
+}
+
+fn oauth_error(body: &str) -> anyhow::Error {
+    match serde_json::from_str::<OAuthResponse>(body) {
+        Ok(OAuthResponse {
+            error: Some(error),
+            error_description,
+            ..
+        }) => match error_description {
+            Some(description) => anyhow!("Microsoft answered {}: {}", error, description),
+            None => anyhow!("Microsoft answered {}", error),
+        },
+        _ => anyhow!("Unexpected answer from Microsoft: {}", body),
+    }
+}
+
+// What joining online mode servers takes, see join_server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinecraftToken {
+    pub access_token: String,
+    // Seconds since the epoch, about a day after it was handed out
+    pub expires_at: u64,
+    pub profile: Profile,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct XboxResponse {
+    token: String,
+    display_claims: Value,
+}
+
+#[derive(Deserialize)]
+struct MinecraftLogin {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct MinecraftProfile {
+    id: Uuid,
+    name: String,
+}
+
+// Xbox Live, then XSTS for Minecraft's relying party, then Minecraft itself
+pub fn minecraft_login(http: &HttpClient, microsoft_access_token: &str) -> Result<MinecraftToken> {
+    let xbox: XboxResponse = read_json(http.post_json(
+        XBOX_USER_AUTH,
+        &json!({
+            "Properties": {
+                "AuthMethod": "RPS",
+                "SiteName": "user.auth.xboxlive.com",
+                "RpsTicket": format!("d={}", microsoft_access_token),
+            },
+            "RelyingParty": "http://auth.xboxlive.com",
+            "TokenType": "JWT",
+        }),
+    ))
+    .context("Xbox Live refused the Microsoft account")?;
+
+    // XSTS answers 401 for accounts without an Xbox profile, or those of
+    // children not added to a family
+    let xsts: XboxResponse = read_json(http.post_json(
+        XBOX_XSTS_AUTH,
+        &json!({
+            "Properties": { "SandboxId": "RETAIL", "UserTokens": [xbox.token] },
+            "RelyingParty": "rp://api.minecraftservices.com/",
+            "TokenType": "JWT",
+        }),
+    ))
+    .context("Xbox Live refused the account, does it have an Xbox profile?")?;
+    let user_hash = xsts.display_claims["xui"][0]["uhs"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Xbox Live left out the user hash"))?;
+
+    let login: MinecraftLogin = read_json(http.post_json(
+        &format!("{}/authentication/login_with_xbox", MINECRAFT_SERVICES),
+        &json!({ "identityToken": format!("XBL3.0 x={};{}", user_hash, xsts.token) }),
+    ))
+    .context("Minecraft refused the Xbox Live account")?;
+
+    // 404 here means the account doesn't own the game
+    let profile: MinecraftProfile = http
+        .get_json_authorized(
+            &format!("{}/minecraft/profile", MINECRAFT_SERVICES),
+            &login.access_token,
+        )
+        .context("The account has no Minecraft profile, does it own the game?")?;
+
+    Ok(MinecraftToken {
+        access_token: login.access_token,
+        expires_at: unix_time() + login.expires_in,
+        profile: Profile {
+            uuid: profile.id,
+            name: profile.name,
+            properties: Vec::new(),
+        },
+    })
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(
+    response: Result<ureq::http::Response<ureq::Body>>,
+) -> Result<T> {
+    let body = response?.body_mut().read_to_string()?;
+    Ok(serde_json::from_str(&body)?)
+}
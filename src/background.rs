@@ -0,0 +1,127 @@
+use crate::{Client, Event, Packet, ShutdownToken};
+use anyhow::{anyhow, Result};
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// How long the background thread waits for packets before looking at its commands
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+enum BackgroundCommand {
+    Chat(String),
+    Command(String),
+    Packet(Packet),
+}
+
+// Hands chat to a client running in the background, from any thread
+#[derive(Clone)]
+pub struct CommandSender {
+    commands: Sender<BackgroundCommand>,
+    shutdown: ShutdownToken,
+}
+
+impl CommandSender {
+    pub fn send_chat_message(&self, message: &str) -> Result<()> {
+        self.send(BackgroundCommand::Chat(String::from(message)))
+    }
+
+    // `command` without the leading slash
+    pub fn send_command(&self, command: &str) -> Result<()> {
+        self.send(BackgroundCommand::Command(String::from(command)))
+    }
+
+    pub fn send_packet(&self, packet: Packet) -> Result<()> {
+        self.send(BackgroundCommand::Packet(packet))
+    }
+
+    // Disconnects, the event channel closes once the thread is done
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    fn send(&self, command: BackgroundCommand) -> Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| anyhow!("The background client has stopped"))
+    }
+}
+
+// A client running on its own thread. Keep alives and teleports are
+// answered there, everything else arrives on `events` until the connection
+// ends or every receiver and sender is dropped.
+pub struct BackgroundClient {
+    pub events: Receiver<Event>,
+    pub commands: CommandSender,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl BackgroundClient {
+    pub(crate) fn spawn(client: Client) -> BackgroundClient {
+        let (event_sender, events) = mpsc::channel();
+        let (commands, receiver) = mpsc::channel();
+        let shutdown = client.shutdown_token();
+        let thread = thread::spawn(move || run(client, &receiver, &event_sender));
+
+        BackgroundClient {
+            events,
+            commands: CommandSender { commands, shutdown },
+            thread,
+        }
+    }
+
+    // Waits for the thread to end, e.g. after commands.shutdown(), with the
+    // error that ended it. Ending because of a shutdown counts as success.
+    pub fn join(self) -> Result<()> {
+        drop(self.events);
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("Background client panicked")),
+        }
+    }
+}
+
+fn run(
+    mut client: Client,
+    commands: &Receiver<BackgroundCommand>,
+    events: &Sender<Event>,
+) -> Result<()> {
+    let result = serve(&mut client, commands, events);
+    match result {
+        // The read failing is how a shutdown ends it
+        Err(_) if client.shutdown_token().is_cancelled() => Ok(()),
+        result => result,
+    }
+}
+
+fn serve(
+    client: &mut Client,
+    commands: &Receiver<BackgroundCommand>,
+    events: &Sender<Event>,
+) -> Result<()> {
+    loop {
+        for command in commands.try_iter() {
+            match command {
+                BackgroundCommand::Chat(message) => {
+                    client.send_chat_message(&message)?;
+                }
+                BackgroundCommand::Command(command) => {
+                    client.send_command(&command)?;
+                }
+                BackgroundCommand::Packet(packet) => client.send_packet(&packet)?,
+            }
+        }
+
+        let event = match client.poll_event(POLL_INTERVAL)? {
+            Some(event) => event,
+            None => continue,
+        };
+        if let Event::Packet(packet) = &event {
+            client.answer_keep_alive(packet)?;
+        }
+        if events.send(event).is_err() {
+            return Ok(());
+        }
+    }
+}
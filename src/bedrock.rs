@@ -0,0 +1,123 @@
+// Status of Bedrock Edition servers, which speak RakNet over UDP instead of
+// the Java protocol. Only the unconnected ping is implemented, it's all the
+// multiplayer list needs.
+use anyhow::{anyhow, Result};
+use rand::RngExt;
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_BEDROCK_PORT: u16 = 19132;
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1C;
+// Marks RakNet's offline messages
+const MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+
+// What the server id string of the pong says, see ping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BedrockStatus {
+    // "MCPE", or "MCEE" for Education Edition
+    pub edition: String,
+    pub motd: String,
+    // Second line of the MOTD, usually the world name
+    pub sub_motd: Option<String>,
+    pub protocol: i32,
+    pub version: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub server_id: String,
+    pub game_mode: Option<String>,
+    pub port_v4: Option<u16>,
+    pub port_v6: Option<u16>,
+    // Round trip of the ping
+    pub latency: Duration,
+}
+
+// Sends a RakNet unconnected ping and reads the server id string from the
+// pong, e.g. "MCPE;Dedicated Server;594;1.20.10;0;10;...;Bedrock level;Survival;1;19132;19133;"
+pub fn ping(host: &str, port: u16, timeout: Duration) -> Result<BedrockStatus> {
+    let address = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("{} resolved to nothing", host))?;
+    let local: SocketAddr = match address {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+        SocketAddr::V6(_) => "[::]:0".parse()?,
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(address)?;
+    socket.set_read_timeout(Some(timeout))?;
+
+    let started = Instant::now();
+    // RakNet echoes the time back, which tells our pong from stale ones
+    let time = rand::rng().random::<i64>() & i64::MAX;
+    let guid: i64 = rand::rng().random();
+    let mut request = vec![UNCONNECTED_PING];
+    request.extend_from_slice(&time.to_be_bytes());
+    request.extend_from_slice(&MAGIC);
+    request.extend_from_slice(&guid.to_be_bytes());
+    socket.send(&request)?;
+
+    let mut response = [0u8; 2048];
+    loop {
+        let length = socket
+            .recv(&mut response)
+            .map_err(|error| anyhow!("No answer to the Bedrock ping: {}", error))?;
+        let response = &response[..length];
+        if response.len() >= 9
+            && response[0] == UNCONNECTED_PONG
+            && response[1..9] == time.to_be_bytes()
+        {
+            return parse_pong(response, started.elapsed());
+        }
+    }
+}
+
+fn parse_pong(pong: &[u8], latency: Duration) -> Result<BedrockStatus> {
+    // Id, time, server guid and magic come before the string
+    let rest = pong
+        .get(1 + 8 + 8..)
+        .filter(|rest| rest.starts_with(&MAGIC))
+        .ok_or_else(|| anyhow!("Bedrock pong without RakNet's magic"))?;
+    let rest = &rest[MAGIC.len()..];
+    if rest.len() < 2 {
+        return Err(anyhow!("Bedrock pong ended early"));
+    }
+    let length = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+    let text = rest
+        .get(2..2 + length)
+        .ok_or_else(|| anyhow!("Bedrock pong ended early"))?;
+    let text = String::from_utf8_lossy(text);
+
+    let fields: Vec<&str> = text.split(';').collect();
+    let field = |index: usize| fields.get(index).copied().filter(|field| !field.is_empty());
+    let required = |index: usize, name: &str| {
+        field(index).ok_or_else(|| anyhow!("Bedrock pong is missing the {}", name))
+    };
+    let number = |index: usize, name: &str| -> Result<u32> {
+        required(index, name)?
+            .parse()
+            .map_err(|_| anyhow!("Bedrock pong has a bad {}", name))
+    };
+
+    Ok(BedrockStatus {
+        edition: String::from(required(0, "edition")?),
+        motd: String::from(field(1).unwrap_or_default()),
+        protocol: required(2, "protocol")?
+            .parse()
+            .map_err(|_| anyhow!("Bedrock pong has a bad protocol"))?,
+        version: String::from(required(3, "version")?),
+        players: number(4, "player count")?,
+        max_players: number(5, "maximum players")?,
+        server_id: String::from(field(6).unwrap_or_default()),
+        sub_motd: field(7).map(String::from),
+        game_mode: field(8).map(String::from),
+        port_v4: field(10).and_then(|port| port.parse().ok()),
+        port_v6: field(11).and_then(|port| port.parse().ok()),
+        latency,
+    })
+}
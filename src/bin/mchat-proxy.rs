@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use mchat::{
+    ConnectionLimits, NextState, Route, ShutdownToken, StatusTemplate, Throttle, VirtualHosts,
+};
+use std::{
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Status server and hostname based proxy for Minecraft"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Answer status pings from a template")]
+    ServeStatus {
+        template: String,
+        #[arg(default_value = "0.0.0.0:25565")]
+        address: String,
+    },
+    #[command(about = "Forward connections to backends by the hostname players typed")]
+    Route {
+        #[arg(short, long, default_value = "0.0.0.0:25565")]
+        listen: String,
+        #[arg(
+            short,
+            long = "route",
+            value_name = "HOSTNAME=BACKEND",
+            help = "Hostnames may start with *. to match subdomains"
+        )]
+        routes: Vec<String>,
+        #[arg(long, value_name = "BACKEND", help = "For hostnames without a route")]
+        fallback: Option<String>,
+        #[arg(long, help = "Log the handshake of every connection")]
+        sniff: bool,
+    },
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let shutdown = ShutdownToken::new();
+    let token = shutdown.clone();
+    ctrlc::set_handler(move || token.cancel()).context("Failed to install Ctrl-C handler")?;
+
+    let result = match cli.command {
+        Command::ServeStatus { template, address } => serve_status(&template, &address, &shutdown),
+        Command::Route {
+            listen,
+            routes,
+            fallback,
+            sniff,
+        } => route(&listen, &routes, fallback, sniff, &shutdown),
+    };
+
+    shutdown.shutdown();
+    result
+}
+
+fn serve_status(template: &str, address: &str, shutdown: &ShutdownToken) -> Result<()> {
+    let template = Arc::new(Mutex::new(StatusTemplate::load(template)?));
+    let throttle = Throttle::new(ConnectionLimits::default());
+
+    accept_loop(address, shutdown, move |stream| {
+        let (mut connection, _permit) = throttle.accept(stream)?;
+        connection.set_read_timeout(Some(throttle.limits().handshake_timeout))?;
+
+        match connection.handshake().next_state {
+            NextState::Status => {
+                let status = template.lock().unwrap().render()?;
+                connection.respond_status(&status)
+            }
+            NextState::Login | NextState::Transfer => {
+                connection.disconnect_login("This server only answers pings")
+            }
+        }
+    })
+}
+
+fn route(
+    listen: &str,
+    routes: &[String],
+    fallback: Option<String>,
+    sniff: bool,
+    shutdown: &ShutdownToken,
+) -> Result<()> {
+    let mut hosts = VirtualHosts::new();
+    for route in routes {
+        let (hostname, backend) = route
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Route {} should look like hostname=backend", route))?;
+        hosts = hosts.route(hostname, Route::Backend(String::from(backend)));
+    }
+    if let Some(backend) = fallback {
+        hosts = hosts.fallback(Route::Backend(backend));
+    }
+
+    let hosts = Arc::new(hosts);
+    let throttle = Throttle::new(ConnectionLimits::default());
+
+    accept_loop(listen, shutdown, move |stream| {
+        let (connection, _permit) = throttle.accept(stream)?;
+        if sniff {
+            let handshake = connection.handshake();
+            println!(
+                "{} -> {}:{} (protocol {}, {:?})",
+                connection.peer_addr(),
+                handshake.hostname,
+                handshake.port,
+                handshake.protocol_version,
+                handshake.next_state
+            );
+        }
+
+        // The handshake timeout would cut idle players off once spliced
+        connection.set_read_timeout(None)?;
+        hosts.serve(connection)
+    })
+}
+
+// Handles every connection on its own thread until the token is cancelled
+fn accept_loop(
+    address: &str,
+    shutdown: &ShutdownToken,
+    handler: impl Fn(TcpStream) -> Result<()> + Clone + Send + 'static,
+) -> Result<()> {
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("Failed to listen on {}", address))?;
+    println!("Listening on {}", address);
+
+    shutdown.wake_on_cancel(listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        let stream = match stream {
+            Ok(val) => val,
+            Err(error) => {
+                eprintln!("Failed to accept connection: {}", error);
+                continue;
+            }
+        };
+
+        let handler = handler.clone();
+        shutdown.spawn(move |_| {
+            if let Err(error) = handler(stream) {
+                eprintln!("{:#}", error);
+            }
+        });
+    }
+
+    Ok(())
+}
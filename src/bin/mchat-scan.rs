@@ -0,0 +1,207 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use mchat::{probe_server, scan_servers, Locale, ScanResult, ShutdownToken};
+use serde_json::json;
+use std::{
+    fs,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Check many Minecraft servers at once, or one over time"
+)]
+struct Cli {
+    #[arg(long, global = true, default_value_t = 5, value_name = "SECONDS")]
+    timeout: u64,
+    #[arg(long, global = true, help = "One JSON object per line")]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Query the status of every server given")]
+    Scan {
+        #[arg(help = "host or host:port")]
+        servers: Vec<String>,
+        #[arg(short, long, help = "Read more servers from a file, one per line")]
+        file: Option<String>,
+        #[arg(short, long, default_value_t = 16, help = "Servers queried at once")]
+        jobs: usize,
+    },
+    #[command(about = "Query one server repeatedly until interrupted")]
+    Monitor {
+        server: String,
+        #[arg(short, long, default_value_t = 60, value_name = "SECONDS")]
+        interval: u64,
+    },
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let shutdown = ShutdownToken::new();
+    let token = shutdown.clone();
+    ctrlc::set_handler(move || token.cancel()).context("Failed to install Ctrl-C handler")?;
+
+    let timeout = Duration::from_secs(cli.timeout);
+    let result = match cli.command {
+        Command::Scan {
+            mut servers,
+            file,
+            jobs,
+        } => {
+            if let Some(file) = file {
+                let list = fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {}", file))?;
+                servers.extend(
+                    list.lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(String::from),
+                );
+            }
+            scan(servers, jobs, timeout, cli.json, &shutdown)
+        }
+        Command::Monitor { server, interval } => monitor(
+            &server,
+            Duration::from_secs(interval),
+            timeout,
+            cli.json,
+            &shutdown,
+        ),
+    };
+
+    shutdown.shutdown();
+    result
+}
+
+fn scan(
+    servers: Vec<String>,
+    jobs: usize,
+    timeout: Duration,
+    json: bool,
+    shutdown: &ShutdownToken,
+) -> Result<()> {
+    if servers.is_empty() {
+        return Err(anyhow!("No servers to scan"));
+    }
+
+    let count = servers.len();
+    let probes = scan_servers(servers, jobs, timeout, shutdown);
+
+    // Printed as they finish, slow servers don't hold up the rest
+    let locale = Locale::from_env();
+    let mut failed = 0;
+    for probe in probes {
+        failed += probe.outcome.is_err() as usize;
+        print_probe(&probe, &locale, json);
+    }
+
+    if !json {
+        eprintln!(
+            "{} of {} servers answered",
+            locale.format_integer((count - failed) as i64),
+            locale.format_integer(count as i64)
+        );
+    }
+
+    Ok(())
+}
+
+fn monitor(
+    server: &str,
+    interval: Duration,
+    timeout: Duration,
+    json: bool,
+    shutdown: &ShutdownToken,
+) -> Result<()> {
+    let locale = Locale::from_env();
+    loop {
+        let outcome = probe_server(server, timeout, shutdown);
+        if shutdown.is_cancelled() {
+            return Ok(());
+        }
+        print_probe(
+            &ScanResult {
+                index: 0,
+                server: String::from(server),
+                outcome,
+            },
+            &locale,
+            json,
+        );
+
+        if shutdown.wait_timeout(interval) {
+            return Ok(());
+        }
+    }
+}
+
+fn print_probe(probe: &ScanResult, locale: &Locale, json: bool) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as i64);
+
+    match (&probe.outcome, json) {
+        (Ok((report, latency)), true) => {
+            let status = &report.status;
+            println!(
+                "{}",
+                json!({
+                    "server": probe.server,
+                    "timestamp": now,
+                    "online": true,
+                    "version": status.version.name,
+                    "protocol": status.version.protocol,
+                    "players": status.players.online,
+                    "max_players": status.players.max,
+                    "latency_ms": latency.map(|latency| latency.as_secs_f64() * 1000.0),
+                    "motd": status.description.to_plain(),
+                })
+            );
+        }
+        (Err(error), true) => println!(
+            "{}",
+            json!({
+                "server": probe.server,
+                "timestamp": now,
+                "online": false,
+                "error": format!("{:#}", error),
+            })
+        ),
+        (Ok((report, latency)), false) => {
+            let status = &report.status;
+            let latency = match latency {
+                Some(latency) => format!(
+                    "{} ms",
+                    locale.format_decimal(latency.as_secs_f64() * 1000.0, 1)
+                ),
+                None => String::from("no ping"),
+            };
+            let motd = status.description.to_plain();
+            println!(
+                "{} {}  {} ({})  {}/{}  {}  {}",
+                locale.format_time(now),
+                probe.server,
+                status.version.name,
+                status.version.protocol,
+                locale.format_integer(status.players.online as i64),
+                locale.format_integer(status.players.max as i64),
+                latency,
+                motd.lines().next().unwrap_or_default().trim()
+            );
+        }
+        (Err(error), false) => {
+            println!(
+                "{} {}  down: {:#}",
+                locale.format_time(now),
+                probe.server,
+                error
+            )
+        }
+    }
+}
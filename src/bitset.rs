@@ -0,0 +1,74 @@
+// Protocol BitSet, bits packed into longs from the least significant bit of
+// the first long upwards. Sent as a varint count of longs then the longs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BitSet {
+    words: Vec<i64>,
+}
+
+impl BitSet {
+    pub fn new() -> BitSet {
+        BitSet::default()
+    }
+
+    pub fn from_words(words: Vec<i64>) -> BitSet {
+        BitSet { words }
+    }
+
+    pub fn words(&self) -> &[i64] {
+        &self.words
+    }
+
+    // Bits past the end are unset
+    pub fn get(&self, index: usize) -> bool {
+        self.words
+            .get(index / 64)
+            .is_some_and(|word| word >> (index % 64) & 1 == 1)
+    }
+
+    // Grows as needed when setting, never shrinks
+    pub fn set(&mut self, index: usize, value: bool) {
+        let word = index / 64;
+        if word >= self.words.len() {
+            if !value {
+                return;
+            }
+            self.words.resize(word + 1, 0);
+        }
+        match value {
+            true => self.words[word] |= 1 << (index % 64),
+            false => self.words[word] &= !(1 << (index % 64)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    // Indices of the set bits, in order
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits >> bit & 1 == 1)
+                .map(move |bit| word * 64 + bit)
+        })
+    }
+
+    // Fixed BitSet: exactly ceil(bits / 8) bytes, no length in front
+    pub(crate) fn from_fixed_bytes(bytes: &[u8]) -> BitSet {
+        let mut set = BitSet::new();
+        for (index, byte) in bytes.iter().enumerate() {
+            for bit in 0..8 {
+                set.set(index * 8 + bit, byte >> bit & 1 == 1);
+            }
+        }
+        set
+    }
+
+    pub(crate) fn to_fixed_bytes(&self, bits: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; bits.div_ceil(8)];
+        for index in self.ones().take_while(|index| *index < bits) {
+            bytes[index / 8] |= 1 << (index % 8);
+        }
+        bytes
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{Component, Event, Packet};
+use anyhow::Result;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BossBar {
+    pub uuid: Uuid,
+    pub title: Component,
+    // From 0 to 1
+    pub health: f32,
+    pub color: i32,
+    pub division: i32,
+    pub flags: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BossBars {
+    bars: HashMap<Uuid, BossBar>,
+}
+
+impl BossBars {
+    pub fn bars(&self) -> &HashMap<Uuid, BossBar> {
+        &self.bars
+    }
+
+    pub fn get(&self, uuid: Uuid) -> Option<&BossBar> {
+        self.bars.get(&uuid)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.bars.clear();
+    }
+
+    // Boss Bar, returns the event describing the change if the bar is known
+    pub(crate) fn handle_boss_bar(&mut self, packet: &Packet) -> Result<Option<Event>> {
+        let mut reader = packet.reader();
+        let uuid = reader.read_uuid()?;
+        let action = reader.read_varint()?;
+
+        if action == 0 {
+            let bar = BossBar {
+                uuid,
+                title: Component::from_json(reader.read_str()?)?,
+                health: reader.read_f32()?,
+                color: reader.read_varint()?,
+                division: reader.read_varint()?,
+                flags: reader.read_u8()?,
+            };
+            self.bars.insert(uuid, bar.clone());
+            return Ok(Some(Event::BossBarUpdated(bar)));
+        }
+        if action == 1 {
+            return Ok(self.bars.remove(&uuid).map(Event::BossBarRemoved));
+        }
+
+        let bar = match self.bars.get_mut(&uuid) {
+            Some(val) => val,
+            None => return Ok(None),
+        };
+        match action {
+            2 => bar.health = reader.read_f32()?,
+            3 => bar.title = Component::from_json(reader.read_str()?)?,
+            4 => {
+                bar.color = reader.read_varint()?;
+                bar.division = reader.read_varint()?;
+            }
+            5 => bar.flags = reader.read_u8()?,
+            _ => return Ok(None),
+        }
+
+        Ok(Some(Event::BossBarUpdated(bar.clone())))
+    }
+}
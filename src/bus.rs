@@ -0,0 +1,322 @@
+use crate::{event_to_json, proxy, split_host_port, Event, ShutdownToken, StatusSample};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Sender},
+    time::Duration,
+};
+
+// Bounds connecting and waiting for the broker to acknowledge
+const BUS_TIMEOUT: Duration = Duration::from_secs(5);
+// Identifies us to MQTT brokers that want a client id
+const MQTT_CLIENT_ID: &str = "mchat";
+
+// Where an EventPublisher sends to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BusTarget {
+    // Published with QoS 0 to <topic>/chat, <topic>/join and so on
+    Mqtt {
+        address: String,
+        topic: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    // Published to <channel>:chat, <channel>:join and so on
+    Redis {
+        address: String,
+        channel: String,
+        password: Option<String>,
+    },
+}
+
+impl BusTarget {
+    // "mqtt://[user:password@]host[:port]/topic" or
+    // "redis://[:password@]host[:port]/channel". The topic may have slashes
+    // of its own, the ports default to 1883 and 6379.
+    pub fn parse(url: &str) -> Result<BusTarget> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| anyhow!("{} is missing a scheme, e.g. mqtt://", url))?;
+        let (authority, name) = rest
+            .split_once('/')
+            .filter(|(_, name)| !name.is_empty())
+            .ok_or_else(|| anyhow!("{} is missing a topic or channel after the host", url))?;
+        let (credentials, host) = match authority.rsplit_once('@') {
+            Some((credentials, host)) => (Some(credentials), host),
+            None => (None, authority),
+        };
+        let (username, password) = match credentials {
+            Some(credentials) => match credentials.split_once(':') {
+                Some((username, password)) => (username, Some(String::from(password))),
+                None => (credentials, None),
+            },
+            None => ("", None),
+        };
+        let username = Some(String::from(username)).filter(|username| !username.is_empty());
+
+        let (host, port) = split_host_port(host)?;
+        let address = |default: u16| format!("{}:{}", host, port.unwrap_or(default));
+        match scheme {
+            "mqtt" => Ok(BusTarget::Mqtt {
+                address: address(1883),
+                topic: String::from(name),
+                username,
+                password,
+            }),
+            "redis" => Ok(BusTarget::Redis {
+                address: address(6379),
+                channel: String::from(name),
+                password,
+            }),
+            other => Err(anyhow!("Unknown message bus {}, use mqtt or redis", other)),
+        }
+    }
+}
+
+// Publishes chat, joins, leaves, deaths and status samples as JSON to an
+// MQTT topic or a Redis pub/sub channel, for home automation or alerting
+// to react to. Events are in the event_to_json format. Connects on the
+// first publish and again after the connection failed.
+pub struct EventPublisher {
+    target: BusTarget,
+    stream: Option<TcpStream>,
+}
+
+impl EventPublisher {
+    pub fn new(target: BusTarget) -> EventPublisher {
+        EventPublisher {
+            target,
+            stream: None,
+        }
+    }
+
+    pub fn target(&self) -> &BusTarget {
+        &self.target
+    }
+
+    // What `event` is published under, None for events that aren't
+    pub fn kind(event: &Event) -> Option<&'static str> {
+        match event {
+            Event::ChatMessage(_) => Some("chat"),
+            Event::SystemMessage { overlay, .. } if !overlay => Some("system"),
+            Event::PlayerJoined(_) => Some("join"),
+            Event::PlayerLeft(_) => Some("leave"),
+            Event::Died { .. } => Some("death"),
+            _ => None,
+        }
+    }
+
+    // Returns whether `event` was one to publish
+    pub fn publish_event(&mut self, event: &Event) -> Result<bool> {
+        let Some(kind) = EventPublisher::kind(event) else {
+            return Ok(false);
+        };
+        self.publish(kind, event_to_json(event).to_string().as_bytes())?;
+        Ok(true)
+    }
+
+    // Under "status", as StatusSample::to_json
+    pub fn publish_sample(&mut self, sample: &StatusSample) -> Result<()> {
+        self.publish("status", sample.to_json().to_string().as_bytes())
+    }
+
+    // Sends `payload` to the topic or channel with `kind` appended
+    pub fn publish(&mut self, kind: &str, payload: &[u8]) -> Result<()> {
+        // Whatever state a failed connection is left in, the next publish
+        // starts over on a new one
+        let mut stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => connect(&self.target)?,
+        };
+
+        match &self.target {
+            BusTarget::Mqtt { topic, .. } => {
+                let mut body = Vec::new();
+                write_mqtt_string(&mut body, &format!("{}/{}", topic, kind))?;
+                body.extend_from_slice(payload);
+                write_mqtt_packet(&mut stream, 0x30, &body)?; // PUBLISH, QoS 0, not retained
+            }
+            BusTarget::Redis { channel, .. } => {
+                let channel = format!("{}:{}", channel, kind);
+                write_redis_command(&mut stream, &[b"PUBLISH", channel.as_bytes(), payload])?;
+                // The number of subscribers that got it, which doesn't matter
+                read_redis_reply(&mut stream)?;
+            }
+        }
+
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    // Publishes events from a thread of the token, in order, so a slow
+    // broker doesn't hold up the connection. Failed publishes are handed to
+    // `on_error` and dropped, the publisher keeps going.
+    pub fn spawn(
+        mut self,
+        token: &ShutdownToken,
+        mut on_error: impl FnMut(anyhow::Error) + Send + 'static,
+    ) -> Sender<Event> {
+        let (sender, events) = mpsc::channel::<Event>();
+        token.spawn(move |token| {
+            for event in events {
+                if token.is_cancelled() {
+                    return;
+                }
+                if let Err(error) = self.publish_event(&event) {
+                    on_error(error);
+                }
+            }
+            self.disconnect();
+        });
+        sender
+    }
+
+    // Says goodbye to MQTT brokers, which would otherwise log a lost client
+    pub fn disconnect(&mut self) {
+        if let (Some(mut stream), BusTarget::Mqtt { .. }) = (self.stream.take(), &self.target) {
+            let _ = write_mqtt_packet(&mut stream, 0xE0, &[]); // DISCONNECT
+        }
+    }
+}
+
+impl Drop for EventPublisher {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+fn connect(target: &BusTarget) -> Result<TcpStream> {
+    let address = match target {
+        BusTarget::Mqtt { address, .. } | BusTarget::Redis { address, .. } => address,
+    };
+    let mut stream = proxy::connect_tcp(address, Some(BUS_TIMEOUT))
+        .with_context(|| format!("Failed to connect to {}", address))?;
+    stream.set_read_timeout(Some(BUS_TIMEOUT))?;
+    stream.set_write_timeout(Some(BUS_TIMEOUT))?;
+
+    match target {
+        BusTarget::Mqtt {
+            username, password, ..
+        } => mqtt_connect(&mut stream, username.as_deref(), password.as_deref())?,
+        BusTarget::Redis {
+            password: Some(password),
+            ..
+        } => {
+            write_redis_command(&mut stream, &[b"AUTH", password.as_bytes()])?;
+            read_redis_reply(&mut stream).context("Redis refused the password")?;
+        }
+        BusTarget::Redis { .. } => {}
+    }
+
+    Ok(stream)
+}
+
+// MQTT 3.1.1 CONNECT with a clean session and no keep alive, then waits
+// for the CONNACK
+fn mqtt_connect(
+    stream: &mut TcpStream,
+    username: Option<&str>,
+    password: Option<&str>,
+) -> Result<()> {
+    // A password without a username is a 5.0 thing, 3.1.1 brokers get an
+    // empty username with it
+    let username = username.or(password.map(|_| ""));
+    let mut flags = 0x02; // Clean session
+    if username.is_some() {
+        flags |= 0x80;
+    }
+    if password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = Vec::new();
+    write_mqtt_string(&mut body, "MQTT")?; // Protocol name
+    body.push(4); // Protocol level, 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&0u16.to_be_bytes()); // Keep alive, off
+    write_mqtt_string(&mut body, MQTT_CLIENT_ID)?;
+    if let Some(username) = username {
+        write_mqtt_string(&mut body, username)?;
+    }
+    if let Some(password) = password {
+        write_mqtt_string(&mut body, password)?;
+    }
+    write_mqtt_packet(stream, 0x10, &body)?;
+
+    let mut connack = [0u8; 4];
+    stream
+        .read_exact(&mut connack)
+        .context("The MQTT broker didn't answer")?;
+    match connack {
+        [0x20, 2, _, 0] => Ok(()),
+        [0x20, 2, _, code] => Err(anyhow!(
+            "The MQTT broker refused the connection: {}",
+            match code {
+                1 => "unacceptable protocol version",
+                2 => "client id rejected",
+                3 => "server unavailable",
+                4 => "bad username or password",
+                5 => "not authorized",
+                _ => "unknown reason",
+            }
+        )),
+        _ => Err(anyhow!("Expected a CONNACK from the MQTT broker")),
+    }
+}
+
+fn write_mqtt_string(out: &mut Vec<u8>, text: &str) -> Result<()> {
+    let length = u16::try_from(text.len()).context("MQTT strings are at most 65535 bytes")?;
+    out.extend_from_slice(&length.to_be_bytes());
+    out.extend_from_slice(text.as_bytes());
+    Ok(())
+}
+
+// Fixed header with the remaining length in MQTT's own varint, then the body
+fn write_mqtt_packet(stream: &mut TcpStream, header: u8, body: &[u8]) -> Result<()> {
+    if body.len() > 268_435_455 {
+        return Err(anyhow!("MQTT packets are at most 256 MB"));
+    }
+
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    stream.write_all(&packet)?;
+    Ok(())
+}
+
+// A RESP array of bulk strings, which Redis takes for any command
+fn write_redis_command(stream: &mut TcpStream, arguments: &[&[u8]]) -> Result<()> {
+    let mut command = format!("*{}\r\n", arguments.len()).into_bytes();
+    for argument in arguments {
+        command.extend_from_slice(format!("${}\r\n", argument.len()).as_bytes());
+        command.extend_from_slice(argument);
+        command.extend_from_slice(b"\r\n");
+    }
+    stream.write_all(&command)?;
+    Ok(())
+}
+
+// The first line of the reply, an error reply as an error. The commands we
+// send only ever get single line replies.
+fn read_redis_reply(stream: &mut TcpStream) -> Result<String> {
+    let mut line = String::new();
+    BufReader::new(stream.take(1024)).read_line(&mut line)?;
+    let line = line.trim_end();
+    match line.strip_prefix('-') {
+        Some(error) => Err(anyhow!("Redis answered {}", error)),
+        None if line.is_empty() => Err(anyhow!("Redis closed the connection")),
+        None => Ok(String::from(line)),
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{runs, PlainRenderer, Renderer};
+use serde::{Deserialize, Serialize};
+
+// A JSON text component. Servers may send a bare string or an array instead
+// of an object, all of which deserialize into this.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(from = "RawComponent")]
+pub struct Component {
+    pub text: String,
+    pub translate: Option<String>,
+    pub with: Vec<Component>,
+    pub color: Option<String>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+    pub extra: Vec<Component>,
+}
+
+// Vanilla picks the component kind by which key is present and rejects
+// objects with neither, so text is always written unless translating
+impl Serialize for Component {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        match &self.translate {
+            Some(key) => {
+                map.serialize_entry("translate", key)?;
+                if !self.with.is_empty() {
+                    map.serialize_entry("with", &self.with)?;
+                }
+            }
+            None => map.serialize_entry("text", &self.text)?,
+        }
+        if let Some(color) = &self.color {
+            map.serialize_entry("color", color)?;
+        }
+        let flags = [
+            ("bold", self.bold),
+            ("italic", self.italic),
+            ("underlined", self.underlined),
+            ("strikethrough", self.strikethrough),
+            ("obfuscated", self.obfuscated),
+        ];
+        for (name, flag) in flags {
+            if let Some(flag) = flag {
+                map.serialize_entry(name, &flag)?;
+            }
+        }
+        if !self.extra.is_empty() {
+            map.serialize_entry("extra", &self.extra)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawComponent {
+    Text(String),
+    Number(serde_json::Number),
+    Bool(bool),
+    List(Vec<Component>),
+    Object(Box<ComponentFields>),
+}
+
+// Mirror of Component's fields, needed so the object form doesn't recurse
+// back into RawComponent
+#[derive(Deserialize)]
+struct ComponentFields {
+    #[serde(default)]
+    text: String,
+    translate: Option<String>,
+    #[serde(default)]
+    with: Vec<Component>,
+    color: Option<String>,
+    #[serde(default, deserialize_with = "flag")]
+    bold: Option<bool>,
+    #[serde(default, deserialize_with = "flag")]
+    italic: Option<bool>,
+    #[serde(default, deserialize_with = "flag")]
+    underlined: Option<bool>,
+    #[serde(default, deserialize_with = "flag")]
+    strikethrough: Option<bool>,
+    #[serde(default, deserialize_with = "flag")]
+    obfuscated: Option<bool>,
+    #[serde(default)]
+    extra: Vec<Component>,
+}
+
+// A style flag, true or false in JSON but a byte in NBT, e.g. the chat
+// decorations of the registry codec
+fn flag<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Byte(i8),
+    }
+
+    Ok(
+        Option::<Flag>::deserialize(deserializer)?.map(|flag| match flag {
+            Flag::Bool(value) => value,
+            Flag::Byte(value) => value != 0,
+        }),
+    )
+}
+
+impl From<RawComponent> for Component {
+    fn from(raw: RawComponent) -> Component {
+        match raw {
+            RawComponent::Text(text) => Component::text(&text),
+            RawComponent::Number(number) => Component::text(&number.to_string()),
+            RawComponent::Bool(value) => Component::text(&value.to_string()),
+            RawComponent::List(mut components) => {
+                if components.is_empty() {
+                    return Component::default();
+                }
+                // The first element is the parent of the rest
+                let mut parent = components.remove(0);
+                parent.extra.extend(components);
+                parent
+            }
+            RawComponent::Object(fields) => Component {
+                text: fields.text,
+                translate: fields.translate,
+                with: fields.with,
+                color: fields.color,
+                bold: fields.bold,
+                italic: fields.italic,
+                underlined: fields.underlined,
+                strikethrough: fields.strikethrough,
+                obfuscated: fields.obfuscated,
+                extra: fields.extra,
+            },
+        }
+    }
+}
+
+impl Component {
+    pub fn text(text: &str) -> Component {
+        Component {
+            text: String::from(text),
+            ..Component::default()
+        }
+    }
+
+    pub fn translate(key: &str, with: Vec<Component>) -> Component {
+        Component {
+            translate: Some(String::from(key)),
+            with,
+            ..Component::default()
+        }
+    }
+
+    pub fn color(mut self, color: &str) -> Component {
+        self.color = Some(String::from(color));
+        self
+    }
+
+    pub fn bold(mut self) -> Component {
+        self.bold = Some(true);
+        self
+    }
+
+    pub fn push(mut self, child: Component) -> Component {
+        self.extra.push(child);
+        self
+    }
+
+    // A "§6Gold §lbold" string as components, one child per styled run
+    pub fn from_legacy(text: &str) -> Component {
+        let flag = |enabled: bool| enabled.then_some(true);
+        let extra = runs(&Component::text(text))
+            .into_iter()
+            .map(|(text, style)| Component {
+                text,
+                color: style.color,
+                bold: flag(style.bold),
+                italic: flag(style.italic),
+                underlined: flag(style.underlined),
+                strikethrough: flag(style.strikethrough),
+                obfuscated: flag(style.obfuscated),
+                ..Component::default()
+            })
+            .collect();
+
+        Component {
+            extra,
+            ..Component::default()
+        }
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Component> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        // Serializing plain structs of strings can't fail
+        serde_json::to_string(self).unwrap()
+    }
+
+    // Flattens the component tree into unformatted text
+    pub fn to_plain(&self) -> String {
+        PlainRenderer.render(self)
+    }
+}
+
+// The handful of vanilla translation keys chat actually uses. Anything else
+// is shown as the key followed by its arguments.
+pub fn translate_fallback(key: &str, arguments: &[String]) -> String {
+    let pattern = match key {
+        "chat.type.text" => "<%s> %s",
+        "chat.type.announcement" => "[%s] %s",
+        "chat.type.emote" => "* %s %s",
+        "chat.type.admin" => "[%s: %s]",
+        "chat.type.team.text" => "%s <%s> %s",
+        "commands.message.display.incoming" => "%s whispers to you: %s",
+        "commands.message.display.outgoing" => "You whisper to %s: %s",
+        "multiplayer.player.joined" => "%s joined the game",
+        "multiplayer.player.left" => "%s left the game",
+        _ => {
+            return match arguments.is_empty() {
+                true => String::from(key),
+                false => format!("{} {}", key, arguments.join(" ")),
+            }
+        }
+    };
+
+    format_pattern(pattern, arguments)
+}
+
+// Fills %s and %1$s style placeholders the way vanilla's translator does
+pub fn format_pattern(pattern: &str, arguments: &[String]) -> String {
+    let mut result = String::new();
+    let mut next = 0;
+    let mut rest = pattern;
+
+    while let Some(index) = rest.find('%') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        if let Some(after) = rest.strip_prefix('s') {
+            result.push_str(arguments.get(next).map(String::as_str).unwrap_or(""));
+            next += 1;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('%') {
+            result.push('%');
+            rest = after;
+        } else if let Some((position, after)) = rest.split_once("$s") {
+            match position.parse::<usize>() {
+                Ok(position) if position > 0 => {
+                    result.push_str(
+                        arguments
+                            .get(position - 1)
+                            .map(String::as_str)
+                            .unwrap_or(""),
+                    );
+                    rest = after;
+                }
+                _ => result.push('%'),
+            }
+        } else {
+            result.push('%');
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
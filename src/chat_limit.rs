@@ -0,0 +1,108 @@
+use crate::Packet;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Outgoing chat allowed, as a token bucket: `burst` messages right away,
+// then one every 1 / `per_second` seconds. Vanilla kicks for spam at
+// around 20 messages within a few seconds, the default stays well below.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChatRate {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+impl Default for ChatRate {
+    fn default() -> ChatRate {
+        ChatRate {
+            per_second: 1.0,
+            burst: 3,
+        }
+    }
+}
+
+// What became of a chat message or command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendResult {
+    Sent,
+    // Over the rate, sent in order as the limit allows while events are read
+    Queued,
+}
+
+pub(crate) struct ChatLimiter {
+    rate: Option<ChatRate>,
+    tokens: f64,
+    refilled: Instant,
+    queue: VecDeque<Packet>,
+}
+
+impl ChatLimiter {
+    pub fn new(rate: Option<ChatRate>) -> ChatLimiter {
+        ChatLimiter {
+            rate,
+            tokens: rate.map_or(0.0, |rate| rate.burst as f64),
+            refilled: Instant::now(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    // Sends right away when allowed and nothing is waiting before it
+    pub fn submit(&mut self, packet: Packet) -> Option<Packet> {
+        if self.queue.is_empty() && self.take() {
+            return Some(packet);
+        }
+        self.queue.push_back(packet);
+        None
+    }
+
+    // The next queued packet, if its turn has come
+    pub fn pop_ready(&mut self) -> Option<Packet> {
+        if self.queue.is_empty() || !self.take() {
+            return None;
+        }
+        self.queue.pop_front()
+    }
+
+    // How long until the next queued packet can go, None with nothing queued
+    pub fn wait(&mut self) -> Option<Duration> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        let rate = self.rate?;
+        self.refill(rate);
+        let missing = (1.0 - self.tokens).max(0.0);
+        // A rate of 0 never frees up
+        Some(Duration::try_from_secs_f64(missing / rate.per_second).unwrap_or(Duration::MAX))
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    fn take(&mut self) -> bool {
+        let rate = match self.rate {
+            Some(rate) => rate,
+            None => return true,
+        };
+        self.refill(rate);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    fn refill(&mut self, rate: ChatRate) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        // Below one token nothing could ever be sent
+        let capacity = rate.burst.max(1) as f64;
+        self.tokens = (self.tokens + elapsed * rate.per_second.max(0.0)).min(capacity);
+        self.refilled = now;
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{event_to_json, locale::civil_from_days, Event};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// How often a ChatLogger starts new files, on top of max_size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Hourly,
+    Daily,
+}
+
+// The files written for one period, or one part of it once max_size is hit
+struct LogFiles {
+    period: String,
+    part: u32,
+    text: Option<File>,
+    jsonl: Option<File>,
+    // Of the bigger of the two, they rotate together
+    size: u64,
+}
+
+// Writes every chat and system message to dated files in a directory, as
+// plain text lines and as JSON lines in the event_to_json format:
+// chat-2026-10-16.log and chat-2026-10-16.jsonl, then chat-2026-10-16.1.log
+// and so on once a file outgrows max_size. Dates and times are UTC, so logs
+// from bots in different places line up.
+pub struct ChatLogger {
+    directory: PathBuf,
+    prefix: String,
+    text: bool,
+    jsonl: bool,
+    rotation: Rotation,
+    max_size: Option<u64>,
+    current: Option<LogFiles>,
+}
+
+impl ChatLogger {
+    // The directory is made on the first message if it doesn't exist yet
+    pub fn new(directory: impl Into<PathBuf>) -> ChatLogger {
+        ChatLogger {
+            directory: directory.into(),
+            prefix: String::from("chat"),
+            text: true,
+            jsonl: true,
+            rotation: Rotation::Daily,
+            max_size: None,
+            current: None,
+        }
+    }
+
+    // Starts every file name, "chat" by default
+    pub fn prefix(mut self, prefix: &str) -> ChatLogger {
+        self.prefix = String::from(prefix);
+        self
+    }
+
+    pub fn text(mut self, enabled: bool) -> ChatLogger {
+        self.text = enabled;
+        self
+    }
+
+    pub fn jsonl(mut self, enabled: bool) -> ChatLogger {
+        self.jsonl = enabled;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: Rotation) -> ChatLogger {
+        self.rotation = rotation;
+        self
+    }
+
+    // Bytes per file before the next part of the period is started
+    pub fn max_size(mut self, bytes: Option<u64>) -> ChatLogger {
+        self.max_size = bytes;
+        self
+    }
+
+    // Files of the current period, the ones being written to
+    pub fn current_files(&self) -> Vec<PathBuf> {
+        match &self.current {
+            Some(files) => self.paths(&files.period, files.part),
+            None => Vec::new(),
+        }
+    }
+
+    // Makes sure everything logged so far is on disk, e.g. before exiting
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(files) = &mut self.current {
+            for file in [&mut files.text, &mut files.jsonl].into_iter().flatten() {
+                file.sync_data()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Logs chat and system messages and ignores any other event, the
+    // action bar included. Returns whether the event was logged.
+    pub fn log(&mut self, event: &Event) -> Result<bool> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as i64);
+        self.log_at(now, event)
+    }
+
+    // Like log, as if it happened at `timestamp`, milliseconds since the epoch
+    pub fn log_at(&mut self, timestamp: i64, event: &Event) -> Result<bool> {
+        let text = match event {
+            Event::ChatMessage(message) => message.to_component().to_plain(),
+            Event::SystemMessage {
+                message, overlay, ..
+            } if !overlay => message.to_plain(),
+            _ => return Ok(false),
+        };
+
+        let line = format!("{} {}\n", utc_date_time(timestamp), text);
+        let mut value = event_to_json(event);
+        value["logged_at"] = json!(timestamp);
+        let json_line = format!("{}\n", value);
+
+        let files = self.files_for(timestamp)?;
+        let mut written = 0;
+        if let Some(file) = &mut files.text {
+            file.write_all(line.as_bytes())?;
+            written = line.len();
+        }
+        if let Some(file) = &mut files.jsonl {
+            file.write_all(json_line.as_bytes())?;
+            written = written.max(json_line.len());
+        }
+        files.size += written as u64;
+
+        Ok(true)
+    }
+
+    // Opens the next files when the period changed or the current ones are full
+    fn files_for(&mut self, timestamp: i64) -> Result<&mut LogFiles> {
+        let period = self.period(timestamp);
+        let reuse = match &self.current {
+            Some(files) => files.period == period && !self.full(files.size),
+            None => false,
+        };
+        if !reuse {
+            let mut part = match &self.current {
+                Some(files) if files.period == period => files.part + 1,
+                _ => 0,
+            };
+            fs::create_dir_all(&self.directory).with_context(|| {
+                format!(
+                    "Failed to create log directory {}",
+                    self.directory.display()
+                )
+            })?;
+            // Picks up where an earlier run left off, skipping full parts
+            while self.full(self.existing_size(&period, part)) {
+                part += 1;
+            }
+            self.current = Some(self.open(period, part)?);
+        }
+
+        Ok(self.current.as_mut().unwrap())
+    }
+
+    fn full(&self, size: u64) -> bool {
+        self.max_size.is_some_and(|max| size >= max)
+    }
+
+    fn period(&self, timestamp: i64) -> String {
+        let seconds = timestamp.div_euclid(1000);
+        let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+        match self.rotation {
+            Rotation::Daily => format!("{}-{:02}-{:02}", year, month, day),
+            Rotation::Hourly => format!(
+                "{}-{:02}-{:02}-{:02}",
+                year,
+                month,
+                day,
+                seconds.rem_euclid(86_400) / 3600
+            ),
+        }
+    }
+
+    // The text file first, then the JSON lines one, whichever are enabled
+    fn paths(&self, period: &str, part: u32) -> Vec<PathBuf> {
+        let stem = match part {
+            0 => format!("{}-{}", self.prefix, period),
+            part => format!("{}-{}.{}", self.prefix, period, part),
+        };
+        [(self.text, "log"), (self.jsonl, "jsonl")]
+            .into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, extension)| self.directory.join(format!("{}.{}", stem, extension)))
+            .collect()
+    }
+
+    fn existing_size(&self, period: &str, part: u32) -> u64 {
+        self.paths(period, part)
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn open(&self, period: String, part: u32) -> Result<LogFiles> {
+        let size = self.existing_size(&period, part);
+        let stem = self.paths(&period, part);
+        let mut paths = stem.iter();
+        let text = match self.text {
+            true => Some(append(paths.next().unwrap())?),
+            false => None,
+        };
+        let jsonl = match self.jsonl {
+            true => Some(append(paths.next().unwrap())?),
+            false => None,
+        };
+
+        Ok(LogFiles {
+            period,
+            part,
+            text,
+            jsonl,
+            size,
+        })
+    }
+}
+
+fn append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open chat log {}", path.display()))
+}
+
+fn utc_date_time(timestamp: i64) -> String {
+    let seconds = timestamp.div_euclid(1000);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
@@ -0,0 +1,107 @@
+use crate::{Packet, ProtocolFeatures};
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+// Parts of the skin shown, the bits of ClientInformation::skin_parts
+pub const SKIN_CAPE: u8 = 0x01;
+pub const SKIN_JACKET: u8 = 0x02;
+pub const SKIN_LEFT_SLEEVE: u8 = 0x04;
+pub const SKIN_RIGHT_SLEEVE: u8 = 0x08;
+pub const SKIN_LEFT_PANTS: u8 = 0x10;
+pub const SKIN_RIGHT_PANTS: u8 = 0x20;
+pub const SKIN_HAT: u8 = 0x40;
+pub const SKIN_ALL: u8 = 0x7F;
+
+// Which chat the server should bother sending us
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatMode {
+    Enabled,
+    CommandsOnly,
+    Hidden,
+}
+
+impl FromStr for ChatMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<ChatMode> {
+        match mode {
+            "enabled" => Ok(ChatMode::Enabled),
+            "commands" | "commands-only" => Ok(ChatMode::CommandsOnly),
+            "hidden" => Ok(ChatMode::Hidden),
+            other => Err(anyhow!("Unknown chat mode {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MainHand {
+    Left,
+    Right,
+}
+
+// The settings vanilla reports with the Client Information packet once it's
+// in the world. Some servers pick the language of their messages from `locale`,
+// and some anti-cheats kick clients that never send it at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInformation {
+    // Minecraft's own tags, e.g. en_us or de_de
+    pub locale: String,
+    // In chunks, 2 to 32
+    pub view_distance: u8,
+    pub chat_mode: ChatMode,
+    pub chat_colors: bool,
+    // SKIN_* bits
+    pub skin_parts: u8,
+    pub main_hand: MainHand,
+    pub text_filtering: bool,
+    // Whether we show up in the sample of players in the status response
+    pub server_listing: bool,
+}
+
+// What a fresh vanilla install sends
+impl Default for ClientInformation {
+    fn default() -> ClientInformation {
+        ClientInformation {
+            locale: String::from("en_us"),
+            view_distance: 10,
+            chat_mode: ChatMode::Enabled,
+            chat_colors: true,
+            skin_parts: SKIN_ALL,
+            main_hand: MainHand::Right,
+            text_filtering: false,
+            server_listing: true,
+        }
+    }
+}
+
+impl ClientInformation {
+    pub(crate) fn to_packet(&self, features: &ProtocolFeatures) -> Result<Packet> {
+        let mut packet = Packet::new();
+        packet.write_varint(features.client_information_packet_id as i32)?; // Protocol ID
+        packet.write_string(&self.locale)?;
+        packet.write_slice(&[self.view_distance]);
+        packet.write_varint(match self.chat_mode {
+            ChatMode::Enabled => 0,
+            ChatMode::CommandsOnly => 1,
+            ChatMode::Hidden => 2,
+        })?;
+        packet.write_bool(self.chat_colors);
+        packet.write_slice(&[self.skin_parts]);
+        packet.write_varint(match self.main_hand {
+            MainHand::Left => 0,
+            MainHand::Right => 1,
+        })?;
+        packet.write_bool(self.text_filtering);
+        packet.write_bool(self.server_listing);
+        Ok(packet)
+    }
+}
+
+// The minecraft:brand plugin message, what F3 shows as the client's name
+pub(crate) fn brand_packet(features: &ProtocolFeatures, brand: &str) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.plugin_message_packet_id as i32)?; // Protocol ID
+    packet.write_string("minecraft:brand")?; // Channel
+    packet.write_string(brand)?; // Data, a string of its own
+    Ok(packet)
+}
@@ -0,0 +1,56 @@
+use crate::{Component, Packet, ProtocolFeatures};
+use anyhow::Result;
+use std::time::Duration;
+
+// Vanilla drops requests longer than this
+pub const MAX_COMPLETION_LENGTH: usize = 32500;
+// How long tab_complete waits for the server before giving up
+pub const COMPLETION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub text: String,
+    pub tooltip: Option<Component>,
+    // The part of the request the suggestion replaces, in characters
+    pub start: usize,
+    pub length: usize,
+}
+
+pub(crate) fn request(
+    features: &ProtocolFeatures,
+    transaction_id: i32,
+    text: &str,
+) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.command_suggestions_request_packet_id as i32)?; // Protocol ID
+    packet.write_varint(transaction_id)?; // Transaction ID
+    packet.write_string(text)?; // Text
+
+    Ok(packet)
+}
+
+// Command Suggestions Response, returns the transaction id and the matches
+pub(crate) fn parse_response(packet: &Packet) -> Result<(i32, Vec<Suggestion>)> {
+    let mut reader = packet.reader();
+    let transaction_id = reader.read_varint()?;
+    let start = reader.read_varint()?.max(0) as usize;
+    let length = reader.read_varint()?.max(0) as usize;
+    let count = reader.read_varint()?;
+
+    let mut suggestions = Vec::new();
+    for _ in 0..count {
+        let text = String::from(reader.read_str()?);
+        let tooltip = match reader.read_bool()? {
+            true => Some(Component::from_json(reader.read_str()?)?),
+            false => None,
+        };
+        suggestions.push(Suggestion {
+            text,
+            tooltip,
+            start,
+            length,
+        });
+    }
+
+    Ok((transaction_id, suggestions))
+}
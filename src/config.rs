@@ -0,0 +1,322 @@
+use anyhow::{anyhow, Context, Result};
+use mchat::{ChatLogger, ChatMode, ClientInformation, Rotation};
+use serde::Deserialize;
+use std::{collections::HashMap, env, fs, path::PathBuf, time::Duration};
+
+// ~/.config/mchat/config.toml, everything optional:
+//
+//   username = "extremq"
+//   locale = "de_DE"
+//   hide = ["join-leave"]
+//   mute = ["^\\[Vote\\]"]
+//
+//   [auth]
+//   client_id = "00000000-0000-0000-0000-000000000000"
+//   encrypt = true
+//
+//   [servers.survival]
+//   host = "mc.example.com:25566"
+//   username = "relay"
+//
+//   [servers.hypixel]
+//   host = "mc.hypixel.net"
+//   account = "main"
+//
+//   [client]
+//   brand = "vanilla"
+//   locale = "de_de"
+//   view_distance = 4
+//   farewell = "Bot going offline"
+//
+//   [reconnect]
+//   enabled = true
+//   delay = 1
+//   max_delay = 60
+//
+//   [logging]
+//   chat_log = "/var/log/mchat/chat.log"
+//   directory = "/var/log/mchat"
+//   rotation = "daily"
+//   max_size = 10485760
+//
+//   [webhook]
+//   url = "https://discord.com/api/webhooks/..."
+//   template = "{content}"
+//   listen = "127.0.0.1:8125"
+//
+//   [publish]
+//   url = "mqtt://localhost/mchat"
+//
+//   [aliases]
+//   "!home" = "/warp home"
+//   "!greet" = "Welcome to the server, $1!"
+//   "!afk" = ["/msg $1 brb", "/afk"]
+//
+//   [[rules]]
+//   pattern = "^!discord$"
+//   reply = "Join us at https://discord.gg/example, {sender}"
+//   cooldown = 30
+//
+//   [[highlight]]
+//   pattern = "(?i)\\b{name}\\b"
+//   color = "gold"
+//   bell = true
+//
+// Flags given on the command line win over the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub username: Option<String>,
+    // Saved with mchat accounts add, joins as its profile
+    pub account: Option<String>,
+    pub locale: Option<String>,
+    pub hide: Vec<String>,
+    // Chat lines matching any of these regexes are hidden, F2 shows them
+    pub mute: Vec<String>,
+    // Lines sent from the chat UI, for Up and Ctrl-R. Defaults to history in
+    // mchat::data_directory.
+    pub history: Option<PathBuf>,
+    pub servers: HashMap<String, SavedServer>,
+    pub aliases: HashMap<String, AliasConfig>,
+    pub auth: Auth,
+    pub client: ClientConfig,
+    pub reconnect: Reconnect,
+    pub logging: Logging,
+    pub webhook: Webhook,
+    pub publish: Publish,
+    pub rules: Vec<RuleConfig>,
+    pub highlight: Vec<HighlightConfig>,
+}
+
+// What an alias typed in the chat UI sends instead, one line or several in
+// order, see alias::Aliases for the $1 and $* placeholders
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum AliasConfig {
+    Line(String),
+    Lines(Vec<String>),
+}
+
+// Can be named instead of a host on the command line
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedServer {
+    // host or host:port
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub account: Option<String>,
+    pub protocol: Option<i32>,
+    // Seconds
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    // Kept for online mode, which isn't supported yet
+    pub access_token: Option<String>,
+    // Azure app that mchat accounts add signs in with, registered for personal
+    // Microsoft accounts with the XboxLive.signin scope
+    pub client_id: Option<String>,
+    // Where mchat accounts keeps sessions, see mchat::SessionCache::default_path
+    pub session_cache: Option<PathBuf>,
+    // Encrypt them with a passphrase, from MCHAT_PASSPHRASE or asked for
+    pub encrypt: bool,
+}
+
+// What we tell servers about ourselves after logging in, see
+// mchat::ClientInformation, and what we say on the way out. Unset fields
+// keep vanilla's values.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    // "vanilla" by default, an empty one isn't sent
+    pub brand: Option<String>,
+    // Minecraft's tags like en_us, not the locale chat is shown in
+    pub locale: Option<String>,
+    pub view_distance: Option<u8>,
+    // "enabled", "commands" or "hidden"
+    pub chat_mode: Option<String>,
+    // Said in chat when quitting, Ctrl-C included, before disconnecting
+    pub farewell: Option<String>,
+}
+
+impl ClientConfig {
+    pub fn brand(&self) -> Option<&str> {
+        match self.brand.as_deref() {
+            Some("") => None,
+            Some(brand) => Some(brand),
+            None => Some("vanilla"),
+        }
+    }
+
+    pub fn information(&self) -> Result<ClientInformation> {
+        let mut information = ClientInformation::default();
+        if let Some(locale) = &self.locale {
+            information.locale = locale.to_ascii_lowercase();
+        }
+        if let Some(distance) = self.view_distance {
+            if !(2..=32).contains(&distance) {
+                return Err(anyhow!(
+                    "The view distance has to be 2 to 32, not {}",
+                    distance
+                ));
+            }
+            information.view_distance = distance;
+        }
+        if let Some(mode) = &self.chat_mode {
+            information.chat_mode = mode.parse::<ChatMode>()?;
+        }
+        Ok(information)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Reconnect {
+    pub enabled: bool,
+    // Seconds before the first attempt, doubled after every failed one
+    pub delay: u64,
+    pub max_delay: u64,
+    // Give up after this many attempts in a row, 0 never gives up
+    pub max_attempts: u32,
+}
+
+impl Default for Reconnect {
+    fn default() -> Reconnect {
+        Reconnect {
+            enabled: false,
+            delay: 1,
+            max_delay: 60,
+            max_attempts: 0,
+        }
+    }
+}
+
+impl Reconnect {
+    pub fn delay(&self) -> Duration {
+        Duration::from_secs(self.delay)
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        Duration::from_secs(self.max_delay.max(self.delay))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Logging {
+    // Every line shown in the chat view is appended here with a timestamp
+    pub chat_log: Option<PathBuf>,
+    // Chat and system messages go to dated files in here, see mchat::ChatLogger
+    pub directory: Option<PathBuf>,
+    // "hourly" or "daily", the default
+    pub rotation: Option<Rotation>,
+    // Bytes per file before starting another, unlimited by default
+    pub max_size: Option<u64>,
+    // Both the plain text and the JSON lines files are written by default
+    pub text: Option<bool>,
+    pub jsonl: Option<bool>,
+}
+
+impl Logging {
+    pub fn chat_logger(&self) -> Option<ChatLogger> {
+        let directory = self.directory.as_ref()?;
+        Some(
+            ChatLogger::new(directory)
+                .rotation(self.rotation.unwrap_or(Rotation::Daily))
+                .max_size(self.max_size)
+                .text(self.text.unwrap_or(true))
+                .jsonl(self.jsonl.unwrap_or(true)),
+        )
+    }
+}
+
+// A chat bridge, needs mchat built with the http feature
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Webhook {
+    // Incoming chat is posted here
+    pub url: Option<String>,
+    // "discord", the default, or "slack"
+    pub format: Option<String>,
+    // {sender}, {content} and {message}, see mchat::WebhookBridge
+    pub template: Option<String>,
+    // System messages are only posted with one
+    pub system_template: Option<String>,
+    // Messages POSTed here are said in the chat, e.g. by a Discord bot
+    pub listen: Option<String>,
+    // Required as "Authorization: Bearer <secret>" when set
+    pub secret: Option<String>,
+}
+
+// Events for home automation and alerting, needs mchat built with the bus
+// feature. Chat publishes chat, joins, leaves and deaths, monitor its samples.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Publish {
+    // mqtt://[user:password@]host[:port]/topic or
+    // redis://[:password@]host[:port]/channel, see mchat::BusTarget
+    pub url: Option<String>,
+}
+
+// Run against incoming chat, see mchat::ChatRules. `reply` and `run` may use
+// $1, $name and {sender}; `run` goes to sh -c with MCHAT_SENDER and
+// MCHAT_MESSAGE set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub pattern: String,
+    pub reply: Option<String>,
+    pub run: Option<String>,
+    // Seconds
+    #[serde(default)]
+    pub cooldown: u64,
+}
+
+// Chat lines matching `pattern` are drawn in `color`, yellow by default, and
+// ring the terminal bell with `bell`. {name} stands for the username joined
+// as. F3 turns highlighting off and on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HighlightConfig {
+    pub pattern: String,
+    pub color: Option<String>,
+    #[serde(default)]
+    pub bell: bool,
+}
+
+impl Config {
+    // An explicitly given file has to exist, the default one may be missing
+    pub fn load(path: Option<PathBuf>) -> Result<Config> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if !required && error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default())
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|value| !value.is_empty()) {
+        Some(base) => PathBuf::from(base),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(base.join("mchat").join("config.toml"))
+}
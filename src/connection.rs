@@ -0,0 +1,575 @@
+use crate::{
+    frame, metrics::Metrics, BitSet, CancelRegistration, Packet, ShutdownToken, Transport,
+    DEFAULT_COMPRESSION_LEVEL, VARINT_CONTINUE_BIT, VARINT_SEGMENT_BITS,
+};
+use anyhow::{anyhow, Context, Result};
+use flate2::{Decompress, FlushDecompress, Status};
+use std::{
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::Duration,
+};
+
+// Where a client connection is in the protocol. The handshake picks Status
+// or Login, a successful login moves on to Play, and a disconnect or a
+// finished status ping leaves it Closed. Nothing leads back to Handshaking,
+// that takes a new connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    Handshaking,
+    Status,
+    Login,
+    Play,
+    Closed,
+}
+
+impl ConnectionState {
+    pub fn can_become(self, next: ConnectionState) -> bool {
+        use ConnectionState::*;
+        matches!(
+            (self, next),
+            (Handshaking, Status) | (Handshaking, Login) | (Login, Play) | (_, Closed)
+        )
+    }
+}
+
+// The framed packet stream shared by both ends of a connection
+pub struct Connection {
+    reader: BufReader<Box<dyn Transport>>,
+    // Shared with the ConnectionWriters handed out, so whole frames go out
+    // one at a time whichever thread sends them
+    writer: Arc<SharedWriter>,
+    compression: Option<usize>,
+    compression_level: u32,
+    // Frame bodies are read into this first, so it's reused across packets
+    scratch: Vec<u8>,
+    metrics: Option<Metrics>,
+    // Handed out weakly by closer(), so it's gone with the connection
+    closer: Arc<dyn Transport>,
+    // See close_on_cancel, removed from the token along with the connection
+    cancel_registration: Option<CancelRegistration>,
+    // A SkippablePacket failed to skip to the end of its frame, so where
+    // the next one starts is anyone's guess
+    desynced: bool,
+    // Ids the reads hand out, the others are skipped, see set_packet_filter
+    filter: Option<BitSet>,
+    filtered: u64,
+}
+
+impl Connection {
+    pub fn new(stream: Box<dyn Transport>) -> Result<Connection> {
+        Ok(Connection {
+            closer: Arc::from(stream.try_clone()?),
+            reader: BufReader::new(stream.try_clone()?),
+            writer: Arc::new(Mutex::new(BufWriter::new(stream))),
+            compression: None,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            scratch: Vec::new(),
+            metrics: None,
+            cancel_registration: None,
+            desynced: false,
+            filter: None,
+            filtered: 0,
+        })
+    }
+
+    pub fn compression(&self) -> Option<usize> {
+        self.compression
+    }
+
+    pub fn set_compression(&mut self, threshold: Option<usize>) {
+        self.compression = threshold;
+    }
+
+    // zlib level of what we send once compression is on, see Packet::to_frame_at
+    pub fn set_compression_level(&mut self, level: u32) {
+        self.compression_level = level;
+    }
+
+    // Counts every packet and byte going through from now on
+    pub(crate) fn set_metrics(&mut self, metrics: Option<Metrics>) {
+        self.metrics = metrics;
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        Ok(self.reader.get_ref().set_read_timeout(timeout)?)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        Ok(self.reader.get_ref().set_write_timeout(timeout)?)
+    }
+
+    // Waits up to `timeout` for the next packet to start arriving, without
+    // consuming anything. A closed stream counts as readable so the following
+    // read gets to report it. A zero timeout only looks at what's there.
+    pub fn wait_readable(&mut self, timeout: Duration) -> Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(true);
+        }
+
+        let result = if timeout.is_zero() {
+            // Zero isn't a valid read timeout, not blocking at all is the same
+            self.reader.get_ref().set_nonblocking(true)?;
+            let result = self.reader.fill_buf().map(|_| ());
+            self.reader.get_ref().set_nonblocking(false)?;
+            result
+        } else {
+            let previous = self.reader.get_ref().read_timeout()?;
+            self.set_read_timeout(Some(timeout))?;
+            let result = self.reader.fill_buf().map(|_| ());
+            self.set_read_timeout(previous)?;
+            result
+        };
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Ok(false)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    // A second handle on the stream, e.g. to shut it down from another thread
+    pub fn try_clone_stream(&self) -> Result<Box<dyn Transport>> {
+        Ok(self.reader.get_ref().try_clone()?)
+    }
+
+    // A handle for shutting the socket down from elsewhere that doesn't keep
+    // it open, unlike try_clone_stream
+    pub(crate) fn closer(&self) -> Weak<dyn Transport> {
+        Arc::downgrade(&self.closer)
+    }
+
+    // A blocked read won't notice the token on its own, closing the socket
+    // wakes it up with an error
+    pub(crate) fn close_on_cancel(&mut self, token: &ShutdownToken) {
+        let closer = self.closer();
+        self.cancel_registration = Some(token.register_on_cancel(move || {
+            if let Some(stream) = closer.upgrade() {
+                let _ = stream.shutdown();
+            }
+        }));
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.reader
+            .get_ref()
+            .peer_addr()
+            .ok_or_else(|| anyhow!("The connection has no peer address"))
+    }
+
+    // Sends off anything still buffered, then ends the stream both ways.
+    // Fine to call on a stream the other end already closed.
+    pub fn close(&self) -> Result<()> {
+        let flushed = lock(&self.writer)?.flush();
+        match self.closer.shutdown() {
+            Err(error) if error.kind() != ErrorKind::NotConnected => return Err(error.into()),
+            _ => {}
+        }
+        Ok(flushed?)
+    }
+
+    // Gives back the stream plus whatever was already read from it but not
+    // yet parsed, so the caller can take over the raw byte stream.
+    pub fn into_inner(self) -> Result<(Box<dyn Transport>, Vec<u8>)> {
+        lock(&self.writer)?.flush()?;
+        let buffered = self.reader.buffer().to_vec();
+
+        Ok((self.reader.into_inner(), buffered))
+    }
+
+    pub fn send_packet(&mut self, packet: &Packet) -> Result<()> {
+        send_frame(
+            &self.writer,
+            packet,
+            self.compression,
+            self.compression_level,
+            self.metrics.as_ref(),
+        )
+    }
+
+    // A sending handle for another thread. It keeps the compression of right
+    // now, so only take one once that's settled.
+    pub(crate) fn writer(&self) -> ConnectionWriter {
+        ConnectionWriter {
+            writer: self.writer.clone(),
+            compression: self.compression,
+            compression_level: self.compression_level,
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    // Starts on the next packet but reads no further than its id, so one
+    // nobody wants can be skipped without its body ever being in memory,
+    // see SkippablePacket
+    pub fn next_packet(&mut self) -> Result<SkippablePacket<'_>> {
+        let length = self.read_frame_length()?;
+        frame::check_frame_length(length)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.packet_received(length);
+        }
+
+        let threshold = self.compression;
+        let mut packet = SkippablePacket {
+            connection: self,
+            frame_length: length,
+            header_length: 0,
+            remaining: length,
+            inflate: None,
+            data_length: None,
+            id: 0,
+        };
+        if let Some(threshold) = threshold {
+            let data_length = frame::check_data_length(packet.read_raw_varint()?, threshold)?;
+            packet.header_length = length - packet.remaining;
+            if data_length != 0 {
+                packet.inflate = Some(Decompress::new(true));
+                packet.data_length = Some(data_length);
+            }
+        }
+        let mut id = [0u8];
+        packet
+            .read_exact(&mut id)
+            .context("Framing error: packet ended before its id")?;
+        packet.id = id[0];
+
+        Ok(packet)
+    }
+
+    // Makes read_packet and read_packet_into skip every packet whose id
+    // isn't set in `filter`, before any of its body is decompressed or
+    // copied. None reads everything again. Meant for the play state, where
+    // busy servers send plenty a bot never looks at; next_packet still
+    // returns every packet.
+    pub fn set_packet_filter(&mut self, filter: Option<BitSet>) {
+        self.filter = filter;
+    }
+
+    // Packets skipped by the filter on this connection so far
+    pub fn filtered_packets(&self) -> u64 {
+        self.filtered
+    }
+
+    pub fn read_packet(&mut self) -> Result<Packet> {
+        let mut packet = Packet::new();
+        self.read_packet_into(&mut packet)?;
+
+        Ok(packet)
+    }
+
+    // Reads the next packet into `packet`, reusing its buffer instead of
+    // allocating a new one. Meant for loops that inspect and drop packets.
+    pub fn read_packet_into(&mut self, packet: &mut Packet) -> Result<()> {
+        if self.filter.is_some() {
+            return self.read_filtered_into(packet);
+        }
+        let payload_length = self.read_frame_length()?;
+        frame::check_frame_length(payload_length)?;
+
+        self.scratch.resize(payload_length, 0);
+        self.reader.read_exact(&mut self.scratch).with_context(|| {
+            format!("Connection closed inside a {} byte packet", payload_length)
+        })?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.packet_received(payload_length);
+        }
+        frame::decode_body_into(&self.scratch, self.compression, packet)
+    }
+
+    fn read_filtered_into(&mut self, packet: &mut Packet) -> Result<()> {
+        loop {
+            let next = self.next_packet()?;
+            let wanted = next
+                .connection
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.get(next.id() as usize));
+            if wanted {
+                return next.read_into(packet);
+            }
+            next.skip()?;
+
+            self.filtered += 1;
+            if let Some(metrics) = &self.metrics {
+                metrics.packet_filtered();
+            }
+        }
+    }
+
+    // Mostly the whole length prefix is buffered already and parsed in place.
+    // Otherwise it's read byte by byte straight from the stream, since we
+    // can't know how many bytes it spans before seeing the continue bits.
+    fn read_frame_length(&mut self) -> Result<usize> {
+        if self.desynced {
+            return Err(anyhow!(
+                "Framing error: lost track of the frames after a packet failed to skip"
+            ));
+        }
+        if let Ok(Some((length, size))) = frame::decode_varint(self.reader.buffer()) {
+            self.reader.consume(size);
+            return Ok(length);
+        }
+
+        let mut value = 0u32;
+        for position in 0..5 {
+            let mut byte = [0u8];
+            match self.reader.read_exact(&mut byte) {
+                // Between packets is where a server closing on us ends up
+                Err(error) if position == 0 && error.kind() == ErrorKind::UnexpectedEof => {
+                    return Err(anyhow!("The server closed the connection"));
+                }
+                result => result?,
+            }
+
+            value |= ((byte[0] as i32 & VARINT_SEGMENT_BITS) as u32) << (7 * position);
+            if byte[0] as i32 & VARINT_CONTINUE_BIT == 0 {
+                return Ok(value as usize);
+            }
+        }
+
+        Err(anyhow!(
+            "Framing error: packet length varint exceeds 5 bytes"
+        ))
+    }
+}
+
+// A packet of which only the id has been read, from Connection::next_packet.
+// Reading it gives the rest of the packet as it comes off the stream,
+// inflated if it was compressed. Whatever isn't read is skipped when it's
+// dropped, compressed bytes without inflating them, so a megabyte of chunk
+// data costs no more memory than a keep alive.
+pub struct SkippablePacket<'a> {
+    connection: &'a mut Connection,
+    frame_length: usize,
+    // Of the data length in front of the id, in compressed connections
+    header_length: usize,
+    // Bytes of the frame still on the stream
+    remaining: usize,
+    // Only for frames compressed as a whole
+    inflate: Option<Decompress>,
+    data_length: Option<usize>,
+    id: u8,
+}
+
+impl SkippablePacket<'_> {
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    // Size of the frame without its length prefix, compressed if it was
+    pub fn frame_length(&self) -> usize {
+        self.frame_length
+    }
+
+    // Size of the packet with its id, once inflated
+    pub fn length(&self) -> usize {
+        self.data_length
+            .unwrap_or(self.frame_length - self.header_length)
+    }
+
+    // Same as dropping it, but failing to get to the end of the frame is
+    // reported instead of only breaking the next read
+    pub fn skip(mut self) -> Result<()> {
+        self.discard()
+            .context("Framing error: connection failed while skipping a packet")
+    }
+
+    pub fn read(self) -> Result<Packet> {
+        let mut packet = Packet::new();
+        self.read_into(&mut packet)?;
+
+        Ok(packet)
+    }
+
+    // Like Connection::read_packet_into, reusing the allocation of `packet`
+    pub fn read_into(mut self, packet: &mut Packet) -> Result<()> {
+        packet.clear();
+        packet.buffer.push(self.id);
+        match self.data_length {
+            Some(data_length) => {
+                packet.buffer.reserve(data_length);
+                // The id is in already, so this is a byte more than should be
+                // left, enough to tell when it inflates to too much
+                self.by_ref()
+                    .take(data_length as u64)
+                    .read_to_end(&mut packet.buffer)?;
+                if packet.buffer.len() != data_length {
+                    return Err(anyhow!(
+                        "Framing error: expected {} decompressed bytes, got {}",
+                        data_length,
+                        packet.buffer.len()
+                    ));
+                }
+            }
+            None => {
+                let length = self.frame_length;
+                packet.buffer.reserve(self.remaining);
+                self.read_to_end(&mut packet.buffer).with_context(|| {
+                    format!("Connection closed inside a {} byte packet", length)
+                })?;
+            }
+        }
+        packet.read_protocol_id()?;
+
+        Ok(())
+    }
+
+    // Reads straight from the frame, without inflating
+    fn read_raw(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        // Reading nothing would still wait for the next frame to arrive
+        let limit = out.len().min(self.remaining);
+        if limit == 0 {
+            return Ok(0);
+        }
+        let read = self.connection.reader.read(&mut out[..limit])?;
+        if read == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= read;
+        Ok(read)
+    }
+
+    // The data length in front of compressed frames
+    fn read_raw_varint(&mut self) -> Result<i32> {
+        let mut value = 0u32;
+        for position in 0..5 {
+            let mut byte = [0u8];
+            self.read_raw(&mut byte)?;
+            value |= ((byte[0] as i32 & VARINT_SEGMENT_BITS) as u32) << (7 * position);
+            if byte[0] as i32 & VARINT_CONTINUE_BIT == 0 {
+                return Ok(value as i32);
+            }
+        }
+
+        Err(anyhow!("Framing error: data length varint exceeds 5 bytes"))
+    }
+
+    fn discard(&mut self) -> io::Result<()> {
+        while self.remaining > 0 {
+            let reader = &mut self.connection.reader;
+            let available = match reader.fill_buf() {
+                Ok(buffer) => buffer.len().min(self.remaining),
+                Err(error) => {
+                    self.connection.desynced = true;
+                    return Err(error);
+                }
+            };
+            if available == 0 {
+                self.connection.desynced = true;
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            reader.consume(available);
+            self.remaining -= available;
+        }
+
+        Ok(())
+    }
+}
+
+// The rest of the packet after the id, inflated as it's read
+impl Read for SkippablePacket<'_> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let Some(inflate) = &mut self.inflate else {
+            return self.read_raw(out);
+        };
+
+        loop {
+            let reader = &mut self.connection.reader;
+            let input = match self.remaining {
+                0 => &[],
+                remaining => {
+                    let input = reader.fill_buf()?;
+                    &input[..input.len().min(remaining)]
+                }
+            };
+            let (read_before, written_before) = (inflate.total_in(), inflate.total_out());
+            let status = inflate
+                .decompress(input, out, FlushDecompress::None)
+                .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+            let available = input.len();
+            let read = (inflate.total_in() - read_before) as usize;
+            let written = (inflate.total_out() - written_before) as usize;
+            reader.consume(read);
+            self.remaining -= read;
+
+            if written > 0 || out.is_empty() || status == Status::StreamEnd {
+                return Ok(written);
+            }
+            if read == 0 {
+                return match available == 0 && self.remaining > 0 {
+                    true => Err(ErrorKind::UnexpectedEof.into()),
+                    // Either the frame ran out before the zlib stream did,
+                    // or zlib can't make sense of it
+                    false => Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "Framing error: compressed packet is cut short or corrupt",
+                    )),
+                };
+            }
+        }
+    }
+}
+
+impl Drop for SkippablePacket<'_> {
+    fn drop(&mut self) {
+        // A failure leaves the connection desynced, which the next read reports
+        let _ = self.discard();
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ConnectionWriter {
+    writer: Arc<SharedWriter>,
+    compression: Option<usize>,
+    compression_level: u32,
+    metrics: Option<Metrics>,
+}
+
+impl ConnectionWriter {
+    pub fn send_packet(&self, packet: &Packet) -> Result<()> {
+        send_frame(
+            &self.writer,
+            packet,
+            self.compression,
+            self.compression_level,
+            self.metrics.as_ref(),
+        )
+    }
+}
+
+fn send_frame(
+    writer: &SharedWriter,
+    packet: &Packet,
+    compression: Option<usize>,
+    compression_level: u32,
+    metrics: Option<&Metrics>,
+) -> Result<()> {
+    let mut writer = lock(writer)?;
+    // Frames are flushed as soon as they're written, so the buffer only
+    // saves a copy for small ones. A body that wouldn't fit goes straight
+    // to the stream, header and all in one vectored write.
+    let size = match packet.buffer.len() >= writer.capacity() {
+        true => {
+            writer.flush()?;
+            packet.write_frame_to(writer.get_mut(), compression, compression_level)?
+        }
+        false => packet.write_frame_to(&mut *writer, compression, compression_level)?,
+    };
+    writer.flush()?;
+    if let Some(metrics) = metrics {
+        metrics.packet_sent(size);
+    }
+
+    Ok(())
+}
+
+type SharedWriter = Mutex<BufWriter<Box<dyn Transport>>>;
+
+fn lock(writer: &SharedWriter) -> Result<MutexGuard<'_, BufWriter<Box<dyn Transport>>>> {
+    writer
+        .lock()
+        .map_err(|_| anyhow!("A thread panicked while sending"))
+}
@@ -0,0 +1,77 @@
+use crate::{BitSet, ProtocolFeatures};
+use std::fmt;
+
+// What the client does with a play packet whose id isn't in the release it
+// speaks, or added with ClientBuilder::expect_packets. Usually the server is
+// on a newer version than we think, or a plugin sends something custom.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeMode {
+    // Fail the read with UnexpectedPacket, to find out right away when
+    // following a protocol update
+    Strict,
+    // Hand it over as Event::UnknownPacket and keep going
+    #[default]
+    Lenient,
+}
+
+// The error of reads in DecodeMode::Strict once an unexpected packet arrived:
+// error.downcast_ref::<UnexpectedPacket>()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnexpectedPacket {
+    pub id: Option<u8>,
+    // Of the packet's fields, without the id
+    pub length: usize,
+}
+
+impl fmt::Display for UnexpectedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.id {
+            Some(id) => write!(f, "Unexpected packet 0x{:02X}", id)?,
+            None => write!(f, "Packet without an id")?,
+        }
+        write!(f, " with {} bytes of fields", self.length)
+    }
+}
+
+impl std::error::Error for UnexpectedPacket {}
+
+// The release's own ids, those past its range the features table names
+// (e.g. a newer transfer packet), and the `extra` ones
+pub(crate) fn expected_packets(features: &ProtocolFeatures, extra: &[u8]) -> BitSet {
+    let mut expected = BitSet::new();
+    for id in 0..=features.last_play_packet_id {
+        expected.set(id as usize, true);
+    }
+    let named = features.transfer_packet_id;
+    for id in named.into_iter().chain(extra.iter().copied()) {
+        expected.set(id as usize, true);
+    }
+    expected
+}
+
+// What ClientBuilder::filter_packets lets through: the `wanted` ids and
+// those the client can't do without, to stay connected (keep alives,
+// teleports, resource packs, respawning) and to report chat and kicks
+pub(crate) fn packet_filter(features: &ProtocolFeatures, wanted: &[u8]) -> BitSet {
+    let mut filter = BitSet::new();
+    let required = [
+        features.keep_alive_packet_id,
+        features.disconnect_packet_id,
+        features.login_play_packet_id,
+        features.player_chat_packet_id,
+        features.system_chat_packet_id,
+        features.combat_death_packet_id,
+        features.synchronize_position_packet_id,
+        features.resource_pack_packet_id,
+        features.respawn_packet_id,
+        features.set_health_packet_id,
+    ];
+    let ids = required
+        .into_iter()
+        .chain(features.transfer_packet_id)
+        .chain(wanted.iter().copied());
+    for id in ids {
+        filter.set(id as usize, true);
+    }
+    filter
+}
@@ -0,0 +1,265 @@
+use anyhow::Result;
+use mchat::{
+    offline_uuid, Component, MinecraftListener, PlayerAction, PlayerInfo, ServerPlayer,
+    ShutdownToken,
+};
+use rand::{rngs::StdRng, RngExt};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+// Made up regulars of the demo server
+const PLAYERS: &[&str] = &[
+    "Steve_Builds",
+    "AlexMines",
+    "redstone_rach",
+    "xX_Creeper_Xx",
+    "BlockyMcBlockface",
+];
+
+const LINES: &[&str] = &[
+    "anyone got spare iron?",
+    "the new farm is finally done",
+    "gg",
+    "who took the diamonds from the community chest",
+    "brb dinner",
+    "lol",
+    "does anyone know the nether hub coords?",
+    "nice build!",
+    "i just found a mineshaft under spawn",
+    "can someone help me with this redstone door",
+    "back",
+    "creepers blew up my house again",
+    "selling enchanted books at my shop",
+    "wait how do you make a beacon",
+    "ty!",
+];
+
+const ADVANCEMENTS: &[&str] = &[
+    "Getting an Upgrade",
+    "Acquire Hardware",
+    "Diamonds!",
+    "We Need to Go Deeper",
+    "Hot Stuff",
+];
+
+const DEATHS: &[&str] = &["death.attack.drown", "death.fell.accident.generic"];
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+// A scripted server on localhost, for trying the chat UI without one.
+// Returns where it listens, it runs until the token is cancelled.
+pub fn start(shutdown: &ShutdownToken) -> Result<SocketAddr> {
+    let listener = MinecraftListener::bind("127.0.0.1:0")?.status(
+        &serde_json::json!({
+            "version": { "name": "1.19", "protocol": mchat::PROTOCOL_VERSION },
+            "players": { "max": 20, "online": PLAYERS.len() },
+            "description": { "text": "mchat demo server", "color": "gold" },
+        })
+        .to_string(),
+    );
+    let address = listener.local_addr()?;
+
+    shutdown.wake_on_cancel(address);
+
+    let token = shutdown.clone();
+    shutdown.spawn(move |_| {
+        while !token.is_cancelled() {
+            // Errors only end that one connection
+            if let Ok(Some(player)) = listener.accept() {
+                token.spawn(move |token| {
+                    let _ = Demo::new(player).run(&token);
+                });
+            }
+        }
+    });
+
+    Ok(address)
+}
+
+struct Demo {
+    player: ServerPlayer,
+    rng: StdRng,
+    online: Vec<&'static str>,
+    // Someone greets back after the player said hi
+    greeting: bool,
+}
+
+impl Demo {
+    fn new(player: ServerPlayer) -> Demo {
+        Demo {
+            player,
+            rng: rand::make_rng(),
+            online: PLAYERS[..3].to_vec(),
+            greeting: false,
+        }
+    }
+
+    fn run(&mut self, shutdown: &ShutdownToken) -> Result<()> {
+        for name in self.online.clone() {
+            self.player.send_player_added(&demo_player(name))?;
+        }
+        let me = PlayerInfo {
+            uuid: self.player.uuid(),
+            name: String::from(self.player.name()),
+            properties: Vec::new(),
+            gamemode: 0,
+            latency: 0,
+            display_name: None,
+        };
+        self.player.send_player_added(&me)?;
+        self.joined(self.player.name().to_owned())?;
+        self.system(
+            Component::text("This is a demo server, nothing here is real. Try /help.")
+                .color("gray"),
+        )?;
+
+        let mut next_step = Instant::now() + self.delay();
+        let mut next_keep_alive = Instant::now() + KEEP_ALIVE_INTERVAL;
+        while !shutdown.is_cancelled() {
+            if self.player.wait_readable(Duration::from_millis(100))? {
+                match self.player.read_action()? {
+                    PlayerAction::Chat(message) => self.chat(&message)?,
+                    PlayerAction::Command(command) => self.command(&command)?,
+                    _ => {}
+                }
+            }
+
+            if Instant::now() >= next_keep_alive {
+                self.player.send_keep_alive(self.rng.random())?;
+                next_keep_alive = Instant::now() + KEEP_ALIVE_INTERVAL;
+            }
+            if Instant::now() >= next_step {
+                self.step()?;
+                next_step = Instant::now() + self.delay();
+            }
+        }
+
+        self.player
+            .disconnect(&Component::text("The demo server was stopped"))
+    }
+
+    fn delay(&mut self) -> Duration {
+        Duration::from_millis(self.rng.random_range(1500..6000))
+    }
+
+    fn pick(&mut self, options: &[&'static str]) -> &'static str {
+        options[self.rng.random_range(0..options.len())]
+    }
+
+    // One random thing happening on the server
+    fn step(&mut self) -> Result<()> {
+        if self.greeting {
+            self.greeting = false;
+            let name = self.pick(&self.online.clone());
+            let greeting = format!("hey {}!", self.player.name());
+            return self
+                .player
+                .send_player_chat(offline_uuid(name), name, &greeting);
+        }
+
+        match self.rng.random_range(0..20) {
+            0..=13 => {
+                let name = self.pick(&self.online.clone());
+                let line = self.pick(LINES);
+                self.player.send_player_chat(offline_uuid(name), name, line)
+            }
+            14 | 15 => {
+                let name = self.pick(&self.online.clone());
+                let advancement = self.pick(ADVANCEMENTS);
+                self.system(Component::translate(
+                    "chat.type.advancement.task",
+                    vec![
+                        Component::text(name),
+                        Component::text(&format!("[{}]", advancement)).color("green"),
+                    ],
+                ))
+            }
+            16 => {
+                let name = self.pick(&self.online.clone());
+                let death = self.pick(DEATHS);
+                self.system(Component::translate(death, vec![Component::text(name)]))
+            }
+            _ => {
+                // Someone joins or leaves, keeping at least one other around
+                let offline: Vec<&str> = PLAYERS
+                    .iter()
+                    .filter(|name| !self.online.contains(name))
+                    .copied()
+                    .collect();
+                if !offline.is_empty() && (self.online.len() < 2 || self.rng.random_bool(0.5)) {
+                    let name = self.pick(&offline);
+                    self.online.push(name);
+                    self.player.send_player_added(&demo_player(name))?;
+                    self.joined(String::from(name))
+                } else {
+                    let index = self.rng.random_range(0..self.online.len());
+                    let name = self.online.remove(index);
+                    self.player.send_player_removed(offline_uuid(name))?;
+                    self.system(
+                        Component::translate(
+                            "multiplayer.player.left",
+                            vec![Component::text(name)],
+                        )
+                        .color("yellow"),
+                    )
+                }
+            }
+        }
+    }
+
+    // Servers echo chat back to its sender like to everyone else
+    fn chat(&mut self, message: &str) -> Result<()> {
+        let (uuid, name) = (self.player.uuid(), self.player.name().to_owned());
+        self.player.send_player_chat(uuid, &name, message)?;
+
+        let lowercase = message.to_lowercase();
+        self.greeting = ["hi", "hello", "hey"]
+            .iter()
+            .any(|greeting| lowercase.split_whitespace().any(|word| word == *greeting));
+        Ok(())
+    }
+
+    fn command(&mut self, command: &str) -> Result<()> {
+        let reply = match command.split_whitespace().next().unwrap_or_default() {
+            "help" => Component::text("Demo commands: /list, /help"),
+            "list" => {
+                let mut names: Vec<&str> = self.online.clone();
+                let me = self.player.name().to_owned();
+                names.push(&me);
+                let reply = Component::text(&format!(
+                    "There are {} of a max of 20 players online: {}",
+                    names.len(),
+                    names.join(", ")
+                ));
+                return self.system(reply);
+            }
+            _ => Component::text("Unknown command, this is only a demo server. Try /help.")
+                .color("red"),
+        };
+        self.system(reply)
+    }
+
+    fn joined(&mut self, name: String) -> Result<()> {
+        self.system(
+            Component::translate("multiplayer.player.joined", vec![Component::text(&name)])
+                .color("yellow"),
+        )
+    }
+
+    fn system(&mut self, message: Component) -> Result<()> {
+        self.player.send_system_message(&message, false)
+    }
+}
+
+fn demo_player(name: &str) -> PlayerInfo {
+    PlayerInfo {
+        uuid: offline_uuid(name),
+        name: String::from(name),
+        properties: Vec::new(),
+        gamemode: 0,
+        latency: 20 + name.len() as i32 * 7,
+        display_name: None,
+    }
+}
@@ -0,0 +1,312 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+// Lines of history kept, in memory and on disk
+const HISTORY_SIZE: usize = 1000;
+
+// What a key did to the line
+pub enum Edit {
+    Changed,
+    // Enter, the line is in the history now
+    Submit(String),
+    // Ctrl-D on an empty line
+    Eof,
+    // Not a key for the editor
+    Ignored,
+}
+
+// Ctrl-R in progress: the query and the history entry it found
+struct Search {
+    query: String,
+    found: Option<usize>,
+}
+
+// The chat input with readline's keys: moving and deleting by character and
+// word, Up and Down through the history and Ctrl-R searching it. The history
+// is appended to `file` as lines are sent, except for lines starting with a
+// space and /login and /register, which carry passwords.
+pub struct LineEditor {
+    line: String,
+    // In characters
+    cursor: usize,
+    history: Vec<String>,
+    // The entry shown while going through the history with Up and Down,
+    // history.len() for the line being typed
+    browsing: usize,
+    // The line being typed, kept while browsing
+    draft: String,
+    search: Option<Search>,
+    file: Option<PathBuf>,
+}
+
+impl LineEditor {
+    // A missing or unreadable file starts an empty history
+    pub fn new(file: Option<PathBuf>) -> LineEditor {
+        let mut history: Vec<String> = file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        if history.len() > HISTORY_SIZE {
+            history.drain(..history.len() - HISTORY_SIZE);
+            // Trimmed on disk too, so appending doesn't grow it forever
+            if let Some(file) = &file {
+                let _ = fs::write(file, history.join("\n") + "\n");
+            }
+        }
+
+        LineEditor {
+            line: String::new(),
+            cursor: 0,
+            browsing: history.len(),
+            history,
+            draft: String::new(),
+            search: None,
+            file,
+        }
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.line.is_empty()
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    // The prompt and the line, plus the column of the cursor in them
+    pub fn prompt(&self) -> (String, usize) {
+        match &self.search {
+            Some(search) => {
+                let prefix = format!("(search) {}", search.query);
+                let found = search.found.map_or("", |index| &self.history[index]);
+                let column = prefix.chars().count();
+                (format!("{}: {}", prefix, found), column)
+            }
+            None => (format!("> {}", self.line), 2 + self.cursor),
+        }
+    }
+
+    // Swaps the characters start..end for `text`, leaving the cursor after it
+    pub fn replace(&mut self, start: usize, end: usize, text: &str) {
+        let (start, end) = (self.offset(start), self.offset(end));
+        self.line.replace_range(start..end, text);
+        self.cursor = self.line[..start].chars().count() + text.chars().count();
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Edit {
+        if self.search.is_some() {
+            if let Some(edit) = self.handle_search_key(key) {
+                return edit;
+            }
+        }
+
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Enter => return self.submit(),
+            KeyCode::Char('d') if control && self.line.is_empty() => return Edit::Eof,
+            KeyCode::Char('d') if control => self.delete_forward(),
+            KeyCode::Char('a') if control => self.cursor = 0,
+            KeyCode::Char('e') if control => self.cursor = self.length(),
+            KeyCode::Char('b') if control => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Char('f') if control => self.cursor = (self.cursor + 1).min(self.length()),
+            KeyCode::Char('b') if alt => self.cursor = self.word_start(),
+            KeyCode::Char('f') if alt => self.cursor = self.word_end(),
+            KeyCode::Char('w') if control => {
+                let start = self.word_start();
+                self.replace(start, self.cursor, "");
+            }
+            KeyCode::Char('u') if control => self.replace(0, self.cursor, ""),
+            KeyCode::Char('k') if control => self.replace(self.cursor, self.length(), ""),
+            KeyCode::Char('p') if control => self.browse_back(),
+            KeyCode::Char('n') if control => self.browse_forward(),
+            KeyCode::Char('r') if control => {
+                self.search = Some(Search {
+                    query: String::new(),
+                    found: None,
+                })
+            }
+            KeyCode::Char(_) if control || alt => return Edit::Ignored,
+            KeyCode::Char(character) => {
+                let offset = self.offset(self.cursor);
+                self.line.insert(offset, character);
+                self.cursor += 1;
+            }
+            KeyCode::Backspace if control || alt => {
+                let start = self.word_start();
+                self.replace(start, self.cursor, "");
+            }
+            KeyCode::Backspace if self.cursor > 0 => self.replace(self.cursor - 1, self.cursor, ""),
+            KeyCode::Delete => self.delete_forward(),
+            KeyCode::Left if control || alt => self.cursor = self.word_start(),
+            KeyCode::Right if control || alt => self.cursor = self.word_end(),
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.length()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.length(),
+            KeyCode::Up => self.browse_back(),
+            KeyCode::Down => self.browse_forward(),
+            _ => return Edit::Ignored,
+        }
+
+        Edit::Changed
+    }
+
+    // Typing narrows the search, Ctrl-R finds the next older match, Esc and
+    // Ctrl-G give up. Any other key takes the match and goes on as usual.
+    fn handle_search_key(&mut self, key: KeyEvent) -> Option<Edit> {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        let search = self.search.as_mut()?;
+        match key.code {
+            KeyCode::Char('r') if control => {
+                let before = search.found.unwrap_or(self.history.len());
+                if let Some(found) = find(&self.history[..before], &search.query) {
+                    search.found = Some(found);
+                }
+            }
+            KeyCode::Char('g') if control => self.search = None,
+            KeyCode::Esc => self.search = None,
+            KeyCode::Char(character) if !control => {
+                search.query.push(character);
+                search.found = find(&self.history, &search.query);
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.found = find(&self.history, &search.query);
+            }
+            _ => {
+                if let Some(found) = self.search.take().and_then(|search| search.found) {
+                    self.line = self.history[found].clone();
+                    self.cursor = self.length();
+                    self.browsing = found;
+                }
+                return None;
+            }
+        }
+
+        Some(Edit::Changed)
+    }
+
+    fn submit(&mut self) -> Edit {
+        if self.line.is_empty() {
+            return Edit::Ignored;
+        }
+        let line = std::mem::take(&mut self.line);
+        self.cursor = 0;
+        self.draft.clear();
+
+        if remembered(&line) && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > HISTORY_SIZE {
+                self.history.remove(0);
+            }
+            if let Some(file) = &self.file {
+                // Losing the history isn't worth interrupting the chat for
+                let _ = append(file, &line);
+            }
+        }
+        self.browsing = self.history.len();
+
+        Edit::Submit(line)
+    }
+
+    fn browse_back(&mut self) {
+        if self.browsing == 0 {
+            return;
+        }
+        if self.browsing == self.history.len() {
+            self.draft = self.line.clone();
+        }
+        self.browsing -= 1;
+        self.line = self.history[self.browsing].clone();
+        self.cursor = self.length();
+    }
+
+    fn browse_forward(&mut self) {
+        if self.browsing >= self.history.len() {
+            return;
+        }
+        self.browsing += 1;
+        self.line = match self.history.get(self.browsing) {
+            Some(entry) => entry.clone(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.cursor = self.length();
+    }
+
+    fn delete_forward(&mut self) {
+        if self.cursor < self.length() {
+            self.replace(self.cursor, self.cursor + 1, "");
+        }
+    }
+
+    fn length(&self) -> usize {
+        self.line.chars().count()
+    }
+
+    // Byte offset of the character at `index`, clamped to the end
+    fn offset(&self, index: usize) -> usize {
+        self.line
+            .char_indices()
+            .nth(index)
+            .map_or(self.line.len(), |(offset, _)| offset)
+    }
+
+    // Start of the word before the cursor, skipping spaces first like readline
+    fn word_start(&self) -> usize {
+        let characters: Vec<char> = self.line.chars().take(self.cursor).collect();
+        let mut index = characters.len();
+        while index > 0 && characters[index - 1].is_whitespace() {
+            index -= 1;
+        }
+        while index > 0 && !characters[index - 1].is_whitespace() {
+            index -= 1;
+        }
+        index
+    }
+
+    fn word_end(&self) -> usize {
+        let characters: Vec<char> = self.line.chars().collect();
+        let mut index = self.cursor;
+        while index < characters.len() && characters[index].is_whitespace() {
+            index += 1;
+        }
+        while index < characters.len() && !characters[index].is_whitespace() {
+            index += 1;
+        }
+        index
+    }
+}
+
+// The newest entry containing `query`
+fn find(history: &[String], query: &str) -> Option<usize> {
+    if query.is_empty() {
+        return None;
+    }
+    history.iter().rposition(|entry| entry.contains(query))
+}
+
+fn remembered(line: &str) -> bool {
+    let command = line.split_whitespace().next().unwrap_or_default();
+    !line.starts_with(' ') && !matches!(command, "/login" | "/l" | "/register" | "/reg")
+}
+
+fn append(file: &Path, line: &str) -> std::io::Result<()> {
+    if let Some(directory) = file.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    writeln!(options.open(file)?, "{}", line)
+}
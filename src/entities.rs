@@ -0,0 +1,236 @@
+use crate::{fixed_to_f64, Packet, PacketReader, PlayerPosition, ProtocolFeatures};
+use anyhow::Result;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Relative moves are sent in 1/4096ths of a block
+const DELTA_FRACTION_BITS: u32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    Player,
+    // Numeric id from the entity type registry
+    Type(i32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub kind: EntityKind,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+}
+
+impl Entity {
+    pub fn distance_to(&self, position: &PlayerPosition) -> f64 {
+        let (dx, dy, dz) = (
+            self.x - position.x,
+            self.y - position.y,
+            self.z - position.z,
+        );
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+}
+
+// Every entity the server has spawned within our view distance
+#[derive(Debug, Clone, Default)]
+pub struct EntityTracker {
+    entities: HashMap<i32, Entity>,
+    paused: bool,
+}
+
+impl EntityTracker {
+    pub fn entities(&self) -> &HashMap<i32, Entity> {
+        &self.entities
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    // Player UUIDs match the ones in the tab list, so names can be looked up there
+    pub fn find_by_uuid(&self, uuid: Uuid) -> Option<&Entity> {
+        self.entities.values().find(|entity| entity.uuid == uuid)
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &Entity> {
+        self.entities
+            .values()
+            .filter(|entity| entity.kind == EntityKind::Player)
+    }
+
+    // Entities within `radius` blocks of `position`, closest first
+    pub fn nearby(&self, position: &PlayerPosition, radius: f64) -> Vec<&Entity> {
+        let mut nearby: Vec<(f64, &Entity)> = self
+            .entities
+            .values()
+            .map(|entity| (entity.distance_to(position), entity))
+            .filter(|(distance, _)| *distance <= radius)
+            .collect();
+        nearby.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        nearby.into_iter().map(|(_, entity)| entity).collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entities.clear();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // While paused entity packets are swallowed without being decoded. The
+    // server doesn't resend what's already in view, so after resuming only
+    // entities spawned from then on are known.
+    pub(crate) fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if paused {
+            self.entities.clear();
+        }
+    }
+
+    // Applies any of the entity packets, returns false for everything else
+    pub(crate) fn handle_packet(
+        &mut self,
+        packet: &Packet,
+        features: &ProtocolFeatures,
+    ) -> Result<bool> {
+        let Some(packet_id) = packet.get_protocol_id() else {
+            return Ok(false);
+        };
+        if self.paused {
+            let entity_packets = [
+                features.spawn_entity_packet_id,
+                features.spawn_player_packet_id,
+                features.entity_position_packet_id,
+                features.entity_position_rotation_packet_id,
+                features.entity_rotation_packet_id,
+                features.remove_entities_packet_id,
+                features.teleport_entity_packet_id,
+            ];
+            return Ok(entity_packets.contains(&packet_id));
+        }
+
+        let mut reader = packet.reader();
+        match packet_id {
+            _ if packet_id == features.spawn_entity_packet_id => {
+                // Spawn entity
+                let id = reader.read_varint()?;
+                let uuid = reader.read_uuid()?;
+                let kind = EntityKind::Type(reader.read_varint()?);
+                let (x, y, z) = read_position(&mut reader)?;
+                let pitch = reader.read_angle()?;
+                let yaw = reader.read_angle()?;
+                self.spawn(Entity {
+                    id,
+                    uuid,
+                    kind,
+                    x,
+                    y,
+                    z,
+                    yaw,
+                    pitch,
+                    on_ground: false,
+                });
+            }
+            _ if packet_id == features.spawn_player_packet_id => {
+                // Spawn player
+                let id = reader.read_varint()?;
+                let uuid = reader.read_uuid()?;
+                let (x, y, z) = read_position(&mut reader)?;
+                let yaw = reader.read_angle()?;
+                let pitch = reader.read_angle()?;
+                self.spawn(Entity {
+                    id,
+                    uuid,
+                    kind: EntityKind::Player,
+                    x,
+                    y,
+                    z,
+                    yaw,
+                    pitch,
+                    on_ground: false,
+                });
+            }
+            _ if packet_id == features.entity_position_packet_id
+                || packet_id == features.entity_position_rotation_packet_id =>
+            {
+                // Update entity position, with rotation in the second
+                let id = reader.read_varint()?;
+                let dx = fixed_to_f64(reader.read_i16()? as i64, DELTA_FRACTION_BITS);
+                let dy = fixed_to_f64(reader.read_i16()? as i64, DELTA_FRACTION_BITS);
+                let dz = fixed_to_f64(reader.read_i16()? as i64, DELTA_FRACTION_BITS);
+                let rotation = match packet_id == features.entity_position_rotation_packet_id {
+                    true => Some((reader.read_angle()?, reader.read_angle()?)),
+                    false => None,
+                };
+                let on_ground = reader.read_bool()?;
+
+                if let Some(entity) = self.entities.get_mut(&id) {
+                    entity.x += dx;
+                    entity.y += dy;
+                    entity.z += dz;
+                    if let Some((yaw, pitch)) = rotation {
+                        entity.yaw = yaw;
+                        entity.pitch = pitch;
+                    }
+                    entity.on_ground = on_ground;
+                }
+            }
+            _ if packet_id == features.entity_rotation_packet_id => {
+                // Update entity rotation
+                let id = reader.read_varint()?;
+                let yaw = reader.read_angle()?;
+                let pitch = reader.read_angle()?;
+                let on_ground = reader.read_bool()?;
+
+                if let Some(entity) = self.entities.get_mut(&id) {
+                    entity.yaw = yaw;
+                    entity.pitch = pitch;
+                    entity.on_ground = on_ground;
+                }
+            }
+            _ if packet_id == features.remove_entities_packet_id => {
+                // Remove entities
+                let count = reader.read_varint()?;
+                for _ in 0..count {
+                    self.entities.remove(&reader.read_varint()?);
+                }
+            }
+            _ if packet_id == features.teleport_entity_packet_id => {
+                // Teleport entity
+                let id = reader.read_varint()?;
+                let (x, y, z) = read_position(&mut reader)?;
+                let yaw = reader.read_angle()?;
+                let pitch = reader.read_angle()?;
+                let on_ground = reader.read_bool()?;
+
+                if let Some(entity) = self.entities.get_mut(&id) {
+                    entity.x = x;
+                    entity.y = y;
+                    entity.z = z;
+                    entity.yaw = yaw;
+                    entity.pitch = pitch;
+                    entity.on_ground = on_ground;
+                }
+            }
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    fn spawn(&mut self, entity: Entity) {
+        self.entities.insert(entity.id, entity);
+    }
+}
+
+fn read_position(reader: &mut PacketReader) -> Result<(f64, f64, f64)> {
+    Ok((reader.read_f64()?, reader.read_f64()?, reader.read_f64()?))
+}
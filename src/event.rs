@@ -0,0 +1,98 @@
+use crate::{
+    BossBar, ChatMessage, Component, MessageCategory, NextState, Packet, PlayerInfo,
+    PlayerPosition, PlayerStats, QueuePosition, ResourcePackRequest, ResourcePackStatus,
+};
+use uuid::Uuid;
+
+// Steps of connecting, in the order they happen. Protocol 759 has no
+// configuration phase, and encryption (online mode) isn't supported yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginPhase {
+    HandshakeSent { next_state: NextState },
+    CompressionEnabled { threshold: usize },
+    LoginSuccess { uuid: Uuid, name: String },
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    // Queued while logging in, delivered by next_event once login returns.
+    // Use the login phase hook to see them as they happen.
+    LoginPhase(LoginPhase),
+    PlayerJoined(PlayerInfo),
+    PlayerLeft(PlayerInfo),
+    // Only sent with pause_when_idle: nobody but us is online anymore and
+    // expensive work is paused, until Resumed when someone joins again
+    Idle,
+    Resumed,
+    // The server moved us, already confirmed
+    Teleported(PlayerPosition),
+    // Empty message when death was only noticed through the health dropping to zero
+    Died {
+        message: Component,
+    },
+    // Health, food or saturation changed, `previous` is from before the update
+    HealthChanged {
+        previous: PlayerStats,
+        current: PlayerStats,
+    },
+    // Food ran out, health drains from here on until we eat
+    Starving,
+    // `score` is None when the entry was removed from the objective
+    ScoreChanged {
+        objective: String,
+        entry: String,
+        score: Option<i32>,
+    },
+    // A boss bar was added or changed, carries its new state
+    BossBarUpdated(BossBar),
+    BossBarRemoved(BossBar),
+    // Big text in the middle of the screen
+    Title(Component),
+    // Smaller text under the title
+    Subtitle(Component),
+    // Text above the hotbar
+    ActionBar(Component),
+    // How long titles take to fade in, stay and fade out, in ticks
+    TitleTimes {
+        fade_in: i32,
+        stay: i32,
+        fade_out: i32,
+    },
+    // Titles were hidden, `reset` also restores the default text and times
+    TitlesCleared {
+        reset: bool,
+    },
+    // A resource pack was offered and answered with `status` according to the
+    // policy. `error` says why a download failed.
+    ResourcePack {
+        request: ResourcePackRequest,
+        status: ResourcePackStatus,
+        error: Option<String>,
+    },
+    ChatMessage(Box<ChatMessage>),
+    // Server generated chat line. `overlay` ones belong above the hotbar.
+    SystemMessage {
+        message: Component,
+        category: MessageCategory,
+        overlay: bool,
+    },
+    // A queue plugin's chat or action bar line told us where we are in line,
+    // sent whenever it changes
+    QueuePosition(QueuePosition),
+    // Moved from the queue to the server we waited for
+    Joined,
+    // The server sent us to `host`:`port` and we logged in there, see
+    // ClientBuilder::follow_transfers
+    Transferred {
+        host: String,
+        port: u16,
+    },
+    // The server kicked us. Reads from here on fail with Kicked.
+    Disconnected {
+        reason: Component,
+    },
+    // Anything no tracker consumed, handed over untouched
+    Packet(Packet),
+    // A play packet the release doesn't have, in DecodeMode::Lenient
+    UnknownPacket(Packet),
+}
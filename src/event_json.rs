@@ -0,0 +1,221 @@
+use crate::{
+    BossBar, ChatKind, Component, Event, LoginPhase, MessageCategory, NextState, PlayerInfo,
+    PlayerPosition, PlayerStats, ResourcePackStatus,
+};
+use serde_json::{json, Value};
+
+// Version of the JSON events below, sent as "schema_version" in every one.
+//
+// Within a version consumers can rely on:
+// - the "type" of an event and the fields listed for it keeping their name,
+//   meaning and JSON type
+// - new fields and new event types being added without a version bump, so
+//   unknown fields and types have to be ignored
+// - "packet" events only ever carrying the packet id, their contents are
+//   whatever the server sent
+//
+// Renaming or removing anything, or changing what a field holds, bumps the
+// version.
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+// One event as a JSON object, the form used wherever events leave the
+// process, e.g. the C API. Components are in the JSON chat format.
+pub fn event_to_json(event: &Event) -> Value {
+    let mut value = match event {
+        Event::LoginPhase(phase) => match phase {
+            LoginPhase::HandshakeSent { next_state } => json!({
+                "type": "handshake_sent",
+                "next_state": match next_state {
+                    NextState::Status => "status",
+                    NextState::Login => "login",
+                    NextState::Transfer => "transfer",
+                },
+            }),
+            LoginPhase::CompressionEnabled { threshold } => json!({
+                "type": "compression_enabled",
+                "threshold": threshold,
+            }),
+            LoginPhase::LoginSuccess { uuid, name } => json!({
+                "type": "login_success",
+                "uuid": uuid.to_string(),
+                "name": name,
+            }),
+        },
+        Event::PlayerJoined(player) => player_json("player_joined", player),
+        Event::PlayerLeft(player) => player_json("player_left", player),
+        Event::Idle => json!({ "type": "idle" }),
+        Event::Resumed => json!({ "type": "resumed" }),
+        Event::Teleported(position) => json!({
+            "type": "teleported",
+            "position": position_json(position),
+        }),
+        Event::Died { message } => json!({
+            "type": "died",
+            "text": message.to_plain(),
+            "component": component_json(message),
+        }),
+        Event::HealthChanged { previous, current } => json!({
+            "type": "health_changed",
+            "previous": stats_json(previous),
+            "current": stats_json(current),
+        }),
+        Event::Starving => json!({ "type": "starving" }),
+        Event::ScoreChanged {
+            objective,
+            entry,
+            score,
+        } => json!({
+            "type": "score_changed",
+            "objective": objective,
+            "entry": entry,
+            "score": score,
+        }),
+        Event::BossBarUpdated(bar) => boss_bar_json("boss_bar_updated", bar),
+        Event::BossBarRemoved(bar) => boss_bar_json("boss_bar_removed", bar),
+        Event::Title(text) => text_json("title", text),
+        Event::Subtitle(text) => text_json("subtitle", text),
+        Event::ActionBar(text) => text_json("action_bar", text),
+        Event::TitleTimes {
+            fade_in,
+            stay,
+            fade_out,
+        } => json!({
+            "type": "title_times",
+            "fade_in": fade_in,
+            "stay": stay,
+            "fade_out": fade_out,
+        }),
+        Event::TitlesCleared { reset } => json!({
+            "type": "titles_cleared",
+            "reset": reset,
+        }),
+        Event::ResourcePack {
+            request,
+            status,
+            error,
+        } => json!({
+            "type": "resource_pack",
+            "url": request.url,
+            "hash": request.hash,
+            "forced": request.forced,
+            "status": match status {
+                ResourcePackStatus::SuccessfullyLoaded => "loaded",
+                ResourcePackStatus::Declined => "declined",
+                ResourcePackStatus::FailedDownload => "failed_download",
+                ResourcePackStatus::Accepted => "accepted",
+            },
+            "error": error,
+        }),
+        Event::ChatMessage(message) => json!({
+            "type": "chat",
+            "sender": message.sender.to_string(),
+            "sender_name": message.sender_name.to_plain(),
+            "kind": match message.kind {
+                ChatKind::Public => "public",
+                ChatKind::Whisper => "whisper",
+                ChatKind::System => "system",
+                ChatKind::ServerAnnouncement => "announcement",
+            },
+            "text": message.content.to_plain(),
+            "component": component_json(&message.to_component()),
+            "timestamp": message.timestamp,
+        }),
+        Event::SystemMessage {
+            message,
+            category,
+            overlay,
+        } => json!({
+            "type": "system",
+            "text": message.to_plain(),
+            "component": component_json(message),
+            "category": match category {
+                MessageCategory::JoinLeave => "join-leave",
+                MessageCategory::Death => "death",
+                MessageCategory::Advancement => "advancement",
+                MessageCategory::Other => "other",
+            },
+            "overlay": overlay,
+        }),
+        Event::QueuePosition(queue) => json!({
+            "type": "queue_position",
+            "position": queue.position,
+            "length": queue.length,
+            "estimated_wait": queue.estimated_wait.map(|wait| wait.as_secs()),
+        }),
+        Event::Joined => json!({ "type": "joined" }),
+        Event::Transferred { host, port } => json!({
+            "type": "transferred",
+            "host": host,
+            "port": port,
+        }),
+        Event::Disconnected { reason } => json!({
+            "type": "disconnected",
+            "text": reason.to_plain(),
+            "component": component_json(reason),
+        }),
+        Event::Packet(packet) => json!({
+            "type": "packet",
+            "id": packet.get_protocol_id(),
+        }),
+        Event::UnknownPacket(packet) => json!({
+            "type": "unknown_packet",
+            "id": packet.get_protocol_id(),
+        }),
+    };
+
+    value["schema_version"] = json!(EVENT_SCHEMA_VERSION);
+    value
+}
+
+fn component_json(component: &Component) -> Value {
+    serde_json::from_str(&component.to_json()).unwrap_or(Value::Null)
+}
+
+fn text_json(kind: &str, text: &Component) -> Value {
+    json!({
+        "type": kind,
+        "text": text.to_plain(),
+        "component": component_json(text),
+    })
+}
+
+fn player_json(kind: &str, player: &PlayerInfo) -> Value {
+    json!({
+        "type": kind,
+        "uuid": player.uuid.to_string(),
+        "name": player.name,
+        "gamemode": player.gamemode,
+        "latency": player.latency,
+    })
+}
+
+fn position_json(position: &PlayerPosition) -> Value {
+    json!({
+        "x": position.x,
+        "y": position.y,
+        "z": position.z,
+        "yaw": position.yaw,
+        "pitch": position.pitch,
+    })
+}
+
+fn stats_json(stats: &PlayerStats) -> Value {
+    json!({
+        "health": stats.health,
+        "food": stats.food,
+        "saturation": stats.saturation,
+        "experience_bar": stats.experience_bar,
+        "level": stats.level,
+        "total_experience": stats.total_experience,
+    })
+}
+
+fn boss_bar_json(kind: &str, bar: &BossBar) -> Value {
+    json!({
+        "type": kind,
+        "uuid": bar.uuid.to_string(),
+        "title": bar.title.to_plain(),
+        "component": component_json(&bar.title),
+        "health": bar.health,
+    })
+}
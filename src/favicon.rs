@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use base64::prelude::*;
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+use std::{io::Cursor, path::Path};
+
+pub const FAVICON_SIZE: u32 = 64;
+const FAVICON_PREFIX: &str = "data:image/png;base64,";
+
+// Loads any image the image crate understands and turns it into a status favicon
+pub fn favicon_from_file(path: impl AsRef<Path>) -> Result<String> {
+    let path = path.as_ref();
+    let image =
+        image::open(path).with_context(|| format!("Failed to open image {}", path.display()))?;
+
+    favicon_from_image(&image)
+}
+
+pub fn favicon_from_bytes(bytes: &[u8]) -> Result<String> {
+    favicon_from_image(&image::load_from_memory(bytes)?)
+}
+
+// Crops to a centered square and scales to 64x64, since clients reject
+// favicons of any other size
+pub fn favicon_from_image(image: &DynamicImage) -> Result<String> {
+    let image = if image.width() == FAVICON_SIZE && image.height() == FAVICON_SIZE {
+        image.clone()
+    } else {
+        image.resize_to_fill(FAVICON_SIZE, FAVICON_SIZE, FilterType::Lanczos3)
+    };
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    Ok(format!("{}{}", FAVICON_PREFIX, BASE64_STANDARD.encode(png)))
+}
+
+// The inverse, for tools that want to look at a server's favicon
+pub fn decode_favicon(favicon: &str) -> Result<DynamicImage> {
+    let encoded = favicon.strip_prefix(FAVICON_PREFIX).unwrap_or(favicon);
+    let png = BASE64_STANDARD.decode(encoded.trim())?;
+
+    Ok(image::load_from_memory_with_format(&png, ImageFormat::Png)?)
+}
@@ -0,0 +1,224 @@
+// C ABI for tooling written in other languages, declared in include/mchat.h.
+// Functions returning int give 0 on success and -1 on failure, with the
+// reason available from mchat_last_error on the same thread. Strings handed
+// out must be released with mchat_string_free.
+use crate::{
+    event_to_json, AnsiRenderer, Client, Component, Event, HtmlRenderer, MarkdownRenderer,
+    PlainRenderer, Renderer, EVENT_SCHEMA_VERSION,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    time::Duration,
+};
+
+pub struct MchatClient {
+    client: Client,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+// Runs `body` with errors and panics turned into `failed`, nothing may unwind
+// into the caller's frames
+fn guard<T>(failed: T, body: impl FnOnce() -> Result<T>) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(error)) => {
+            set_error(format!("{:#}", error));
+            failed
+        }
+        Err(_) => {
+            set_error(String::from("mchat panicked"));
+            failed
+        }
+    }
+}
+
+unsafe fn read_str<'a>(pointer: *const c_char, name: &str) -> Result<&'a str> {
+    if pointer.is_null() {
+        return Err(anyhow!("{} is NULL", name));
+    }
+    Ok(CStr::from_ptr(pointer).to_str()?)
+}
+
+unsafe fn client<'a>(client: *mut MchatClient) -> Result<&'a mut Client> {
+    match client.as_mut() {
+        Some(handle) => Ok(&mut handle.client),
+        None => Err(anyhow!("client is NULL")),
+    }
+}
+
+unsafe fn write_string(out: *mut *mut c_char, value: String) -> Result<()> {
+    if out.is_null() {
+        return Err(anyhow!("out is NULL"));
+    }
+    *out = CString::new(value)?.into_raw();
+    Ok(())
+}
+
+// Message of the last failure on this thread, NULL if there was none. Valid
+// until the next mchat call on the same thread.
+#[no_mangle]
+pub extern "C" fn mchat_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| match &*error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// # Safety
+/// `string` must come from this library and not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// # Safety
+/// `host` and `username` must be NUL terminated, `username` may be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_connect(
+    host: *const c_char,
+    port: u16,
+    username: *const c_char,
+) -> *mut MchatClient {
+    guard(ptr::null_mut(), || {
+        let mut builder = Client::builder(read_str(host, "host")?, port);
+        if !username.is_null() {
+            builder = builder.username(read_str(username, "username")?);
+        }
+
+        let client = builder.connect()?;
+        Ok(Box::into_raw(Box::new(MchatClient { client })))
+    })
+}
+
+// Version of the event JSON handed out by mchat_poll_event
+#[no_mangle]
+pub extern "C" fn mchat_event_schema_version() -> u32 {
+    EVENT_SCHEMA_VERSION
+}
+
+/// # Safety
+/// `client` must come from mchat_connect and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_client_free(client: *mut MchatClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// # Safety
+/// `client` must come from mchat_connect.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_login(client: *mut MchatClient) -> c_int {
+    guard(-1, || {
+        self::client(client)?.login()?;
+        Ok(0)
+    })
+}
+
+/// # Safety
+/// `client` must come from mchat_connect, `out` receives the status JSON.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_status(client: *mut MchatClient, out: *mut *mut c_char) -> c_int {
+    guard(-1, || {
+        let status = self::client(client)?.status()?;
+        write_string(out, status)?;
+        Ok(0)
+    })
+}
+
+/// # Safety
+/// `client` must come from mchat_connect, `message` must be NUL terminated.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_send_chat(
+    client: *mut MchatClient,
+    message: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let message = read_str(message, "message")?;
+        self::client(client)?.send_chat_message(message)?;
+        Ok(0)
+    })
+}
+
+/// # Safety
+/// `client` must come from mchat_connect, `command` must be NUL terminated
+/// and comes without the leading slash.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_send_command(
+    client: *mut MchatClient,
+    command: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let command = read_str(command, "command")?;
+        self::client(client)?.send_command(command)?;
+        Ok(0)
+    })
+}
+
+/// Waits up to `timeout_ms` for the next event and stores it in `out` as a
+/// JSON object with "type" and "schema_version" fields. Returns 1 for an
+/// event, 0 on timeout and -1 on failure. Keep alives are answered along the
+/// way.
+///
+/// # Safety
+/// `client` must come from mchat_connect.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_poll_event(
+    client: *mut MchatClient,
+    timeout_ms: u32,
+    out: *mut *mut c_char,
+) -> c_int {
+    guard(-1, || {
+        let client = self::client(client)?;
+        let event = match client.poll_event(Duration::from_millis(timeout_ms as u64))? {
+            Some(event) => event,
+            None => return Ok(0),
+        };
+
+        if let Event::Packet(packet) = &event {
+            client.answer_keep_alive(packet)?;
+        }
+
+        write_string(out, event_to_json(&event).to_string())?;
+        Ok(1)
+    })
+}
+
+/// `format` is 0 for plain text, 1 for ANSI escapes, 2 for HTML and 3 for
+/// Discord flavoured markdown.
+///
+/// # Safety
+/// `json` must be NUL terminated.
+#[no_mangle]
+pub unsafe extern "C" fn mchat_render_component(
+    json: *const c_char,
+    format: c_int,
+    out: *mut *mut c_char,
+) -> c_int {
+    guard(-1, || {
+        let component = Component::from_json(read_str(json, "json")?)?;
+        let rendered = match format {
+            0 => PlainRenderer.render(&component),
+            1 => AnsiRenderer.render(&component),
+            2 => HtmlRenderer.render(&component),
+            3 => MarkdownRenderer.render(&component),
+            _ => return Err(anyhow!("Unknown format {}", format)),
+        };
+        write_string(out, rendered)?;
+        Ok(0)
+    })
+}
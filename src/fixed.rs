@@ -0,0 +1,11 @@
+// Fixed-point numbers with `fraction_bits` bits after the binary point, like
+// the 1/4096 block deltas of relative entity moves
+pub fn fixed_to_f64(value: i64, fraction_bits: u32) -> f64 {
+    value as f64 / (1u64 << fraction_bits) as f64
+}
+
+// Rounds to the nearest step, saturating at the ends of i64. Callers narrow
+// it further to the field's width.
+pub fn f64_to_fixed(value: f64, fraction_bits: u32) -> i64 {
+    (value * (1u64 << fraction_bits) as f64).round() as i64
+}
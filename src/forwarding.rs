@@ -0,0 +1,88 @@
+use crate::{Packet, ProfileProperty};
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+const VELOCITY_MODERN_DEFAULT: u8 = 1;
+
+// The player identity a proxy would normally vouch for when talking to a backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardedPlayer {
+    pub address: IpAddr,
+    pub uuid: Uuid,
+    pub properties: Vec<ProfileProperty>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Forwarding {
+    // Legacy forwarding, smuggled through the handshake hostname
+    BungeeCord(ForwardedPlayer),
+    // Modern forwarding, answered through a login plugin request
+    Velocity {
+        secret: Vec<u8>,
+        player: ForwardedPlayer,
+    },
+}
+
+impl Forwarding {
+    pub fn handshake_hostname(&self, hostname: &str) -> Result<String> {
+        match self {
+            Forwarding::BungeeCord(player) => {
+                let mut forwarded =
+                    format!("{}\0{}\0{}", hostname, player.address, player.uuid.simple());
+                if !player.properties.is_empty() {
+                    forwarded.push('\0');
+                    forwarded.push_str(&serde_json::to_string(&player.properties)?);
+                }
+                Ok(forwarded)
+            }
+            Forwarding::Velocity { .. } => Ok(String::from(hostname)),
+        }
+    }
+
+    // Builds the signed payload for a velocity:player_info request, or None if
+    // this forwarding mode doesn't answer that channel.
+    pub fn velocity_response(&self, username: &str, request: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (secret, player) = match self {
+            Forwarding::Velocity { secret, player } => (secret, player),
+            Forwarding::BungeeCord(_) => return Ok(None),
+        };
+
+        // Newer backends announce the highest version they understand, we only speak v1
+        if let Some(&requested) = request.first() {
+            if requested < VELOCITY_MODERN_DEFAULT {
+                return Err(anyhow!(
+                    "Backend requested unsupported Velocity forwarding version {}",
+                    requested
+                ));
+            }
+        }
+
+        let mut payload = Packet::new();
+        payload.write_varint(VELOCITY_MODERN_DEFAULT as i32)?; // forwarding version
+        payload.write_string(&player.address.to_string())?; // client address
+        payload.write_uuid(&player.uuid); // uuid
+        payload.write_string(username)?; // username
+        payload.write_varint(player.properties.len() as i32)?; // properties
+        for property in &player.properties {
+            payload.write_string(&property.name)?;
+            payload.write_string(&property.value)?;
+            payload.write_bool(property.signature.is_some());
+            if let Some(signature) = &property.signature {
+                payload.write_string(signature)?;
+            }
+        }
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+            .map_err(|_| anyhow!("Invalid Velocity forwarding secret"))?;
+        mac.update(&payload.buffer);
+
+        let mut response = mac.finalize().into_bytes().to_vec();
+        response.extend_from_slice(&payload.buffer);
+
+        Ok(Some(response))
+    }
+}
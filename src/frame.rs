@@ -0,0 +1,243 @@
+use crate::{Packet, PacketReader, MAX_PACKET_LENGTH, VARINT_CONTINUE_BIT, VARINT_SEGMENT_BITS};
+use anyhow::{anyhow, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{self, IoSlice, Read, Write};
+
+// What Java's Deflater, and so vanilla, compresses with
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+// What vanilla accepts after decompression, 2^23. Checked before reserving
+// room for it, the length comes straight from the peer.
+pub const MAX_DECOMPRESSED_LENGTH: usize = 8388608;
+
+// One length-prefixed packet as it travels over the wire
+#[derive(Debug)]
+pub struct Frame {
+    pub packet: Packet,
+    // Bytes the frame occupied on the wire, length prefix included
+    pub size: usize,
+}
+
+impl Frame {
+    // Parses the frame at the start of `bytes`. Returns None when the slice
+    // doesn't hold a complete frame yet, so callers can wait for more data.
+    pub fn parse(bytes: &[u8], compression: Option<usize>) -> Result<Option<Frame>> {
+        let (length, prefix_size) = match decode_varint(bytes)? {
+            None => return Ok(None),
+            Some(val) => val,
+        };
+        check_frame_length(length)?;
+
+        if bytes.len() < prefix_size + length {
+            return Ok(None);
+        }
+
+        let body = &bytes[prefix_size..prefix_size + length];
+        Ok(Some(Frame {
+            packet: decode_body(body, compression)?,
+            size: prefix_size + length,
+        }))
+    }
+}
+
+impl Packet {
+    pub fn into_frame(self, compression: Option<usize>) -> Vec<u8> {
+        self.to_frame(compression)
+    }
+
+    pub fn to_frame(&self, compression: Option<usize>) -> Vec<u8> {
+        self.to_frame_at(compression, DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    // With zlib `level`, 0 (stored) to 9 (smallest)
+    pub fn to_frame_at(&self, compression: Option<usize>, level: u32) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(self.buffer.len() + 10);
+        // Writing into a Vec can't fail
+        self.write_frame_to(&mut frame, compression, level).unwrap();
+        frame
+    }
+
+    // Like to_frame_at, but straight into `writer`: the length prefix is
+    // put together on the stack and goes out along with the packet in one
+    // vectored write. Only a compressed body needs a buffer of its own, and
+    // a buffered `writer` still copies, which is why big frames bypass the
+    // connection's. Returns the size of the frame.
+    pub fn write_frame_to(
+        &self,
+        writer: &mut impl Write,
+        compression: Option<usize>,
+        level: u32,
+    ) -> io::Result<usize> {
+        let compressed;
+        let (data_length, body) = match compression {
+            None => (None, self.buffer.as_slice()),
+            // Data length 0 means uncompressed
+            Some(threshold) if self.buffer.len() < threshold => (Some(0), self.buffer.as_slice()),
+            Some(_) => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level.min(9)));
+                encoder.write_all(&self.buffer)?;
+                compressed = encoder.finish()?;
+                (Some(self.buffer.len() as i32), compressed.as_slice())
+            }
+        };
+
+        let data_length = data_length.map(StackVarint::new).unwrap_or_default();
+        let length = StackVarint::new((data_length.len + body.len()) as i32);
+        let mut slices = [
+            IoSlice::new(length.bytes()),
+            IoSlice::new(data_length.bytes()),
+            IoSlice::new(body),
+        ];
+        write_all_vectored(writer, &mut slices)?;
+
+        Ok(length.len + data_length.len + body.len())
+    }
+}
+
+// A varint encoded without allocating, for frame headers
+#[derive(Default)]
+struct StackVarint {
+    buffer: [u8; 5],
+    len: usize,
+}
+
+impl StackVarint {
+    fn new(value: i32) -> StackVarint {
+        let mut varint = StackVarint::default();
+        let mut value = value as u32;
+        loop {
+            if value & !(VARINT_SEGMENT_BITS as u32) == 0 {
+                varint.buffer[varint.len] = value as u8;
+                varint.len += 1;
+                return varint;
+            }
+            varint.buffer[varint.len] =
+                (value as i32 & VARINT_SEGMENT_BITS | VARINT_CONTINUE_BIT) as u8;
+            varint.len += 1;
+            value >>= 7;
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+// Write::write_all_vectored isn't stable yet
+fn write_all_vectored(writer: &mut impl Write, mut slices: &mut [IoSlice]) -> io::Result<()> {
+    IoSlice::advance_slices(&mut slices, 0);
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn check_frame_length(length: usize) -> Result<()> {
+    if length == 0 {
+        return Err(anyhow!("Framing error: received an empty packet"));
+    }
+    if length > MAX_PACKET_LENGTH {
+        return Err(anyhow!(
+            "Framing error: packet length {} exceeds the maximum of {}",
+            length,
+            MAX_PACKET_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+// The length a compressed frame says its packet inflates to, 0 for one
+// sent as it is
+pub(crate) fn check_data_length(data_length: i32, threshold: usize) -> Result<usize> {
+    let data_length = usize::try_from(data_length)
+        .ok()
+        .filter(|length| *length <= MAX_DECOMPRESSED_LENGTH)
+        .ok_or_else(|| {
+            anyhow!(
+                "Framing error: decompressed length {} is out of range",
+                data_length
+            )
+        })?;
+
+    // Like vanilla, packets under the threshold have to come uncompressed,
+    // with a data length of 0
+    if data_length != 0 && data_length < threshold {
+        return Err(anyhow!(
+            "Framing error: compressed packet of {} bytes is under the threshold of {}",
+            data_length,
+            threshold
+        ));
+    }
+
+    Ok(data_length)
+}
+
+// Decodes a varint prefix, returning the value and how many bytes it used
+pub(crate) fn decode_varint(bytes: &[u8]) -> Result<Option<(usize, usize)>> {
+    let mut value = 0u32;
+    for (position, byte) in bytes.iter().take(5).enumerate() {
+        value |= ((*byte as i32 & VARINT_SEGMENT_BITS) as u32) << (7 * position);
+        if *byte as i32 & VARINT_CONTINUE_BIT == 0 {
+            return Ok(Some((value as usize, position + 1)));
+        }
+    }
+
+    if bytes.len() >= 5 {
+        return Err(anyhow!(
+            "Framing error: packet length varint exceeds 5 bytes"
+        ));
+    }
+
+    Ok(None)
+}
+
+// Turns a frame body (everything after the length prefix) into a packet
+pub(crate) fn decode_body(body: &[u8], compression: Option<usize>) -> Result<Packet> {
+    let mut packet = Packet::new();
+    decode_body_into(body, compression, &mut packet)?;
+
+    Ok(packet)
+}
+
+// Same as decode_body, but reuses the allocation already held by `packet`
+pub(crate) fn decode_body_into(
+    body: &[u8],
+    compression: Option<usize>,
+    packet: &mut Packet,
+) -> Result<()> {
+    packet.clear();
+
+    match compression {
+        None => packet.buffer.extend_from_slice(body),
+        Some(threshold) => {
+            let mut reader = PacketReader::new(body);
+            let data_length = check_data_length(reader.read_varint()?, threshold)?;
+            let data = reader.remaining();
+
+            if data_length == 0 {
+                packet.buffer.extend_from_slice(data);
+            } else {
+                packet.buffer.reserve(data_length);
+                ZlibDecoder::new(data)
+                    .take(data_length as u64 + 1)
+                    .read_to_end(&mut packet.buffer)?;
+                if packet.buffer.len() != data_length {
+                    return Err(anyhow!(
+                        "Framing error: expected {} decompressed bytes, got {}",
+                        data_length,
+                        packet.buffer.len()
+                    ));
+                }
+            }
+        }
+    }
+    packet.read_protocol_id()?;
+
+    Ok(())
+}
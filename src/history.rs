@@ -0,0 +1,120 @@
+use crate::{NextState, PlayerPosition};
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const DEFAULT_HISTORY_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateChange {
+    Connected { hostname: String, port: u16 },
+    HandshakeSent(NextState),
+    CompressionSet(Option<usize>),
+    LoggedIn { username: String },
+    Moved(PlayerPosition),
+    PlayerCount(usize),
+    Idle(bool),
+    Died,
+    Respawned,
+    Disconnected { reason: String },
+}
+
+impl fmt::Display for StateChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateChange::Connected { hostname, port } => {
+                write!(f, "connected to {}:{}", hostname, port)
+            }
+            StateChange::HandshakeSent(next_state) => {
+                write!(f, "handshake sent, next state {:?}", next_state)
+            }
+            StateChange::CompressionSet(Some(threshold)) => {
+                write!(f, "compression enabled at {} bytes", threshold)
+            }
+            StateChange::CompressionSet(None) => write!(f, "compression disabled"),
+            StateChange::LoggedIn { username } => write!(f, "logged in as {}", username),
+            StateChange::Moved(position) => write!(
+                f,
+                "moved to {:.2} {:.2} {:.2} (yaw {:.1}, pitch {:.1})",
+                position.x, position.y, position.z, position.yaw, position.pitch
+            ),
+            StateChange::PlayerCount(count) => write!(f, "{} players in the tab list", count),
+            StateChange::Idle(true) => write!(f, "paused, nobody else online"),
+            StateChange::Idle(false) => write!(f, "resumed"),
+            StateChange::Died => write!(f, "died"),
+            StateChange::Respawned => write!(f, "respawned"),
+            StateChange::Disconnected { reason } => write!(f, "disconnected: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateSnapshot {
+    pub at: SystemTime,
+    pub change: StateChange,
+}
+
+// The last few things the client believed about its own state, so a
+// failure hours into a session can be explained after the fact
+#[derive(Debug, Clone)]
+pub struct StateHistory {
+    entries: VecDeque<StateSnapshot>,
+    capacity: usize,
+}
+
+impl Default for StateHistory {
+    fn default() -> StateHistory {
+        StateHistory::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl StateHistory {
+    pub fn new(capacity: usize) -> StateHistory {
+        StateHistory {
+            entries: VecDeque::with_capacity(capacity.min(DEFAULT_HISTORY_CAPACITY)),
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, change: StateChange) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(StateSnapshot {
+            at: SystemTime::now(),
+            change,
+        });
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &StateSnapshot> {
+        self.entries.iter()
+    }
+
+    pub fn last(&self) -> Option<&StateSnapshot> {
+        self.entries.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // One line per entry, oldest first, with unix timestamps in milliseconds
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        for entry in &self.entries {
+            let millis = entry
+                .at
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_millis())
+                .unwrap_or_default();
+            // Writing into a String can't fail
+            writeln!(dump, "[{}] {}", millis, entry.change).unwrap();
+        }
+        dump
+    }
+}
@@ -0,0 +1,199 @@
+use crate::ProxyConfig;
+use anyhow::{anyhow, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{io::ErrorKind, thread, time::Duration};
+use ureq::{http::Response, Agent, Body, Proxy, ProxyProtocol, Timeout};
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    // Applies to each step (connecting, sending, waiting for the response)
+    // rather than the whole request, so big downloads aren't cut off
+    pub timeout: Duration,
+    // Extra attempts after a failed one, for transport errors and 5xx/429 only.
+    // POSTs are only retried when they never reached the server.
+    pub retries: u32,
+    // Doubled after every failed attempt
+    pub retry_delay: Duration,
+    pub proxy: Option<ProxyConfig>,
+    pub user_agent: String,
+}
+
+impl Default for HttpConfig {
+    fn default() -> HttpConfig {
+        HttpConfig {
+            timeout: Duration::from_secs(30),
+            retries: 2,
+            retry_delay: Duration::from_millis(500),
+            proxy: None,
+            user_agent: format!("mchat/{}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+// The one HTTPS stack every web integration goes through, so timeouts,
+// retries and proxying behave the same everywhere. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    agent: Agent,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl Default for HttpClient {
+    fn default() -> HttpClient {
+        // Without a proxy there is nothing that could fail to parse
+        HttpClient::new(HttpConfig::default()).unwrap()
+    }
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Result<HttpClient> {
+        let proxy = match &config.proxy {
+            Some(proxy) => Some(to_ureq_proxy(proxy)?),
+            None => None,
+        };
+
+        let agent = Agent::config_builder()
+            .timeout_connect(Some(config.timeout))
+            .timeout_send_request(Some(config.timeout))
+            .timeout_recv_response(Some(config.timeout))
+            .proxy(proxy)
+            .user_agent(config.user_agent.as_str())
+            .build()
+            .into();
+
+        Ok(HttpClient {
+            agent,
+            retries: config.retries,
+            retry_delay: config.retry_delay,
+        })
+    }
+
+    pub fn get(&self, url: &str) -> Result<Response<Body>> {
+        self.with_retries(url, true, || self.agent.get(url).call())
+    }
+
+    // `query` is percent-encoded and appended to the URL
+    pub fn get_with_query(&self, url: &str, query: &[(&str, &str)]) -> Result<Response<Body>> {
+        self.with_retries(url, true, || {
+            self.agent
+                .get(url)
+                .query_pairs(query.iter().copied())
+                .call()
+        })
+    }
+
+    pub fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let mut response = self.get(url)?;
+        let body = response.body_mut().read_to_string()?;
+
+        serde_json::from_str(&body).with_context(|| format!("Invalid JSON from {}", url))
+    }
+
+    pub fn post_json(&self, url: &str, body: &impl Serialize) -> Result<Response<Body>> {
+        let body = serde_json::to_vec(body)?;
+        self.with_retries(url, false, || {
+            self.agent
+                .post(url)
+                .header("Content-Type", "application/json")
+                .send(&body[..])
+        })
+    }
+
+    // With "Authorization: Bearer <token>", as APIs like Minecraft's want
+    pub fn get_json_authorized<T: DeserializeOwned>(&self, url: &str, token: &str) -> Result<T> {
+        let authorization = format!("Bearer {}", token);
+        let mut response = self.with_retries(url, true, || {
+            self.agent
+                .get(url)
+                .header("Authorization", &authorization)
+                .call()
+        })?;
+        let body = response.body_mut().read_to_string()?;
+
+        serde_json::from_str(&body).with_context(|| format!("Invalid JSON from {}", url))
+    }
+
+    // Unlike the others this answers with any status, OAuth puts its errors
+    // (and "keep waiting") in the body of a 400
+    pub fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Response<Body>> {
+        self.with_retries(url, false, || {
+            self.agent
+                .post(url)
+                .config()
+                .http_status_as_error(false)
+                .build()
+                .send_form(form.iter().copied())
+        })
+    }
+
+    // Requests that aren't `idempotent` might have been acted on by the
+    // time they failed, so they're only tried again if they never got out
+    fn with_retries(
+        &self,
+        url: &str,
+        idempotent: bool,
+        mut request: impl FnMut() -> Result<Response<Body>, ureq::Error>,
+    ) -> Result<Response<Body>> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match request() {
+                Ok(response) => return Ok(response),
+                Err(error) if attempt < self.retries && is_transient(&error, idempotent) => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(error) => {
+                    return Err(error).with_context(|| {
+                        format!("Request to {} failed after {} attempts", url, attempt + 1)
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn is_transient(error: &ureq::Error, idempotent: bool) -> bool {
+    if !idempotent {
+        return never_sent(error);
+    }
+    match error {
+        ureq::Error::StatusCode(status) => *status == 429 || *status >= 500,
+        ureq::Error::Io(_) | ureq::Error::Timeout(_) => true,
+        _ => never_sent(error),
+    }
+}
+
+// Failures while resolving or connecting, before the request went out
+fn never_sent(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::HostNotFound | ureq::Error::ConnectionFailed => true,
+        ureq::Error::Timeout(timeout) => matches!(timeout, Timeout::Resolve | Timeout::Connect),
+        ureq::Error::Io(error) => error.kind() == ErrorKind::ConnectionRefused,
+        _ => false,
+    }
+}
+
+// SOCKS5 goes through socks5h so the proxy resolves hostnames, like our
+// own Minecraft connections do
+fn to_ureq_proxy(proxy: &ProxyConfig) -> Result<Proxy> {
+    let (protocol, auth) = match proxy {
+        ProxyConfig::Socks5 { auth, .. } => (ProxyProtocol::Socks5h, auth),
+        ProxyConfig::HttpConnect { auth, .. } => (ProxyProtocol::Http, auth),
+    };
+    let (host, port) = proxy
+        .address()
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("Proxy address {} has no port", proxy.address()))?;
+
+    let mut builder = Proxy::builder(protocol)
+        .host(host.trim_start_matches('[').trim_end_matches(']'))
+        .port(port.parse()?);
+    if let Some(auth) = auth {
+        builder = builder.username(&auth.username).password(&auth.password);
+    }
+
+    Ok(builder.build()?)
+}
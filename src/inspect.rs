@@ -0,0 +1,336 @@
+use crate::{
+    frame, ChatMessage, ChatTypes, Component, ConnectionState, Frame, Handshake, Packet,
+    PacketReader, Profile, ProtocolFeatures,
+};
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::*;
+use std::fmt;
+
+// Which way a packet travels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Clientbound,
+    Serverbound,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Clientbound => "clientbound",
+            Direction::Serverbound => "serverbound",
+        })
+    }
+}
+
+// What the packet decoders made of a blob, for debugging protocol mismatches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedPacket {
+    pub id: u8,
+    // As wiki.vg names it, "Unknown" for ids we don't decode
+    pub name: &'static str,
+    // In the order they're on the wire
+    pub fields: Vec<(&'static str, String)>,
+    // Bytes after the last decoded field. Anything but 0 for a known packet
+    // means the layout didn't match.
+    pub unread: Vec<u8>,
+}
+
+impl fmt::Display for DecodedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02X} {}", self.id, self.name)?;
+        for (name, value) in &self.fields {
+            write!(f, "\n  {}: {}", name, value)?;
+        }
+        if !self.unread.is_empty() {
+            write!(
+                f,
+                "\n  {} bytes left unread: {}",
+                self.unread.len(),
+                to_hex(&self.unread)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Hex, with or without spaces, colons or a 0x prefix, or else base64
+pub fn parse_blob(text: &str) -> Result<Vec<u8>> {
+    let text = text.trim();
+    let digits: String = text
+        .strip_prefix("0x")
+        .unwrap_or(text)
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+    if !digits.is_empty()
+        && digits.len().is_multiple_of(2)
+        && digits.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return (0..digits.len())
+            .step_by(2)
+            .map(|index| Ok(u8::from_str_radix(&digits[index..index + 2], 16)?))
+            .collect();
+    }
+
+    let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64_STANDARD
+        .decode(&compact)
+        .or_else(|_| BASE64_STANDARD_NO_PAD.decode(&compact))
+        .or_else(|_| BASE64_URL_SAFE_NO_PAD.decode(&compact))
+        .map_err(|_| anyhow!("Expected the packet as hex or base64"))
+}
+
+// A packet id followed by its fields, as it is after the framing comes off
+pub fn decode_packet(
+    features: &ProtocolFeatures,
+    direction: Direction,
+    state: ConnectionState,
+    body: &[u8],
+) -> Result<DecodedPacket> {
+    let packet = frame::decode_body(body, None).context("The packet has no id")?;
+    decode(features, direction, state, &packet)
+}
+
+// A whole frame, length prefix and all, compressed when `compression` is
+pub fn decode_frame(
+    features: &ProtocolFeatures,
+    direction: Direction,
+    state: ConnectionState,
+    bytes: &[u8],
+    compression: Option<usize>,
+) -> Result<DecodedPacket> {
+    let frame = Frame::parse(bytes, compression)?
+        .ok_or_else(|| anyhow!("The frame is cut short, its length says there's more"))?;
+    if frame.size < bytes.len() {
+        return Err(anyhow!(
+            "{} bytes after the frame, is it more than one?",
+            bytes.len() - frame.size
+        ));
+    }
+    decode(features, direction, state, &frame.packet)
+}
+
+pub(crate) fn decode(
+    features: &ProtocolFeatures,
+    direction: Direction,
+    state: ConnectionState,
+    packet: &Packet,
+) -> Result<DecodedPacket> {
+    let id = packet
+        .get_protocol_id()
+        .ok_or_else(|| anyhow!("The packet has no id"))?;
+    let mut reader = packet.reader();
+    let mut fields = Vec::new();
+    let name = decode_fields(
+        features,
+        direction,
+        state,
+        id,
+        packet,
+        &mut reader,
+        &mut fields,
+    )
+    .with_context(|| format!("Failed to decode {} packet 0x{:02X}", direction, id))?;
+
+    Ok(DecodedPacket {
+        id,
+        name,
+        fields,
+        unread: reader.remaining().to_vec(),
+    })
+}
+
+fn decode_fields(
+    features: &ProtocolFeatures,
+    direction: Direction,
+    state: ConnectionState,
+    id: u8,
+    packet: &Packet,
+    reader: &mut PacketReader,
+    fields: &mut Vec<(&'static str, String)>,
+) -> Result<&'static str> {
+    use ConnectionState::*;
+    use Direction::*;
+
+    let name = match (direction, state, id) {
+        (_, Closed, _) => return Err(anyhow!("Nothing is sent once the connection is closed")),
+
+        (Serverbound, Handshaking, 0x00) => {
+            let mut copy = packet.clone();
+            let handshake = Handshake::from_packet(&mut copy)?;
+            fields.push(("protocol version", handshake.protocol_version.to_string()));
+            fields.push(("hostname", format!("{:?}", handshake.hostname)));
+            fields.push(("port", handshake.port.to_string()));
+            fields.push(("next state", format!("{:?}", handshake.next_state)));
+            reader.read_bytes(copy.cursor - packet.cursor)?;
+            "Handshake"
+        }
+
+        (Clientbound, Status, 0x00) => {
+            fields.push(("json", reader.read_str()?.to_owned()));
+            "Status Response"
+        }
+        (Clientbound, Status, 0x01) => {
+            fields.push(("payload", reader.read_i64()?.to_string()));
+            "Pong Response"
+        }
+        (Serverbound, Status, 0x00) => "Status Request",
+        (Serverbound, Status, 0x01) => {
+            fields.push(("payload", reader.read_i64()?.to_string()));
+            "Ping Request"
+        }
+
+        (Clientbound, Login, 0x00) => {
+            fields.push(("reason", read_component(reader)?));
+            "Disconnect (login)"
+        }
+        (Clientbound, Login, 0x01) => {
+            fields.push(("server id", format!("{:?}", reader.read_str()?)));
+            let key = reader.read_byte_array()?;
+            fields.push(("public key", format!("{} bytes", key.len())));
+            fields.push(("verify token", to_hex(reader.read_byte_array()?)));
+            "Encryption Request"
+        }
+        (Clientbound, Login, id) if id == features.login_success_packet_id => {
+            let profile = Profile::read(reader)?;
+            fields.push(("uuid", profile.uuid.to_string()));
+            fields.push(("username", profile.name));
+            let properties: Vec<&str> = profile
+                .properties
+                .iter()
+                .map(|property| property.name.as_str())
+                .collect();
+            fields.push(("properties", format!("{:?}", properties)));
+            "Login Success"
+        }
+        (Clientbound, Login, id) if id == features.compression_packet_id => {
+            fields.push(("threshold", reader.read_varint()?.to_string()));
+            "Set Compression"
+        }
+        (Clientbound, Login, 0x04) => {
+            fields.push(("message id", reader.read_varint()?.to_string()));
+            fields.push(("channel", reader.read_str()?.to_owned()));
+            fields.push(("data", to_hex(reader.read_bytes(reader.remaining().len())?)));
+            "Login Plugin Request"
+        }
+        (Serverbound, Login, 0x00) => {
+            fields.push(("username", format!("{:?}", reader.read_str()?)));
+            if features.has_login_signature {
+                let has_signature = reader.read_bool()?;
+                fields.push(("has signature", has_signature.to_string()));
+                if has_signature {
+                    fields.push(("expires at", reader.read_i64()?.to_string()));
+                    let key = reader.read_byte_array()?;
+                    fields.push(("public key", format!("{} bytes", key.len())));
+                    let signature = reader.read_byte_array()?;
+                    fields.push(("signature", format!("{} bytes", signature.len())));
+                }
+            }
+            "Login Start"
+        }
+        (Serverbound, Login, 0x02) => {
+            fields.push(("message id", reader.read_varint()?.to_string()));
+            let successful = reader.read_bool()?;
+            fields.push(("successful", successful.to_string()));
+            if successful {
+                fields.push(("data", to_hex(reader.read_bytes(reader.remaining().len())?)));
+            }
+            "Login Plugin Response"
+        }
+
+        (Clientbound, Play, id) if id == features.login_play_packet_id => {
+            fields.push(("entity id", reader.read_i32()?.to_string()));
+            fields.push(("hardcore", reader.read_bool()?.to_string()));
+            fields.push(("game mode", reader.read_u8()?.to_string()));
+            // The registry codec and the rest aren't worth printing
+            reader.read_bytes(reader.remaining().len())?;
+            "Login (play)"
+        }
+        (Clientbound, Play, id) if id == features.player_chat_packet_id => {
+            let message = ChatMessage::from_packet(packet, &ChatTypes::default())?;
+            fields.push(("sender", message.sender.to_string()));
+            fields.push(("sender name", message.sender_name.to_plain()));
+            fields.push(("content", message.content.to_plain()));
+            fields.push(("chat type", message.chat_type.to_string()));
+            fields.push(("timestamp", message.timestamp.to_string()));
+            // The salt and signature, which nothing checks
+            reader.read_bytes(reader.remaining().len())?;
+            "Player Chat Message"
+        }
+        (Clientbound, Play, id) if id == features.system_chat_packet_id => {
+            fields.push(("content", read_component(reader)?));
+            fields.push(("type", reader.read_varint()?.to_string()));
+            "System Chat Message"
+        }
+        (Clientbound, Play, id) if id == features.disconnect_packet_id => {
+            fields.push(("reason", read_component(reader)?));
+            "Disconnect (play)"
+        }
+        (Clientbound, Play, id) if id == features.keep_alive_packet_id => {
+            fields.push(("id", reader.read_i64()?.to_string()));
+            "Keep Alive"
+        }
+        (Clientbound, Play, 0x33) => {
+            fields.push(("player id", reader.read_varint()?.to_string()));
+            fields.push(("killer id", reader.read_i32()?.to_string()));
+            fields.push(("message", read_component(reader)?));
+            "Combat Death"
+        }
+        (Clientbound, Play, 0x40) => {
+            fields.push(("text", read_component(reader)?));
+            "Set Action Bar Text"
+        }
+        (Clientbound, Play, 0x58) => {
+            fields.push(("text", read_component(reader)?));
+            "Set Subtitle Text"
+        }
+        (Clientbound, Play, 0x5A) => {
+            fields.push(("text", read_component(reader)?));
+            "Set Title Text"
+        }
+        (Serverbound, Play, id)
+            if id == features.chat_message_packet_id || id == features.chat_command_packet_id =>
+        {
+            let command = id == features.chat_command_packet_id;
+            fields.push((
+                if command { "command" } else { "message" },
+                format!("{:?}", reader.read_str()?),
+            ));
+            if features.has_chat_signing {
+                fields.push(("timestamp", reader.read_i64()?.to_string()));
+                fields.push(("salt", reader.read_i64()?.to_string()));
+                reader.read_bytes(reader.remaining().len())?; // Signatures
+            }
+            if command {
+                "Chat Command"
+            } else {
+                "Chat Message"
+            }
+        }
+        (Serverbound, Play, id) if id == features.keep_alive_response_packet_id => {
+            fields.push(("id", reader.read_i64()?.to_string()));
+            "Keep Alive"
+        }
+
+        _ => {
+            fields.push((
+                "payload",
+                to_hex(reader.read_bytes(reader.remaining().len())?),
+            ));
+            "Unknown"
+        }
+    };
+
+    Ok(name)
+}
+
+// The plain text, with the JSON after it when that says more
+fn read_component(reader: &mut PacketReader) -> Result<String> {
+    let json = reader.read_str()?;
+    let component = Component::from_json(json)?;
+    Ok(format!("{:?} {}", component.to_plain(), json))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
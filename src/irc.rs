@@ -0,0 +1,517 @@
+use crate::{multiline::one_line, Event, ShutdownToken};
+use anyhow::{anyhow, Result};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+// What vanilla IRC allows per line, CRLF included. Longer lines are cut.
+const MAX_LINE: usize = 512;
+// A client taking longer than this to accept a line is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// The name we use as the server and for messages without a player behind them
+const SERVER_NAME: &str = "mchat";
+
+// How clients of an IrcServer talk to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineProtocol {
+    // Enough of RFC 1459 for an IRC client to join the one channel
+    Irc,
+    // Every line is a chat message, both ways. For nc, scripts and the like.
+    Plain,
+}
+
+// A message someone sent to the bridge, to be said in the server's chat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgedMessage {
+    // The nick, None for plain clients
+    pub nick: Option<String>,
+    pub text: String,
+}
+
+impl BridgedMessage {
+    // "<nick> text", or only the text from plain clients
+    pub fn to_chat(&self) -> String {
+        match &self.nick {
+            Some(nick) => format!("<{}> {}", nick, self.text),
+            None => self.text.clone(),
+        }
+    }
+}
+
+struct Peer {
+    id: u64,
+    nick: String,
+    joined: bool,
+    stream: TcpStream,
+}
+
+#[derive(Default)]
+struct Peers {
+    next_id: u64,
+    peers: Vec<Peer>,
+}
+
+// A tiny local server that exposes the Minecraft chat as one IRC channel
+// (or plain lines), so any IRC client can join and talk through the bot.
+// Chat goes out with forward, what the clients say comes out of the
+// receiver bind returns.
+#[derive(Clone)]
+pub struct IrcServer {
+    address: SocketAddr,
+    protocol: LineProtocol,
+    channel: String,
+    peers: Arc<Mutex<Peers>>,
+}
+
+impl IrcServer {
+    // Accepts clients on `address` from threads of the token, until it's
+    // cancelled. `channel` is the only one there is, e.g. "#minecraft".
+    // With a password, IRC clients have to send it with PASS before
+    // registering. Plain clients don't log in, only bind to localhost for them.
+    pub fn bind(
+        address: &str,
+        protocol: LineProtocol,
+        channel: &str,
+        password: Option<String>,
+        token: &ShutdownToken,
+    ) -> Result<(IrcServer, Receiver<BridgedMessage>)> {
+        if !channel.starts_with('#') || channel.contains([' ', ',', '\x07']) {
+            return Err(anyhow!("{:?} is not a channel name", channel));
+        }
+
+        let listener = TcpListener::bind(address)?;
+        let server = IrcServer {
+            address: listener.local_addr()?,
+            protocol,
+            channel: String::from(channel),
+            peers: Arc::default(),
+        };
+        let (sender, messages) = mpsc::channel();
+
+        // Connected clients are woken by closing their sockets
+        token.wake_on_cancel(server.address);
+        let wake = server.clone();
+        token.on_cancel(move || {
+            for peer in &wake.peers.lock().unwrap().peers {
+                let _ = peer.stream.shutdown(Shutdown::Both);
+            }
+        });
+
+        let accepting = server.clone();
+        token.spawn(move |token| {
+            for stream in listener.incoming() {
+                if token.is_cancelled() {
+                    return;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let server = accepting.clone();
+                let sender = sender.clone();
+                let password = password.clone();
+                token.spawn(move |_| {
+                    // Errors only end that one client
+                    let _ = server.serve(stream, sender, password.as_deref());
+                });
+            }
+        });
+
+        Ok((server, messages))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    // Clients currently in the channel
+    pub fn client_count(&self) -> usize {
+        let peers = self.peers.lock().unwrap();
+        peers.peers.iter().filter(|peer| peer.joined).count()
+    }
+
+    // Sends chat and system messages on to every client in the channel.
+    // Returns whether `event` was one to forward.
+    pub fn forward(&self, event: &Event) -> bool {
+        match event {
+            Event::ChatMessage(chat) => {
+                self.say(Some(&chat.sender_name.to_plain()), &chat.content.to_plain());
+                true
+            }
+            Event::SystemMessage {
+                message, overlay, ..
+            } if !overlay => {
+                self.say(None, &message.to_plain());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Says `text` in the channel as `sender`, or as the server itself for
+    // None. Lines of a multiline text go out one by one.
+    pub fn say(&self, sender: Option<&str>, text: &str) {
+        self.say_except(sender, text, None);
+    }
+
+    fn say_except(&self, sender: Option<&str>, text: &str, except: Option<u64>) {
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let line = match self.protocol {
+                LineProtocol::Plain => match sender {
+                    Some(sender) => format!("<{}> {}", sender, line),
+                    None => String::from(line),
+                },
+                LineProtocol::Irc => match sender {
+                    // Player names never hold spaces, the rest of a nick's
+                    // rules don't matter to clients displaying it
+                    Some(sender) => format!(
+                        ":{}!{}@minecraft PRIVMSG {} :{}",
+                        sender, sender, self.channel, line
+                    ),
+                    None => format!(":{} NOTICE {} :{}", SERVER_NAME, self.channel, line),
+                },
+            };
+            self.broadcast(&line, except);
+        }
+    }
+
+    fn broadcast(&self, line: &str, except: Option<u64>) {
+        let mut peers = self.peers.lock().unwrap();
+        // A client that can't keep up is dropped, reading on its thread
+        // notices the shut down socket and ends it
+        peers.peers.retain_mut(|peer| {
+            if !peer.joined || Some(peer.id) == except {
+                return true;
+            }
+            let sent = write_line(&mut peer.stream, line).is_ok();
+            if !sent {
+                let _ = peer.stream.shutdown(Shutdown::Both);
+            }
+            sent
+        });
+    }
+
+    fn serve(
+        &self,
+        stream: TcpStream,
+        sender: Sender<BridgedMessage>,
+        password: Option<&str>,
+    ) -> Result<()> {
+        // Broadcasts wait on every client, a stuck one mustn't hold them up
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let id = {
+            let mut peers = self.peers.lock().unwrap();
+            peers.next_id += 1;
+            let id = peers.next_id;
+            peers.peers.push(Peer {
+                id,
+                nick: String::new(),
+                // Plain clients are in the channel from the start
+                joined: self.protocol == LineProtocol::Plain,
+                stream: stream.try_clone()?,
+            });
+            id
+        };
+
+        let result = match self.protocol {
+            LineProtocol::Plain => self.serve_plain(&stream, &sender),
+            LineProtocol::Irc => IrcSession {
+                server: self,
+                id,
+                stream: &stream,
+                password,
+                authorized: password.is_none(),
+                nick: None,
+                user: false,
+                registered: false,
+            }
+            .run(&sender),
+        };
+
+        let peer = {
+            let mut peers = self.peers.lock().unwrap();
+            let index = peers.peers.iter().position(|peer| peer.id == id);
+            index.map(|index| peers.peers.remove(index))
+        };
+        if let Some(peer) = peer.filter(|peer| peer.joined && self.protocol == LineProtocol::Irc) {
+            self.broadcast(
+                &format!(":{}!{}@localhost QUIT :Gone", peer.nick, peer.nick),
+                None,
+            );
+        }
+        result
+    }
+
+    fn serve_plain(&self, stream: &TcpStream, sender: &Sender<BridgedMessage>) -> Result<()> {
+        for line in BufReader::new(stream).lines() {
+            let text = one_line(&line?);
+            if text.is_empty() {
+                continue;
+            }
+            if sender.send(BridgedMessage { nick: None, text }).is_err() {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_to(&self, id: u64, line: &str) -> Result<()> {
+        let mut peers = self.peers.lock().unwrap();
+        match peers.peers.iter_mut().find(|peer| peer.id == id) {
+            Some(peer) => write_line(&mut peer.stream, line),
+            None => Err(anyhow!("The client is gone")),
+        }
+    }
+
+    fn set_peer(&self, id: u64, update: impl FnOnce(&mut Peer)) {
+        let mut peers = self.peers.lock().unwrap();
+        if let Some(peer) = peers.peers.iter_mut().find(|peer| peer.id == id) {
+            update(peer);
+        }
+    }
+
+    fn nick_in_use(&self, id: u64, nick: &str) -> bool {
+        let peers = self.peers.lock().unwrap();
+        peers
+            .peers
+            .iter()
+            .any(|peer| peer.id != id && peer.nick.eq_ignore_ascii_case(nick))
+    }
+
+    fn nicks(&self) -> Vec<String> {
+        let peers = self.peers.lock().unwrap();
+        peers
+            .peers
+            .iter()
+            .filter(|peer| peer.joined)
+            .map(|peer| peer.nick.clone())
+            .collect()
+    }
+}
+
+// One IRC client, from registration until it quits
+struct IrcSession<'a> {
+    server: &'a IrcServer,
+    id: u64,
+    stream: &'a TcpStream,
+    password: Option<&'a str>,
+    authorized: bool,
+    nick: Option<String>,
+    user: bool,
+    registered: bool,
+}
+
+impl IrcSession<'_> {
+    fn run(&mut self, sender: &Sender<BridgedMessage>) -> Result<()> {
+        for line in BufReader::new(self.stream).lines() {
+            let line = line?;
+            let Some((command, params)) = parse_line(&line) else {
+                continue;
+            };
+
+            match command.to_ascii_uppercase().as_str() {
+                "CAP" if params.first().map(String::as_str) == Some("LS") => {
+                    // No capabilities, clients go on without any
+                    self.send(&format!(":{} CAP * LS :", SERVER_NAME))?;
+                }
+                "CAP" => {}
+                "PASS" => {
+                    self.authorized |= self.password.is_some()
+                        && params.first().map(String::as_str) == self.password;
+                }
+                "NICK" => self.set_nick(params.first())?,
+                "USER" => self.user = true,
+                "PING" => self.send(&format!(
+                    ":{} PONG {} :{}",
+                    SERVER_NAME,
+                    SERVER_NAME,
+                    params.first().map_or("", String::as_str)
+                ))?,
+                "QUIT" => return Ok(()),
+                _ if !self.registered => {
+                    self.reply("451", ":You have not registered")?;
+                }
+                "JOIN" => {
+                    for channel in params.first().map_or("", String::as_str).split(',') {
+                        self.join(channel)?;
+                    }
+                }
+                "PART" => {
+                    let nick = self.nick();
+                    self.server.broadcast(
+                        &format!(":{}!{}@localhost PART {}", nick, nick, self.server.channel),
+                        None,
+                    );
+                    self.server.set_peer(self.id, |peer| peer.joined = false);
+                }
+                "PRIVMSG" | "NOTICE" => {
+                    let (Some(target), Some(text)) = (params.first(), params.get(1)) else {
+                        self.reply("412", ":No text to send")?;
+                        continue;
+                    };
+                    if !target.eq_ignore_ascii_case(&self.server.channel) {
+                        self.reply("401", &format!("{} :No such nick/channel", target))?;
+                        continue;
+                    }
+                    let text = one_line(text);
+                    if text.is_empty() || command.eq_ignore_ascii_case("NOTICE") {
+                        continue;
+                    }
+                    // The other IRC clients see it right away, the players
+                    // once the bot has said it
+                    let nick = self.nick();
+                    self.server.say_except(Some(&nick), &text, Some(self.id));
+                    let message = BridgedMessage {
+                        nick: Some(nick),
+                        text,
+                    };
+                    if sender.send(message).is_err() {
+                        return Ok(());
+                    }
+                }
+                "NAMES" => self.names()?,
+                "MODE" | "WHO" => {
+                    // Clients ask right after joining, an empty answer will do
+                    let end = match command.eq_ignore_ascii_case("WHO") {
+                        true => ("315", ":End of WHO list"),
+                        false => ("324", "+nt"),
+                    };
+                    self.reply(end.0, &format!("{} {}", self.server.channel, end.1))?;
+                }
+                other => self.reply("421", &format!("{} :Unknown command", other))?,
+            }
+
+            if !self.registered && self.user && self.nick.is_some() {
+                if !self.authorized {
+                    self.reply("464", ":Password incorrect")?;
+                    return Ok(());
+                }
+                self.registered = true;
+                self.reply("001", ":Welcome to the Minecraft chat bridge")?;
+                self.reply("422", ":MOTD File is missing")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn nick(&self) -> String {
+        self.nick.clone().unwrap_or_else(|| String::from("*"))
+    }
+
+    fn set_nick(&mut self, nick: Option<&String>) -> Result<()> {
+        let Some(nick) = nick.filter(|nick| is_nick(nick)) else {
+            return self.reply("432", ":Erroneous nickname");
+        };
+        if self.server.nick_in_use(self.id, nick) {
+            return self.reply("433", &format!("{} :Nickname is already in use", nick));
+        }
+
+        if self.registered {
+            let old = self.nick();
+            let line = format!(":{}!{}@localhost NICK :{}", old, old, nick);
+            self.server.broadcast(&line, Some(self.id));
+            self.send(&line)?;
+        }
+        let stored = nick.clone();
+        self.server.set_peer(self.id, |peer| peer.nick = stored);
+        self.nick = Some(nick.clone());
+        Ok(())
+    }
+
+    fn join(&mut self, channel: &str) -> Result<()> {
+        if !channel.eq_ignore_ascii_case(&self.server.channel) {
+            return self.reply(
+                "403",
+                &format!("{} :Only {} is here", channel, self.server.channel),
+            );
+        }
+
+        // Everyone in the channel sees us join, us included
+        self.server.set_peer(self.id, |peer| peer.joined = true);
+        let nick = self.nick();
+        self.server.broadcast(
+            &format!(":{}!{}@localhost JOIN {}", nick, nick, self.server.channel),
+            None,
+        );
+        self.reply(
+            "332",
+            &format!("{} :Minecraft chat, say something", self.server.channel),
+        )?;
+        self.names()
+    }
+
+    fn names(&mut self) -> Result<()> {
+        let channel = &self.server.channel;
+        self.reply(
+            "353",
+            &format!("= {} :{}", channel, self.server.nicks().join(" ")),
+        )?;
+        self.reply("366", &format!("{} :End of NAMES list", channel))
+    }
+
+    // A numeric reply addressed to this client
+    fn reply(&mut self, numeric: &str, text: &str) -> Result<()> {
+        let line = format!(":{} {} {} {}", SERVER_NAME, numeric, self.nick(), text);
+        self.send(&line)
+    }
+
+    // Through the server, so lines don't interleave with broadcasts
+    fn send(&mut self, line: &str) -> Result<()> {
+        self.server.send_to(self.id, line)
+    }
+}
+
+// Splits "[:prefix] COMMAND params... [:trailing]" into the command and
+// its parameters, None for an empty line
+fn parse_line(line: &str) -> Option<(String, Vec<String>)> {
+    let mut rest = line.trim_end_matches(['\r', '\n']);
+    if let Some(prefixed) = rest.strip_prefix(':') {
+        rest = prefixed.split_once(' ').map_or("", |(_, rest)| rest);
+    }
+
+    let (middle, trailing) = match rest.split_once(" :") {
+        Some((middle, trailing)) => (middle, Some(trailing)),
+        None => (rest, None),
+    };
+    let mut words = middle.split(' ').filter(|word| !word.is_empty());
+    let command = words.next()?;
+    let mut params: Vec<String> = words.map(String::from).collect();
+    params.extend(trailing.map(String::from));
+
+    Some((String::from(command), params))
+}
+
+fn is_nick(nick: &str) -> bool {
+    !nick.is_empty()
+        && nick.len() <= 30
+        && !nick.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && nick
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_[]\\`^{}|".contains(c))
+}
+
+// One write per line, cut to fit
+fn write_line(stream: &mut TcpStream, line: &str) -> Result<()> {
+    stream.write_all(format!("{}\r\n", truncate_line(line)).as_bytes())?;
+    Ok(())
+}
+
+// Cut on a character boundary so it fits in an IRC line with its CRLF
+fn truncate_line(line: &str) -> &str {
+    let mut end = line.len().min(MAX_LINE - 2);
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    &line[..end]
+}
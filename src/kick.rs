@@ -0,0 +1,49 @@
+use crate::Component;
+use std::fmt;
+
+// The error once the server has kicked us, from login or from the reads
+// after Event::Disconnected. Keeps the reason as sent, styling and all, so
+// it can be rendered: error.downcast_ref::<Kicked>().
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kicked {
+    pub reason: Component,
+    // Refused while logging in, e.g. not whitelisted or banned
+    pub during_login: bool,
+}
+
+impl Kicked {
+    // The message with the reason in its own styling, for any Renderer
+    pub fn to_component(&self) -> Component {
+        let prefix = match self.during_login {
+            true => "Disconnected while logging in: ",
+            false => "Kicked by the server: ",
+        };
+        // Only the prefix is red, the reason may well have no color of its own
+        Component::default()
+            .push(Component::text(prefix).color("red"))
+            .push(self.reason.clone())
+    }
+}
+
+impl fmt::Display for Kicked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_component().to_plain())
+    }
+}
+
+impl std::error::Error for Kicked {}
+
+// The error of every call on a client after Client::disconnect, with the
+// reason it was given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyClosed {
+    pub reason: String,
+}
+
+impl fmt::Display for AlreadyClosed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The client already disconnected: {}", self.reason)
+    }
+}
+
+impl std::error::Error for AlreadyClosed {}
@@ -0,0 +1,48 @@
+use std::{collections::VecDeque, time::Duration};
+
+// Samples Client::latency averages over
+pub const LATENCY_WINDOW: usize = 8;
+
+// Round trips to the server, as it measures them of us and reports them in
+// the tab list. Ping Request and Pong Response only came in 1.20.2, which
+// none of PROTOCOLS speaks.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LatencyTracker {
+    samples: VecDeque<Duration>,
+    // Last value from the tab list, so repeats of it aren't counted again
+    reported: Option<i32>,
+}
+
+impl LatencyTracker {
+    pub fn clear(&mut self) {
+        *self = LatencyTracker::default();
+    }
+
+    // Milliseconds from the tab list. Servers report 0 until they've
+    // measured anything, which isn't a sample.
+    pub fn reported(&mut self, milliseconds: i32) {
+        if milliseconds <= 0 || self.reported == Some(milliseconds) {
+            return;
+        }
+        self.reported = Some(milliseconds);
+        self.record(Duration::from_millis(milliseconds as u64));
+    }
+
+    fn record(&mut self, sample: Duration) {
+        if self.samples.len() == LATENCY_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        let count = u32::try_from(self.samples.len())
+            .ok()
+            .filter(|count| *count > 0)?;
+        Some(self.samples.iter().sum::<Duration>() / count)
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.samples.back().copied()
+    }
+}
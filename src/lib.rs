@@ -1,12 +1,170 @@
 use anyhow::{anyhow, Context, Result};
+use chat_limit::ChatLimiter;
+use rand::{rngs::StdRng, RngExt, SeedableRng};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 use std::{
-    io::{BufReader, BufWriter, Read, Write},
-    net::{IpAddr, TcpStream},
-    result,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{HashMap, VecDeque},
+    io::Write,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-#[derive(Debug)]
+pub mod auth;
+mod background;
+pub mod bedrock;
+mod bitset;
+mod boss_bar;
+#[cfg(feature = "bus")]
+mod bus;
+mod chat;
+mod chat_limit;
+mod chat_log;
+mod client_information;
+mod completion;
+mod connection;
+mod decode_mode;
+mod entities;
+mod event;
+mod event_json;
+mod favicon;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod fixed;
+mod forwarding;
+mod frame;
+mod history;
+mod http;
+mod inspect;
+mod irc;
+mod kick;
+mod latency;
+mod limits;
+mod listener;
+mod locale;
+mod messages;
+mod metrics;
+mod monitor;
+mod movement;
+mod multiline;
+mod nbt;
+mod players;
+mod pool;
+mod position;
+mod profile;
+mod protocol;
+mod proxy;
+mod proxy_protocol;
+mod query;
+mod queue;
+pub mod rcon;
+mod reader;
+mod registry;
+mod render;
+mod resource_pack;
+mod responder;
+mod rules;
+mod scan;
+mod scoreboard;
+mod server;
+mod session_cache;
+mod shutdown;
+mod sniffer;
+mod split;
+mod srv;
+mod stats;
+mod status;
+mod status_template;
+mod supervisor;
+pub mod testing;
+mod transport;
+mod vhost;
+#[cfg(feature = "http")]
+mod webhook;
+
+pub use background::{BackgroundClient, CommandSender};
+pub use bitset::BitSet;
+pub use boss_bar::{BossBar, BossBars};
+#[cfg(feature = "bus")]
+pub use bus::{BusTarget, EventPublisher};
+pub use chat::{format_pattern, translate_fallback, Component};
+pub use chat_limit::{ChatRate, SendResult};
+pub use chat_log::{ChatLogger, Rotation};
+pub use client_information::{
+    ChatMode, ClientInformation, MainHand, SKIN_ALL, SKIN_CAPE, SKIN_HAT, SKIN_JACKET,
+    SKIN_LEFT_PANTS, SKIN_LEFT_SLEEVE, SKIN_RIGHT_PANTS, SKIN_RIGHT_SLEEVE,
+};
+pub use completion::{Suggestion, COMPLETION_TIMEOUT, MAX_COMPLETION_LENGTH};
+pub use connection::{Connection, ConnectionState, SkippablePacket};
+pub use decode_mode::{DecodeMode, UnexpectedPacket};
+pub use entities::{Entity, EntityKind, EntityTracker};
+pub use event::{Event, LoginPhase};
+pub use event_json::{event_to_json, EVENT_SCHEMA_VERSION};
+pub use favicon::{
+    decode_favicon, favicon_from_bytes, favicon_from_file, favicon_from_image, FAVICON_SIZE,
+};
+
+pub use fixed::{f64_to_fixed, fixed_to_f64};
+pub use forwarding::{ForwardedPlayer, Forwarding, VELOCITY_CHANNEL};
+pub use frame::{Frame, DEFAULT_COMPRESSION_LEVEL, MAX_DECOMPRESSED_LENGTH};
+pub use history::{StateChange, StateHistory, StateSnapshot, DEFAULT_HISTORY_CAPACITY};
+pub use http::{HttpClient, HttpConfig};
+pub use inspect::{decode_frame, decode_packet, parse_blob, DecodedPacket, Direction};
+pub use irc::{BridgedMessage, IrcServer, LineProtocol};
+pub use kick::{AlreadyClosed, Kicked};
+use latency::LatencyTracker;
+pub use latency::LATENCY_WINDOW;
+pub use limits::{ConnectionLimits, ConnectionPermit, Throttle};
+pub use listener::{MinecraftListener, PlayerAction, ServerPlayer};
+pub use locale::{DateOrder, Locale};
+pub use messages::{ChatKind, ChatMessage, MessageCategory, MessageFilter};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use monitor::{StatusMonitor, StatusSample};
+pub use movement::{PlayerPosition, TICK_INTERVAL};
+pub use multiline::{split_chat_message, DEFAULT_CONTINUATION, MAX_CHAT_LENGTH};
+pub use nbt::{from_tag, to_tag, Tag, MAX_NBT_DEPTH};
+pub use players::{PlayerInfo, PlayerList};
+pub use pool::{ClientId, ClientPool, PoolEvent};
+pub use position::{Angle, BlockPosition};
+pub use profile::{offline_uuid, Profile, ProfileProperty};
+pub use protocol::{ProtocolFeatures, PROTOCOLS};
+pub use proxy::{ProxyAuth, ProxyConfig};
+pub use proxy_protocol::{ProxyHeader, ProxyProtocolVersion};
+pub use query::{query_basic, query_full, BasicStat, FullStat};
+use queue::QueueDetector;
+pub use queue::QueuePosition;
+pub use reader::PacketReader;
+pub use registry::{ChatDecoration, ChatParameter, ChatTypes};
+pub use render::{
+    color_rgb, named_color_rgb, runs, AnsiRenderer, HtmlRenderer, MarkdownRenderer, PlainRenderer,
+    Renderer, Style, SECTION_SIGN,
+};
+pub use resource_pack::{
+    download_resource_pack, ResourcePackPolicy, ResourcePackRequest, ResourcePackStatus,
+};
+pub use responder::{Responder, Response, TriggerHandler, TriggerRate};
+pub use rules::{ChatCallback, ChatMatch, ChatRules};
+pub use scan::{probe_server, scan_servers, ScanResult};
+pub use scoreboard::{DisplaySlot, Objective, Scoreboard};
+pub use server::{Handshake, NextState, ServerConnection};
+pub use session_cache::{data_directory, CachedSession, SessionCache, REFRESH_MARGIN};
+pub use shutdown::{CancelRegistration, ShutdownToken};
+pub use sniffer::{SniffedPacket, Sniffer};
+pub use split::{ClientReader, ClientWriter};
+pub use srv::{lookup_srv, lookup_srv_with};
+pub use stats::{PlayerStats, SPRINT_FOOD_LEVEL};
+pub use status::{
+    PlayerSample, Players, ServerStatus, StatusBuilder, StatusFix, StatusReport, Version,
+};
+pub use status_template::{DynamicPlayers, StatusTemplate, TemplateFile};
+pub use supervisor::{RestartPolicy, Supervisor};
+pub use transport::{memory_pipe, StreamTransport, Transport};
+use uuid::Uuid;
+pub use vhost::{Route, VirtualHosts};
+#[cfg(feature = "http")]
+pub use webhook::{listen_relay, RelayedMessage, WebhookBridge, WebhookFormat};
+
+#[derive(Debug, Clone, Default)]
 pub struct Packet {
     pub buffer: Vec<u8>,
     pub cursor: usize,
@@ -45,59 +203,81 @@ impl Packet {
         Ok(())
     }
 
-    fn read_string(&mut self) -> Result<String> {
-        let length = self.read_varint()? as usize;
-        let value = String::from_utf8(self.buffer[self.cursor..self.cursor + length].to_vec())?;
-        self.cursor += length;
+    // Empties the packet but keeps its allocation around for reuse
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.cursor = 0;
+        self.protocol_id = None;
+    }
+
+    // Borrowing reader positioned at the current cursor
+    pub fn reader(&self) -> PacketReader<'_> {
+        PacketReader::new(&self.buffer[self.cursor.min(self.buffer.len())..])
+    }
+
+    // Runs a read on the borrowed reader and moves our cursor past what it consumed
+    fn read_with<T>(&mut self, read: impl FnOnce(&mut PacketReader) -> Result<T>) -> Result<T> {
+        let mut reader = self.reader();
+        let value = read(&mut reader)?;
+        self.cursor += reader.cursor();
 
         Ok(value)
     }
 
-    fn write_varint(&mut self, mut value: i32) -> Result<()> {
-        let mut iterations = 1;
-        loop {
-            if iterations > 5 {
-                return Err(anyhow!("Varint exceeds maximum allowed size"));
-            }
+    fn read_string(&mut self) -> Result<String> {
+        self.read_with(|reader| Ok(reader.read_str()?.to_owned()))
+    }
 
-            if (value & !VARINT_SEGMENT_BITS) == 0 {
-                self.buffer.push(value as u8);
-                return Ok(());
-            }
+    fn write_bool(&mut self, value: bool) {
+        self.buffer.push(value as u8);
+    }
 
-            self.buffer
-                .push((value & VARINT_SEGMENT_BITS | VARINT_CONTINUE_BIT) as u8);
+    fn write_uuid(&mut self, value: &Uuid) {
+        self.buffer.extend_from_slice(value.as_bytes());
+    }
 
-            value = ((value as u32) >> 7) as i32;
-            iterations += 1;
-        }
+    pub fn write_varint(&mut self, value: i32) -> Result<()> {
+        encode_varint(value, &mut self.buffer);
+
+        Ok(())
     }
 
     fn read_varint(&mut self) -> Result<i32> {
-        let mut value = 0i32;
-        let mut bit_position = 0i32;
+        self.read_with(|reader| reader.read_varint())
+    }
 
-        loop {
-            if self.cursor >= self.buffer.len() {
-                return Err(anyhow!("Buffer is too short to read a valid varint"));
-            }
+    // No 1.19 packet we decode carries one, it's here for the ones built
+    // outside the crate. Read them back with PacketReader::read_varlong.
+    pub fn write_varlong(&mut self, value: i64) -> Result<()> {
+        encode_varlong(value, &mut self.buffer);
 
-            let current_byte = self.buffer[self.cursor];
-            self.cursor += 1;
+        Ok(())
+    }
 
-            value |= (current_byte as i32 & VARINT_SEGMENT_BITS) << bit_position;
+    pub fn write_position(&mut self, position: &BlockPosition) -> Result<()> {
+        self.write_slice(&position.to_packed()?.to_be_bytes());
 
-            if (current_byte as i32 & VARINT_CONTINUE_BIT) == 0 {
-                break;
-            }
+        Ok(())
+    }
 
-            bit_position += 7;
-            if bit_position >= 32 {
-                return Err(anyhow!("Varint too large"));
-            }
+    // Degrees, rounded down to the nearest 1/256 of a turn
+    pub fn write_angle(&mut self, degrees: f32) {
+        self.buffer.push(Angle::from_degrees(degrees).0);
+    }
+
+    pub fn write_bitset(&mut self, set: &BitSet) -> Result<()> {
+        let length = i32::try_from(set.words().len())?;
+        self.write_varint(length)?;
+        for word in set.words() {
+            self.write_slice(&word.to_be_bytes());
         }
 
-        Ok(value)
+        Ok(())
+    }
+
+    // Only the first `bits` bits are written, as ceil(bits / 8) bytes
+    pub fn write_fixed_bitset(&mut self, set: &BitSet, bits: usize) {
+        self.write_slice(&set.to_fixed_bytes(bits));
     }
 
     fn read_protocol_id(&mut self) -> Result<u8> {
@@ -108,7 +288,7 @@ impl Packet {
         self.cursor += 1;
 
         self.protocol_id
-            .map(|id| Ok(id))
+            .map(Ok)
             .unwrap_or_else(|| Err(anyhow!("Protocol ID is somehow missing")))
     }
 
@@ -121,175 +301,2004 @@ impl Packet {
     }
 
     fn read_slice(&mut self, amount: usize) -> Result<&[u8]> {
-        if self.cursor + amount > self.buffer.len() - 1 {
-            return Err(anyhow!("Could not read slice past buffer."));
+        let start = self.cursor;
+        self.read_with(|reader| reader.read_bytes(amount).map(|_| ()))?;
+
+        Ok(&self.buffer[start..self.cursor])
+    }
+}
+
+// A 32 bit value always fits in 5 groups of 7 bits, so this can't fail
+fn encode_varint(value: i32, out: &mut Vec<u8>) {
+    let mut value = value as u32;
+    while value & !(VARINT_SEGMENT_BITS as u32) != 0 {
+        out.push((value as i32 & VARINT_SEGMENT_BITS | VARINT_CONTINUE_BIT) as u8);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// Same for 64 bits in up to 10 groups
+fn encode_varlong(value: i64, out: &mut Vec<u8>) {
+    let mut value = value as u64;
+    loop {
+        if (value & !(VARINT_SEGMENT_BITS as u64)) == 0 {
+            out.push(value as u8);
+            return;
         }
-        let result = &self.buffer[self.cursor..self.cursor + amount];
-        self.cursor += amount;
-        Ok(result)
+
+        out.push((value as i32 & VARINT_SEGMENT_BITS | VARINT_CONTINUE_BIT) as u8);
+        value >>= 7;
     }
 }
 
+fn chat_message(features: &ProtocolFeatures, message: &str, salt: u64) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.chat_message_packet_id as i32)?; // protocol id
+    packet.write_string(message)?; // Message
+    if !features.has_chat_signing {
+        return Ok(packet);
+    }
+    let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    packet.write_slice(&timestamp_ms.to_be_bytes()); // timestamp
+    packet.write_slice(&salt.to_be_bytes()); // salt
+    packet.write_slice(&[0u8; 1]); // signature length
+    packet.write_slice(&[0u8; 1]); // signed preview
+
+    Ok(packet)
+}
+
+fn keep_alive(features: &ProtocolFeatures, id: i64) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.keep_alive_response_packet_id as i32)?; // Protocol ID
+    packet.write_slice(&id.to_be_bytes()); // Keep Alive ID
+
+    Ok(packet)
+}
+
+fn chat_command(features: &ProtocolFeatures, command: &str, salt: u64) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.chat_command_packet_id as i32)?; // protocol id
+    packet.write_string(command)?; // Command
+    if !features.has_chat_signing {
+        return Ok(packet);
+    }
+    let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    packet.write_slice(&timestamp_ms.to_be_bytes()); // timestamp
+    packet.write_slice(&salt.to_be_bytes()); // salt
+    packet.write_varint(0)?; // argument signature count
+    packet.write_bool(false); // signed preview
+
+    Ok(packet)
+}
+
 pub struct Client {
-    handshake_performed: bool,
-    reader: BufReader<TcpStream>,
-    writer: BufWriter<TcpStream>,
+    state: ConnectionState,
+    connection: Connection,
     hostname: String,
     port: u16,
+    proxy: Option<ProxyConfig>,
+    proxy_header: Option<ProxyHeader>,
+    username: String,
+    forwarding: Option<Forwarding>,
+    rng: StdRng,
+    login_plugin_handler: Option<LoginPluginHandler>,
+    login_phase_hook: Option<LoginPhaseHook>,
+    send_hooks: Vec<PacketHook>,
+    receive_hooks: Vec<PacketHook>,
+    resource_pack_policy: ResourcePackPolicy,
+    auto_respawn: bool,
+    dead: bool,
+    position: Option<PlayerPosition>,
+    on_ground: bool,
+    sneaking: bool,
+    sprinting: bool,
+    entity_id: Option<i32>,
+    position_updates: bool,
+    last_position_update: Instant,
+    events: VecDeque<Event>,
+    players: PlayerList,
+    entities: EntityTracker,
+    stats: PlayerStats,
+    scoreboard: Scoreboard,
+    boss_bars: BossBars,
+    http: HttpClient,
+    shutdown: ShutdownToken,
+    lenient_status: bool,
+    protocol_version: i32,
+    features: ProtocolFeatures,
+    connect_timeout: Option<Duration>,
+    pause_when_idle: bool,
+    follow_transfers: bool,
+    client_information: Option<ClientInformation>,
+    brand: Option<String>,
+    decode_mode: DecodeMode,
+    // Play packet ids handed over as Event::Packet, see DecodeMode
+    expected_packets: BitSet,
+    // Play packet ids read at all, see ClientBuilder::filter_packets
+    packet_filter: Option<BitSet>,
+    chat_limiter: ChatLimiter,
+    continuation: String,
+    idle: bool,
+    chat_rules: ChatRules,
+    responder: Responder,
+    chat_types: ChatTypes,
+    queue: QueueDetector,
+    // Set while waiting in a join queue, see Event::QueuePosition
+    queue_position: Option<QueuePosition>,
+    next_transaction_id: i32,
+    profile: Option<Profile>,
+    history: StateHistory,
+    metrics: Option<metrics::Metrics>,
+    kicked: Option<Kicked>,
+    // Set by disconnect, for good
+    closed: Option<AlreadyClosed>,
+    latency: LatencyTracker,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    compression_level: u32,
+    // For online mode, see ClientBuilder::session
+    access_token: Option<String>,
+    chat_logger: Option<ChatLogger>,
+    connector: Option<Connector>,
+}
+
+// Dropping a client closes its connection like disconnect does, so the
+// server sees a clean end of stream rather than a reset
+impl Drop for Client {
+    fn drop(&mut self) {
+        if self.closed.is_none() {
+            let _ = self.connection.close();
+        }
+    }
+}
+
+pub struct ClientBuilder {
+    hostname: String,
+    port: u16,
+    proxy: Option<ProxyConfig>,
+    proxy_header: Option<ProxyHeader>,
+    username: String,
+    forwarding: Option<Forwarding>,
+    seed: Option<u64>,
+    login_plugin_handler: Option<LoginPluginHandler>,
+    login_phase_hook: Option<LoginPhaseHook>,
+    send_hooks: Vec<PacketHook>,
+    receive_hooks: Vec<PacketHook>,
+    resource_pack_policy: ResourcePackPolicy,
+    auto_respawn: bool,
+    history_capacity: usize,
+    position_updates: bool,
+    http: Option<HttpClient>,
+    shutdown: ShutdownToken,
+    lenient_status: bool,
+    protocol_version: i32,
+    features: Option<ProtocolFeatures>,
+    connect_timeout: Option<Duration>,
+    pause_when_idle: bool,
+    follow_transfers: bool,
+    client_information: Option<ClientInformation>,
+    brand: Option<String>,
+    decode_mode: DecodeMode,
+    expected_packets: Vec<u8>,
+    packet_filter: Option<Vec<u8>>,
+    chat_rate: Option<ChatRate>,
+    continuation: String,
+    chat_rules: ChatRules,
+    responder: Responder,
+    metrics: Option<metrics::Metrics>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    compression_level: u32,
+    access_token: Option<String>,
+    chat_logger: Option<ChatLogger>,
+    connector: Option<Connector>,
+}
+
+// Gets the channel and payload of a Login Plugin Request, returns the response
+// payload or None to tell the server the channel isn't understood
+pub type LoginPluginHandler = Box<dyn FnMut(&str, &[u8]) -> Option<Vec<u8>> + Send>;
+
+// Called the moment each login phase completes, e.g. to time them or drive a progress bar
+pub type LoginPhaseHook = Box<dyn FnMut(&LoginPhase) + Send>;
+
+// Sees a packet on its way out or in and may change it, returning false
+// drops it. Outgoing packets start with their id as a varint, incoming ones
+// have it parsed off already, see Packet::get_protocol_id.
+pub type PacketHook = Box<dyn FnMut(&mut Packet) -> bool + Send>;
+
+// Opens a new stream to the server, in place of dialing hostname:port
+pub type Connector = Box<dyn FnMut() -> Result<Box<dyn Transport>> + Send>;
+
+impl ClientBuilder {
+    pub fn new(hostname: &str, port: u16) -> ClientBuilder {
+        ClientBuilder {
+            hostname: String::from(hostname),
+            port,
+            proxy: None,
+            proxy_header: None,
+            username: String::from("extremq"),
+            forwarding: None,
+            seed: None,
+            login_plugin_handler: None,
+            login_phase_hook: None,
+            send_hooks: Vec::new(),
+            receive_hooks: Vec::new(),
+            resource_pack_policy: ResourcePackPolicy::Accept,
+            auto_respawn: true,
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+            position_updates: false,
+            http: None,
+            shutdown: ShutdownToken::new(),
+            lenient_status: false,
+            protocol_version: PROTOCOL_VERSION,
+            features: None,
+            connect_timeout: None,
+            pause_when_idle: false,
+            follow_transfers: true,
+            client_information: Some(ClientInformation::default()),
+            brand: Some(String::from("vanilla")),
+            decode_mode: DecodeMode::default(),
+            expected_packets: Vec::new(),
+            packet_filter: None,
+            chat_rate: Some(ChatRate::default()),
+            continuation: String::from(DEFAULT_CONTINUATION),
+            chat_rules: ChatRules::new(),
+            responder: Responder::new(),
+            metrics: None,
+            read_timeout: None,
+            write_timeout: None,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            access_token: None,
+            chat_logger: None,
+            connector: None,
+        }
+    }
+
+    pub fn proxy(mut self, proxy: ProxyConfig) -> ClientBuilder {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    // Sent ahead of the handshake for servers sitting behind a load balancer
+    // that expects the PROXY protocol
+    pub fn proxy_header(mut self, header: ProxyHeader) -> ClientBuilder {
+        self.proxy_header = Some(header);
+        self
+    }
+
+    pub fn username(mut self, username: &str) -> ClientBuilder {
+        self.username = String::from(username);
+        self
+    }
+
+    pub fn forwarding(mut self, forwarding: Forwarding) -> ClientBuilder {
+        self.forwarding = Some(forwarding);
+        self
+    }
+
+    // Fixes every random value the client produces (salts etc.) so runs can be replayed
+    pub fn seed(mut self, seed: u64) -> ClientBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn login_plugin_handler(
+        mut self,
+        handler: impl FnMut(&str, &[u8]) -> Option<Vec<u8>> + Send + 'static,
+    ) -> ClientBuilder {
+        self.login_plugin_handler = Some(Box::new(handler));
+        self
+    }
+
+    pub fn login_phase_hook(
+        mut self,
+        hook: impl FnMut(&LoginPhase) + Send + 'static,
+    ) -> ClientBuilder {
+        self.login_phase_hook = Some(Box::new(hook));
+        self
+    }
+
+    // Runs on everything send_packet sends, in the order added, until one
+    // drops the packet. Writers from split don't run them.
+    pub fn send_hook(
+        mut self,
+        hook: impl FnMut(&mut Packet) -> bool + Send + 'static,
+    ) -> ClientBuilder {
+        self.send_hooks.push(Box::new(hook));
+        self
+    }
+
+    // Runs on everything read_packet reads, before the client handles it.
+    // A dropped packet is as good as never sent, the next one is read instead.
+    pub fn receive_hook(
+        mut self,
+        hook: impl FnMut(&mut Packet) -> bool + Send + 'static,
+    ) -> ClientBuilder {
+        self.receive_hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn resource_pack_policy(mut self, policy: ResourcePackPolicy) -> ClientBuilder {
+        self.resource_pack_policy = policy;
+        self
+    }
+
+    // Respawn right away on death instead of idling on the death screen
+    pub fn auto_respawn(mut self, auto_respawn: bool) -> ClientBuilder {
+        self.auto_respawn = auto_respawn;
+        self
+    }
+
+    // How many state changes to keep for error reports, 0 turns recording off
+    pub fn history_capacity(mut self, capacity: usize) -> ClientBuilder {
+        self.history_capacity = capacity;
+        self
+    }
+
+    // Keep sending our position every tick while waiting for events, like a
+    // vanilla client does. Needed for anything that walks around.
+    pub fn position_updates(mut self, enabled: bool) -> ClientBuilder {
+        self.position_updates = enabled;
+        self
+    }
+
+    // Used for everything the client fetches over the web. Defaults to one
+    // going through the same proxy as the connection.
+    pub fn http_client(mut self, http: HttpClient) -> ClientBuilder {
+        self.http = Some(http);
+        self
+    }
+
+    // Share one token between the client and everything else in the process
+    // so cancelling it tears them all down together
+    pub fn shutdown_token(mut self, token: ShutdownToken) -> ClientBuilder {
+        self.shutdown = token;
+        self
+    }
+
+    // Repair broken status responses instead of failing on them, see
+    // ServerStatus::parse_lenient. Meant for scanners.
+    pub fn lenient_status(mut self, lenient: bool) -> ClientBuilder {
+        self.lenient_status = lenient;
+        self
+    }
+
+    // Announced in the handshake. Packets follow the matching entry of
+    // PROTOCOLS, or the 1.19 ones for a version without an entry, which
+    // mostly helps with status pings and servers running ViaVersion.
+    pub fn protocol_version(mut self, version: i32) -> ClientBuilder {
+        self.protocol_version = version;
+        self
+    }
+
+    // Packet ids and layouts to use instead of the PROTOCOLS entry, e.g. for
+    // a snapshot. The handshake still announces protocol_version.
+    pub fn protocol_features(mut self, features: ProtocolFeatures) -> ClientBuilder {
+        self.features = Some(features);
+        self
+    }
+
+    // Stop tracking entities while we're the only one online, see Event::Idle.
+    // Saves work for always-on bots on servers that are often empty.
+    pub fn pause_when_idle(mut self, pause: bool) -> ClientBuilder {
+        self.pause_when_idle = pause;
+        self
+    }
+
+    // Log in wherever a Transfer packet (1.20.5+) sends us, on by default.
+    // Turned off, the packet comes out as Event::Packet instead. No release
+    // in PROTOCOLS has a transfer_packet_id, so this does nothing unless
+    // protocol_features is given one. The login that follows has no
+    // configuration phase either, so it's for servers that go straight to
+    // play like 1.19 did. A connector can't be pointed at the new host, so
+    // with one set a transfer fails the read instead.
+    pub fn follow_transfers(mut self, follow: bool) -> ClientBuilder {
+        self.follow_transfers = follow;
+        self
+    }
+
+    // Sent after Login (play), vanilla's defaults unless changed. None skips
+    // the packet, which some servers kick for.
+    pub fn client_information(mut self, information: Option<ClientInformation>) -> ClientBuilder {
+        self.client_information = information;
+        self
+    }
+
+    // Sent on the minecraft:brand channel along with it, "vanilla" by
+    // default. None doesn't send one.
+    pub fn brand(mut self, brand: Option<&str>) -> ClientBuilder {
+        self.brand = brand.map(String::from);
+        self
+    }
+
+    // What to do with play packets outside the release's ids, lenient by
+    // default, see DecodeMode
+    pub fn decode_mode(mut self, mode: DecodeMode) -> ClientBuilder {
+        self.decode_mode = mode;
+        self
+    }
+
+    // Play packet ids to accept on top of the release's, e.g. ones a server
+    // plugin adds. They come out as Event::Packet in either mode.
+    pub fn expect_packets(mut self, ids: &[u8]) -> ClientBuilder {
+        self.expected_packets.extend_from_slice(ids);
+        self
+    }
+
+    // Once in play, only these packet ids and the ones the client needs
+    // itself (keep alives, chat, kicks and the like) are read, the rest are
+    // skipped without decompressing or copying them, see
+    // Connection::set_packet_filter. filter_packets(&[]) suits a bot that
+    // only chats. Counted in Client::filtered_packets.
+    pub fn filter_packets(mut self, ids: &[u8]) -> ClientBuilder {
+        self.packet_filter
+            .get_or_insert_with(Vec::new)
+            .extend_from_slice(ids);
+        self
+    }
+
+    // Messages and commands over this rate are queued instead of sent, None
+    // sends everything right away. One per second with a burst of 3 by default.
+    pub fn chat_rate(mut self, rate: Option<ChatRate>) -> ClientBuilder {
+        self.chat_rate = rate;
+        self
+    }
+
+    // Starts the wrapped parts of send_chat_multiline, "... " by default
+    pub fn continuation_prefix(mut self, prefix: &str) -> ClientBuilder {
+        self.continuation = String::from(prefix);
+        self
+    }
+
+    // Answers matching chat and system messages, see ChatRules
+    pub fn chat_rules(mut self, rules: ChatRules) -> ClientBuilder {
+        self.chat_rules = rules;
+        self
+    }
+
+    // Runs keyword triggers against player chat, see Responder
+    pub fn responder(mut self, responder: Responder) -> ClientBuilder {
+        self.responder = responder;
+        self
+    }
+
+    // Counts packets, chat, logins and more into `metrics`, see Metrics
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> ClientBuilder {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Bounds connecting, including reconnects for a new handshake
+    pub fn connect_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    // Fails reads once the server has been silent this long. Vanilla gives
+    // up after 30s, keep alives come every 15s.
+    pub fn read_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    // Fails sends the server doesn't take in this long
+    pub fn write_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    // zlib level of what we send once the server turns compression on, 0
+    // trades bandwidth for CPU, 9 the other way around
+    pub fn compression_level(mut self, level: u32) -> ClientBuilder {
+        self.compression_level = level.min(9);
+        self
+    }
+
+    // Joins as the session's profile. Its access token is kept for online
+    // mode, until encryption is supported only the name is used.
+    pub fn session(mut self, session: &CachedSession) -> ClientBuilder {
+        self.username = session.name.clone();
+        self.access_token = Some(session.access_token.clone());
+        self
+    }
+
+    // Logs chat and system messages as they're handed out by next_event
+    // and poll_event, see ChatLogger
+    pub fn chat_logger(mut self, logger: ChatLogger) -> ClientBuilder {
+        self.chat_logger = Some(logger);
+        self
+    }
+
+    // Opens connections with `connector` instead of dialing the server, e.g.
+    // through a unix socket or an SSH tunnel. It's called again for every
+    // connection a status ping or a login after one needs. The handshake
+    // still names hostname and port; the proxy and proxy header are skipped.
+    pub fn connector(
+        mut self,
+        connector: impl FnMut() -> Result<Box<dyn Transport>> + Send + 'static,
+    ) -> ClientBuilder {
+        self.connector = Some(Box::new(connector));
+        self
+    }
+
+    // Connects to a unix socket instead, e.g. of a proxy on the same machine
+    #[cfg(unix)]
+    pub fn unix_socket(self, path: impl Into<std::path::PathBuf>) -> ClientBuilder {
+        let path = path.into();
+        self.connector(move || {
+            let stream = UnixStream::connect(&path)
+                .with_context(|| format!("Failed to connect to {}", path.display()))?;
+            Ok(Box::new(stream) as Box<dyn Transport>)
+        })
+    }
+
+    // Runs over a stream that's already open. It's good for one connection,
+    // anything needing a second fails, see connector for those.
+    pub fn transport(self, transport: impl Transport) -> ClientBuilder {
+        let mut transport = Some(Box::new(transport) as Box<dyn Transport>);
+        self.connector(move || {
+            transport.take().ok_or_else(|| {
+                anyhow!("The transport was already used, reconnecting needs a connector")
+            })
+        })
+    }
+
+    pub fn connect(mut self) -> Result<Client> {
+        let stream = open_stream(
+            self.connector.as_mut(),
+            &self.hostname,
+            self.port,
+            self.proxy.as_ref(),
+            self.proxy_header.as_ref(),
+            self.connect_timeout,
+        )?;
+
+        let http = match self.http {
+            Some(http) => http,
+            None => HttpClient::new(HttpConfig {
+                proxy: self.proxy.clone(),
+                ..HttpConfig::default()
+            })?,
+        };
+
+        let connection = Connection::new(stream)?;
+        let features = self.features.unwrap_or_else(|| {
+            ProtocolFeatures::for_version(self.protocol_version)
+                .copied()
+                .unwrap_or_default()
+        });
+
+        let mut history = StateHistory::new(self.history_capacity);
+        history.record(StateChange::Connected {
+            hostname: self.hostname.clone(),
+            port: self.port,
+        });
+
+        let mut client = Client {
+            state: ConnectionState::Handshaking,
+            connection,
+            hostname: self.hostname,
+            port: self.port,
+            proxy: self.proxy,
+            proxy_header: self.proxy_header,
+            username: self.username,
+            forwarding: self.forwarding,
+            rng: match self.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => rand::make_rng(),
+            },
+            login_plugin_handler: self.login_plugin_handler,
+            login_phase_hook: self.login_phase_hook,
+            send_hooks: self.send_hooks,
+            receive_hooks: self.receive_hooks,
+            resource_pack_policy: self.resource_pack_policy,
+            auto_respawn: self.auto_respawn,
+            dead: false,
+            position: None,
+            on_ground: true,
+            sneaking: false,
+            sprinting: false,
+            entity_id: None,
+            position_updates: self.position_updates,
+            last_position_update: Instant::now(),
+            events: VecDeque::new(),
+            players: PlayerList::default(),
+            entities: EntityTracker::default(),
+            stats: PlayerStats::default(),
+            scoreboard: Scoreboard::default(),
+            boss_bars: BossBars::default(),
+            http,
+            shutdown: self.shutdown,
+            lenient_status: self.lenient_status,
+            protocol_version: self.protocol_version,
+            features,
+            connect_timeout: self.connect_timeout,
+            pause_when_idle: self.pause_when_idle,
+            follow_transfers: self.follow_transfers,
+            client_information: self.client_information,
+            brand: self.brand,
+            decode_mode: self.decode_mode,
+            expected_packets: decode_mode::expected_packets(&features, &self.expected_packets),
+            packet_filter: self
+                .packet_filter
+                .map(|wanted| decode_mode::packet_filter(&features, &wanted)),
+            chat_limiter: ChatLimiter::new(self.chat_rate),
+            continuation: self.continuation,
+            idle: false,
+            chat_rules: self.chat_rules,
+            responder: self.responder,
+            chat_types: ChatTypes::default(),
+            queue: QueueDetector::default(),
+            queue_position: None,
+            next_transaction_id: 0,
+            profile: None,
+            history,
+            metrics: self.metrics,
+            kicked: None,
+            closed: None,
+            latency: LatencyTracker::default(),
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            compression_level: self.compression_level,
+            access_token: self.access_token,
+            chat_logger: self.chat_logger,
+            connector: self.connector,
+        };
+        client.setup_connection()?;
+
+        Ok(client)
+    }
+}
+
+// Later hooks don't see a packet an earlier one dropped
+fn run_hooks(hooks: &mut [PacketHook], packet: &mut Packet) -> bool {
+    hooks.iter_mut().all(|hook| hook(packet))
+}
+
+fn open_stream(
+    connector: Option<&mut Connector>,
+    hostname: &str,
+    port: u16,
+    proxy: Option<&ProxyConfig>,
+    proxy_header: Option<&ProxyHeader>,
+    timeout: Option<Duration>,
+) -> Result<Box<dyn Transport>> {
+    if let Some(connector) = connector {
+        return connector();
+    }
+
+    let mut stream = match proxy {
+        Some(proxy) => proxy
+            .connect_timeout(hostname, port, timeout)
+            .with_context(|| format!("Failed to connect to {}:{} through proxy", hostname, port))?,
+        None => {
+            let address = format!("{}:{}", hostname, port);
+            proxy::connect_tcp(&address, timeout)
+                .with_context(|| format!("Failed to connect to {}", address))?
+        }
+    };
+
+    if let Some(header) = proxy_header {
+        stream.write_all(&header.encode()?)?;
+    }
+
+    Ok(Box::new(stream))
+}
+
+// "example.com", "example.com:25566" or "[::1]:25566", the port is None when
+// not given. Shared by the binaries so they all accept the same addresses.
+pub fn split_host_port(address: &str) -> Result<(String, Option<u16>)> {
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| anyhow!("Missing ] in {}", address))?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => Some(
+                port.parse()
+                    .with_context(|| format!("Bad port in {}", address))?,
+            ),
+            None if rest.is_empty() => None,
+            None => return Err(anyhow!("Unexpected {:?} after ] in {}", rest, address)),
+        };
+        return Ok((String::from(host), port));
+    }
+
+    match address.split_once(':') {
+        // More than one colon is a bare IPv6 address
+        Some((host, port)) if !port.contains(':') => Ok((
+            String::from(host),
+            Some(
+                port.parse()
+                    .with_context(|| format!("Bad port in {}", address))?,
+            ),
+        )),
+        _ => Ok((String::from(address), None)),
+    }
 }
 
 const VARINT_SEGMENT_BITS: i32 = 0x7F;
 const VARINT_CONTINUE_BIT: i32 = 0x80;
+pub const PROTOCOL_VERSION: i32 = 759; // 1.19
+pub const DEFAULT_PORT: u16 = 25565;
+pub const MAX_PACKET_LENGTH: usize = 2097151; // 2^21 - 1, what a 3 byte varint can hold
 
 impl Client {
     pub fn new(hostname: &str, port: u16) -> Result<Client> {
-        let address = format!("{}:{}", hostname, port);
+        ClientBuilder::new(hostname, port).connect()
+    }
 
-        let stream = TcpStream::connect(&address)
-            .with_context(|| format!("Failed to connect to {}", address))?;
+    pub fn builder(hostname: &str, port: u16) -> ClientBuilder {
+        ClientBuilder::new(hostname, port)
+    }
 
-        Ok(Client {
-            handshake_performed: false,
-            reader: BufReader::new(stream.try_clone()?),
-            writer: BufWriter::new(stream.try_clone()?),
-            hostname: String::from(hostname),
-            port,
-        })
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
     }
 
-    fn invalidate_handshake(&mut self) -> Result<()> {
-        if self.handshake_performed {
-            let address = format!("{}:{}", self.hostname, self.port);
-            let stream = TcpStream::connect(&address)
-                .with_context(|| format!("Failed to connect to {}", address))?;
-            self.reader = BufReader::new(stream.try_clone()?);
-            self.writer = BufWriter::new(stream.try_clone()?);
-            self.handshake_performed = true
+    // The packet ids and layouts this client speaks
+    pub fn features(&self) -> &ProtocolFeatures {
+        &self.features
+    }
+
+    // Packets skipped by ClientBuilder::filter_packets since the last
+    // connect, the totals across reconnects are in the metrics
+    pub fn filtered_packets(&self) -> u64 {
+        self.connection.filtered_packets()
+    }
+
+    fn set_state(&mut self, next: ConnectionState) -> Result<()> {
+        if !self.state.can_become(next) {
+            return Err(anyhow!("Can't go from {:?} to {:?}", self.state, next));
+        }
+        self.state = next;
+
+        Ok(())
+    }
+
+    fn require_state(&self, expected: ConnectionState) -> Result<()> {
+        match self.state == expected {
+            true => Ok(()),
+            false => Err(anyhow!(
+                "Needs the {:?} state, in {:?}",
+                expected,
+                self.state
+            )),
+        }
+    }
+
+    // What every new connection gets from the builder
+    fn setup_connection(&mut self) -> Result<()> {
+        self.connection.set_metrics(self.metrics.clone());
+        self.connection
+            .set_compression_level(self.compression_level);
+        self.connection.set_read_timeout(self.read_timeout)?;
+        self.connection.set_write_timeout(self.write_timeout)?;
+        self.connection.close_on_cancel(&self.shutdown);
+        Ok(())
+    }
+
+    // Handshakes only happen once per connection, so anything past that
+    // starts over on a new one with all state from the old one dropped
+    fn fresh_connection(&mut self) -> Result<()> {
+        self.check_closed()?;
+        if self.state != ConnectionState::Handshaking {
+            let stream = open_stream(
+                self.connector.as_mut(),
+                &self.hostname,
+                self.port,
+                self.proxy.as_ref(),
+                self.proxy_header.as_ref(),
+                self.connect_timeout,
+            )?;
+            self.connection = Connection::new(stream)?;
+            self.setup_connection()?;
+            self.events.clear();
+            self.players.clear();
+            self.entities.clear();
+            self.stats = PlayerStats::default();
+            self.scoreboard.clear();
+            self.boss_bars.clear();
+            self.chat_types = ChatTypes::default();
+            self.chat_limiter.clear();
+            self.position = None;
+            self.sneaking = false;
+            self.sprinting = false;
+            self.entity_id = None;
+            self.idle = false;
+            self.kicked = None;
+            self.queue_position = None;
+            self.latency.clear();
+            self.entities.set_paused(false);
+            self.state = ConnectionState::Handshaking;
+            self.history.record(StateChange::Connected {
+                hostname: self.hostname.clone(),
+                port: self.port,
+            });
         }
 
         Ok(())
     }
 
     pub fn login(&mut self) -> Result<()> {
-        self.invalidate_handshake()?;
+        self.login_as(NextState::Login)
+    }
 
-        let mut packet = Packet::new();
-        packet.write_varint(0x00)?; // protocol id
-        packet.write_varint(759)?; // protocol version
-        packet.write_string(&self.hostname)?; // hostname
-        packet.write_slice(&self.port.to_be_bytes()); // port
-        packet.write_varint(2)?;
+    fn login_as(&mut self, next_state: NextState) -> Result<()> {
+        self.fresh_connection()?;
+
+        let hostname = match &self.forwarding {
+            Some(forwarding) => forwarding.handshake_hostname(&self.hostname)?,
+            None => self.hostname.clone(),
+        };
 
-        self.send_packet(&packet)?; // Send Handshake with login as next state
-        self.handshake_performed = true;
+        let handshake = Handshake {
+            protocol_version: self.protocol_version,
+            hostname,
+            port: self.port,
+            next_state,
+        };
+        self.send_packet(&handshake.to_packet()?)?; // Send Handshake with login as next state
+        self.set_state(ConnectionState::Login)?;
+        self.history.record(StateChange::HandshakeSent(next_state));
+        self.login_phase(LoginPhase::HandshakeSent { next_state });
 
         let mut packet = Packet::new();
-        packet.write_varint(0x00)?; // Protocol ID
-        packet.write_string("extremq")?; // Username
-        packet.write_slice(&[0u8; 1]); // Has Sig Data
+        packet.write_varint(self.features.login_start_packet_id as i32)?; // Protocol ID
+        packet.write_string(&self.username)?; // Username
+        if self.features.has_login_signature {
+            packet.write_bool(false); // Has Sig Data
+        }
 
         self.send_packet(&packet)?; // Send login start
 
-        let mut response = self.block_until_packet_id(0x02)?; // Get login completed
-        println!("UUID: {:?}", response.read_slice(16)?); // Read UUID
-        println!("Username: {:?}", response.read_string()?); // Read Username
+        loop {
+            let mut response = self.read_packet()?;
 
+            match response.get_protocol_id() {
+                Some(id) if id == self.features.login_success_packet_id => {
+                    // Get login completed
+                    let profile = Profile::read(&mut response.reader())?;
+                    self.set_state(ConnectionState::Play)?;
+                    self.connection
+                        .set_packet_filter(self.packet_filter.clone());
+                    self.history.record(StateChange::LoggedIn {
+                        username: profile.name.clone(),
+                    });
+                    self.login_phase(LoginPhase::LoginSuccess {
+                        uuid: profile.uuid,
+                        name: profile.name.clone(),
+                    });
+                    self.profile = Some(profile);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.logged_in();
+                    }
+                    return Ok(());
+                }
+                Some(id) if id == self.features.compression_packet_id => {
+                    // Set compression, a negative threshold turns it off
+                    let threshold = usize::try_from(response.read_varint()?).ok();
+                    self.connection.set_compression(threshold);
+                    self.history.record(StateChange::CompressionSet(threshold));
+                    if let Some(threshold) = threshold {
+                        self.login_phase(LoginPhase::CompressionEnabled { threshold });
+                    }
+                }
+                Some(id) if id == self.features.encryption_request_packet_id => {
+                    // Encryption request, only sent by online mode servers
+                    return Err(match self.access_token {
+                        Some(_) => anyhow!(
+                            "Server requested encryption, which isn't supported yet even with a session"
+                        ),
+                        None => anyhow!(
+                            "Server requested encryption, online mode servers are not supported"
+                        ),
+                    });
+                }
+                Some(id) if id == self.features.login_plugin_request_packet_id => {
+                    self.handle_login_plugin_request(&response)?
+                }
+                Some(id) if id == self.features.login_disconnect_packet_id => {
+                    // Disconnect (login), e.g. whitelisted or banned
+                    let reason = Component::from_json(response.reader().read_str()?)?;
+                    self.set_state(ConnectionState::Closed)?;
+                    let kicked = Kicked {
+                        reason,
+                        during_login: true,
+                    };
+                    self.kicked = Some(kicked.clone());
+                    return Err(kicked.into());
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    fn send_client_information(&mut self) -> Result<()> {
+        if let Some(brand) = &self.brand {
+            let packet = client_information::brand_packet(&self.features, brand)?;
+            self.send_packet(&packet)?;
+        }
+        if let Some(information) = &self.client_information {
+            let packet = information.to_packet(&self.features)?;
+            self.send_packet(&packet)?;
+        }
         Ok(())
     }
 
+    fn login_phase(&mut self, phase: LoginPhase) {
+        if let Some(hook) = &mut self.login_phase_hook {
+            hook(&phase);
+        }
+        self.events.push_back(Event::LoginPhase(phase));
+    }
+
+    fn handle_login_plugin_request(&mut self, request: &Packet) -> Result<()> {
+        let mut reader = request.reader();
+        let message_id = reader.read_varint()?;
+        let channel = reader.read_str()?;
+        let data = reader.remaining();
+
+        let response = match (&self.forwarding, &mut self.login_plugin_handler) {
+            (Some(forwarding), _) if channel == VELOCITY_CHANNEL => {
+                forwarding.velocity_response(&self.username, data)?
+            }
+            (_, Some(handler)) => handler(channel, data),
+            _ => None,
+        };
+
+        // Servers wait for an answer to every request, so anything we don't
+        // understand still gets an unsuccessful response
+        let mut packet = Packet::new();
+        packet.write_varint(self.features.login_plugin_response_packet_id as i32)?; // Protocol ID
+        packet.write_varint(message_id)?; // Message ID
+        packet.write_bool(response.is_some()); // Successful
+        if let Some(response) = response {
+            packet.write_slice(&response); // Data
+        }
+
+        self.send_packet(&packet)
+    }
+
     pub fn status(&mut self) -> Result<String> {
-        self.invalidate_handshake()?;
+        let packet = self.request_status()?;
+        let mut reader = packet.reader();
+        match self.lenient_status {
+            true => Ok(reader.read_str_lossy()?.into_owned()),
+            false => Ok(reader.read_str()?.to_owned()),
+        }
+    }
+
+    pub fn server_status(&mut self) -> Result<ServerStatus> {
+        Ok(self.server_status_report()?.status)
+    }
+
+    // Also lists what had to be repaired when lenient status parsing is on
+    pub fn server_status_report(&mut self) -> Result<StatusReport> {
+        let packet = self.request_status()?;
+        self.parse_status(&packet)
+    }
+
+    // Round trip time of a status ping. The server closes the connection
+    // afterwards, like after any status exchange.
+    pub fn ping(&mut self) -> Result<Duration> {
+        self.request_status()?;
+        self.ping_after_status()
+    }
 
+    // Status and ping over one connection, the way the multiplayer list does it
+    pub fn status_with_ping(&mut self) -> Result<(StatusReport, Duration)> {
+        let packet = self.request_status()?;
+        let report = self.parse_status(&packet)?;
+        Ok((report, self.ping_after_status()?))
+    }
+
+    fn parse_status(&self, packet: &Packet) -> Result<StatusReport> {
+        let mut reader = packet.reader();
+        match self.lenient_status {
+            true => ServerStatus::parse_lenient(reader.read_byte_array()?),
+            false => Ok(StatusReport {
+                status: ServerStatus::parse(reader.read_str()?)?,
+                fixes: Vec::new(),
+            }),
+        }
+    }
+
+    fn ping_after_status(&mut self) -> Result<Duration> {
+        let payload: i64 = self.rng.random();
         let mut packet = Packet::new();
-        packet.write_varint(0x00)?; // protocol id
-        packet.write_varint(759)?; // protocol version
-        packet.write_string(&self.hostname)?; // hostname
-        packet.write_slice(&self.port.to_be_bytes()); // port
-        packet.write_varint(1)?;
+        packet.write_varint(0x01)?; // Protocol ID
+        packet.write_slice(&payload.to_be_bytes()); // Payload
 
-        self.send_packet(&packet)?; // Send Handshake with login as next state
-        self.handshake_performed = true;
+        let sent = Instant::now();
+        self.send_packet(&packet)?;
+        let pong = self.block_until_packet_id(0x01, None)?;
+        let elapsed = sent.elapsed();
+
+        self.set_state(ConnectionState::Closed)?;
+
+        if pong.reader().read_i64()? != payload {
+            return Err(anyhow!("Server answered the ping with a different payload"));
+        }
+
+        Ok(elapsed)
+    }
+
+    // Applies to reads on the current connection only
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.connection.set_read_timeout(timeout)
+    }
+
+    // Returns the status response packet
+    fn request_status(&mut self) -> Result<Packet> {
+        self.fresh_connection()?;
+
+        let handshake = Handshake {
+            protocol_version: self.protocol_version,
+            hostname: self.hostname.clone(),
+            port: self.port,
+            next_state: NextState::Status,
+        };
+        self.send_packet(&handshake.to_packet()?)?; // Send Handshake with status as next state
+        self.set_state(ConnectionState::Status)?;
+        self.history
+            .record(StateChange::HandshakeSent(NextState::Status));
 
         let mut packet = Packet::new();
         packet.write_varint(0x00)?; // Protocol ID
 
         self.send_packet(&packet)?; // Send status packet
 
-        let mut packet = self.block_until_packet_id(0x00)?;
-        Ok(packet.read_string()?)
+        self.block_until_packet_id(0x00, None)
     }
 
-    pub fn send_chat_message(&mut self) -> Result<()> {
-        let mut packet = Packet::new();
-        packet.write_varint(0x04)?; // protocol id
-        packet.write_string("salut baietii")?; // Message
-        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
-        packet.write_slice(&timestamp_ms.to_be_bytes()); // timestamp
-        packet.write_slice(&[0u8; 8]); // salt
-        packet.write_slice(&[0u8; 1]); // signature length
-        packet.write_slice(&[0u8; 1]); // signed preview
+    // Messages are sent unsigned, servers enforcing secure chat will refuse them
+    pub fn send_chat_message(&mut self, message: &str) -> Result<SendResult> {
+        self.require_state(ConnectionState::Play)?;
+        let packet = chat_message(&self.features, message, self.rng.random())?;
+        self.send_limited(packet)
+    }
 
-        self.send_packet(&packet)?;
+    // `command` without the leading slash
+    pub fn send_command(&mut self, command: &str) -> Result<SendResult> {
+        self.require_state(ConnectionState::Play)?;
+        let packet = chat_command(&self.features, command, self.rng.random())?;
+        self.send_limited(packet)
+    }
+
+    fn send_limited(&mut self, packet: Packet) -> Result<SendResult> {
+        self.send_queued_chat()?;
+        match self.chat_limiter.submit(packet) {
+            Some(packet) => {
+                self.send_chat_packet(&packet)?;
+                Ok(SendResult::Sent)
+            }
+            None => Ok(SendResult::Queued),
+        }
+    }
+
+    fn send_queued_chat(&mut self) -> Result<()> {
+        while let Some(packet) = self.chat_limiter.pop_ready() {
+            self.send_chat_packet(&packet)?;
+        }
 
         Ok(())
     }
 
-    pub fn send_packet(&mut self, packet: &Packet) -> Result<()> {
-        let mut length = Packet::new();
-        length.write_varint(packet.buffer.len() as i32)?;
+    fn send_chat_packet(&mut self, packet: &Packet) -> Result<()> {
+        self.send_packet(packet)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.chat_sent();
+        }
+
+        Ok(())
+    }
+
+    // Text of any length, as many messages as it takes to fit the limit per
+    // message, see split_chat_message. They go out in order at the chat rate.
+    pub fn send_chat_multiline(&mut self, text: &str) -> Result<Vec<SendResult>> {
+        split_chat_message(text, &self.continuation)
+            .iter()
+            .map(|message| self.send_chat_message(message))
+            .collect()
+    }
 
-        self.writer.write(&length.buffer)?;
-        self.writer.write(&packet.buffer)?;
-        self.writer.flush()?;
+    // Messages and commands waiting for the chat rate to allow them
+    pub fn queued_chat(&self) -> usize {
+        self.chat_limiter.queued()
+    }
+
+    // Moves the client to a thread of its own that answers keep alives and
+    // teleports, with events coming out of a channel. Log in first.
+    pub fn spawn_background(self) -> Result<BackgroundClient> {
+        self.require_state(ConnectionState::Play)?;
+        Ok(BackgroundClient::spawn(self))
+    }
+
+    // A private message through /msg, which every server has
+    pub fn send_whisper(&mut self, player: &str, message: &str) -> Result<SendResult> {
+        if player.is_empty() || player.contains(char::is_whitespace) {
+            return Err(anyhow!("{:?} is not a player name", player));
+        }
+        self.send_command(&format!("msg {} {}", player, message))
+    }
+
+    // Splits off a writer that sends from any thread while this client, as
+    // the reader, keeps handling what comes in. Only once logged in, the
+    // writer can't follow compression changes.
+    pub fn split(self) -> Result<(ClientReader, ClientWriter)> {
+        self.require_state(ConnectionState::Play)?;
+        let writer = ClientWriter::new(
+            self.connection.writer(),
+            self.features,
+            self.shutdown.clone(),
+        );
+        Ok((ClientReader::new(self), writer))
+    }
 
-        println!("Sent: {:?} {:?}", length.buffer, packet.buffer);
+    pub fn send_packet(&mut self, packet: &Packet) -> Result<()> {
+        self.check_closed()?;
+        if self.send_hooks.is_empty() {
+            return self.connection.send_packet(packet);
+        }
+        let mut packet = packet.clone();
+        if run_hooks(&mut self.send_hooks, &mut packet) {
+            self.connection.send_packet(&packet)?;
+        }
 
         Ok(())
     }
 
-    pub fn block_until_packet_id(&mut self, packet_id: u8) -> Result<Packet> {
-        println!("waiting for {}", packet_id);
+    // Keep alives come out of next_event as packets, answering them is up to
+    // the caller unless spawn_background does it. False for any other packet.
+    pub fn answer_keep_alive(&mut self, packet: &Packet) -> Result<bool> {
+        if packet.get_protocol_id() != Some(self.features.keep_alive_packet_id) {
+            return Ok(false);
+        }
+        let id = packet.reader().read_i64()?;
+        self.send_packet(&keep_alive(&self.features, id)?)?;
+
+        Ok(true)
+    }
+
+    // Average round trip of the last LATENCY_WINDOW measurements the server
+    // reported in the tab list, None until the first one
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency.average()
+    }
+
+    // The most recent measurement on its own
+    pub fn last_latency(&self) -> Option<Duration> {
+        self.latency.last()
+    }
+
+    // Fails if the packet doesn't come within `timeout`, None waits forever
+    pub fn block_until_packet_id(
+        &mut self,
+        packet_id: u8,
+        timeout: Option<Duration>,
+    ) -> Result<Packet> {
+        self.block_until_packet(timeout, |packet| {
+            packet.get_protocol_id() == Some(packet_id)
+        })?
+        .ok_or_else(|| anyhow!("Packet {:#04x} didn't arrive in time", packet_id))
+    }
+
+    // Reads until a packet passes `predicate` and returns it, or None once
+    // `timeout` is up. Nothing read on the way is lost: in the play state
+    // it's handled as usual, before that it's queued as Event::Packet, and
+    // either way it comes out of next_event later.
+    pub fn block_until_packet(
+        &mut self,
+        timeout: Option<Duration>,
+        mut predicate: impl FnMut(&Packet) -> bool,
+    ) -> Result<Option<Packet>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
         loop {
-            let packet = match self.read_packet()? {
-                None => continue,
-                Some(val) => val,
-            };
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if !self.connection.wait_readable(remaining)? {
+                    return Ok(None);
+                }
+            }
+            let packet = self.read_packet()?;
 
-            let id = match packet.get_protocol_id() {
-                None => continue,
-                Some(val) => val,
-            };
+            if predicate(&packet) {
+                return Ok(Some(packet));
+            }
+            match self.state {
+                ConnectionState::Play => self.handle_packet(packet)?,
+                _ => self.events.push_back(Event::Packet(packet)),
+            }
+        }
+    }
+
+    // None right away if no packet has started arriving yet
+    pub fn try_read_packet(&mut self) -> Result<Option<Packet>> {
+        self.read_packet_timeout(Duration::ZERO)
+    }
+
+    // None if no packet started arriving within `timeout`. One that did is
+    // read to its end, however long the rest takes, and so is the next one
+    // if a receive hook drops it.
+    pub fn read_packet_timeout(&mut self, timeout: Duration) -> Result<Option<Packet>> {
+        match self.connection.wait_readable(timeout)? {
+            true => Ok(Some(self.read_packet()?)),
+            false => Ok(None),
+        }
+    }
+
+    pub fn read_packet(&mut self) -> Result<Packet> {
+        let mut packet = Packet::new();
+        self.read_packet_into(&mut packet)?;
+
+        Ok(packet)
+    }
+
+    pub fn players(&self) -> &HashMap<Uuid, PlayerInfo> {
+        self.players.players()
+    }
+
+    pub fn player_list(&self) -> &PlayerList {
+        &self.players
+    }
+
+    pub fn entities(&self) -> &EntityTracker {
+        &self.entities
+    }
+
+    pub fn stats(&self) -> PlayerStats {
+        self.stats
+    }
+
+    // Asks the server how `partial` could continue, like pressing tab in
+    // the chat box. Commands need their leading slash. Packets that arrive
+    // while waiting are handled as usual and queued for next_event.
+    pub fn tab_complete(&mut self, partial: &str) -> Result<Vec<Suggestion>> {
+        if partial.len() > MAX_COMPLETION_LENGTH {
+            return Err(anyhow!(
+                "Text to complete is longer than {} bytes",
+                MAX_COMPLETION_LENGTH
+            ));
+        }
 
-            if id == packet_id {
-                return Ok(packet);
+        self.next_transaction_id = self.next_transaction_id.wrapping_add(1);
+        let transaction_id = self.next_transaction_id;
+        self.send_packet(&completion::request(
+            &self.features,
+            transaction_id,
+            partial,
+        )?)?;
+
+        let deadline = Instant::now() + COMPLETION_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !self.connection.wait_readable(remaining)? {
+                return Err(anyhow!("Server didn't answer the completion request"));
+            }
+
+            let packet = self.read_packet()?;
+            if packet.get_protocol_id() == Some(self.features.command_suggestions_packet_id) {
+                let (id, suggestions) = completion::parse_response(&packet)?;
+                if id == transaction_id {
+                    return Ok(suggestions);
+                }
+            }
+            self.handle_packet(packet)?;
+        }
+    }
+
+    pub fn scoreboard(&self) -> &Scoreboard {
+        &self.scoreboard
+    }
+
+    pub fn boss_bars(&self) -> &BossBars {
+        &self.boss_bars
+    }
+
+    // Reads packets until one of them produces an event. Packets nothing
+    // tracks are passed through as Event::Packet.
+    pub fn next_event(&mut self) -> Result<Event> {
+        loop {
+            if let Some(event) = self.pop_event()? {
+                return Ok(event);
+            }
+
+            if self.shutdown.is_cancelled() {
+                return Err(anyhow!("Client was shut down"));
             }
+            self.check_kicked()?;
+            self.check_closed()?;
+
+            self.send_finished_work()?;
+            self.send_queued_chat()?;
+            let chat_wait = self.chat_limiter.wait();
+            if (self.position_updates || chat_wait.is_some()) && !self.wait_for_packet(chat_wait)? {
+                continue;
+            }
+
+            self.read_and_handle()?;
+        }
+    }
+
+    fn pop_event(&mut self) -> Result<Option<Event>> {
+        let event = self.events.pop_front();
+        if let (Some(logger), Some(event)) = (&mut self.chat_logger, &event) {
+            logger.log(event)?;
+        }
+        Ok(event)
+    }
+
+    // Reads one packet and handles it, failures carry the state history
+    fn read_and_handle(&mut self) -> Result<()> {
+        let result = self
+            .read_packet()
+            .and_then(|packet| self.handle_packet(packet));
+        if let Err(error) = result {
+            // The read failing is expected once the socket was closed for shutdown
+            if self.shutdown.is_cancelled() {
+                return Err(anyhow!("Client was shut down"));
+            }
+            return Err(error.context(format!(
+                "Client state history (oldest first):\n{}",
+                self.history.dump()
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Once kicked there's nothing left to read, only the reason to report
+    fn check_kicked(&self) -> Result<()> {
+        match &self.kicked {
+            Some(kicked) => Err(kicked.clone().into()),
+            None => Ok(()),
+        }
+    }
+
+    fn check_closed(&self) -> Result<()> {
+        match &self.closed {
+            Some(closed) => Err(closed.clone().into()),
+            None => Ok(()),
         }
     }
 
-    pub fn read_packet(&mut self) -> Result<Option<Packet>> {
-        let mut response = Packet::new();
+    // Ends the session from our side: whatever is still buffered goes out,
+    // then the stream is closed and the client is Closed for good. Every
+    // call after fails with AlreadyClosed, this one included. The protocol
+    // has no way to tell the server why, so `reason` only goes into the
+    // state history and the error. Chat held back by the rate limit is
+    // dropped, not sent.
+    pub fn disconnect(&mut self, reason: &str) -> Result<()> {
+        self.check_closed()?;
+        let result = self.connection.close();
+        self.state = ConnectionState::Closed;
+        self.events.clear();
+        self.chat_limiter.clear();
+        self.queue_position = None;
+        self.history.record(StateChange::Disconnected {
+            reason: String::from(reason),
+        });
+        self.closed = Some(AlreadyClosed {
+            reason: String::from(reason),
+        });
+        result
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.closed.is_some()
+    }
+
+    // Why the server last kicked us, until the next connection
+    pub fn kicked(&self) -> Option<&Kicked> {
+        self.kicked.as_ref()
+    }
+
+    // Stops next_event, from this or any other thread holding the token.
+    // Everything else sharing the token is cancelled too.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        self.shutdown.clone()
+    }
+
+    // Like next_event, but gives up after `timeout` and returns None, for
+    // loops that have other things to do in between
+    pub fn poll_event(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        let deadline = Instant::now() + timeout;
         loop {
-            let mut byte = [0u8];
-            self.reader.read_exact(&mut byte)?;
+            if let Some(event) = self.pop_event()? {
+                return Ok(Some(event));
+            }
+            if self.shutdown.is_cancelled() {
+                return Err(anyhow!("Client was shut down"));
+            }
+            self.check_kicked()?;
+            self.check_closed()?;
 
-            response.buffer.extend_from_slice(&byte);
-            if response.buffer.len() > 5 {
+            self.send_finished_work()?;
+            self.send_queued_chat()?;
+            let mut wait = deadline.saturating_duration_since(Instant::now());
+            if let Some(chat_wait) = self.chat_limiter.wait() {
+                wait = wait.min(chat_wait);
+            }
+            if self.position_updates {
+                if self.last_position_update.elapsed() >= TICK_INTERVAL {
+                    self.tick()?;
+                }
+                wait = wait.min(TICK_INTERVAL.saturating_sub(self.last_position_update.elapsed()));
+            }
+
+            if self.connection.wait_readable(wait)? {
+                self.read_and_handle()?;
+            } else if Instant::now() >= deadline {
                 return Ok(None);
             }
+        }
+    }
+
+    // Our own UUID, known once logged in
+    pub fn uuid(&self) -> Option<Uuid> {
+        self.profile.as_ref().map(|profile| profile.uuid)
+    }
+
+    // Who the server logged us in as, which may differ from the username
+    // we asked for
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
 
-            if byte[0] as i32 & VARINT_CONTINUE_BIT == 0 {
-                break;
+    // As the server sent them at login, vanilla's until then
+    pub fn chat_types(&self) -> &ChatTypes {
+        &self.chat_types
+    }
+
+    pub fn history(&self) -> &StateHistory {
+        &self.history
+    }
+
+    // True while pause_when_idle has paused us because nobody else is online.
+    // Relays can check it to skip their own expensive work too.
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    fn set_idle(&mut self, idle: bool) {
+        if idle == self.idle {
+            return;
+        }
+        self.idle = idle;
+        self.entities.set_paused(idle);
+        self.history.record(StateChange::Idle(idle));
+        self.events
+            .push_back(if idle { Event::Idle } else { Event::Resumed });
+    }
+
+    fn handle_packet(&mut self, packet: Packet) -> Result<()> {
+        // Ids from the features table come first, so they win over the fixed
+        // ones below if a release reuses one of those
+        let features = self.features;
+        match packet.get_protocol_id() {
+            Some(id) if id == features.login_play_packet_id => {
+                // Login (play), our entity id is needed for player commands
+                self.entity_id = Some(packet.reader().read_i32()?);
+                self.chat_types = ChatTypes::from_codec(&registry::login_registry_codec(&packet)?)?;
+                self.entities.clear();
+                self.events.push_back(Event::Packet(packet));
+                // Like vanilla, which waits for this packet before sending them
+                self.send_client_information()?;
+                self.leave_queue()?;
+            }
+            Some(id) if id == features.player_chat_packet_id => {
+                // Player chat
+                let message = ChatMessage::from_packet(&packet, &self.chat_types)?;
+                if let Some(metrics) = &self.metrics {
+                    metrics.chat_received();
+                }
+                if Some(message.sender) != self.uuid() {
+                    let answers = self.chat_rules.evaluate(
+                        Some(&message.sender_name.to_plain()),
+                        &message.content.to_plain(),
+                    );
+                    self.send_answers(answers)?;
+                    let replies = self.responder.respond(&message);
+                    self.send_answers(replies)?;
+                }
+                self.events.push_back(Event::ChatMessage(Box::new(message)));
+            }
+            Some(id) if id == features.system_chat_packet_id => {
+                // System chat
+                let mut reader = packet.reader();
+                let message = Component::from_json(reader.read_str()?)?;
+                let overlay = reader.read_varint()? == 2; // 2 is game info, above the hotbar
+                if !overlay {
+                    let answers = self.chat_rules.evaluate(None, &message.to_plain());
+                    self.send_answers(answers)?;
+                }
+                let plain = message.to_plain();
+                self.events.push_back(Event::SystemMessage {
+                    category: MessageCategory::classify(&message),
+                    message,
+                    overlay,
+                });
+                self.check_queue(&plain)?;
+            }
+            Some(id) if Some(id) == features.transfer_packet_id => self.handle_transfer(&packet)?,
+            Some(id) if id == features.disconnect_packet_id => {
+                // Disconnect (play), the reason comes out as an event and
+                // then as the error of every read after it
+                let reason = Component::from_json(packet.reader().read_str()?)?;
+                self.set_state(ConnectionState::Closed)?;
+                self.kicked = Some(Kicked {
+                    reason: reason.clone(),
+                    during_login: false,
+                });
+                self.events.push_back(Event::Disconnected { reason });
+            }
+            Some(id) if id == features.player_info_packet_id => {
+                // Player info
+                let count = self.players.players().len();
+                let events = self.players.handle_player_info(&packet)?;
+                self.events.extend(events);
+                if self.players.players().len() != count {
+                    self.history
+                        .record(StateChange::PlayerCount(self.players.players().len()));
+                }
+                let us = self
+                    .uuid()
+                    .and_then(|uuid| self.players.players().get(&uuid));
+                if let Some(us) = us {
+                    self.latency.reported(us.latency);
+                }
+                if let Some(metrics) = &self.metrics {
+                    metrics.set_online_players(self.players.players().len());
+                    if let Some(us) = us {
+                        metrics.set_ping(us.latency);
+                    }
+                }
+                if self.pause_when_idle {
+                    let others = self
+                        .players
+                        .players()
+                        .keys()
+                        .filter(|uuid| Some(**uuid) != self.uuid())
+                        .count();
+                    self.set_idle(others == 0);
+                }
+            }
+            Some(id) if id == features.combat_death_packet_id => {
+                // Combat death
+                let mut reader = packet.reader();
+                reader.read_varint()?; // player id
+                reader.read_i32()?; // killer entity id
+                let message = Component::from_json(reader.read_str()?)?;
+                self.handle_death(message)?;
+            }
+            Some(id) if id == features.boss_bar_packet_id => {
+                // Boss bar
+                let event = self.boss_bars.handle_boss_bar(&packet)?;
+                self.events.extend(event);
+            }
+            Some(id) if id == features.display_objective_packet_id => {
+                self.scoreboard.handle_display(&packet)?
+            }
+            Some(id) if id == features.update_objectives_packet_id => {
+                self.scoreboard.handle_objective(&packet)?
+            }
+            Some(id) if id == features.update_score_packet_id => {
+                // Update score
+                let events = self.scoreboard.handle_score(&packet)?;
+                self.events.extend(events);
+            }
+            Some(id) if id == features.clear_titles_packet_id => {
+                // Clear titles
+                let reset = packet.reader().read_bool()?;
+                self.events.push_back(Event::TitlesCleared { reset });
+            }
+            Some(id) if id == features.action_bar_packet_id => {
+                // Action bar
+                let text = Component::from_json(packet.reader().read_str()?)?;
+                let plain = text.to_plain();
+                self.events.push_back(Event::ActionBar(text));
+                self.check_queue(&plain)?;
+            }
+            Some(id) if id == features.subtitle_packet_id => {
+                // Subtitle
+                let text = Component::from_json(packet.reader().read_str()?)?;
+                self.events.push_back(Event::Subtitle(text));
+            }
+            Some(id) if id == features.title_packet_id => {
+                // Title
+                let text = Component::from_json(packet.reader().read_str()?)?;
+                self.events.push_back(Event::Title(text));
+            }
+            Some(id) if id == features.title_times_packet_id => {
+                // Title animation times
+                let mut reader = packet.reader();
+                self.events.push_back(Event::TitleTimes {
+                    fade_in: reader.read_i32()?,
+                    stay: reader.read_i32()?,
+                    fade_out: reader.read_i32()?,
+                });
+            }
+            Some(id) if id == features.synchronize_position_packet_id => {
+                self.handle_teleport(&packet)?
+            }
+            Some(id) if id == features.resource_pack_packet_id => {
+                self.handle_resource_pack(&packet)?
+            }
+            Some(id) if id == features.respawn_packet_id => {
+                // Respawn, also sent on dimension changes
+                if self.dead {
+                    self.history.record(StateChange::Respawned);
+                }
+                self.dead = false;
+                self.entities.clear();
+                self.events.push_back(Event::Packet(packet));
+                // Proxies move us off the queue server with a respawn
+                self.leave_queue()?;
+            }
+            Some(id) if id == features.set_health_packet_id => {
+                // Set health, a non-positive health is the only death signal on some servers
+                let previous = self.stats;
+                self.stats.apply_health(&packet)?;
+                self.events.push_back(Event::HealthChanged {
+                    previous,
+                    current: self.stats,
+                });
+                if self.stats.is_starving() && !previous.is_starving() {
+                    self.events.push_back(Event::Starving);
+                }
+
+                if self.stats.health > 0.0 {
+                    if self.dead {
+                        self.history.record(StateChange::Respawned);
+                    }
+                    self.dead = false;
+                } else if !self.dead {
+                    self.handle_death(Component::default())?;
+                }
+            }
+            Some(id) if id == features.set_experience_packet_id => {
+                self.stats.apply_experience(&packet)?
+            }
+            _ if self.entities.handle_packet(&packet, &features)? => {}
+            _ => self.handle_untracked_packet(packet)?,
+        }
+
+        Ok(())
+    }
+
+    fn handle_untracked_packet(&mut self, packet: Packet) -> Result<()> {
+        let id = packet.get_protocol_id();
+        if id.is_some_and(|id| self.expected_packets.get(id as usize)) {
+            self.events.push_back(Event::Packet(packet));
+            return Ok(());
+        }
+        match self.decode_mode {
+            DecodeMode::Lenient => self.events.push_back(Event::UnknownPacket(packet)),
+            DecodeMode::Strict => {
+                return Err(UnexpectedPacket {
+                    id,
+                    length: packet.buffer.len().saturating_sub(packet.cursor),
+                }
+                .into())
+            }
+        }
+        Ok(())
+    }
+
+    // The new server gets the same username, forwarding and session. With a
+    // connector set the transfer is refused, it only dials the old server.
+    fn handle_transfer(&mut self, packet: &Packet) -> Result<()> {
+        let mut reader = packet.reader();
+        let host = String::from(reader.read_str()?);
+        let port = u16::try_from(reader.read_varint()?)
+            .map_err(|_| anyhow!("Transfer to an invalid port"))?;
+        if !self.follow_transfers {
+            self.events.push_back(Event::Packet(packet.clone()));
+            return Ok(());
+        }
+
+        if self.connector.is_some() {
+            return Err(anyhow!(
+                "Can't follow the transfer to {}:{}, the connector only dials the original server",
+                host,
+                port
+            ));
+        }
+
+        self.hostname = host.clone();
+        self.port = port;
+        self.login_as(NextState::Transfer)
+            .with_context(|| format!("Failed to follow the transfer to {}:{}", host, port))?;
+        self.events.push_back(Event::Transferred { host, port });
+        Ok(())
+    }
+
+    // A queue plugin told us our position. Queues can take hours, so reads
+    // don't time out while waiting in one, keep alives are answered as always.
+    fn check_queue(&mut self, text: &str) -> Result<()> {
+        let position = match self.queue.detect(text) {
+            Some(position) => position,
+            None => return Ok(()),
+        };
+        if self.queue_position.is_none() {
+            self.connection.set_read_timeout(None)?;
+        }
+        if self.queue_position.as_ref() != Some(&position) {
+            self.queue_position = Some(position.clone());
+            self.events.push_back(Event::QueuePosition(position));
+        }
+        Ok(())
+    }
+
+    fn leave_queue(&mut self) -> Result<()> {
+        if self.queue_position.take().is_none() {
+            return Ok(());
+        }
+        self.connection.set_read_timeout(self.read_timeout)?;
+        self.events.push_back(Event::Joined);
+        Ok(())
+    }
+
+    // Where we are in the server's join queue, None when not waiting in one
+    pub fn queue_position(&self) -> Option<&QueuePosition> {
+        self.queue_position.as_ref()
+    }
+
+    fn send_answers(&mut self, answers: Vec<String>) -> Result<()> {
+        for answer in answers {
+            match answer.strip_prefix('/') {
+                Some(command) => self.send_command(command)?,
+                None => self.send_chat_message(&answer)?,
+            };
+        }
+        Ok(())
+    }
+
+    // Answers of the responder's spawned work
+    fn send_finished_work(&mut self) -> Result<()> {
+        if self.state() != ConnectionState::Play {
+            return Ok(());
+        }
+        let answers = self.responder.finished();
+        self.send_answers(answers)
+    }
+
+    // Servers kick clients that leave a teleport unconfirmed, so we answer it
+    // like vanilla does: confirm, then report the position we ended up at
+    fn handle_teleport(&mut self, packet: &Packet) -> Result<()> {
+        let teleport = movement::Teleport::from_packet(packet, self.position.unwrap_or_default())?;
+        self.position = Some(teleport.position);
+        self.history.record(StateChange::Moved(teleport.position));
+
+        self.send_packet(&movement::confirm_teleportation(
+            &self.features,
+            teleport.teleport_id,
+        )?)?;
+        self.send_packet(&movement::position_and_rotation(
+            &self.features,
+            &teleport.position,
+            false,
+        )?)?;
+
+        self.events.push_back(Event::Teleported(teleport.position));
+        Ok(())
+    }
+
+    // None until the server has placed us somewhere
+    pub fn position(&self) -> Option<PlayerPosition> {
+        self.position
+    }
+
+    // Ticks until a packet starts arriving, returns false if none did before
+    // the next tick or queued chat message was due
+    fn wait_for_packet(&mut self, chat_wait: Option<Duration>) -> Result<bool> {
+        let mut wait = chat_wait.unwrap_or(Duration::MAX);
+        if self.position_updates {
+            if self.last_position_update.elapsed() >= TICK_INTERVAL {
+                self.tick()?;
             }
+            wait = wait.min(TICK_INTERVAL.saturating_sub(self.last_position_update.elapsed()));
+        }
+
+        self.connection.wait_readable(wait)
+    }
+
+    // Reports where we are to the server. Called every tick when position
+    // updates are enabled, does nothing before the server has placed us.
+    pub fn tick(&mut self) -> Result<()> {
+        self.last_position_update = Instant::now();
+        match self.position {
+            Some(position) => self.send_packet(&movement::position_and_rotation(
+                &self.features,
+                &position,
+                self.on_ground,
+            )?),
+            None => Ok(()),
+        }
+    }
+
+    fn current_position(&self) -> Result<PlayerPosition> {
+        self.position
+            .ok_or_else(|| anyhow!("The server hasn't told us where we are yet"))
+    }
+
+    pub fn set_position(&mut self, position: PlayerPosition) -> Result<()> {
+        self.position = Some(position);
+        self.last_position_update = Instant::now();
+        self.send_packet(&movement::position_and_rotation(
+            &self.features,
+            &position,
+            self.on_ground,
+        )?)
+    }
+
+    // Servers reject moves of more than ~10 blocks per packet, longer trips
+    // have to be split up by the caller
+    pub fn move_to(&mut self, x: f64, y: f64, z: f64) -> Result<()> {
+        let position = PlayerPosition {
+            x,
+            y,
+            z,
+            ..self.current_position()?
+        };
+        self.position = Some(position);
+        self.last_position_update = Instant::now();
+        self.send_packet(&movement::position(
+            &self.features,
+            &position,
+            self.on_ground,
+        )?)
+    }
+
+    pub fn look_at(&mut self, x: f64, y: f64, z: f64) -> Result<()> {
+        let position = self.current_position()?.looking_at(x, y, z);
+        self.position = Some(position);
+        self.last_position_update = Instant::now();
+        self.send_packet(&movement::rotation(
+            &self.features,
+            &position,
+            self.on_ground,
+        )?)
+    }
+
+    pub fn set_on_ground(&mut self, on_ground: bool) {
+        self.on_ground = on_ground;
+    }
+
+    pub fn is_sneaking(&self) -> bool {
+        self.sneaking
+    }
+
+    pub fn set_sneaking(&mut self, sneaking: bool) -> Result<()> {
+        if sneaking == self.sneaking {
+            return Ok(());
+        }
+
+        let command = if sneaking {
+            movement::PlayerCommand::StartSneaking
+        } else {
+            movement::PlayerCommand::StopSneaking
+        };
+        self.send_player_command(command)?;
+        self.sneaking = sneaking;
+        Ok(())
+    }
+
+    pub fn is_sprinting(&self) -> bool {
+        self.sprinting
+    }
+
+    pub fn set_sprinting(&mut self, sprinting: bool) -> Result<()> {
+        if sprinting == self.sprinting {
+            return Ok(());
+        }
+
+        let command = if sprinting {
+            movement::PlayerCommand::StartSprinting
+        } else {
+            movement::PlayerCommand::StopSprinting
+        };
+        self.send_player_command(command)?;
+        self.sprinting = sprinting;
+        Ok(())
+    }
+
+    fn send_player_command(&mut self, command: movement::PlayerCommand) -> Result<()> {
+        let entity_id = self
+            .entity_id
+            .ok_or_else(|| anyhow!("Not in the play state yet"))?;
+        self.send_packet(&movement::player_command(
+            &self.features,
+            entity_id,
+            command,
+        )?)
+    }
+
+    fn handle_death(&mut self, message: Component) -> Result<()> {
+        let already_dead = self.dead;
+        self.dead = true;
+        if !already_dead {
+            self.history.record(StateChange::Died);
+        }
+        self.events.push_back(Event::Died { message });
+
+        if self.auto_respawn && !already_dead {
+            self.respawn()?;
         }
-        let payload_length = response.read_varint()? as usize;
-        response
-            .buffer
-            .resize(response.buffer.len() + payload_length, 0);
 
-        self.reader
-            .read_exact(&mut response.buffer[response.cursor..])?;
-        response.read_protocol_id()?;
+        Ok(())
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.dead
+    }
+
+    pub fn respawn(&mut self) -> Result<()> {
+        let mut packet = Packet::new();
+        packet.write_varint(self.features.client_command_packet_id as i32)?; // Protocol ID
+        packet.write_varint(0)?; // Action: perform respawn
+
+        self.send_packet(&packet)
+    }
 
-        Ok(Some(response))
+    fn handle_resource_pack(&mut self, packet: &Packet) -> Result<()> {
+        let request = ResourcePackRequest::from_packet(packet)?;
+
+        // Downloads block the connection, big packs may outlast the keep-alive
+        let mut error = None;
+        let status = match &self.resource_pack_policy {
+            ResourcePackPolicy::Decline => ResourcePackStatus::Declined,
+            ResourcePackPolicy::Accept => {
+                self.send_resource_pack_status(ResourcePackStatus::Accepted)?;
+                ResourcePackStatus::SuccessfullyLoaded
+            }
+            ResourcePackPolicy::AcceptAndDownload(directory) => {
+                let directory = directory.clone();
+                self.send_resource_pack_status(ResourcePackStatus::Accepted)?;
+                match download_resource_pack(&self.http, &request, &directory) {
+                    Ok(_) => ResourcePackStatus::SuccessfullyLoaded,
+                    Err(failure) => {
+                        error = Some(format!("{:#}", failure));
+                        ResourcePackStatus::FailedDownload
+                    }
+                }
+            }
+        };
+        self.send_resource_pack_status(status)?;
+
+        self.events.push_back(Event::ResourcePack {
+            request,
+            status,
+            error,
+        });
+        Ok(())
+    }
+
+    pub fn send_resource_pack_status(&mut self, status: ResourcePackStatus) -> Result<()> {
+        let mut packet = Packet::new();
+        packet.write_varint(self.features.resource_pack_status_packet_id as i32)?; // Protocol ID
+        packet.write_varint(status as i32)?; // Result
+
+        self.send_packet(&packet)
+    }
+
+    pub fn read_packet_into(&mut self, packet: &mut Packet) -> Result<()> {
+        self.check_closed()?;
+        loop {
+            self.connection.read_packet_into(packet)?;
+            if run_hooks(&mut self.receive_hooks, packet) {
+                return Ok(());
+            }
+        }
     }
 }
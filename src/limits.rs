@@ -0,0 +1,148 @@
+use crate::ServerConnection;
+use anyhow::{anyhow, Result};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    // Simultaneous open connections allowed from one address
+    pub max_per_ip: usize,
+    // How long a client gets to send its handshake before being dropped
+    pub handshake_timeout: Duration,
+    // Connection attempts allowed per address within `window`...
+    pub max_attempts: usize,
+    pub window: Duration,
+    // ...before it gets refused outright for this long
+    pub greylist_duration: Duration,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> ConnectionLimits {
+        ConnectionLimits {
+            max_per_ip: 3,
+            handshake_timeout: Duration::from_secs(5),
+            max_attempts: 10,
+            window: Duration::from_secs(60),
+            greylist_duration: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct AddressState {
+    active: usize,
+    attempts: VecDeque<Instant>,
+    greylisted_until: Option<Instant>,
+}
+
+impl AddressState {
+    fn is_idle(&self, now: Instant) -> bool {
+        self.active == 0
+            && self.attempts.is_empty()
+            && self.greylisted_until.is_none_or(|until| until <= now)
+    }
+}
+
+// Shared between every accepting thread, cloning is cheap
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    limits: ConnectionLimits,
+    addresses: Arc<Mutex<HashMap<IpAddr, AddressState>>>,
+}
+
+// Holds one of the address' connection slots until dropped
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    address: IpAddr,
+    addresses: Arc<Mutex<HashMap<IpAddr, AddressState>>>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut addresses = self.addresses.lock().unwrap();
+        if let Some(state) = addresses.get_mut(&self.address) {
+            state.active = state.active.saturating_sub(1);
+        }
+    }
+}
+
+impl Throttle {
+    pub fn new(limits: ConnectionLimits) -> Throttle {
+        Throttle {
+            limits,
+            addresses: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn limits(&self) -> &ConnectionLimits {
+        &self.limits
+    }
+
+    pub fn is_greylisted(&self, address: IpAddr) -> bool {
+        let addresses = self.addresses.lock().unwrap();
+        addresses
+            .get(&address)
+            .and_then(|state| state.greylisted_until)
+            .is_some_and(|until| until > Instant::now())
+    }
+
+    // Records a connection attempt and hands out a slot if the address is within limits
+    pub fn admit(&self, address: IpAddr) -> Result<ConnectionPermit> {
+        let now = Instant::now();
+        let mut addresses = self.addresses.lock().unwrap();
+        addresses.retain(|_, state| {
+            while state
+                .attempts
+                .front()
+                .is_some_and(|attempt| now.duration_since(*attempt) > self.limits.window)
+            {
+                state.attempts.pop_front();
+            }
+            !state.is_idle(now)
+        });
+
+        let state = addresses.entry(address).or_default();
+        if let Some(until) = state.greylisted_until {
+            if until > now {
+                return Err(anyhow!("{} is greylisted", address));
+            }
+            state.greylisted_until = None;
+        }
+
+        state.attempts.push_back(now);
+        if state.attempts.len() > self.limits.max_attempts {
+            state.greylisted_until = Some(now + self.limits.greylist_duration);
+            state.attempts.clear();
+            return Err(anyhow!("{} connected too often, greylisting it", address));
+        }
+
+        if state.active >= self.limits.max_per_ip {
+            return Err(anyhow!(
+                "{} already has {} open connections",
+                address,
+                state.active
+            ));
+        }
+        state.active += 1;
+
+        Ok(ConnectionPermit {
+            address,
+            addresses: Arc::clone(&self.addresses),
+        })
+    }
+
+    // Admits the stream and reads its handshake under the handshake timeout
+    pub fn accept(&self, stream: TcpStream) -> Result<(ServerConnection, ConnectionPermit)> {
+        let permit = self.admit(stream.peer_addr()?.ip())?;
+
+        stream.set_read_timeout(Some(self.limits.handshake_timeout))?;
+        let connection = ServerConnection::accept(stream)?;
+        connection.set_read_timeout(None)?;
+
+        Ok((connection, permit))
+    }
+}
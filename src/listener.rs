@@ -0,0 +1,300 @@
+use crate::{offline_uuid, Component, Handshake, NextState, Packet, PlayerInfo, ServerConnection};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+// The server side of the protocol: answers status pings and logs players in
+// without encryption, like an offline mode server. Enough for mock servers
+// in tests and simple chat relays, there is no world behind it.
+pub struct MinecraftListener {
+    listener: TcpListener,
+    status: String,
+    compression: Option<usize>,
+    handshake_timeout: Duration,
+}
+
+impl MinecraftListener {
+    pub fn bind(address: impl ToSocketAddrs) -> Result<MinecraftListener> {
+        Ok(MinecraftListener {
+            listener: TcpListener::bind(address)?,
+            status: String::from(
+                r#"{"version":{"name":"1.19","protocol":759},"players":{"max":20,"online":0},"description":{"text":"A mchat server"}}"#,
+            ),
+            compression: None,
+            handshake_timeout: Duration::from_secs(10),
+        })
+    }
+
+    // Bound to port 0 the OS picks a free one, this tells which
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // Status JSON sent to pings
+    pub fn status(mut self, status: &str) -> MinecraftListener {
+        self.status = String::from(status);
+        self
+    }
+
+    // Packets from this size on are compressed after login
+    pub fn compression_threshold(mut self, threshold: Option<usize>) -> MinecraftListener {
+        self.compression = threshold;
+        self
+    }
+
+    // Bounds everything before play, so a silent client can't hold accept up
+    pub fn handshake_timeout(mut self, timeout: Duration) -> MinecraftListener {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    // Takes the next connection through status or login. Returns None after
+    // answering a ping, or the player once logged in. An error only concerns
+    // that one connection, the listener can keep accepting.
+    pub fn accept(&self) -> Result<Option<ServerPlayer>> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_read_timeout(Some(self.handshake_timeout))?;
+        let mut connection = ServerConnection::accept(stream)?;
+
+        match connection.handshake().next_state {
+            NextState::Status => {
+                connection.respond_status(&self.status)?;
+                Ok(None)
+            }
+            NextState::Login | NextState::Transfer => {
+                let (name, uuid) = connection.complete_login(self.compression)?;
+                connection.set_read_timeout(None)?;
+                Ok(Some(ServerPlayer {
+                    connection,
+                    name,
+                    uuid,
+                }))
+            }
+        }
+    }
+}
+
+// What a player in play sent us
+#[derive(Debug, Clone)]
+pub enum PlayerAction {
+    Chat(String),
+    // Without the leading slash
+    Command(String),
+    KeepAlive(i64),
+    // Anything else, untouched
+    Packet(Packet),
+}
+
+// A logged in player on our side, in the play state
+pub struct ServerPlayer {
+    connection: ServerConnection,
+    name: String,
+    uuid: Uuid,
+}
+
+impl ServerPlayer {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.connection.peer_addr()
+    }
+
+    pub fn handshake(&self) -> &Handshake {
+        self.connection.handshake()
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.connection.set_read_timeout(timeout)
+    }
+
+    pub fn send_packet(&mut self, packet: &Packet) -> Result<()> {
+        self.connection.send_packet(packet)
+    }
+
+    pub fn read_packet(&mut self) -> Result<Packet> {
+        self.connection.read_packet()
+    }
+
+    // Waits up to `timeout` for the player to send something, so scripted
+    // servers can do their own thing in between
+    pub fn wait_readable(&mut self, timeout: Duration) -> Result<bool> {
+        self.connection.wait_readable(timeout)
+    }
+
+    // Blocks until the player sends something
+    pub fn read_action(&mut self) -> Result<PlayerAction> {
+        let packet = self.read_packet()?;
+        let mut reader = packet.reader();
+        Ok(match packet.get_protocol_id() {
+            Some(0x04) => PlayerAction::Chat(String::from(reader.read_str()?)),
+            Some(0x03) => PlayerAction::Command(String::from(reader.read_str()?)),
+            Some(0x11) => PlayerAction::KeepAlive(reader.read_i64()?),
+            _ => PlayerAction::Packet(packet),
+        })
+    }
+
+    // `overlay` shows it above the hotbar instead of in chat
+    pub fn send_system_message(&mut self, message: &Component, overlay: bool) -> Result<()> {
+        self.send_packet(&system_message(message, overlay)?)
+    }
+
+    // Plain chat from `sender`, unsigned like everything this crate sends
+    pub fn send_player_chat(
+        &mut self,
+        sender: Uuid,
+        sender_name: &str,
+        message: &str,
+    ) -> Result<()> {
+        self.send_packet(&player_chat(sender, sender_name, message)?)
+    }
+
+    // Adds someone to the tab list, which is all clients know of who's online
+    pub fn send_player_added(&mut self, player: &PlayerInfo) -> Result<()> {
+        self.send_packet(&player_added(player)?)
+    }
+
+    pub fn send_player_removed(&mut self, uuid: Uuid) -> Result<()> {
+        self.send_packet(&player_removed(uuid)?)
+    }
+
+    // Clients expect one every 15 seconds or so, and answer with the same id
+    pub fn send_keep_alive(&mut self, id: i64) -> Result<()> {
+        self.send_packet(&keep_alive(id)?)
+    }
+
+    pub fn disconnect(&mut self, reason: &Component) -> Result<()> {
+        self.send_packet(&disconnect(reason)?)
+    }
+}
+
+impl ServerConnection {
+    // Logs a client in without encryption, returns its name and offline UUID
+    pub fn complete_login(&mut self, compression: Option<usize>) -> Result<(String, Uuid)> {
+        let start = self.read_packet()?;
+        if start.get_protocol_id() != Some(0x00) {
+            return Err(anyhow!(
+                "Expected login start, got packet {:?}",
+                start.get_protocol_id()
+            ));
+        }
+        let name = String::from(start.reader().read_str().context("Bad login start")?);
+        let uuid = offline_uuid(&name);
+
+        if let Some(threshold) = compression {
+            self.send_packet(&set_compression(threshold)?)?;
+            self.set_compression(Some(threshold));
+        }
+        self.send_packet(&login_success(uuid, &name)?)?;
+
+        Ok((name, uuid))
+    }
+}
+
+// Clientbound packets of the server side, also used by the mock server
+
+pub(crate) fn set_compression(threshold: usize) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(0x03)?; // Protocol ID
+    packet.write_varint(threshold as i32)?; // Threshold
+
+    Ok(packet)
+}
+
+pub(crate) fn login_success(uuid: Uuid, name: &str) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(0x02)?; // Protocol ID
+    packet.write_uuid(&uuid); // UUID
+    packet.write_string(name)?; // Username
+    packet.write_varint(0)?; // Property count
+
+    Ok(packet)
+}
+
+pub(crate) fn system_message(message: &Component, overlay: bool) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(0x5F)?; // Protocol ID
+    packet.write_string(&message.to_json())?; // Content
+    packet.write_varint(if overlay { 2 } else { 1 })?; // Type, 2 is game info
+
+    Ok(packet)
+}
+
+pub(crate) fn player_chat(sender: Uuid, sender_name: &str, message: &str) -> Result<Packet> {
+    let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+
+    let mut packet = Packet::new();
+    packet.write_varint(0x30)?; // Protocol ID
+    packet.write_string(&Component::text(message).to_json())?; // Signed content
+    packet.write_bool(false); // Has unsigned content
+    packet.write_varint(0)?; // Chat type, plain chat
+    packet.write_uuid(&sender); // Sender
+    packet.write_string(&Component::text(sender_name).to_json())?; // Sender name
+    packet.write_bool(false); // Has team name
+    packet.write_slice(&timestamp_ms.to_be_bytes()); // Timestamp
+    packet.write_slice(&0u64.to_be_bytes()); // Salt
+    packet.write_varint(0)?; // Signature length
+
+    Ok(packet)
+}
+
+pub(crate) fn player_added(player: &PlayerInfo) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(0x34)?; // Protocol ID
+    packet.write_varint(0)?; // Action, add player
+    packet.write_varint(1)?; // Player count
+    packet.write_uuid(&player.uuid); // UUID
+    packet.write_string(&player.name)?; // Name
+    packet.write_varint(player.properties.len() as i32)?; // Property count
+    for property in &player.properties {
+        packet.write_string(&property.name)?; // Name
+        packet.write_string(&property.value)?; // Value
+        packet.write_bool(property.signature.is_some()); // Is signed
+        if let Some(signature) = &property.signature {
+            packet.write_string(signature)?; // Signature
+        }
+    }
+    packet.write_varint(player.gamemode)?; // Gamemode
+    packet.write_varint(player.latency)?; // Ping
+    packet.write_bool(player.display_name.is_some()); // Has display name
+    if let Some(display_name) = &player.display_name {
+        packet.write_string(display_name)?; // Display name
+    }
+    packet.write_bool(false); // Has signature data
+
+    Ok(packet)
+}
+
+pub(crate) fn player_removed(uuid: Uuid) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(0x34)?; // Protocol ID
+    packet.write_varint(4)?; // Action, remove player
+    packet.write_varint(1)?; // Player count
+    packet.write_uuid(&uuid); // UUID
+
+    Ok(packet)
+}
+
+pub(crate) fn keep_alive(id: i64) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(0x1E)?; // Protocol ID
+    packet.write_slice(&id.to_be_bytes()); // Keep alive id
+
+    Ok(packet)
+}
+
+pub(crate) fn disconnect(reason: &Component) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(0x17)?; // Protocol ID
+    packet.write_string(&reason.to_json())?; // Reason
+
+    Ok(packet)
+}
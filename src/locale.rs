@@ -0,0 +1,239 @@
+use std::{env, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    YearMonthDay,
+    DayMonthYear,
+    MonthDayYear,
+}
+
+// How numbers, latencies and timestamps are written for people reading our
+// output. Only covers what the monitoring output needs, not a full CLDR.
+// The default is the C locale: no grouping, ISO dates and a 24 hour clock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    pub decimal_separator: char,
+    // Between groups of three digits, None leaves numbers ungrouped
+    pub grouping_separator: Option<char>,
+    pub date_order: DateOrder,
+    pub date_separator: char,
+    pub twelve_hour_clock: bool,
+    // Timestamps are shifted by this much, there is no time zone database
+    pub utc_offset_minutes: i32,
+}
+
+impl Default for Locale {
+    fn default() -> Locale {
+        Locale {
+            decimal_separator: '.',
+            grouping_separator: None,
+            date_order: DateOrder::YearMonthDay,
+            date_separator: '-',
+            twelve_hour_clock: false,
+            utc_offset_minutes: 0,
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = std::convert::Infallible;
+
+    // POSIX (de_DE.UTF-8) and BCP 47 (de-DE) tags, unknown ones get the default
+    fn from_str(tag: &str) -> Result<Locale, Self::Err> {
+        let tag = tag.split(['.', '@']).next().unwrap_or_default();
+        let (language, region) = match tag.split_once(['_', '-']) {
+            Some((language, region)) => (language, region),
+            None => (tag, ""),
+        };
+        let language = language.to_ascii_lowercase();
+        let region = region.to_ascii_uppercase();
+
+        let mut locale = Locale::default();
+        match language.as_str() {
+            "en" => {
+                locale.grouping_separator = Some(',');
+                locale.date_separator = '/';
+                match region.as_str() {
+                    "US" | "PH" => {
+                        locale.date_order = DateOrder::MonthDayYear;
+                        locale.twelve_hour_clock = true;
+                    }
+                    "CA" => {
+                        locale.date_separator = '-';
+                        locale.twelve_hour_clock = true;
+                    }
+                    "AU" | "NZ" | "IN" => {
+                        locale.date_order = DateOrder::DayMonthYear;
+                        locale.twelve_hour_clock = true;
+                    }
+                    _ => locale.date_order = DateOrder::DayMonthYear,
+                }
+            }
+            "de" | "da" | "nb" | "no" | "fi" | "tr" | "ro" | "id" => {
+                locale.decimal_separator = ',';
+                locale.grouping_separator = Some('.');
+                locale.date_order = DateOrder::DayMonthYear;
+                locale.date_separator = '.';
+                if region == "CH" {
+                    locale.decimal_separator = '.';
+                    locale.grouping_separator = Some('\'');
+                }
+            }
+            "nl" | "it" | "es" | "pt" | "el" => {
+                locale.decimal_separator = ',';
+                locale.grouping_separator = Some('.');
+                locale.date_order = DateOrder::DayMonthYear;
+                locale.date_separator = if language == "nl" { '-' } else { '/' };
+            }
+            "fr" | "ru" | "uk" | "pl" | "cs" | "sk" | "sv" | "hu" | "bg" => {
+                locale.decimal_separator = ',';
+                // No-break space, so numbers aren't wrapped in the middle
+                locale.grouping_separator = Some('\u{a0}');
+                locale.date_order = DateOrder::DayMonthYear;
+                locale.date_separator = match language.as_str() {
+                    "fr" => '/',
+                    "sv" | "hu" => '-',
+                    _ => '.',
+                };
+                if language == "sv" || language == "hu" {
+                    locale.date_order = DateOrder::YearMonthDay;
+                }
+            }
+            "ja" | "zh" | "ko" => {
+                locale.grouping_separator = Some(',');
+                locale.date_separator = if language == "ko" { '.' } else { '/' };
+            }
+            _ => {}
+        }
+
+        Ok(locale)
+    }
+}
+
+impl Locale {
+    // The locale the user's environment asks for, following the POSIX
+    // precedence of LC_ALL over LC_NUMERIC over LANG
+    pub fn from_env() -> Locale {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .map(|tag| tag.parse().unwrap())
+            .unwrap_or_default()
+    }
+
+    pub fn with_utc_offset(mut self, minutes: i32) -> Locale {
+        self.utc_offset_minutes = minutes;
+        self
+    }
+
+    pub fn format_integer(&self, value: i64) -> String {
+        let digits = value.unsigned_abs().to_string();
+        let mut formatted = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+        if value < 0 {
+            formatted.push('-');
+        }
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                if let Some(separator) = self.grouping_separator {
+                    formatted.push(separator);
+                }
+            }
+            formatted.push(digit);
+        }
+        formatted
+    }
+
+    pub fn format_decimal(&self, value: f64, precision: usize) -> String {
+        let fixed = format!("{:.*}", precision, value.abs());
+        let (whole, fraction) = match fixed.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (fixed.as_str(), None),
+        };
+
+        // Anything too big for an i64 isn't worth grouping
+        let mut formatted = match whole.parse::<i64>() {
+            Ok(whole) => self.format_integer(whole),
+            Err(_) => String::from(whole),
+        };
+        if value.is_sign_negative() && fixed.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            formatted.insert(0, '-');
+        }
+        if let Some(fraction) = fraction {
+            formatted.push(self.decimal_separator);
+            formatted.push_str(fraction);
+        }
+        formatted
+    }
+
+    pub fn format_latency(&self, milliseconds: i32) -> String {
+        format!("{} ms", self.format_integer(milliseconds as i64))
+    }
+
+    // Time of day for a timestamp in milliseconds since the epoch
+    pub fn format_time(&self, timestamp: i64) -> String {
+        let seconds = self.local_seconds(timestamp).rem_euclid(86_400);
+        let (hour, minute, second) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+        if self.twelve_hour_clock {
+            let suffix = if hour < 12 { "AM" } else { "PM" };
+            let hour = match hour % 12 {
+                0 => 12,
+                hour => hour,
+            };
+            format!("{}:{:02}:{:02} {}", hour, minute, second, suffix)
+        } else {
+            format!("{:02}:{:02}:{:02}", hour, minute, second)
+        }
+    }
+
+    pub fn format_date(&self, timestamp: i64) -> String {
+        let (year, month, day) = civil_from_days(self.local_seconds(timestamp).div_euclid(86_400));
+        let separator = self.date_separator;
+
+        match self.date_order {
+            DateOrder::YearMonthDay => {
+                format!("{}{}{:02}{}{:02}", year, separator, month, separator, day)
+            }
+            DateOrder::DayMonthYear => {
+                format!("{:02}{}{:02}{}{}", day, separator, month, separator, year)
+            }
+            DateOrder::MonthDayYear => {
+                format!("{:02}{}{:02}{}{}", month, separator, day, separator, year)
+            }
+        }
+    }
+
+    pub fn format_date_time(&self, timestamp: i64) -> String {
+        format!(
+            "{} {}",
+            self.format_date(timestamp),
+            self.format_time(timestamp)
+        )
+    }
+
+    fn local_seconds(&self, timestamp: i64) -> i64 {
+        timestamp.div_euclid(1000) + self.utc_offset_minutes as i64 * 60
+    }
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date, after Howard Hinnant's
+// civil_from_days
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
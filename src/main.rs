@@ -1,64 +1,1731 @@
-use anyhow::{Context, Result};
-use base64::prelude::*;
-use colored::Colorize;
-use image::GenericImageView;
-use mchat::{Client, Packet};
-use serde::{Deserialize, Serialize};
+mod alias;
+mod config;
+mod demo;
+mod editor;
+mod tui;
+
+use alias::Aliases;
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use config::{
+    Auth, ClientConfig, Config, HighlightConfig, Publish, RuleConfig, Webhook as WebhookConfig,
+};
+use mchat::{
+    auth,
+    bedrock::{self, DEFAULT_BEDROCK_PORT},
+    color_rgb, data_directory, decode_frame, decode_packet, lookup_srv, parse_blob, query_basic,
+    query_full,
+    rcon::{Rcon, DEFAULT_RCON_PORT},
+    scan_servers, split_host_port, AnsiRenderer, BridgedMessage, CachedSession, ChatRules, Client,
+    ClientBuilder, Component, ConnectionState, Direction, Event, HttpClient, IrcServer, Kicked,
+    LineProtocol, Locale, MessageFilter, ProtocolFeatures, Renderer, ScanResult, ServerStatus,
+    SessionCache, ShutdownToken, SniffedPacket, Sniffer, StatusMonitor, StatusSample, DEFAULT_PORT,
+    PROTOCOLS, PROTOCOL_VERSION,
+};
+#[cfg(feature = "http")]
+use mchat::{listen_relay, split_chat_message, WebhookBridge, WebhookFormat, DEFAULT_CONTINUATION};
+#[cfg(feature = "bus")]
+use mchat::{BusTarget, EventPublisher};
+use regex::Regex;
+use serde_json::{json, Value};
+#[cfg(any(feature = "http", feature = "bus"))]
+use std::sync::mpsc;
 use std::{
-    io::{self, Write},
-    net::{IpAddr, Ipv4Addr},
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    net::{Shutdown, TcpListener},
+    path::PathBuf,
+    process::{self, Command as Process, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
     time::Duration,
 };
-use tokio::{sync::mpsc, task, time::sleep};
 
-#[derive(Serialize, Deserialize)]
-struct Version {
-    name: String,
-    protocol: u32,
+const DEFAULT_TIMEOUT: u64 = 10;
+const DEFAULT_USERNAME: &str = "extremq";
+// What mchat accounts add saves an account as without a name
+const DEFAULT_ACCOUNT: &str = "default";
+// How often the bridge checks for lines from its clients
+const BRIDGE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Parser)]
+#[command(version, about = "Minecraft 1.19 chat client")]
+struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "Config file, defaults to ~/.config/mchat/config.toml"
+    )]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Print what a server shows in the multiplayer list")]
+    Status {
+        #[command(flatten)]
+        server: ServerArgs,
+        #[arg(long, help = "Print the status, latency and target as JSON")]
+        json: bool,
+        #[arg(
+            long,
+            conflicts_with = "json",
+            help = "Print the status JSON as received"
+        )]
+        raw: bool,
+        #[arg(long, help = "Repair malformed status responses")]
+        lenient: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["raw", "lenient"],
+            help = "Ping a Bedrock server instead, on port 19132 by default"
+        )]
+        bedrock: bool,
+    },
+    #[command(about = "Measure the round trip time of status pings")]
+    Ping {
+        #[command(flatten)]
+        server: ServerArgs,
+        #[arg(short = 'c', long, default_value_t = 1, help = "Pings to send")]
+        count: u32,
+        #[arg(long, help = "Print a JSON object per ping, with the status")]
+        json: bool,
+    },
+    #[command(about = "Ask a server with enable-query for its plugins, map and players")]
+    Query {
+        #[command(flatten)]
+        server: ServerArgs,
+        #[arg(long, help = "Only the basic stat, without plugins and player names")]
+        basic: bool,
+        #[arg(long, help = "Print the stat as JSON")]
+        json: bool,
+    },
+    #[command(about = "Query many servers at once and print a table of them")]
+    Scan {
+        #[arg(help = "host or host:port")]
+        servers: Vec<String>,
+        #[arg(short, long, help = "Read more servers from a file, one per line")]
+        file: Option<PathBuf>,
+        #[arg(short, long, default_value_t = 100, help = "Servers queried at once")]
+        concurrency: usize,
+        #[arg(long, value_name = "SECONDS", default_value_t = 5)]
+        timeout: u64,
+        #[arg(long, help = "One JSON object per server, as they answer")]
+        json: bool,
+    },
+    #[command(about = "Poll a server's status and report player count changes")]
+    Monitor {
+        #[command(flatten)]
+        server: ServerArgs,
+        #[arg(
+            long,
+            default_value = "30s",
+            value_parser = parse_interval,
+            help = "Time between polls, e.g. 30s, 5m or 1h"
+        )]
+        interval: Duration,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Also append every sample to this file"
+        )]
+        output: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = SeriesFormat::Csv, help = "Format of --output")]
+        format: SeriesFormat,
+    },
+    #[command(about = "Join a server and chat in an interactive terminal UI")]
+    Chat {
+        #[command(flatten)]
+        server: ServerArgs,
+        #[arg(short, long, help = "Defaults to the config file, then extremq")]
+        username: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "username",
+            help = "Join as a saved account, see mchat accounts"
+        )]
+        account: Option<String>,
+        #[arg(long, value_enum, default_value_t = Mode::Offline)]
+        mode: Mode,
+        #[arg(
+            long,
+            value_name = "CATEGORY,...",
+            help = "Hide system messages: join-leave, death, advancement"
+        )]
+        hide: Option<String>,
+        #[arg(long, help = "Locale for numbers and times, defaults to LC_ALL/LANG")]
+        locale: Option<String>,
+        #[arg(long, help = "Reconnect after losing the connection")]
+        reconnect: bool,
+        #[arg(
+            long,
+            help = "Chat on a built-in scripted server instead, no host needed"
+        )]
+        demo: bool,
+    },
+    #[command(about = "Serve the chat as a local IRC channel, to join from any IRC client")]
+    Bridge {
+        #[command(flatten)]
+        server: ServerArgs,
+        #[arg(short, long, help = "Defaults to the config file, then extremq")]
+        username: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "username",
+            help = "Join as a saved account, see mchat accounts"
+        )]
+        account: Option<String>,
+        #[arg(
+            long,
+            default_value = "127.0.0.1:6667",
+            help = "Where IRC clients connect"
+        )]
+        listen: String,
+        #[arg(long, default_value = "#minecraft")]
+        channel: String,
+        #[arg(long, help = "Plain lines both ways instead of IRC, e.g. for nc")]
+        plain: bool,
+        #[arg(long, help = "Required from IRC clients with PASS")]
+        password: Option<String>,
+        #[arg(long, help = "Reconnect after losing the connection")]
+        reconnect: bool,
+    },
+    #[command(about = "Run console commands over RCON, password from MCHAT_RCON_PASSWORD")]
+    Rcon {
+        #[arg(help = "host or host:port, the port defaults to 25575")]
+        host: String,
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_TIMEOUT)]
+        timeout: u64,
+        #[arg(
+            trailing_var_arg = true,
+            help = "The command, read from stdin one per line when missing"
+        )]
+        command: Vec<String>,
+    },
+    #[command(about = "Manage the Microsoft accounts to join servers as")]
+    Accounts {
+        #[command(subcommand)]
+        command: AccountsCommand,
+    },
+    #[command(about = "Decode a packet given as hex or base64 and print its fields")]
+    Decode {
+        #[arg(help = "The packet id and fields, read from stdin when missing or -")]
+        blob: Option<String>,
+        #[arg(long = "version", value_name = "PROTOCOL", default_value_t = PROTOCOL_VERSION)]
+        protocol: i32,
+        #[arg(long, value_enum)]
+        bound: Bound,
+        #[arg(long, value_enum, default_value_t = State::Play)]
+        state: State,
+        #[arg(long, help = "The blob is a whole frame, starting with its length")]
+        frame: bool,
+        #[arg(
+            long,
+            value_name = "THRESHOLD",
+            requires = "frame",
+            help = "The frame is compressed, as after Set Compression"
+        )]
+        compression: Option<usize>,
+    },
+    #[command(about = "Relay Minecraft clients to a server, printing every packet on the way")]
+    Proxy {
+        #[arg(
+            long,
+            value_name = "[HOST:]PORT",
+            default_value = "25566",
+            help = "A bare port listens on 127.0.0.1"
+        )]
+        listen: String,
+        #[arg(long, value_name = "HOST[:PORT]")]
+        target: String,
+        #[arg(long, help = "Print the payload of packets nothing decodes")]
+        payloads: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AccountsCommand {
+    #[command(about = "Sign in to a Microsoft account and save its session")]
+    Add {
+        #[arg(default_value = DEFAULT_ACCOUNT, help = "Name to save the account under")]
+        name: String,
+    },
+    #[command(about = "List the saved accounts and how long their sessions last")]
+    List {
+        #[arg(long, help = "Refresh the sessions that expired")]
+        refresh: bool,
+    },
+    #[command(about = "Forget a saved account")]
+    Remove { name: String },
+}
+
+#[derive(Args)]
+struct ServerArgs {
+    // Only optional for chat --demo, resolve insists on it otherwise
+    #[arg(help = "host, host:port or the name of a server from the config file")]
+    host: Option<String>,
+    #[arg(short, long, help = "Overrides a port given with the host")]
+    port: Option<u16>,
+    #[arg(long, help = "Protocol version to announce [default: 759]")]
+    protocol: Option<i32>,
+    #[arg(long, value_name = "SECONDS", help = "[default: 10]")]
+    timeout: Option<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mode {
+    Offline,
+    Online,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Bound {
+    Clientbound,
+    Serverbound,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum State {
+    Handshaking,
+    Status,
+    Login,
+    Play,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SeriesFormat {
+    Csv,
+    // One JSON object per line
+    Json,
 }
 
-#[derive(Serialize, Deserialize)]
-struct PlayerSample {
+// Where to connect after merging the flags with the config file
+#[derive(Debug, Clone)]
+struct Target {
+    // The host as given, before the SRV lookup
     name: String,
-    id: String,
+    host: String,
+    port: u16,
+    // Whether host and port came from an SRV record
+    srv: bool,
+    protocol: i32,
+    timeout: Duration,
+    username: Option<String>,
+    account: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Players {
-    max: u32,
-    online: u32,
-    sample: Option<Vec<PlayerSample>>,
+impl ServerArgs {
+    fn resolve(&self, config: &Config) -> Result<Target> {
+        let name = self
+            .host
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing server, give a host or a saved server name"))?;
+        let saved = config.servers.get(name);
+        let (host, port) = split_host_port(saved.map_or(name, |saved| &saved.host))?;
+        let port = self.port.or(saved.and_then(|saved| saved.port)).or(port);
+        let timeout = Duration::from_secs(
+            self.timeout
+                .or(saved.and_then(|saved| saved.timeout))
+                .unwrap_or(DEFAULT_TIMEOUT),
+        );
+
+        // Like vanilla, only servers given without a port are looked up.
+        // A failed lookup just means connecting to the host itself.
+        let srv = match port {
+            Some(_) => None,
+            None => lookup_srv(&host, timeout).unwrap_or(None),
+        };
+        let (host, port, srv) = match srv {
+            Some((target, port)) => (target, port, true),
+            None => (host, port.unwrap_or(DEFAULT_PORT), false),
+        };
+
+        Ok(Target {
+            name: name.clone(),
+            host,
+            port,
+            srv,
+            protocol: self
+                .protocol
+                .or(saved.and_then(|saved| saved.protocol))
+                .unwrap_or(PROTOCOL_VERSION),
+            timeout,
+            username: saved.and_then(|saved| saved.username.clone()),
+            account: saved.and_then(|saved| saved.account.clone()),
+        })
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct Description {
-    text: String,
+impl ServerArgs {
+    // Bedrock servers have no SRV records and their own default port
+    fn resolve_bedrock(&self, config: &Config) -> Result<(String, u16, Duration)> {
+        let name = self
+            .host
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing server, give a host or a saved server name"))?;
+        let saved = config.servers.get(name);
+        let (host, port) = split_host_port(saved.map_or(name, |saved| &saved.host))?;
+        let port = self
+            .port
+            .or(saved.and_then(|saved| saved.port))
+            .or(port)
+            .unwrap_or(DEFAULT_BEDROCK_PORT);
+        let timeout = self
+            .timeout
+            .or(saved.and_then(|saved| saved.timeout))
+            .unwrap_or(DEFAULT_TIMEOUT);
+        Ok((host, port, Duration::from_secs(timeout)))
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct MinecraftStatus {
-    version: Version,
-    players: Players,
-    description: Description,
-    favicon: Option<String>,
-    enforcesSecureChat: Option<bool>,
+impl Target {
+    // A username given with -u wins, then the profile of a saved account,
+    // from --account, the saved server or the config file, then a saved
+    // username
+    fn username(
+        &self,
+        username: Option<String>,
+        account: Option<String>,
+        config: &Config,
+    ) -> Result<String> {
+        if let Some(username) = username {
+            return Ok(username);
+        }
+        let account = account
+            .or_else(|| self.account.clone())
+            .or_else(|| config.account.clone());
+        if let Some(account) = account {
+            let session =
+                session_cache(&config.auth)?.session(&account, config.auth.client_id.as_deref())?;
+            return Ok(session.name);
+        }
+
+        Ok(self
+            .username
+            .clone()
+            .or_else(|| config.username.clone())
+            .unwrap_or_else(|| String::from(DEFAULT_USERNAME)))
+    }
+
+    fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    // For one-off requests, a silent server fails them after the timeout
+    fn builder(&self, shutdown: &ShutdownToken) -> ClientBuilder {
+        self.session_builder(shutdown).read_timeout(self.timeout)
+    }
+
+    // For staying connected, where an idle server may say nothing for a while
+    fn session_builder(&self, shutdown: &ShutdownToken) -> ClientBuilder {
+        Client::builder(&self.host, self.port)
+            .protocol_version(self.protocol)
+            .connect_timeout(self.timeout)
+            .shutdown_token(shutdown.clone())
+    }
 }
 
 fn main() -> Result<()> {
-    let mut client = Client::new("localhost", 25565).with_context(|| "Failed to create client.")?;
-    println!("{}", client.status()?);
-    client.login()?;
+    let cli = Cli::parse();
+    let config = Config::load(cli.config)?;
+
+    // Ctrl-C, SIGTERM and SIGHUP cancel everything sharing the token, main
+    // then waits for the threads to wind down instead of the process dying
+    // mid-write
+    let shutdown = ShutdownToken::new();
+    let token = shutdown.clone();
+    ctrlc::set_handler(move || token.cancel()).context("Failed to install Ctrl-C handler")?;
+
+    let result = match cli.command {
+        Command::Status {
+            server,
+            json,
+            bedrock: true,
+            ..
+        } => bedrock_status(&server, &config, json),
+        Command::Status {
+            server,
+            json,
+            raw,
+            lenient,
+            bedrock: false,
+        } => {
+            let output = match (json, raw) {
+                (true, _) => StatusOutput::Json,
+                (_, true) => StatusOutput::Raw,
+                _ => StatusOutput::Text,
+            };
+            status(&server.resolve(&config)?, output, lenient, &shutdown)
+        }
+        Command::Ping {
+            server,
+            count,
+            json,
+        } => ping(&server.resolve(&config)?, count, json, &shutdown),
+        Command::Query {
+            server,
+            basic,
+            json,
+        } => query(&server.resolve(&config)?, basic, json),
+        Command::Scan {
+            mut servers,
+            file,
+            concurrency,
+            timeout,
+            json,
+        } => {
+            if let Some(file) = file {
+                let list = fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?;
+                servers.extend(
+                    list.lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(String::from),
+                );
+            }
+            scan(
+                servers,
+                concurrency,
+                Duration::from_secs(timeout),
+                json,
+                &shutdown,
+            )
+        }
+        Command::Monitor {
+            server,
+            interval,
+            output,
+            format,
+        } => monitor(
+            &server.resolve(&config)?,
+            interval,
+            output,
+            format,
+            &config.publish,
+            &shutdown,
+        ),
+        Command::Chat {
+            server,
+            username,
+            account,
+            mode,
+            hide,
+            locale,
+            reconnect,
+            demo,
+        } => {
+            let target = match demo {
+                true => Target {
+                    name: String::from("demo"),
+                    host: String::from("127.0.0.1"),
+                    port: demo::start(&shutdown)?.port(),
+                    srv: false,
+                    protocol: PROTOCOL_VERSION,
+                    timeout: Duration::from_secs(DEFAULT_TIMEOUT),
+                    username: None,
+                    account: None,
+                },
+                false => server.resolve(&config)?,
+            };
+            let options = ChatOptions {
+                username: target.username(username, account, &config)?,
+                mode,
+                hide: hide.unwrap_or_else(|| config.hide.join(",")),
+                locale: locale.or_else(|| config.locale.clone()),
+                reconnect,
+            };
+            chat(&target, options, &config, &shutdown)
+        }
+        Command::Bridge {
+            server,
+            username,
+            account,
+            listen,
+            channel,
+            plain,
+            password,
+            reconnect,
+        } => {
+            let target = server.resolve(&config)?;
+            let options = BridgeOptions {
+                username: target.username(username, account, &config)?,
+                listen,
+                channel,
+                protocol: match plain {
+                    true => LineProtocol::Plain,
+                    false => LineProtocol::Irc,
+                },
+                password,
+                reconnect,
+            };
+            bridge(&target, options, &config, &shutdown)
+        }
+        Command::Accounts { command } => accounts(command, &config.auth, &shutdown),
+        Command::Decode {
+            blob,
+            protocol,
+            bound,
+            state,
+            frame,
+            compression,
+        } => decode(blob, protocol, bound, state, frame.then_some(compression)),
+        Command::Proxy {
+            listen,
+            target,
+            payloads,
+        } => proxy(&listen, &target, payloads, &shutdown),
+        Command::Rcon {
+            host,
+            timeout,
+            command,
+        } => rcon(&host, Duration::from_secs(timeout), &command.join(" ")),
+    };
+
+    shutdown.shutdown();
+
+    // Kicks show the reason styled the way the server sent it
+    if let Some(kicked) = result
+        .as_ref()
+        .err()
+        .and_then(|error| error.downcast_ref::<Kicked>())
+    {
+        if io::stderr().is_terminal() {
+            eprintln!("Error: {}", AnsiRenderer.render(&kicked.to_component()));
+            process::exit(1);
+        }
+    }
+    result
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StatusOutput {
+    Text,
+    Json,
+    Raw,
+}
+
+fn status(
+    target: &Target,
+    output: StatusOutput,
+    lenient: bool,
+    shutdown: &ShutdownToken,
+) -> Result<()> {
+    let mut client = target.builder(shutdown).lenient_status(lenient).connect()?;
+
+    match output {
+        StatusOutput::Raw => {
+            println!("{}", client.status()?);
+            return Ok(());
+        }
+        StatusOutput::Json => {
+            let (report, latency) = client.status_with_ping()?;
+            let mut value = status_json(target, &report.status, latency);
+            value["fixes"] = json!(report
+                .fixes
+                .iter()
+                .map(|fix| format!("{:?}", fix))
+                .collect::<Vec<_>>());
+            println!("{}", value);
+            return Ok(());
+        }
+        StatusOutput::Text => {}
+    }
+
+    let report = client.server_status_report()?;
+    for fix in &report.fixes {
+        eprintln!("Repaired: {:?}", fix);
+    }
+
+    let locale = Locale::from_env();
+    let status = report.status;
+    // Legacy § codes are common in all three, hover lists in particular
+    let legacy = |text: &str| AnsiRenderer.render(&Component::text(text));
+    println!("{}", AnsiRenderer.render(&status.description));
+    println!(
+        "{} (protocol {})",
+        legacy(&status.version.name),
+        status.version.protocol
+    );
+    println!(
+        "{} / {} players",
+        locale.format_integer(status.players.online as i64),
+        locale.format_integer(status.players.max as i64)
+    );
+    for player in status.players.sample.iter().flatten() {
+        println!("  {}", legacy(&player.name));
+    }
+
+    Ok(())
+}
+
+fn bedrock_status(server: &ServerArgs, config: &Config, json: bool) -> Result<()> {
+    let (host, port, timeout) = server.resolve_bedrock(config)?;
+    let status = bedrock::ping(&host, port, timeout)?;
+    if json {
+        println!(
+            "{}",
+            json!({
+                "target": { "host": host, "port": port },
+                "latency_ms": status.latency.as_secs_f64() * 1000.0,
+                "status": {
+                    "edition": status.edition,
+                    "motd": status.motd,
+                    "sub_motd": status.sub_motd,
+                    "protocol": status.protocol,
+                    "version": status.version,
+                    "players": status.players,
+                    "max_players": status.max_players,
+                    "game_mode": status.game_mode,
+                },
+            })
+        );
+        return Ok(());
+    }
+
+    let locale = Locale::from_env();
+    let legacy = |text: &str| AnsiRenderer.render(&Component::text(text));
+    println!("{}", legacy(&status.motd));
+    if let Some(sub_motd) = &status.sub_motd {
+        println!("{}", legacy(sub_motd));
+    }
+    println!(
+        "{} {} (protocol {})",
+        status.edition, status.version, status.protocol
+    );
+    println!(
+        "{} / {} players",
+        locale.format_integer(status.players as i64),
+        locale.format_integer(status.max_players as i64)
+    );
+    if let Some(game_mode) = &status.game_mode {
+        println!("{}", game_mode);
+    }
+    Ok(())
+}
+
+fn ping(target: &Target, count: u32, json: bool, shutdown: &ShutdownToken) -> Result<()> {
+    let locale = Locale::from_env();
+    let mut client = target.builder(shutdown).connect()?;
+
+    for attempt in 0..count {
+        if shutdown.is_cancelled() {
+            break;
+        }
+        if attempt > 0 && shutdown.wait_timeout(Duration::from_secs(1)) {
+            break;
+        }
+
+        if json {
+            let (report, latency) = client.status_with_ping()?;
+            println!("{}", status_json(target, &report.status, latency));
+            continue;
+        }
+
+        let elapsed = client.ping()?;
+        println!(
+            "{}: {} ms",
+            target.address(),
+            locale.format_decimal(elapsed.as_secs_f64() * 1000.0, 1)
+        );
+    }
+
+    Ok(())
+}
+
+// The UDP query goes to the game port unless -p says otherwise, which is
+// where servers listen for it by default
+fn query(target: &Target, basic: bool, json: bool) -> Result<()> {
+    let address = (target.host.as_str(), target.port);
+    let locale = Locale::from_env();
+    let count = |players: u32, max_players: u32| {
+        format!(
+            "{} / {} players",
+            locale.format_integer(players as i64),
+            locale.format_integer(max_players as i64)
+        )
+    };
+    let legacy = |text: &str| AnsiRenderer.render(&Component::text(text));
+    if basic {
+        let stat = query_basic(address, target.timeout)?;
+        if json {
+            println!("{}", serde_json::to_string(&stat)?);
+            return Ok(());
+        }
+        println!("{}", legacy(&stat.motd));
+        println!("{} on {}", stat.game_type, stat.map);
+        println!("{}", count(stat.players, stat.max_players));
+        return Ok(());
+    }
+
+    let stat = query_full(address, target.timeout)?;
+    if json {
+        println!("{}", serde_json::to_string(&stat)?);
+        return Ok(());
+    }
+    println!("{}", legacy(&stat.motd));
+    println!("{} {} on {}", stat.game_type, stat.version, stat.map);
+    if let Some(server_mod) = &stat.server_mod {
+        println!("{}: {}", server_mod, stat.plugins.join(", "));
+    }
+    println!("{}", count(stat.players, stat.max_players));
+    for name in &stat.player_names {
+        println!("  {}", name);
+    }
+    Ok(())
+}
+
+fn rcon(host: &str, timeout: Duration, command: &str) -> Result<()> {
+    let (host, port) = split_host_port(host)?;
+    let port = port.unwrap_or(DEFAULT_RCON_PORT);
+    let password = match env::var("MCHAT_RCON_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => read_passphrase(
+            &format!("RCON password for {}:{}", host, port),
+            "MCHAT_RCON_PASSWORD",
+        )?,
+    };
+
+    let mut rcon = Rcon::connect((host.as_str(), port), timeout)
+        .with_context(|| format!("Failed to connect to RCON at {}:{}", host, port))?;
+    rcon.authenticate(&password)?;
+    // Output keeps the server's § codes
+    let mut run = |command: &str| -> Result<()> {
+        let output = rcon.command(command)?;
+        if !output.is_empty() {
+            println!("{}", AnsiRenderer.render(&Component::text(&output)));
+        }
+        Ok(())
+    };
+
+    if !command.is_empty() {
+        return run(command);
+    }
+    for line in io::stdin().lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            run(line.trim())?;
+        }
+    }
+    Ok(())
+}
+
+// What status and ping print with --json, one line each
+fn status_json(target: &Target, status: &ServerStatus, latency: Duration) -> Value {
+    json!({
+        "server": target.name,
+        "target": {
+            "host": target.host,
+            "port": target.port,
+            "srv": target.srv,
+        },
+        "latency_ms": latency.as_secs_f64() * 1000.0,
+        "status": status,
+    })
+}
+
+fn scan(
+    servers: Vec<String>,
+    concurrency: usize,
+    timeout: Duration,
+    json: bool,
+    shutdown: &ShutdownToken,
+) -> Result<()> {
+    if servers.is_empty() {
+        return Err(anyhow!("No servers to scan, give some or a --file"));
+    }
+
+    let count = servers.len();
+    let progress = !json && io::stderr().is_terminal();
+    let mut results = Vec::new();
+    for result in scan_servers(servers, concurrency, timeout, shutdown) {
+        if json {
+            println!("{}", scan_json(&result));
+        } else if progress {
+            eprint!("\r{} of {} done", results.len() + 1, count);
+        }
+        results.push(result);
+    }
+    if json {
+        return Ok(());
+    }
+    if progress {
+        eprintln!();
+    }
+
+    let locale = Locale::from_env();
+    let mut rows = vec![[
+        String::from("SERVER"),
+        String::from("VERSION"),
+        String::from("PLAYERS"),
+        String::from("LATENCY"),
+        String::from("MOTD"),
+    ]];
+    results.sort_by_key(|result| result.index);
+    for result in &results {
+        rows.push(match &result.outcome {
+            Ok((report, latency)) => {
+                let status = &report.status;
+                let motd = status.description.to_plain();
+                [
+                    result.server.clone(),
+                    status.version.name.clone(),
+                    format!(
+                        "{}/{}",
+                        locale.format_integer(status.players.online as i64),
+                        locale.format_integer(status.players.max as i64)
+                    ),
+                    match latency {
+                        Some(latency) => format!(
+                            "{} ms",
+                            locale.format_decimal(latency.as_secs_f64() * 1000.0, 1)
+                        ),
+                        None => String::from("-"),
+                    },
+                    motd.lines().next().unwrap_or_default().trim().to_owned(),
+                ]
+            }
+            Err(error) => [
+                result.server.clone(),
+                String::from("-"),
+                String::from("-"),
+                String::from("-"),
+                format!("down: {:#}", error),
+            ],
+        });
+    }
+
+    // The last column isn't padded, it's free to run long
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let mut line = String::new();
+        for (width, cell) in widths.iter().zip(row) {
+            line.push_str(&format!("{:<width$}  ", cell, width = width));
+        }
+        line.push_str(&row[4]);
+        println!("{}", line);
+    }
+
+    let answered = results
+        .iter()
+        .filter(|result| result.outcome.is_ok())
+        .count();
+    eprintln!(
+        "{} of {} servers answered",
+        locale.format_integer(answered as i64),
+        locale.format_integer(count as i64)
+    );
+
+    Ok(())
+}
+
+fn scan_json(result: &ScanResult) -> Value {
+    match &result.outcome {
+        Ok((report, latency)) => json!({
+            "server": result.server,
+            "online": true,
+            "latency_ms": latency.map(|latency| latency.as_secs_f64() * 1000.0),
+            "status": report.status,
+        }),
+        Err(error) => json!({
+            "server": result.server,
+            "online": false,
+            "error": format!("{:#}", error),
+        }),
+    }
+}
+
+fn monitor(
+    target: &Target,
+    interval: Duration,
+    output: Option<PathBuf>,
+    format: SeriesFormat,
+    publish: &Publish,
+    shutdown: &ShutdownToken,
+) -> Result<()> {
+    let mut publisher = sample_publisher(publish)?;
+    let mut output = match output {
+        Some(path) => {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            // A header only at the top, appending to a series keeps it valid
+            if format == SeriesFormat::Csv && file.metadata()?.len() == 0 {
+                writeln!(file, "timestamp,online,max,latency_ms,error")?;
+            }
+            Some(file)
+        }
+        None => None,
+    };
+
+    let locale = Locale::from_env();
+    let connect = || target.builder(shutdown).connect();
 
+    StatusMonitor::new(shutdown.clone(), interval).run(connect, |sample| {
+        print_sample(&locale, sample);
+        if let Some(file) = &mut output {
+            if let Err(error) = write_sample(file, format, sample) {
+                eprintln!("Failed to write sample: {:#}", error);
+            }
+        }
+        if let Some(publish) = &mut publisher {
+            if let Err(error) = publish(sample) {
+                eprintln!("Failed to publish sample: {:#}", error);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn print_sample(locale: &Locale, sample: &StatusSample) {
+    let time = locale.format_time(sample.timestamp);
+    let (Some(online), Some(max), Some(latency)) = (sample.online(), sample.max(), sample.latency)
+    else {
+        println!(
+            "[{}] unreachable: {}",
+            time,
+            sample.error.as_deref().unwrap_or_default()
+        );
+        return;
+    };
+
+    let change = match sample.online_change {
+        Some(change) if change != 0 => format!(" ({:+})", change),
+        _ => String::new(),
+    };
+    println!(
+        "[{}] {} / {} players{}, {} ms",
+        time,
+        locale.format_integer(online as i64),
+        locale.format_integer(max as i64),
+        change,
+        locale.format_decimal(latency.as_secs_f64() * 1000.0, 1)
+    );
+}
+
+fn write_sample(file: &mut File, format: SeriesFormat, sample: &StatusSample) -> Result<()> {
+    let latency_ms = sample.latency.map(|latency| latency.as_secs_f64() * 1000.0);
+    match format {
+        SeriesFormat::Csv => {
+            let field = |value: Option<String>| value.unwrap_or_default();
+            let error = match &sample.error {
+                Some(error) => format!("\"{}\"", error.replace('"', "\"\"")),
+                None => String::new(),
+            };
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                sample.timestamp,
+                field(sample.online().map(|online| online.to_string())),
+                field(sample.max().map(|max| max.to_string())),
+                field(latency_ms.map(|latency| format!("{:.1}", latency))),
+                error
+            )?;
+        }
+        SeriesFormat::Json => writeln!(file, "{}", sample.to_json())?,
+    }
+
+    Ok(())
+}
+
+// "30s", "5m", "1h", "500ms" or plain seconds
+fn parse_interval(text: &str) -> Result<Duration> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Bad interval {:?}", text))?;
+
+    let interval = match unit {
+        "ms" => Duration::from_millis(number),
+        "" | "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number * 60),
+        "h" => Duration::from_secs(number * 3600),
+        _ => return Err(anyhow!("Unknown unit {:?} in {:?}", unit, text)),
+    };
+    match interval.is_zero() {
+        true => Err(anyhow!("The interval can't be zero")),
+        false => Ok(interval),
+    }
+}
+
+// Chat flags already merged with the config file
+struct ChatOptions {
+    username: String,
+    mode: Mode,
+    hide: String,
+    locale: Option<String>,
+    reconnect: bool,
+}
+
+fn chat(
+    target: &Target,
+    options: ChatOptions,
+    config: &Config,
+    shutdown: &ShutdownToken,
+) -> Result<()> {
+    if options.mode == Mode::Online {
+        let hint = match config.auth.access_token {
+            Some(_) => ", the access token in the config file is unused for now",
+            None => "",
+        };
+        return Err(anyhow!(
+            "Online mode needs encryption and a Microsoft account, which aren't supported yet{}",
+            hint
+        ));
+    }
+
+    let display = match options.hide.is_empty() {
+        true => MessageFilter::new(),
+        false => MessageFilter::parse(&options.hide)?,
+    };
+    let mutes = config
+        .mute
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).with_context(|| format!("Invalid mute pattern {}", pattern))
+        })
+        .collect::<Result<_>>()?;
+    let highlights = config
+        .highlight
+        .iter()
+        .map(|highlight| highlight_rule(highlight, &options.username))
+        .collect::<Result<_>>()?;
+    let aliases = Aliases::new(&config.aliases)?;
+    let locale = match options.locale {
+        Some(tag) => tag.parse()?,
+        None => Locale::from_env(),
+    };
+    let chat_log = match &config.logging.chat_log {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open chat log {}", path.display()))?,
+        ),
+        None => None,
+    };
+    let mut reconnect = config.reconnect.clone();
+    reconnect.enabled |= options.reconnect;
+
+    // The connection gets a token of its own, so Ctrl-C leaves time to say
+    // goodbye before it's closed, see tui::run
+    let session = ShutdownToken::new();
+    let connect = connector(
+        target,
+        &options.username,
+        &config.rules,
+        &config.client,
+        &session,
+    )?;
+    let bridge = webhook_bridge(&config.webhook, shutdown)?;
+    let publisher = chat_publisher(&config.publish, shutdown)?;
+
+    // The first attempt happens before taking over the terminal so errors
+    // stay readable. There's nothing to say goodbye on yet, so Ctrl-C
+    // cancels it right away.
+    let connecting = Arc::new(AtomicBool::new(true));
+    shutdown.on_cancel({
+        let (session, connecting) = (session.clone(), connecting.clone());
+        move || {
+            if connecting.load(Ordering::SeqCst) {
+                session.cancel();
+            }
+        }
+    });
+    let client = connect()?;
+    connecting.store(false, Ordering::SeqCst);
+
+    tui::run(
+        client,
+        connect,
+        tui::Settings {
+            address: target.address(),
+            display,
+            mutes,
+            highlights,
+            aliases,
+            locale,
+            reconnect,
+            chat_log,
+            history: config
+                .history
+                .clone()
+                .or_else(|| Some(data_directory()?.join("history"))),
+            chat_logger: config.logging.chat_logger(),
+            bridge,
+            publisher,
+            farewell: config.client.farewell.clone(),
+        },
+        shutdown,
+        &session,
+    )
+}
+
+fn highlight_rule(config: &HighlightConfig, username: &str) -> Result<tui::Highlight> {
+    let pattern = config.pattern.replace("{name}", &regex::escape(username));
+    let color = config
+        .color
+        .clone()
+        .unwrap_or_else(|| String::from("yellow"));
+    if color_rgb(&color).is_none() {
+        return Err(anyhow!(
+            "Highlight {} has an unknown color {}",
+            config.pattern,
+            color
+        ));
+    }
+
+    Ok(tui::Highlight {
+        pattern: Regex::new(&pattern)
+            .with_context(|| format!("Invalid highlight pattern {}", config.pattern))?,
+        color,
+        bell: config.bell,
+    })
+}
+
+// Makes a logged in client, for the first connection and every reconnect
+fn connector(
+    target: &Target,
+    username: &str,
+    rules: &[RuleConfig],
+    client: &ClientConfig,
+    shutdown: &ShutdownToken,
+) -> Result<impl Fn() -> Result<Client> + Send + 'static> {
+    // Built for every connection, ChatRules holds state that isn't Clone
+    let rules = rules.to_vec();
+    chat_rules(&rules)?;
+    let information = client.information()?;
+    let brand = client.brand().map(String::from);
+
+    let target = target.clone();
+    let shutdown = shutdown.clone();
+    let username = String::from(username);
+    Ok(move || -> Result<Client> {
+        let mut client = target
+            .session_builder(&shutdown)
+            .username(&username)
+            .chat_rules(chat_rules(&rules)?)
+            .client_information(Some(information.clone()))
+            .brand(brand.as_deref())
+            .connect()
+            .with_context(|| "Failed to create client.")?;
+
+        // Only while logging in, an idle server legitimately says nothing for a while
+        client.set_read_timeout(Some(target.timeout))?;
+        client.login()?;
+        client.set_read_timeout(None)?;
+        Ok(client)
+    })
+}
+
+#[cfg(feature = "http")]
+fn webhook_bridge(config: &WebhookConfig, shutdown: &ShutdownToken) -> Result<Option<tui::Bridge>> {
+    let Some(url) = &config.url else {
+        return match config.listen {
+            Some(_) => Err(anyhow!("The webhook relay needs a webhook url too")),
+            None => Ok(None),
+        };
+    };
+    let format = match config.format.as_deref() {
+        None | Some("discord") => WebhookFormat::Discord,
+        Some("slack") => WebhookFormat::Slack,
+        Some(other) => return Err(anyhow!("Unknown webhook format {}", other)),
+    };
+    let mut bridge =
+        WebhookBridge::new(url, format).system_template(config.system_template.as_deref());
+    if let Some(template) = &config.template {
+        bridge = bridge.template(template);
+    }
+
+    let (error_sender, errors) = mpsc::channel();
+    let events = bridge.spawn(shutdown, move |error| {
+        let _ = error_sender.send(format!("{:#}", error));
+    });
+    let relay = match &config.listen {
+        Some(address) => {
+            let (_, messages) = listen_relay(address, config.secret.clone(), shutdown)
+                .with_context(|| format!("Failed to listen for relayed chat on {}", address))?;
+            let (line_sender, lines) = mpsc::channel();
+            shutdown.spawn(move |_| {
+                for message in messages {
+                    for line in split_chat_message(&message.to_chat(), DEFAULT_CONTINUATION) {
+                        if line_sender.send(line).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+            Some(lines)
+        }
+        None => None,
+    };
+
+    Ok(Some(tui::Bridge {
+        events,
+        errors,
+        relay,
+    }))
+}
+
+#[cfg(not(feature = "http"))]
+fn webhook_bridge(config: &WebhookConfig, _: &ShutdownToken) -> Result<Option<tui::Bridge>> {
+    match config.url.is_some() || config.listen.is_some() {
+        true => Err(anyhow!(
+            "The webhook bridge needs mchat built with the http feature"
+        )),
+        false => Ok(None),
+    }
+}
+
+// Bridge flags already merged with the config file
+struct BridgeOptions {
+    username: String,
+    listen: String,
+    channel: String,
+    protocol: LineProtocol,
+    password: Option<String>,
+    reconnect: bool,
+}
+
+fn bridge(
+    target: &Target,
+    options: BridgeOptions,
+    config: &Config,
+    shutdown: &ShutdownToken,
+) -> Result<()> {
+    let (server, messages) = IrcServer::bind(
+        &options.listen,
+        options.protocol,
+        &options.channel,
+        options.password,
+        shutdown,
+    )
+    .with_context(|| format!("Failed to listen on {}", options.listen))?;
+    eprintln!(
+        "Bridging {} to {} on {}",
+        target.address(),
+        server.channel(),
+        server.local_addr()
+    );
+
+    let connect = connector(
+        target,
+        &options.username,
+        &config.rules,
+        &config.client,
+        shutdown,
+    )?;
+    let mut reconnect = config.reconnect.clone();
+    reconnect.enabled |= options.reconnect;
+    let mut delay = reconnect.delay();
+    let mut attempts = 0;
+
+    loop {
+        let result = connect().and_then(|client| {
+            attempts = 0;
+            delay = reconnect.delay();
+            server.say(None, &format!("Connected to {}", target.address()));
+            relay(client, &server, &messages)
+        });
+        if shutdown.is_cancelled() {
+            return Ok(());
+        }
+
+        let error = result.err().unwrap_or_else(|| anyhow!("Connection ended"));
+        eprintln!("Disconnected: {:#}", error);
+        server.say(None, &format!("Disconnected: {:#}", error));
+        // Said while we were away, too late for it now
+        for _ in messages.try_iter() {}
+
+        attempts += 1;
+        let give_up = reconnect.max_attempts != 0 && attempts > reconnect.max_attempts;
+        if !reconnect.enabled || give_up {
+            return Err(error);
+        }
+        eprintln!("Reconnecting in {}s", delay.as_secs());
+        if shutdown.wait_timeout(delay) {
+            return Ok(());
+        }
+        delay = (delay * 2).min(reconnect.max_delay());
+    }
+}
+
+// Passes chat both ways until the connection ends, which is always an error
+fn relay(
+    mut client: Client,
+    server: &IrcServer,
+    messages: &Receiver<BridgedMessage>,
+) -> Result<()> {
     loop {
-        let packet = client.block_until_packet_id(0x1E)?;
-        let mut sender = Packet::from_bytes(&packet.buffer[packet.cursor - 1..]);
-        sender.buffer[0] = 0x11;
-        client.send_packet(&sender)?;
-        client.send_chat_message()?;
+        for message in messages.try_iter() {
+            client.send_chat_multiline(&message.to_chat())?;
+        }
+
+        let Some(event) = client.poll_event(BRIDGE_POLL_INTERVAL)? else {
+            continue;
+        };
+        match &event {
+            // Our own messages, the clients saw them as they sent them
+            Event::ChatMessage(chat) if Some(chat.sender) == client.uuid() => {}
+            Event::Packet(packet) => {
+                client.answer_keep_alive(packet)?;
+            }
+            _ => {
+                server.forward(&event);
+            }
+        }
+    }
+}
+
+fn accounts(command: AccountsCommand, config: &Auth, shutdown: &ShutdownToken) -> Result<()> {
+    let cache = session_cache(config)?;
+    match command {
+        AccountsCommand::Add { name } => {
+            // Microsoft's device code flow: the user signs in on any device
+            // with the code we print, we wait for them and then sign the
+            // account in to Minecraft
+            let client_id = client_id(config)?;
+            let http = HttpClient::default();
+            let code = auth::request_device_code(&http, client_id)?;
+            eprintln!("{}", code.message);
+            let tokens = auth::poll_device_code(&http, client_id, &code, shutdown)?;
+            let session = CachedSession::sign_in(&http, &tokens)?;
+            let replaced = cache.get(&name)?.is_some();
+            cache.insert(&name, session.clone())?;
+            eprintln!(
+                "{} {} as {} ({}) in {}",
+                if replaced { "Replaced" } else { "Saved" },
+                name,
+                session.name,
+                session.uuid,
+                cache.path().display()
+            );
+        }
+        AccountsCommand::List { refresh } => {
+            let names = cache.accounts()?;
+            if names.is_empty() {
+                eprintln!("No saved accounts, add one with mchat accounts add");
+            }
+            let width = names.iter().map(String::len).max().unwrap_or(0);
+            for name in names {
+                let session = match refresh {
+                    true => cache.session(&name, config.client_id.as_deref())?,
+                    false => cache
+                        .get(&name)?
+                        .ok_or_else(|| anyhow!("{} disappeared from the cache", name))?,
+                };
+                let expires_in = session.expires_at.saturating_sub(auth::unix_time());
+                let validity = match expires_in {
+                    0 => String::from("expired, refreshed when used"),
+                    _ => format!(
+                        "valid for {}h{:02}m",
+                        expires_in / 3600,
+                        expires_in / 60 % 60
+                    ),
+                };
+                println!(
+                    "{:width$}  {:16}  {}  {}",
+                    name,
+                    session.name,
+                    session.uuid,
+                    validity,
+                    width = width
+                );
+            }
+        }
+        AccountsCommand::Remove { name } => match cache.remove(&name)? {
+            true => eprintln!("Removed {}", name),
+            false => return Err(anyhow!("There is no account named {}", name)),
+        },
+    }
+    Ok(())
+}
+
+fn client_id(config: &Auth) -> Result<&str> {
+    config.client_id.as_deref().ok_or_else(|| {
+        anyhow!("Signing in needs an Azure app, set client_id under [auth] in the config file")
+    })
+}
+
+// Asks for the passphrase when the config or an existing file want one
+fn session_cache(config: &Auth) -> Result<SessionCache> {
+    let path = match &config.session_cache {
+        Some(path) => path.clone(),
+        None => SessionCache::default_path()
+            .ok_or_else(|| anyhow!("No home directory for the session cache"))?,
+    };
+    let cache = SessionCache::new(path);
+    if !config.encrypt && !cache.is_encrypted()? {
+        return Ok(cache);
+    }
+
+    let passphrase = match env::var("MCHAT_PASSPHRASE") {
+        Ok(passphrase) => passphrase,
+        Err(_) => read_passphrase(
+            &format!("Passphrase for {}", cache.path().display()),
+            "MCHAT_PASSPHRASE",
+        )?,
+    };
+    Ok(cache.passphrase(Some(passphrase)))
+}
+
+// From the terminal, without echoing it. `variable` is where it can come
+// from instead.
+fn read_passphrase(prompt: &str, variable: &str) -> Result<String> {
+    use crossterm::{
+        event::{self, KeyCode, KeyEventKind, KeyModifiers},
+        terminal,
+    };
+
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "{} needs a terminal, set {} to run without one",
+            prompt,
+            variable
+        ));
+    }
+    eprint!("{}: ", prompt);
+    io::stderr().flush()?;
+
+    terminal::enable_raw_mode()?;
+    let mut passphrase = String::new();
+    let result = loop {
+        let key = match event::read() {
+            Ok(event::Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(error) => break Err(error.into()),
+        };
+        match key.code {
+            KeyCode::Enter => break Ok(()),
+            KeyCode::Backspace => {
+                passphrase.pop();
+            }
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                break Err(anyhow!("Cancelled"))
+            }
+            KeyCode::Esc => break Err(anyhow!("Cancelled")),
+            KeyCode::Char(c) => passphrase.push(c),
+            _ => {}
+        }
+    };
+    terminal::disable_raw_mode()?;
+    eprintln!();
+
+    result.map(|_| passphrase)
+}
+
+// `framed` holds the compression threshold of a whole frame
+fn decode(
+    blob: Option<String>,
+    protocol: i32,
+    bound: Bound,
+    state: State,
+    framed: Option<Option<usize>>,
+) -> Result<()> {
+    let features = ProtocolFeatures::for_version(protocol).ok_or_else(|| {
+        anyhow!(
+            "Protocol {} isn't supported, only {}",
+            protocol,
+            PROTOCOLS
+                .iter()
+                .map(|features| format!("{} ({})", features.version, features.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })?;
+    let text = match blob.filter(|blob| blob != "-") {
+        Some(blob) => blob,
+        None => io::read_to_string(io::stdin()).context("Failed to read the packet from stdin")?,
+    };
+    let bytes = parse_blob(&text)?;
+    let direction = match bound {
+        Bound::Clientbound => Direction::Clientbound,
+        Bound::Serverbound => Direction::Serverbound,
+    };
+    let state = match state {
+        State::Handshaking => ConnectionState::Handshaking,
+        State::Status => ConnectionState::Status,
+        State::Login => ConnectionState::Login,
+        State::Play => ConnectionState::Play,
+    };
+
+    let packet = match framed {
+        Some(compression) => decode_frame(features, direction, state, &bytes, compression)?,
+        None => decode_packet(features, direction, state, &bytes)?,
+    };
+    println!("{}", packet);
+    Ok(())
+}
+
+fn proxy(listen: &str, target: &str, payloads: bool, shutdown: &ShutdownToken) -> Result<()> {
+    let listen = match listen.parse::<u16>() {
+        Ok(port) => format!("127.0.0.1:{}", port),
+        Err(_) => String::from(listen),
+    };
+    let (host, port) = split_host_port(target)?;
+    let port = port.unwrap_or(DEFAULT_PORT);
+    let sniffer = Arc::new(Sniffer::new(&host, port));
+
+    let listener =
+        TcpListener::bind(&listen).with_context(|| format!("Failed to listen on {}", listen))?;
+    let wake = listener.local_addr()?;
+    println!("Listening on {}, relaying to {}:{}", wake, host, port);
+    shutdown.wake_on_cancel(wake);
+
+    for (index, stream) in listener.incoming().enumerate() {
+        if shutdown.is_cancelled() {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Failed to accept connection: {}", error);
+                continue;
+            }
+        };
+
+        // Relays only end when a side hangs up, so hang up on the client
+        let closer = stream.try_clone()?;
+        shutdown.on_cancel(move || {
+            let _ = closer.shutdown(Shutdown::Both);
+        });
+        let number = index + 1;
+        let sniffer = sniffer.clone();
+        shutdown.spawn(move |_| {
+            match stream.peer_addr() {
+                Ok(peer) => println!("#{} connected from {}", number, peer),
+                Err(_) => println!("#{} connected", number),
+            }
+            let log = move |packet: &SniffedPacket| print_sniffed(number, packet, payloads);
+            match sniffer.relay(stream, log) {
+                Ok(()) => println!("#{} closed", number),
+                Err(error) => eprintln!("#{} {:#}", number, error),
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn print_sniffed(number: usize, packet: &SniffedPacket, payloads: bool) {
+    let arrow = match packet.direction {
+        Direction::Serverbound => "C->S",
+        Direction::Clientbound => "S->C",
+    };
+    match &packet.decoded {
+        Ok(decoded) if decoded.name == "Unknown" && !payloads => println!(
+            "#{} {} {:?} 0x{:02X} ({} bytes)",
+            number, arrow, packet.state, decoded.id, packet.size
+        ),
+        Ok(decoded) => println!("#{} {} {:?} {}", number, arrow, packet.state, decoded),
+        Err(error) => println!(
+            "#{} {} {:?} {} bytes that don't decode: {}",
+            number, arrow, packet.state, packet.size, error
+        ),
+    }
+}
+
+#[cfg(feature = "bus")]
+fn chat_publisher(config: &Publish, shutdown: &ShutdownToken) -> Result<Option<tui::Publisher>> {
+    let Some(url) = &config.url else {
+        return Ok(None);
+    };
+    let publisher = EventPublisher::new(BusTarget::parse(url)?);
+    let (error_sender, errors) = mpsc::channel();
+    let events = publisher.spawn(shutdown, move |error| {
+        let _ = error_sender.send(format!("{:#}", error));
+    });
+    Ok(Some(tui::Publisher { events, errors }))
+}
+
+#[cfg(not(feature = "bus"))]
+fn chat_publisher(config: &Publish, _: &ShutdownToken) -> Result<Option<tui::Publisher>> {
+    require_bus(config).map(|_| None)
+}
+
+// Called with every sample monitor takes
+type SamplePublisher = Box<dyn FnMut(&StatusSample) -> Result<()>>;
+
+#[cfg(feature = "bus")]
+fn sample_publisher(config: &Publish) -> Result<Option<SamplePublisher>> {
+    let Some(url) = &config.url else {
+        return Ok(None);
+    };
+    let mut publisher = EventPublisher::new(BusTarget::parse(url)?);
+    Ok(Some(Box::new(move |sample| {
+        publisher.publish_sample(sample)
+    })))
+}
+
+#[cfg(not(feature = "bus"))]
+fn sample_publisher(config: &Publish) -> Result<Option<SamplePublisher>> {
+    require_bus(config).map(|_| None)
+}
+
+#[cfg(not(feature = "bus"))]
+fn require_bus(config: &Publish) -> Result<()> {
+    match config.url {
+        Some(_) => Err(anyhow!(
+            "Publishing events needs mchat built with the bus feature"
+        )),
+        None => Ok(()),
+    }
+}
+
+fn chat_rules(rules: &[RuleConfig]) -> Result<ChatRules> {
+    let mut chat_rules = ChatRules::new();
+    for rule in rules {
+        if rule.reply.is_none() && rule.run.is_none() {
+            return Err(anyhow!("Rule {} needs a reply or run", rule.pattern));
+        }
+
+        let (reply, run) = (rule.reply.clone(), rule.run.clone());
+        chat_rules = chat_rules.rule(
+            &rule.pattern,
+            Duration::from_secs(rule.cooldown),
+            move |matched| {
+                if let Some(run) = &run {
+                    // Not waited on, the output would only garble the UI
+                    let spawned = Process::new("sh")
+                        .arg("-c")
+                        .arg(matched.expand(run))
+                        .env("MCHAT_SENDER", matched.sender.unwrap_or_default())
+                        .env("MCHAT_MESSAGE", matched.text)
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .spawn();
+                    // Reaped in the background so it doesn't linger as a zombie
+                    if let Ok(mut child) = spawned {
+                        std::thread::spawn(move || child.wait());
+                    }
+                }
+                reply.as_ref().map(|reply| matched.expand(reply))
+            },
+        )?;
     }
+    Ok(chat_rules)
 }
 
-// let status: MinecraftStatus = serde_json::from_str(&client.status()).unwrap();
+// let status = client.server_status().unwrap();
 
 // let png = status.favicon.unwrap();
 // let png = png.strip_prefix("data:image/png;base64,").unwrap();
@@ -0,0 +1,173 @@
+use crate::{ChatDecoration, ChatTypes, Component, Packet};
+use anyhow::{anyhow, Result};
+use std::{collections::HashSet, str::FromStr};
+use uuid::Uuid;
+
+// A message another player sent, from the Player Chat packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub sender: Uuid,
+    pub sender_name: Component,
+    pub team_name: Option<Component>,
+    // What the server wants shown, which may differ from what was signed
+    pub content: Component,
+    pub signed_content: Component,
+    // Id in the chat type registry sent with Login (play), 0 is plain chat
+    pub chat_type: i32,
+    // What that chat type stands for
+    pub kind: ChatKind,
+    // And how it's shown, None for the bare content
+    pub decoration: Option<ChatDecoration>,
+    // Milliseconds since the epoch, as claimed by the sender
+    pub timestamp: i64,
+}
+
+impl ChatMessage {
+    pub(crate) fn from_packet(packet: &Packet, chat_types: &ChatTypes) -> Result<ChatMessage> {
+        let mut reader = packet.reader();
+        let signed_content = Component::from_json(reader.read_str()?)?;
+        let unsigned_content = match reader.read_bool()? {
+            true => Some(Component::from_json(reader.read_str()?)?),
+            false => None,
+        };
+        let chat_type = reader.read_varint()?;
+        let sender = reader.read_uuid()?;
+        let sender_name = Component::from_json(reader.read_str()?)?;
+        let team_name = match reader.read_bool()? {
+            true => Some(Component::from_json(reader.read_str()?)?),
+            false => None,
+        };
+        let timestamp = reader.read_i64()?;
+
+        Ok(ChatMessage {
+            sender,
+            sender_name,
+            team_name,
+            content: unsigned_content.unwrap_or_else(|| signed_content.clone()),
+            signed_content,
+            chat_type,
+            kind: chat_types.kind(chat_type),
+            decoration: chat_types.decoration(chat_type),
+            timestamp,
+        })
+    }
+
+    // The line as vanilla shows it, decorated the way its chat type says
+    pub fn to_component(&self) -> Component {
+        match &self.decoration {
+            Some(decoration) => {
+                decoration.apply(&self.sender_name, self.team_name.as_ref(), &self.content)
+            }
+            None => self.content.clone(),
+        }
+    }
+}
+
+// Who a message was meant for, from its chat type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChatKind {
+    // Said to everyone, including /me and team chat
+    Public,
+    // /msg, /tell and /w, to us or sent by us
+    Whisper,
+    // Not said by anyone, e.g. /tellraw output
+    System,
+    // /say, the server speaking
+    ServerAnnouncement,
+}
+
+impl ChatKind {
+    // From a chat type name like minecraft:msg_command, unknown ones are public
+    pub fn from_chat_type(name: &str) -> ChatKind {
+        match name.strip_prefix("minecraft:").unwrap_or(name) {
+            "msg_command" | "msg_command_incoming" | "msg_command_outgoing" => ChatKind::Whisper,
+            "say_command" => ChatKind::ServerAnnouncement,
+            "system" | "game_info" | "tellraw_command" => ChatKind::System,
+            _ => ChatKind::Public,
+        }
+    }
+}
+
+// What a system message is about, told apart by its translation key so the
+// result is the same whatever language the server runs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageCategory {
+    JoinLeave,
+    Death,
+    Advancement,
+    Other,
+}
+
+impl MessageCategory {
+    pub fn classify(message: &Component) -> MessageCategory {
+        let key = match &message.translate {
+            Some(key) => key.as_str(),
+            None => return MessageCategory::Other,
+        };
+
+        if key.starts_with("multiplayer.player.joined") || key == "multiplayer.player.left" {
+            MessageCategory::JoinLeave
+        } else if key.starts_with("death.") {
+            MessageCategory::Death
+        } else if key.starts_with("chat.type.advancement.") {
+            MessageCategory::Advancement
+        } else {
+            MessageCategory::Other
+        }
+    }
+}
+
+impl FromStr for MessageCategory {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<MessageCategory> {
+        match name {
+            "join-leave" | "join" | "leave" => Ok(MessageCategory::JoinLeave),
+            "death" => Ok(MessageCategory::Death),
+            "advancement" => Ok(MessageCategory::Advancement),
+            "other" => Ok(MessageCategory::Other),
+            _ => Err(anyhow!("Unknown message category {:?}", name)),
+        }
+    }
+}
+
+// Categories one consumer (the terminal, a relay...) doesn't want to see.
+// Every consumer keeps its own filter so they can be configured independently.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageFilter {
+    suppressed: HashSet<MessageCategory>,
+}
+
+impl MessageFilter {
+    pub fn new() -> MessageFilter {
+        MessageFilter::default()
+    }
+
+    // Comma separated category names, e.g. "join-leave,advancement"
+    pub fn parse(categories: &str) -> Result<MessageFilter> {
+        let mut filter = MessageFilter::new();
+        for name in categories
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            filter.suppress(name.parse()?);
+        }
+
+        Ok(filter)
+    }
+
+    pub fn suppress(&mut self, category: MessageCategory) -> &mut MessageFilter {
+        self.suppressed.insert(category);
+        self
+    }
+
+    pub fn allow(&mut self, category: MessageCategory) -> &mut MessageFilter {
+        self.suppressed.remove(&category);
+        self
+    }
+
+    pub fn allows(&self, category: MessageCategory) -> bool {
+        !self.suppressed.contains(&category)
+    }
+}
@@ -0,0 +1,249 @@
+// Counters and gauges for dashboarding long running bots, rendered in the
+// Prometheus text format. Without the metrics feature Metrics is an empty
+// stand-in, so the places that record don't need a cfg each.
+#[cfg(feature = "metrics")]
+pub use enabled::Metrics;
+
+#[cfg(not(feature = "metrics"))]
+#[derive(Debug, Clone)]
+pub(crate) struct Metrics;
+
+#[cfg(not(feature = "metrics"))]
+impl Metrics {
+    pub(crate) fn packet_received(&self, _payload_length: usize) {}
+    pub(crate) fn packet_sent(&self, _frame_length: usize) {}
+    pub(crate) fn packet_filtered(&self) {}
+    pub(crate) fn chat_received(&self) {}
+    pub(crate) fn chat_sent(&self) {}
+    pub(crate) fn logged_in(&self) {}
+    pub(crate) fn set_ping(&self, _milliseconds: i32) {}
+    pub(crate) fn set_online_players(&self, _players: usize) {}
+}
+
+#[cfg(feature = "metrics")]
+mod enabled {
+    use crate::ShutdownToken;
+    use anyhow::Result;
+    use std::{
+        fmt::Write as _,
+        io::{BufRead, BufReader, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicI64, AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[derive(Debug, Default)]
+    struct Values {
+        packets_received: AtomicU64,
+        packets_sent: AtomicU64,
+        packets_filtered: AtomicU64,
+        bytes_received: AtomicU64,
+        bytes_sent: AtomicU64,
+        chat_received: AtomicU64,
+        chat_sent: AtomicU64,
+        logins: AtomicU64,
+        ping: AtomicI64,
+        online_players: AtomicI64,
+    }
+
+    // Hand the same one to every client a bot makes, e.g. one per reconnect,
+    // and the totals add up across them. Cheap to clone.
+    #[derive(Debug, Clone, Default)]
+    pub struct Metrics {
+        values: Arc<Values>,
+    }
+
+    impl Metrics {
+        pub fn new() -> Metrics {
+            Metrics::default()
+        }
+
+        pub(crate) fn packet_received(&self, payload_length: usize) {
+            self.values.packets_received.fetch_add(1, Ordering::Relaxed);
+            self.values
+                .bytes_received
+                .fetch_add(frame_length(payload_length) as u64, Ordering::Relaxed);
+        }
+
+        pub(crate) fn packet_sent(&self, frame_length: usize) {
+            self.values.packets_sent.fetch_add(1, Ordering::Relaxed);
+            self.values
+                .bytes_sent
+                .fetch_add(frame_length as u64, Ordering::Relaxed);
+        }
+
+        pub(crate) fn packet_filtered(&self) {
+            self.values.packets_filtered.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub(crate) fn chat_received(&self) {
+            self.values.chat_received.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub(crate) fn chat_sent(&self) {
+            self.values.chat_sent.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub(crate) fn logged_in(&self) {
+            self.values.logins.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub(crate) fn set_ping(&self, milliseconds: i32) {
+            self.values
+                .ping
+                .store(milliseconds as i64, Ordering::Relaxed);
+        }
+
+        pub(crate) fn set_online_players(&self, players: usize) {
+            self.values
+                .online_players
+                .store(players as i64, Ordering::Relaxed);
+        }
+
+        // What a /metrics endpoint answers with
+        pub fn render(&self) -> String {
+            let values = &self.values;
+            let logins = values.logins.load(Ordering::Relaxed);
+            let metrics = [
+                (
+                    "mchat_packets_received_total",
+                    "counter",
+                    "Packets read from servers",
+                    values.packets_received.load(Ordering::Relaxed) as i64,
+                ),
+                (
+                    "mchat_packets_sent_total",
+                    "counter",
+                    "Packets sent to servers",
+                    values.packets_sent.load(Ordering::Relaxed) as i64,
+                ),
+                (
+                    "mchat_packets_filtered_total",
+                    "counter",
+                    "Packets skipped unread by the packet filter, also counted as received",
+                    values.packets_filtered.load(Ordering::Relaxed) as i64,
+                ),
+                (
+                    "mchat_bytes_received_total",
+                    "counter",
+                    "Bytes of packet frames read, as compressed on the wire",
+                    values.bytes_received.load(Ordering::Relaxed) as i64,
+                ),
+                (
+                    "mchat_bytes_sent_total",
+                    "counter",
+                    "Bytes of packet frames sent, as compressed on the wire",
+                    values.bytes_sent.load(Ordering::Relaxed) as i64,
+                ),
+                (
+                    "mchat_chat_received_total",
+                    "counter",
+                    "Chat messages received from players",
+                    values.chat_received.load(Ordering::Relaxed) as i64,
+                ),
+                (
+                    "mchat_chat_sent_total",
+                    "counter",
+                    "Chat messages and commands sent",
+                    values.chat_sent.load(Ordering::Relaxed) as i64,
+                ),
+                (
+                    "mchat_logins_total",
+                    "counter",
+                    "Successful logins",
+                    logins as i64,
+                ),
+                (
+                    "mchat_reconnects_total",
+                    "counter",
+                    "Logins after the first one",
+                    logins.saturating_sub(1) as i64,
+                ),
+                (
+                    "mchat_ping_milliseconds",
+                    "gauge",
+                    "Our latency as the server last reported it in the tab list",
+                    values.ping.load(Ordering::Relaxed),
+                ),
+                (
+                    "mchat_online_players",
+                    "gauge",
+                    "Players in the tab list, including us",
+                    values.online_players.load(Ordering::Relaxed),
+                ),
+            ];
+
+            let mut text = String::new();
+            for (name, kind, help, value) in metrics {
+                // Writing to a String can't fail
+                let _ = writeln!(text, "# HELP {} {}", name, help);
+                let _ = writeln!(text, "# TYPE {} {}", name, kind);
+                let _ = writeln!(text, "{} {}", name, value);
+            }
+            text
+        }
+
+        // Answers GET /metrics on `address` from a thread of the token, until
+        // it's cancelled. Returns the bound address, useful with port 0.
+        pub fn serve(&self, address: &str, token: &ShutdownToken) -> Result<SocketAddr> {
+            let listener = TcpListener::bind(address)?;
+            let address = listener.local_addr()?;
+
+            token.wake_on_cancel(address);
+
+            let metrics = self.clone();
+            token.spawn(move |token| {
+                for stream in listener.incoming() {
+                    if token.is_cancelled() {
+                        return;
+                    }
+                    // Errors only end that one scrape
+                    if let Ok(stream) = stream {
+                        let _ = metrics.answer(stream);
+                    }
+                }
+            });
+
+            Ok(address)
+        }
+
+        fn answer(&self, mut stream: TcpStream) -> Result<()> {
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line)?;
+            // The headers are of no interest, but have to be read past
+            let mut header = String::new();
+            while reader.read_line(&mut header)? > 2 {
+                header.clear();
+            }
+
+            let mut parts = request_line.split_whitespace();
+            let (status, body) = match (parts.next(), parts.next()) {
+                (Some("GET"), Some("/metrics")) => ("200 OK", self.render()),
+                _ => ("404 Not Found", String::from("Only /metrics is here\n")),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )?;
+
+            Ok(())
+        }
+    }
+
+    // A frame is its payload plus the varint in front giving its length
+    fn frame_length(payload_length: usize) -> usize {
+        let mut prefix = 1;
+        while payload_length >> (7 * prefix) != 0 {
+            prefix += 1;
+        }
+        payload_length + prefix
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{Client, ServerStatus, ShutdownToken};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// One poll of a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusSample {
+    // Milliseconds since the epoch, when the poll started
+    pub timestamp: i64,
+    pub status: Option<ServerStatus>,
+    pub latency: Option<Duration>,
+    // Players online compared to the last successful poll, None for the first
+    pub online_change: Option<i32>,
+    // Why the server couldn't be polled, status and latency are None then
+    pub error: Option<String>,
+}
+
+impl StatusSample {
+    pub fn online(&self) -> Option<i32> {
+        self.status.as_ref().map(|status| status.players.online)
+    }
+
+    pub fn max(&self) -> Option<i32> {
+        self.status.as_ref().map(|status| status.players.max)
+    }
+
+    // The counts and latency without the rest of the status, one line of
+    // mchat monitor --format json
+    pub fn to_json(&self) -> Value {
+        json!({
+            "timestamp": self.timestamp,
+            "online": self.online(),
+            "max": self.max(),
+            "online_change": self.online_change,
+            "latency_ms": self.latency.map(|latency| latency.as_secs_f64() * 1000.0),
+            "error": self.error,
+        })
+    }
+}
+
+// Polls a server's status every `interval` until the token is cancelled,
+// e.g. to graph its player count. A server that's down is a sample with an
+// error, not the end of monitoring.
+#[derive(Debug, Clone)]
+pub struct StatusMonitor {
+    token: ShutdownToken,
+    interval: Duration,
+    last_online: Option<i32>,
+}
+
+impl StatusMonitor {
+    pub fn new(token: ShutdownToken, interval: Duration) -> StatusMonitor {
+        StatusMonitor {
+            token,
+            interval,
+            last_online: None,
+        }
+    }
+
+    // `connect` makes the client for each poll, so its read timeout and the
+    // like apply to every one of them. `on_sample` runs right after each poll.
+    pub fn run(
+        &mut self,
+        mut connect: impl FnMut() -> Result<Client>,
+        mut on_sample: impl FnMut(&StatusSample),
+    ) {
+        loop {
+            let sample = match connect() {
+                Ok(mut client) => self.sample(&mut client),
+                Err(error) => self.failed(now(), error),
+            };
+            on_sample(&sample);
+
+            if self.token.wait_timeout(self.interval) {
+                return;
+            }
+        }
+    }
+
+    // A single poll, through a client that hasn't logged in
+    pub fn sample(&mut self, client: &mut Client) -> StatusSample {
+        let timestamp = now();
+        let (report, latency) = match client.status_with_ping() {
+            Ok(result) => result,
+            Err(error) => return self.failed(timestamp, error),
+        };
+
+        let online = report.status.players.online;
+        let online_change = self.last_online.map(|last| online - last);
+        self.last_online = Some(online);
+
+        StatusSample {
+            timestamp,
+            status: Some(report.status),
+            latency: Some(latency),
+            online_change,
+            error: None,
+        }
+    }
+
+    fn failed(&self, timestamp: i64, error: anyhow::Error) -> StatusSample {
+        StatusSample {
+            timestamp,
+            status: None,
+            latency: None,
+            online_change: None,
+            error: Some(format!("{:#}", error)),
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
@@ -0,0 +1,169 @@
+use crate::{Packet, ProtocolFeatures};
+use anyhow::Result;
+use std::time::Duration;
+
+// Vanilla clients report their position once per game tick
+pub const TICK_INTERVAL: Duration = Duration::from_millis(50);
+// Height of a standing player's eyes above their feet
+const EYE_HEIGHT: f64 = 1.62;
+
+const RELATIVE_X: u8 = 0x01;
+const RELATIVE_Y: u8 = 0x02;
+const RELATIVE_Z: u8 = 0x04;
+const RELATIVE_YAW: u8 = 0x08;
+const RELATIVE_PITCH: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayerPosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl PlayerPosition {
+    // Turns to face the given point from our eyes
+    pub fn looking_at(mut self, x: f64, y: f64, z: f64) -> PlayerPosition {
+        let dx = x - self.x;
+        let dy = y - (self.y + EYE_HEIGHT);
+        let dz = z - self.z;
+
+        self.yaw = (-dx.atan2(dz)).to_degrees() as f32;
+        self.pitch = (-dy.atan2(dx.hypot(dz))).to_degrees() as f32;
+        self
+    }
+}
+
+// Actions of the Player Command packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PlayerCommand {
+    StartSneaking = 0,
+    StopSneaking = 1,
+    StartSprinting = 3,
+    StopSprinting = 4,
+}
+
+// Synchronize Player Position, the server telling us where we are
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Teleport {
+    pub teleport_id: i32,
+    pub position: PlayerPosition,
+}
+
+impl Teleport {
+    // Fields flagged as relative are offsets from where we currently are
+    pub fn from_packet(packet: &Packet, current: PlayerPosition) -> Result<Teleport> {
+        let mut reader = packet.reader();
+        let x = reader.read_f64()?;
+        let y = reader.read_f64()?;
+        let z = reader.read_f64()?;
+        let yaw = reader.read_f32()?;
+        let pitch = reader.read_f32()?;
+        let flags = reader.read_u8()?;
+        let teleport_id = reader.read_varint()?;
+
+        let relative = |flag: u8| flags & flag != 0;
+        Ok(Teleport {
+            teleport_id,
+            position: PlayerPosition {
+                x: if relative(RELATIVE_X) {
+                    current.x + x
+                } else {
+                    x
+                },
+                y: if relative(RELATIVE_Y) {
+                    current.y + y
+                } else {
+                    y
+                },
+                z: if relative(RELATIVE_Z) {
+                    current.z + z
+                } else {
+                    z
+                },
+                yaw: if relative(RELATIVE_YAW) {
+                    current.yaw + yaw
+                } else {
+                    yaw
+                },
+                pitch: if relative(RELATIVE_PITCH) {
+                    current.pitch + pitch
+                } else {
+                    pitch
+                },
+            },
+        })
+    }
+}
+
+pub(crate) fn confirm_teleportation(
+    features: &ProtocolFeatures,
+    teleport_id: i32,
+) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.confirm_teleport_packet_id as i32)?; // Protocol ID
+    packet.write_varint(teleport_id)?; // Teleport ID
+
+    Ok(packet)
+}
+
+pub(crate) fn position_and_rotation(
+    features: &ProtocolFeatures,
+    position: &PlayerPosition,
+    on_ground: bool,
+) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.player_position_rotation_packet_id as i32)?; // Protocol ID
+    packet.write_slice(&position.x.to_be_bytes()); // X
+    packet.write_slice(&position.y.to_be_bytes()); // Feet Y
+    packet.write_slice(&position.z.to_be_bytes()); // Z
+    packet.write_slice(&position.yaw.to_be_bytes()); // Yaw
+    packet.write_slice(&position.pitch.to_be_bytes()); // Pitch
+    packet.write_bool(on_ground); // On ground
+
+    Ok(packet)
+}
+
+pub(crate) fn position(
+    features: &ProtocolFeatures,
+    position: &PlayerPosition,
+    on_ground: bool,
+) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.player_position_packet_id as i32)?; // Protocol ID
+    packet.write_slice(&position.x.to_be_bytes()); // X
+    packet.write_slice(&position.y.to_be_bytes()); // Feet Y
+    packet.write_slice(&position.z.to_be_bytes()); // Z
+    packet.write_bool(on_ground); // On ground
+
+    Ok(packet)
+}
+
+pub(crate) fn rotation(
+    features: &ProtocolFeatures,
+    position: &PlayerPosition,
+    on_ground: bool,
+) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.player_rotation_packet_id as i32)?; // Protocol ID
+    packet.write_slice(&position.yaw.to_be_bytes()); // Yaw
+    packet.write_slice(&position.pitch.to_be_bytes()); // Pitch
+    packet.write_bool(on_ground); // On ground
+
+    Ok(packet)
+}
+
+pub(crate) fn player_command(
+    features: &ProtocolFeatures,
+    entity_id: i32,
+    command: PlayerCommand,
+) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(features.player_command_packet_id as i32)?; // Protocol ID
+    packet.write_varint(entity_id)?; // Entity ID
+    packet.write_varint(command as i32)?; // Action ID
+    packet.write_varint(0)?; // Jump boost, only used by horses
+
+    Ok(packet)
+}
@@ -0,0 +1,65 @@
+// Longest chat message vanilla servers accept, in characters
+pub const MAX_CHAT_LENGTH: usize = 256;
+pub const DEFAULT_CONTINUATION: &str = "... ";
+
+// Newlines can't be said in chat, and would forge extra lines elsewhere
+pub(crate) fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Splits `text` into messages that fit the limit: one or more per line,
+// wrapped between words where possible. Wrapped parts after the first start
+// with `continuation`, which is dropped if it would leave too little room.
+// Whitespace runs become single spaces, like servers normalize them anyway.
+pub fn split_chat_message(text: &str, continuation: &str) -> Vec<String> {
+    let continuation = match continuation.chars().count() < MAX_CHAT_LENGTH / 2 {
+        true => continuation,
+        false => "",
+    };
+
+    let mut messages = Vec::new();
+    for line in text.lines() {
+        let mut current = String::new();
+        let mut length = 0;
+        // No word in `current` yet, there may be a continuation
+        let mut fresh = true;
+
+        for word in line.split_whitespace() {
+            let mut word = word;
+            loop {
+                let needed = word.chars().count() + usize::from(!fresh);
+                if length + needed <= MAX_CHAT_LENGTH {
+                    if !fresh {
+                        current.push(' ');
+                    }
+                    current.push_str(word);
+                    length += needed;
+                    fresh = false;
+                    break;
+                }
+
+                // A word too long for any message is cut where the room ends
+                if fresh {
+                    let cut = word
+                        .char_indices()
+                        .nth(MAX_CHAT_LENGTH - length)
+                        .map_or(word.len(), |(index, _)| index);
+                    current.push_str(&word[..cut]);
+                    word = &word[cut..];
+                }
+                messages.push(std::mem::replace(&mut current, String::from(continuation)));
+                length = continuation.chars().count();
+                fresh = true;
+                if word.is_empty() {
+                    break;
+                }
+            }
+        }
+
+        if !fresh {
+            messages.push(current);
+        }
+    }
+
+    messages
+}
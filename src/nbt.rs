@@ -0,0 +1,529 @@
+use crate::PacketReader;
+use anyhow::{anyhow, Result};
+use serde::{
+    de::{
+        value::{Error, MapAccessDeserializer, MapDeserializer, SeqDeserializer},
+        DeserializeOwned, Deserializer, IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::SerializeMap,
+    ser::SerializeSeq,
+    Serialize, Serializer,
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// Vanilla refuses anything nested deeper, and so do we, before recursion
+// gets the chance to overflow the stack
+pub const MAX_NBT_DEPTH: usize = 512;
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+
+// Named Binary Tag, the format item data, registries and chunk heightmaps
+// come in. Compounds keep their entries sorted by name, so writing back what
+// was read gives the same tags but not necessarily the same bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    // Elements all have the same type, an empty list is written as a list of End
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+
+    // Entry of a compound, None for anything else
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.get(name),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Tag>> {
+        match self {
+            Tag::Compound(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Any of the integer types, widened
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(value) => Some(*value as i64),
+            Tag::Short(value) => Some(*value as i64),
+            Tag::Int(value) => Some(*value as i64),
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Tag::Float(value) => Some(*value as f64),
+            Tag::Double(value) => Some(*value),
+            _ => self.as_i64().map(|value| value as f64),
+        }
+    }
+
+    // A root tag with its name, as in 1.19 packets and files. Returns None for
+    // the single End byte that stands for "no NBT", e.g. in item slots.
+    pub fn read_named(reader: &mut PacketReader) -> Result<Option<(String, Tag)>> {
+        let id = reader.read_u8()?;
+        if id == END {
+            return Ok(None);
+        }
+        let name = read_string(reader)?;
+        Ok(Some((name, read_payload(reader, id, 0)?)))
+    }
+
+    // A root tag without a name, what 1.20.2 and later send over the network
+    pub fn read_network(reader: &mut PacketReader) -> Result<Option<Tag>> {
+        let id = reader.read_u8()?;
+        if id == END {
+            return Ok(None);
+        }
+        Ok(Some(read_payload(reader, id, 0)?))
+    }
+
+    pub fn write_named(&self, name: &str, out: &mut Vec<u8>) -> Result<()> {
+        out.push(self.id());
+        write_string(name, out)?;
+        self.write_payload(out)
+    }
+
+    pub fn write_network(&self, out: &mut Vec<u8>) -> Result<()> {
+        out.push(self.id());
+        self.write_payload(out)
+    }
+
+    fn write_payload(&self, out: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Tag::Byte(value) => out.push(*value as u8),
+            Tag::Short(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Int(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Long(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Float(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Double(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::ByteArray(values) => {
+                write_length(values.len(), out)?;
+                out.extend(values.iter().map(|value| *value as u8));
+            }
+            Tag::String(value) => write_string(value, out)?,
+            Tag::List(elements) => {
+                let id = elements.first().map_or(END, Tag::id);
+                if elements.iter().any(|element| element.id() != id) {
+                    return Err(anyhow!("NBT list elements must all have the same type"));
+                }
+                out.push(id);
+                write_length(elements.len(), out)?;
+                for element in elements {
+                    element.write_payload(out)?;
+                }
+            }
+            Tag::Compound(entries) => {
+                for (name, tag) in entries {
+                    tag.write_named(name, out)?;
+                }
+                out.push(END);
+            }
+            Tag::IntArray(values) => {
+                write_length(values.len(), out)?;
+                for value in values {
+                    out.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            Tag::LongArray(values) => {
+                write_length(values.len(), out)?;
+                for value in values {
+                    out.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_payload(reader: &mut PacketReader, id: u8, depth: usize) -> Result<Tag> {
+    if depth > MAX_NBT_DEPTH {
+        return Err(anyhow!("NBT nested deeper than {}", MAX_NBT_DEPTH));
+    }
+
+    Ok(match id {
+        BYTE => Tag::Byte(reader.read_u8()? as i8),
+        SHORT => Tag::Short(reader.read_i16()?),
+        INT => Tag::Int(reader.read_i32()?),
+        LONG => Tag::Long(reader.read_i64()?),
+        FLOAT => Tag::Float(reader.read_f32()?),
+        DOUBLE => Tag::Double(reader.read_f64()?),
+        BYTE_ARRAY => {
+            let length = read_length(reader)?;
+            Tag::ByteArray(
+                reader
+                    .read_bytes(length)?
+                    .iter()
+                    .map(|value| *value as i8)
+                    .collect(),
+            )
+        }
+        STRING => Tag::String(read_string(reader)?),
+        LIST => {
+            let element_id = reader.read_u8()?;
+            let length = read_length(reader)?;
+            if element_id == END && length > 0 {
+                return Err(anyhow!("NBT list of {} End tags", length));
+            }
+            // Grown as elements are read rather than reserved up front, a
+            // Tag is far bigger than the byte an element can take
+            let mut elements = Vec::new();
+            for _ in 0..length {
+                elements.push(read_payload(reader, element_id, depth + 1)?);
+            }
+            Tag::List(elements)
+        }
+        COMPOUND => {
+            let mut entries = BTreeMap::new();
+            loop {
+                let id = reader.read_u8()?;
+                if id == END {
+                    break;
+                }
+                let name = read_string(reader)?;
+                entries.insert(name, read_payload(reader, id, depth + 1)?);
+            }
+            Tag::Compound(entries)
+        }
+        INT_ARRAY => {
+            let length = read_length(reader)?;
+            let bytes = reader.read_bytes(length.checked_mul(4).ok_or_else(too_long)?)?;
+            Tag::IntArray(
+                bytes
+                    .chunks_exact(4)
+                    .map(|chunk| i32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                    .collect(),
+            )
+        }
+        LONG_ARRAY => {
+            let length = read_length(reader)?;
+            let bytes = reader.read_bytes(length.checked_mul(8).ok_or_else(too_long)?)?;
+            Tag::LongArray(
+                bytes
+                    .chunks_exact(8)
+                    .map(|chunk| i64::from_be_bytes(chunk.try_into().unwrap()))
+                    .collect(),
+            )
+        }
+        other => return Err(anyhow!("Unknown NBT tag type {}", other)),
+    })
+}
+
+fn too_long() -> anyhow::Error {
+    anyhow!("NBT array too long")
+}
+
+fn read_length(reader: &mut PacketReader) -> Result<usize> {
+    let length = reader.read_i32()?;
+    usize::try_from(length).map_err(|_| anyhow!("Negative NBT length {}", length))
+}
+
+fn write_length(length: usize, out: &mut Vec<u8>) -> Result<()> {
+    let length = i32::try_from(length).map_err(|_| too_long())?;
+    out.extend_from_slice(&length.to_be_bytes());
+    Ok(())
+}
+
+// Strings are Java's modified UTF-8 behind an unsigned short length
+fn read_string(reader: &mut PacketReader) -> Result<String> {
+    let length = reader.read_u16()? as usize;
+    decode_modified_utf8(reader.read_bytes(length)?)
+}
+
+fn write_string(value: &str, out: &mut Vec<u8>) -> Result<()> {
+    let bytes = encode_modified_utf8(value);
+    let length = u16::try_from(bytes.len())
+        .map_err(|_| anyhow!("NBT string of {} bytes is too long", bytes.len()))?;
+    out.extend_from_slice(&length.to_be_bytes());
+    out.extend_from_slice(&bytes);
+    Ok(())
+}
+
+// Modified UTF-8 writes NUL as two bytes and characters outside the BMP as
+// two 3 byte surrogates. Anything else is plain UTF-8.
+fn decode_modified_utf8(bytes: &[u8]) -> Result<String> {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(String::from(text));
+    }
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index] as u16;
+        let (unit, size) = match byte {
+            0x00..=0x7F => (byte, 1),
+            0xC0..=0xDF => ((byte & 0x1F) << 6 | continuation(bytes, index + 1)?, 2),
+            0xE0..=0xEF => (
+                (byte & 0x0F) << 12
+                    | continuation(bytes, index + 1)? << 6
+                    | continuation(bytes, index + 2)?,
+                3,
+            ),
+            _ => return Err(anyhow!("Invalid modified UTF-8 byte {:#04x}", byte)),
+        };
+        units.push(unit);
+        index += size;
+    }
+
+    String::from_utf16(&units).map_err(|_| anyhow!("Unpaired surrogate in NBT string"))
+}
+
+fn continuation(bytes: &[u8], index: usize) -> Result<u16> {
+    match bytes.get(index) {
+        Some(byte) if byte & 0xC0 == 0x80 => Ok((byte & 0x3F) as u16),
+        _ => Err(anyhow!("Truncated modified UTF-8 sequence")),
+    }
+}
+
+fn encode_modified_utf8(value: &str) -> Vec<u8> {
+    if !value
+        .chars()
+        .any(|character| character == '\0' || character as u32 > 0xFFFF)
+    {
+        return value.as_bytes().to_vec();
+    }
+
+    let mut bytes = Vec::with_capacity(value.len() + 8);
+    for unit in value.encode_utf16() {
+        match unit {
+            0x01..=0x7F => bytes.push(unit as u8),
+            0x00 | 0x80..=0x7FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | (unit >> 6 & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+    bytes
+}
+
+// Serializes as the plain values, compounds become maps and every kind of
+// list or array a sequence. That is the shape from_tag deserializes from.
+impl Serialize for Tag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Tag::Byte(value) => serializer.serialize_i8(*value),
+            Tag::Short(value) => serializer.serialize_i16(*value),
+            Tag::Int(value) => serializer.serialize_i32(*value),
+            Tag::Long(value) => serializer.serialize_i64(*value),
+            Tag::Float(value) => serializer.serialize_f32(*value),
+            Tag::Double(value) => serializer.serialize_f64(*value),
+            Tag::String(value) => serializer.serialize_str(value),
+            Tag::ByteArray(values) => serializer.collect_seq(values),
+            Tag::IntArray(values) => serializer.collect_seq(values),
+            Tag::LongArray(values) => serializer.collect_seq(values),
+            Tag::List(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            Tag::Compound(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (name, tag) in entries {
+                    map.serialize_entry(name, tag)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+// Decodes a tag into any Deserialize type, e.g. a registry entry struct.
+// Booleans are accepted from bytes where a bool is asked for, which is how
+// NBT stores them.
+pub fn from_tag<T: DeserializeOwned>(tag: &Tag) -> Result<T> {
+    Ok(T::deserialize(TagDeserializer(tag))?)
+}
+
+// The other way around. The exact NBT types are lost in between: integers
+// become Int or Long depending on their size, floats Double, booleans Byte
+// and sequences Lists.
+pub fn to_tag<T: Serialize>(value: &T) -> Result<Tag> {
+    value_to_tag(serde_json::to_value(value)?)
+}
+
+fn value_to_tag(value: Value) -> Result<Tag> {
+    Ok(match value {
+        Value::Null => return Err(anyhow!("NBT has no null")),
+        Value::Bool(value) => Tag::Byte(value as i8),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => match i32::try_from(value) {
+                Ok(value) => Tag::Int(value),
+                Err(_) => Tag::Long(value),
+            },
+            None => Tag::Double(
+                number
+                    .as_f64()
+                    .ok_or_else(|| anyhow!("{} doesn't fit NBT", number))?,
+            ),
+        },
+        Value::String(value) => Tag::String(value),
+        Value::Array(values) => Tag::List(
+            values
+                .into_iter()
+                .map(value_to_tag)
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(entries) => Tag::Compound(
+            entries
+                .into_iter()
+                .map(|(name, value)| Ok((name, value_to_tag(value)?)))
+                .collect::<Result<_>>()?,
+        ),
+    })
+}
+
+struct TagDeserializer<'a>(&'a Tag);
+
+impl<'de> IntoDeserializer<'de, Error> for TagDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for TagDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_i8(*value),
+            Tag::Short(value) => visitor.visit_i16(*value),
+            Tag::Int(value) => visitor.visit_i32(*value),
+            Tag::Long(value) => visitor.visit_i64(*value),
+            Tag::Float(value) => visitor.visit_f32(*value),
+            Tag::Double(value) => visitor.visit_f64(*value),
+            Tag::String(value) => visitor.visit_borrowed_str(value),
+            Tag::ByteArray(values) => {
+                visitor.visit_seq(SeqDeserializer::new(values.iter().copied()))
+            }
+            Tag::IntArray(values) => {
+                visitor.visit_seq(SeqDeserializer::new(values.iter().copied()))
+            }
+            Tag::LongArray(values) => {
+                visitor.visit_seq(SeqDeserializer::new(values.iter().copied()))
+            }
+            Tag::List(elements) => {
+                visitor.visit_seq(SeqDeserializer::new(elements.iter().map(TagDeserializer)))
+            }
+            Tag::Compound(entries) => visitor.visit_map(MapDeserializer::new(
+                entries
+                    .iter()
+                    .map(|(name, tag)| (name.as_str(), TagDeserializer(tag))),
+            )),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_bool(*value != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    // NBT has no null, a tag that's there is always Some
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Unit variants by name, the others as a compound of one entry
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Tag::String(name) => visitor.visit_enum(name.as_str().into_deserializer()),
+            Tag::Compound(entries) if entries.len() == 1 => {
+                visitor.visit_enum(MapAccessDeserializer::new(MapDeserializer::new(
+                    entries
+                        .iter()
+                        .map(|(name, tag)| (name.as_str(), TagDeserializer(tag))),
+                )))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
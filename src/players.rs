@@ -0,0 +1,118 @@
+use crate::{profile, Event, Packet, PacketReader, ProfileProperty};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerInfo {
+    pub uuid: Uuid,
+    pub name: String,
+    pub properties: Vec<ProfileProperty>,
+    pub gamemode: i32,
+    pub latency: i32,
+    // Raw JSON chat component, if the server overrides the name
+    pub display_name: Option<String>,
+}
+
+// The tab list as the server describes it through Player Info packets
+#[derive(Debug, Clone, Default)]
+pub struct PlayerList {
+    players: HashMap<Uuid, PlayerInfo>,
+}
+
+impl PlayerList {
+    pub fn players(&self) -> &HashMap<Uuid, PlayerInfo> {
+        &self.players
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&PlayerInfo> {
+        self.players
+            .values()
+            .find(|player| player.name.eq_ignore_ascii_case(name))
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.players.clear();
+    }
+
+    // Applies a Player Info packet, returning join and leave events
+    pub(crate) fn handle_player_info(&mut self, packet: &Packet) -> Result<Vec<Event>> {
+        let mut reader = packet.reader();
+        let action = reader.read_varint()?;
+        let count = reader.read_varint()?;
+
+        let mut events = Vec::new();
+        for _ in 0..count {
+            let uuid = reader.read_uuid()?;
+            match action {
+                0 => {
+                    let player = read_added_player(&mut reader, uuid)?;
+                    let joined = !self.players.contains_key(&uuid);
+                    self.players.insert(uuid, player.clone());
+                    if joined {
+                        events.push(Event::PlayerJoined(player));
+                    }
+                }
+                1 => {
+                    let gamemode = reader.read_varint()?;
+                    if let Some(player) = self.players.get_mut(&uuid) {
+                        player.gamemode = gamemode;
+                    }
+                }
+                2 => {
+                    let latency = reader.read_varint()?;
+                    if let Some(player) = self.players.get_mut(&uuid) {
+                        player.latency = latency;
+                    }
+                }
+                3 => {
+                    let display_name = read_optional_string(&mut reader)?;
+                    if let Some(player) = self.players.get_mut(&uuid) {
+                        player.display_name = display_name;
+                    }
+                }
+                4 => {
+                    if let Some(player) = self.players.remove(&uuid) {
+                        events.push(Event::PlayerLeft(player));
+                    }
+                }
+                other => return Err(anyhow!("Unknown player info action {}", other)),
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+fn read_added_player(reader: &mut PacketReader, uuid: Uuid) -> Result<PlayerInfo> {
+    let name = reader.read_str()?.to_owned();
+
+    let properties = profile::read_properties(reader)?;
+
+    let gamemode = reader.read_varint()?;
+    let latency = reader.read_varint()?;
+    let display_name = read_optional_string(reader)?;
+
+    // Chat signing key, which we don't verify
+    if reader.read_bool()? {
+        reader.read_i64()?; // expiry timestamp
+        reader.read_byte_array()?; // public key
+        reader.read_byte_array()?; // signature
+    }
+
+    Ok(PlayerInfo {
+        uuid,
+        name,
+        properties,
+        gamemode,
+        latency,
+        display_name,
+    })
+}
+
+fn read_optional_string(reader: &mut PacketReader) -> Result<Option<String>> {
+    Ok(match reader.read_bool()? {
+        true => Some(reader.read_str()?.to_owned()),
+        false => None,
+    })
+}
@@ -0,0 +1,201 @@
+use crate::{ClientBuilder, Event, ShutdownToken};
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::Duration,
+};
+
+// How long a client thread waits for packets before looking at its commands
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Handed out by ClientPool::add, never reused within a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(pub usize);
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum PoolEvent {
+    // Logged in, events follow
+    Connected,
+    Event(Event),
+    // The client is gone for good, with the error that ended it if any
+    Disconnected(Option<String>),
+}
+
+enum PoolCommand {
+    Chat(String),
+    Command(String),
+}
+
+struct Member {
+    label: String,
+    commands: Sender<PoolCommand>,
+    shutdown: ShutdownToken,
+}
+
+// Runs any number of clients on their own threads, across servers or
+// accounts, and merges their events into one stream tagged with the client
+// they came from. Keep alives are answered by the pool.
+pub struct ClientPool {
+    shutdown: ShutdownToken,
+    members: HashMap<ClientId, Member>,
+    next_id: usize,
+    events: Sender<(ClientId, PoolEvent)>,
+    receiver: Receiver<(ClientId, PoolEvent)>,
+}
+
+impl ClientPool {
+    // Cancelling `shutdown` disconnects every client in the pool
+    pub fn new(shutdown: ShutdownToken) -> ClientPool {
+        let (events, receiver) = mpsc::channel();
+        ClientPool {
+            shutdown,
+            members: HashMap::new(),
+            next_id: 0,
+            events,
+            receiver,
+        }
+    }
+
+    // Connects and logs in on a new thread, failures arrive as
+    // PoolEvent::Disconnected. `label` is for the caller, e.g. host or username.
+    pub fn add(&mut self, label: &str, builder: ClientBuilder) -> ClientId {
+        let id = ClientId(self.next_id);
+        self.next_id += 1;
+
+        // Each client gets its own token so it can be removed on its own
+        let client_shutdown = ShutdownToken::new();
+        let token = client_shutdown.clone();
+        self.shutdown.on_cancel(move || token.cancel());
+
+        let (commands, receiver) = mpsc::channel();
+        let events = self.events.clone();
+        let builder = builder.shutdown_token(client_shutdown.clone());
+        self.shutdown.spawn(move |_| {
+            let error = run(id, builder, &receiver, &events).err();
+            // A removed client ending with an error is expected
+            let error = error.filter(|_| !receiver_closed(&receiver));
+            let _ = events.send((
+                id,
+                PoolEvent::Disconnected(error.map(|e| format!("{:#}", e))),
+            ));
+        });
+
+        self.members.insert(
+            id,
+            Member {
+                label: String::from(label),
+                commands,
+                shutdown: client_shutdown,
+            },
+        );
+        id
+    }
+
+    // Disconnects a client, its Disconnected event still arrives
+    pub fn remove(&mut self, id: ClientId) -> bool {
+        match self.members.remove(&id) {
+            Some(member) => {
+                member.shutdown.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn label(&self, id: ClientId) -> Option<&str> {
+        self.members.get(&id).map(|member| member.label.as_str())
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.members.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    // Messages starting with / are sent as commands
+    pub fn send_chat_message(&self, id: ClientId, message: &str) -> Result<()> {
+        let command = match message.strip_prefix('/') {
+            Some(command) => PoolCommand::Command(String::from(command)),
+            None => PoolCommand::Chat(String::from(message)),
+        };
+        self.send(id, command)
+    }
+
+    pub fn broadcast_chat_message(&self, message: &str) {
+        for id in self.members.keys() {
+            // A client that already ended reports so through its events
+            let _ = self.send_chat_message(*id, message);
+        }
+    }
+
+    // The next event of any client, None if nothing arrived within `timeout`
+    pub fn poll_event(&mut self, timeout: Duration) -> Option<(ClientId, PoolEvent)> {
+        let event = match self.receiver.recv_timeout(timeout) {
+            Ok(event) => event,
+            // We hold a sender ourselves, so the channel never disconnects
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return None,
+        };
+        if let (id, PoolEvent::Disconnected(_)) = &event {
+            self.members.remove(id);
+        }
+        Some(event)
+    }
+
+    fn send(&self, id: ClientId, command: PoolCommand) -> Result<()> {
+        let member = self
+            .members
+            .get(&id)
+            .ok_or_else(|| anyhow!("No client {} in the pool", id))?;
+        member
+            .commands
+            .send(command)
+            .map_err(|_| anyhow!("Client {} ({}) has disconnected", id, member.label))
+    }
+}
+
+fn receiver_closed(receiver: &Receiver<PoolCommand>) -> bool {
+    matches!(receiver.try_recv(), Err(mpsc::TryRecvError::Disconnected))
+}
+
+fn run(
+    id: ClientId,
+    builder: ClientBuilder,
+    commands: &Receiver<PoolCommand>,
+    events: &Sender<(ClientId, PoolEvent)>,
+) -> Result<()> {
+    let mut client = builder.connect()?;
+    client.login()?;
+    events.send((id, PoolEvent::Connected))?;
+
+    loop {
+        for command in commands.try_iter() {
+            match command {
+                PoolCommand::Chat(message) => client.send_chat_message(&message)?,
+                PoolCommand::Command(command) => client.send_command(&command)?,
+            };
+        }
+
+        let event = match client.poll_event(POLL_INTERVAL)? {
+            Some(event) => event,
+            None => continue,
+        };
+        if let Event::Packet(packet) = &event {
+            client.answer_keep_alive(packet)?;
+        }
+        events.send((id, PoolEvent::Event(event)))?;
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Result};
+
+// Coordinates are packed into one long: 26 bits of x, 26 of z, 12 of y
+const HORIZONTAL_BITS: u32 = 26;
+const VERTICAL_BITS: u32 = 12;
+
+// A block in the world, as the Position fields of packets carry it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BlockPosition {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPosition {
+    pub fn new(x: i32, y: i32, z: i32) -> BlockPosition {
+        BlockPosition { x, y, z }
+    }
+
+    pub fn from_packed(value: i64) -> BlockPosition {
+        // Shifting left then arithmetically right sign extends each field
+        BlockPosition {
+            x: (value >> (64 - HORIZONTAL_BITS)) as i32,
+            y: (value << (64 - VERTICAL_BITS) >> (64 - VERTICAL_BITS)) as i32,
+            z: (value << HORIZONTAL_BITS >> (64 - HORIZONTAL_BITS)) as i32,
+        }
+    }
+
+    // Fails for coordinates outside what the packing can hold, which is also
+    // outside any world the server would accept
+    pub fn to_packed(&self) -> Result<i64> {
+        let horizontal = -(1 << (HORIZONTAL_BITS - 1))..(1 << (HORIZONTAL_BITS - 1));
+        let vertical = -(1 << (VERTICAL_BITS - 1))..(1 << (VERTICAL_BITS - 1));
+        if !horizontal.contains(&self.x)
+            || !horizontal.contains(&self.z)
+            || !vertical.contains(&self.y)
+        {
+            return Err(anyhow!("Block position {:?} can't be packed", self));
+        }
+
+        let x = self.x as i64 & ((1 << HORIZONTAL_BITS) - 1);
+        let z = self.z as i64 & ((1 << HORIZONTAL_BITS) - 1);
+        let y = self.y as i64 & ((1 << VERTICAL_BITS) - 1);
+        Ok(x << (64 - HORIZONTAL_BITS) | z << VERTICAL_BITS | y)
+    }
+
+    // The block containing a point, e.g. where a player stands
+    pub fn containing(x: f64, y: f64, z: f64) -> BlockPosition {
+        BlockPosition {
+            x: x.floor() as i32,
+            y: y.floor() as i32,
+            z: z.floor() as i32,
+        }
+    }
+
+    // Middle of the block, handy for looking at it
+    pub fn center(&self) -> (f64, f64, f64) {
+        (
+            self.x as f64 + 0.5,
+            self.y as f64 + 0.5,
+            self.z as f64 + 0.5,
+        )
+    }
+}
+
+// Rotation in steps of 1/256 of a full turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Angle(pub u8);
+
+impl Angle {
+    // Wraps around like vanilla does, so -90 and 270 are the same angle
+    pub fn from_degrees(degrees: f32) -> Angle {
+        Angle((degrees * 256.0 / 360.0).floor() as i32 as u8)
+    }
+
+    pub fn to_degrees(self) -> f32 {
+        self.0 as f32 * 360.0 / 256.0
+    }
+}
@@ -0,0 +1,79 @@
+use crate::PacketReader;
+use anyhow::Result;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+// The UUID offline mode servers give a player, the same one vanilla derives
+pub fn offline_uuid(name: &str) -> Uuid {
+    let mut hash: [u8; 16] = Md5::digest(format!("OfflinePlayer:{}", name)).into();
+    hash[6] = (hash[6] & 0x0F) | 0x30; // version 3
+    hash[8] = (hash[8] & 0x3F) | 0x80; // RFC 4122 variant
+    Uuid::from_bytes(hash)
+}
+
+// Who a player is: what Login Success tells us about ourselves
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub uuid: Uuid,
+    pub name: String,
+    pub properties: Vec<ProfileProperty>,
+}
+
+impl Profile {
+    // The profile an offline mode server gives `name`
+    pub fn offline(name: &str) -> Profile {
+        Profile {
+            uuid: offline_uuid(name),
+            name: String::from(name),
+            properties: Vec::new(),
+        }
+    }
+
+    // Offline mode servers hand out derived UUIDs, online ones Mojang's
+    pub fn is_offline(&self) -> bool {
+        self.uuid == offline_uuid(&self.name)
+    }
+
+    // Skin and cape, base64 JSON signed by Mojang. Offline profiles have none.
+    pub fn textures(&self) -> Option<&ProfileProperty> {
+        self.properties
+            .iter()
+            .find(|property| property.name == "textures")
+    }
+
+    // UUID, name and properties, as in Login Success
+    pub(crate) fn read(reader: &mut PacketReader) -> Result<Profile> {
+        Ok(Profile {
+            uuid: reader.read_uuid()?,
+            name: reader.read_str()?.to_owned(),
+            properties: read_properties(reader)?,
+        })
+    }
+}
+
+// A varint count, then name, value and optional signature of each
+pub(crate) fn read_properties(reader: &mut PacketReader) -> Result<Vec<ProfileProperty>> {
+    let count = reader.read_varint()?;
+    let mut properties = Vec::new();
+    for _ in 0..count {
+        properties.push(ProfileProperty {
+            name: reader.read_str()?.to_owned(),
+            value: reader.read_str()?.to_owned(),
+            signature: match reader.read_bool()? {
+                true => Some(reader.read_str()?.to_owned()),
+                false => None,
+            },
+        });
+    }
+
+    Ok(properties)
+}
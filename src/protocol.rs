@@ -0,0 +1,154 @@
+use crate::PROTOCOL_VERSION;
+
+// What changes between Minecraft releases, in one place. The client's packet
+// code asks its ProtocolFeatures instead of hardcoding ids and layouts, so a
+// release (or a snapshot) that only moves ids around is one more entry in
+// PROTOCOLS. Handshake and status ids are left out, they're the same in
+// every release so any client can ping any server. The testing server only
+// speaks PROTOCOL_VERSION.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolFeatures {
+    pub version: i32,
+    pub name: &'static str,
+    // Chat and commands carry a timestamp, salt and signature (1.19+)
+    pub has_chat_signing: bool,
+    // Login Start has the optional public key of 1.19 and 1.19.1
+    pub has_login_signature: bool,
+
+    // Clientbound login
+    pub login_disconnect_packet_id: u8,
+    pub encryption_request_packet_id: u8,
+    pub login_success_packet_id: u8,
+    pub compression_packet_id: u8,
+    pub login_plugin_request_packet_id: u8,
+
+    // Serverbound login
+    pub login_start_packet_id: u8,
+    pub login_plugin_response_packet_id: u8,
+
+    // Clientbound play
+    pub disconnect_packet_id: u8,
+    pub keep_alive_packet_id: u8,
+    pub login_play_packet_id: u8,
+    pub player_chat_packet_id: u8,
+    pub system_chat_packet_id: u8,
+    pub player_info_packet_id: u8,
+    pub combat_death_packet_id: u8,
+    pub respawn_packet_id: u8,
+    pub set_health_packet_id: u8,
+    pub set_experience_packet_id: u8,
+    pub synchronize_position_packet_id: u8,
+    pub resource_pack_packet_id: u8,
+    pub command_suggestions_packet_id: u8,
+    pub boss_bar_packet_id: u8,
+    pub display_objective_packet_id: u8,
+    pub update_objectives_packet_id: u8,
+    pub update_score_packet_id: u8,
+    pub clear_titles_packet_id: u8,
+    pub action_bar_packet_id: u8,
+    pub subtitle_packet_id: u8,
+    pub title_packet_id: u8,
+    pub title_times_packet_id: u8,
+    pub spawn_entity_packet_id: u8,
+    pub spawn_player_packet_id: u8,
+    pub entity_position_packet_id: u8,
+    pub entity_position_rotation_packet_id: u8,
+    pub entity_rotation_packet_id: u8,
+    pub remove_entities_packet_id: u8,
+    pub teleport_entity_packet_id: u8,
+    // Sends us to another server, None before 1.20.5 and so in every entry
+    // of PROTOCOLS, see ClientBuilder::follow_transfers
+    pub transfer_packet_id: Option<u8>,
+    // Ids up to this one are the release's, anything above is unexpected,
+    // see DecodeMode
+    pub last_play_packet_id: u8,
+
+    // Serverbound play
+    pub chat_command_packet_id: u8,
+    pub chat_message_packet_id: u8,
+    pub client_information_packet_id: u8,
+    pub keep_alive_response_packet_id: u8,
+    pub plugin_message_packet_id: u8,
+    pub confirm_teleport_packet_id: u8,
+    pub client_command_packet_id: u8,
+    pub command_suggestions_request_packet_id: u8,
+    pub player_position_packet_id: u8,
+    pub player_position_rotation_packet_id: u8,
+    pub player_rotation_packet_id: u8,
+    pub player_command_packet_id: u8,
+    pub resource_pack_status_packet_id: u8,
+}
+
+// Every release the packet code knows how to speak
+pub const PROTOCOLS: &[ProtocolFeatures] = &[ProtocolFeatures {
+    version: 759,
+    name: "1.19",
+    has_chat_signing: true,
+    has_login_signature: true,
+    login_disconnect_packet_id: 0x00,
+    encryption_request_packet_id: 0x01,
+    login_success_packet_id: 0x02,
+    compression_packet_id: 0x03,
+    login_plugin_request_packet_id: 0x04,
+    login_start_packet_id: 0x00,
+    login_plugin_response_packet_id: 0x02,
+    disconnect_packet_id: 0x17,
+    keep_alive_packet_id: 0x1E,
+    login_play_packet_id: 0x23,
+    player_chat_packet_id: 0x30,
+    system_chat_packet_id: 0x5F,
+    player_info_packet_id: 0x34,
+    combat_death_packet_id: 0x33,
+    respawn_packet_id: 0x3B,
+    set_health_packet_id: 0x52,
+    set_experience_packet_id: 0x51,
+    synchronize_position_packet_id: 0x36,
+    resource_pack_packet_id: 0x3A,
+    command_suggestions_packet_id: 0x0E,
+    boss_bar_packet_id: 0x0A,
+    display_objective_packet_id: 0x4C,
+    update_objectives_packet_id: 0x53,
+    update_score_packet_id: 0x56,
+    clear_titles_packet_id: 0x10,
+    action_bar_packet_id: 0x40,
+    subtitle_packet_id: 0x58,
+    title_packet_id: 0x5A,
+    title_times_packet_id: 0x5B,
+    spawn_entity_packet_id: 0x00,
+    spawn_player_packet_id: 0x02,
+    entity_position_packet_id: 0x26,
+    entity_position_rotation_packet_id: 0x27,
+    entity_rotation_packet_id: 0x28,
+    remove_entities_packet_id: 0x38,
+    teleport_entity_packet_id: 0x63,
+    transfer_packet_id: None,
+    last_play_packet_id: 0x68,
+    chat_command_packet_id: 0x03,
+    chat_message_packet_id: 0x04,
+    client_information_packet_id: 0x07,
+    keep_alive_response_packet_id: 0x11,
+    plugin_message_packet_id: 0x0C,
+    confirm_teleport_packet_id: 0x00,
+    client_command_packet_id: 0x06,
+    command_suggestions_request_packet_id: 0x08,
+    player_position_packet_id: 0x13,
+    player_position_rotation_packet_id: 0x14,
+    player_rotation_packet_id: 0x15,
+    player_command_packet_id: 0x1D,
+    resource_pack_status_packet_id: 0x23,
+}];
+
+impl ProtocolFeatures {
+    pub fn for_version(version: i32) -> Option<&'static ProtocolFeatures> {
+        PROTOCOLS
+            .iter()
+            .find(|features| features.version == version)
+    }
+}
+
+// The release matching PROTOCOL_VERSION
+impl Default for ProtocolFeatures {
+    fn default() -> ProtocolFeatures {
+        *ProtocolFeatures::for_version(PROTOCOL_VERSION).unwrap()
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::*;
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Ipv6Addr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyAuth {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyConfig {
+    Socks5 {
+        address: String,
+        auth: Option<ProxyAuth>,
+    },
+    HttpConnect {
+        address: String,
+        auth: Option<ProxyAuth>,
+    },
+}
+
+const SOCKS_VERSION: u8 = 0x05;
+const SOCKS_NO_AUTH: u8 = 0x00;
+const SOCKS_USER_PASS: u8 = 0x02;
+const SOCKS_NO_ACCEPTABLE: u8 = 0xFF;
+const SOCKS_CMD_CONNECT: u8 = 0x01;
+const SOCKS_ATYP_IPV4: u8 = 0x01;
+const SOCKS_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS_ATYP_IPV6: u8 = 0x04;
+
+impl ProxyConfig {
+    pub fn socks5(address: &str) -> ProxyConfig {
+        ProxyConfig::Socks5 {
+            address: String::from(address),
+            auth: None,
+        }
+    }
+
+    pub fn http(address: &str) -> ProxyConfig {
+        ProxyConfig::HttpConnect {
+            address: String::from(address),
+            auth: None,
+        }
+    }
+
+    pub fn with_auth(mut self, username: &str, password: &str) -> ProxyConfig {
+        let credentials = Some(ProxyAuth {
+            username: String::from(username),
+            password: String::from(password),
+        });
+        match &mut self {
+            ProxyConfig::Socks5 { auth, .. } | ProxyConfig::HttpConnect { auth, .. } => {
+                *auth = credentials
+            }
+        }
+        self
+    }
+
+    pub fn address(&self) -> &str {
+        match self {
+            ProxyConfig::Socks5 { address, .. } | ProxyConfig::HttpConnect { address, .. } => {
+                address
+            }
+        }
+    }
+
+    // Opens a stream to the proxy and asks it to tunnel to hostname:port.
+    // The returned stream is positioned right after the proxy negotiation.
+    pub fn connect(&self, hostname: &str, port: u16) -> Result<TcpStream> {
+        self.connect_timeout(hostname, port, None)
+    }
+
+    // Like connect, with `timeout` bounding the connection to the proxy and
+    // every read and write of the negotiation after it
+    pub fn connect_timeout(
+        &self,
+        hostname: &str,
+        port: u16,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream> {
+        let mut stream = connect_tcp(self.address(), timeout)
+            .with_context(|| format!("Failed to connect to proxy {}", self.address()))?;
+
+        // A proxy that accepts and then says nothing would hang us forever
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        match self {
+            ProxyConfig::Socks5 { auth, .. } => {
+                socks5_handshake(&mut stream, auth.as_ref(), hostname, port)?
+            }
+            ProxyConfig::HttpConnect { auth, .. } => {
+                http_connect(&mut stream, auth.as_ref(), hostname, port)?
+            }
+        }
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+
+        Ok(stream)
+    }
+}
+
+// TcpStream::connect_timeout only takes a resolved address, so try each one
+// the name resolves to like TcpStream::connect does
+pub(crate) fn connect_tcp(address: &str, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return TcpStream::connect(address),
+    };
+
+    let mut last_error = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    Err(last_error
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Address resolved to nothing")))
+}
+
+fn socks5_handshake(
+    stream: &mut TcpStream,
+    auth: Option<&ProxyAuth>,
+    hostname: &str,
+    port: u16,
+) -> Result<()> {
+    // Greeting with the list of methods we support
+    match auth {
+        Some(_) => stream.write_all(&[SOCKS_VERSION, 2, SOCKS_NO_AUTH, SOCKS_USER_PASS])?,
+        None => stream.write_all(&[SOCKS_VERSION, 1, SOCKS_NO_AUTH])?,
+    }
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice)?;
+    if choice[0] != SOCKS_VERSION {
+        return Err(anyhow!("Proxy is not a SOCKS5 server"));
+    }
+
+    match (choice[1], auth) {
+        (SOCKS_NO_AUTH, _) => {}
+        (SOCKS_USER_PASS, Some(auth)) => {
+            if auth.username.len() > 255 || auth.password.len() > 255 {
+                return Err(anyhow!("SOCKS5 credentials are too long"));
+            }
+
+            // Username/password subnegotiation (RFC 1929)
+            let mut request = vec![0x01, auth.username.len() as u8];
+            request.extend_from_slice(auth.username.as_bytes());
+            request.push(auth.password.len() as u8);
+            request.extend_from_slice(auth.password.as_bytes());
+            stream.write_all(&request)?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status)?;
+            if status[1] != 0x00 {
+                return Err(anyhow!("SOCKS5 proxy rejected the credentials"));
+            }
+        }
+        (SOCKS_NO_ACCEPTABLE, _) => {
+            return Err(anyhow!("SOCKS5 proxy accepted none of our auth methods"))
+        }
+        (method, _) => return Err(anyhow!("SOCKS5 proxy chose unsupported method {}", method)),
+    }
+
+    // IP literals go as addresses, anything else the proxy resolves itself
+    let mut request = vec![SOCKS_VERSION, SOCKS_CMD_CONNECT, 0x00];
+    match hostname.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(SOCKS_ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(SOCKS_ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if hostname.len() > 255 {
+                return Err(anyhow!("Hostname is too long for SOCKS5"));
+            }
+            request.extend_from_slice(&[SOCKS_ATYP_DOMAIN, hostname.len() as u8]);
+            request.extend_from_slice(hostname.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0x00 {
+        return Err(anyhow!(
+            "SOCKS5 connect failed with reply code {}",
+            reply[1]
+        ));
+    }
+
+    // Skip the bound address, we have no use for it
+    let address_length = match reply[3] {
+        SOCKS_ATYP_IPV4 => 4,
+        SOCKS_ATYP_IPV6 => 16,
+        SOCKS_ATYP_DOMAIN => {
+            let mut length = [0u8];
+            stream.read_exact(&mut length)?;
+            length[0] as usize
+        }
+        other => return Err(anyhow!("SOCKS5 proxy sent unknown address type {}", other)),
+    };
+    let mut bound = vec![0u8; address_length + 2];
+    stream.read_exact(&mut bound)?;
+
+    Ok(())
+}
+
+fn http_connect(
+    stream: &mut TcpStream,
+    auth: Option<&ProxyAuth>,
+    hostname: &str,
+    port: u16,
+) -> Result<()> {
+    // An IPv6 literal needs brackets to keep its colons apart from the port's
+    let target = match hostname.parse::<Ipv6Addr>() {
+        Ok(_) => format!("[{}]:{}", hostname, port),
+        Err(_) => format!("{}:{}", hostname, port),
+    };
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+    if let Some(auth) = auth {
+        let credentials = BASE64_STANDARD.encode(format!("{}:{}", auth.username, auth.password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    // Read the response head byte by byte so nothing past it gets buffered away
+    let mut reader = BufReader::with_capacity(1, stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| anyhow!("Malformed HTTP proxy response: {:?}", status_line))?;
+    if status != "200" {
+        return Err(anyhow!(
+            "HTTP proxy refused CONNECT: {}",
+            status_line.trim_end()
+        ));
+    }
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("HTTP proxy closed the connection"));
+        }
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+
+    Ok(())
+}
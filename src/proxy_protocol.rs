@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use std::{
+    io::Read,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V1_MAX_LENGTH: usize = 107;
+const V2_VERSION_PROXY: u8 = 0x21;
+const V2_VERSION_LOCAL: u8 = 0x20;
+const V2_TCP4: u8 = 0x11;
+const V2_TCP6: u8 = 0x21;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProtocolVersion {
+    V1,
+    V2,
+}
+
+// The addresses a load balancer reports for the connection it forwarded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub version: ProxyProtocolVersion,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+impl ProxyHeader {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        if self.source.is_ipv4() != self.destination.is_ipv4() {
+            return Err(anyhow!("PROXY header addresses must be of the same family"));
+        }
+
+        match self.version {
+            ProxyProtocolVersion::V1 => Ok(format!(
+                "PROXY {} {} {} {} {}\r\n",
+                if self.source.is_ipv4() {
+                    "TCP4"
+                } else {
+                    "TCP6"
+                },
+                self.source.ip(),
+                self.destination.ip(),
+                self.source.port(),
+                self.destination.port()
+            )
+            .into_bytes()),
+            ProxyProtocolVersion::V2 => {
+                let mut header = V2_SIGNATURE.to_vec();
+                header.push(V2_VERSION_PROXY);
+
+                let mut addresses = Vec::new();
+                match (self.source.ip(), self.destination.ip()) {
+                    (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                        header.push(V2_TCP4);
+                        addresses.extend_from_slice(&source.octets());
+                        addresses.extend_from_slice(&destination.octets());
+                    }
+                    (IpAddr::V6(source), IpAddr::V6(destination)) => {
+                        header.push(V2_TCP6);
+                        addresses.extend_from_slice(&source.octets());
+                        addresses.extend_from_slice(&destination.octets());
+                    }
+                    _ => unreachable!(),
+                }
+                addresses.extend_from_slice(&self.source.port().to_be_bytes());
+                addresses.extend_from_slice(&self.destination.port().to_be_bytes());
+
+                header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+                header.extend_from_slice(&addresses);
+                Ok(header)
+            }
+        }
+    }
+
+    // Reads a v1 or v2 header off the start of a stream without consuming
+    // anything past it. Returns None for headers that carry no addresses
+    // (v1 UNKNOWN, v2 LOCAL, or families other than TCP over IPv4/IPv6).
+    pub fn read_from(reader: &mut impl Read) -> Result<Option<ProxyHeader>> {
+        // Both versions are at least 12 bytes long
+        let mut start = [0u8; 12];
+        reader.read_exact(&mut start)?;
+
+        if start == V2_SIGNATURE {
+            return read_v2(reader);
+        }
+        if start.starts_with(b"PROXY ") {
+            return read_v1(reader, &start);
+        }
+
+        Err(anyhow!(
+            "Connection did not start with a PROXY protocol header"
+        ))
+    }
+}
+
+fn read_v1(reader: &mut impl Read, start: &[u8]) -> Result<Option<ProxyHeader>> {
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(anyhow!("PROXY v1 header is too long"));
+        }
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] =>
+        {
+            let source: IpAddr = source.parse()?;
+            let destination: IpAddr = destination.parse()?;
+            let ipv4 = *family == "TCP4";
+            if source.is_ipv4() != ipv4 || destination.is_ipv4() != ipv4 {
+                return Err(anyhow!("PROXY v1 addresses don't match {}", family));
+            }
+            Ok(Some(ProxyHeader {
+                version: ProxyProtocolVersion::V1,
+                source: SocketAddr::new(source, source_port.parse()?),
+                destination: SocketAddr::new(destination, destination_port.parse()?),
+            }))
+        }
+        _ => Err(anyhow!("Malformed PROXY v1 header: {:?}", line)),
+    }
+}
+
+fn read_v2(reader: &mut impl Read) -> Result<Option<ProxyHeader>> {
+    let mut fixed = [0u8; 4];
+    reader.read_exact(&mut fixed)?;
+    let length = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+
+    // Always drain the body, even if we end up ignoring it, so the stream
+    // stays aligned on the first real packet
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    match fixed[0] {
+        V2_VERSION_LOCAL => return Ok(None),
+        V2_VERSION_PROXY => {}
+        other => return Err(anyhow!("Unsupported PROXY v2 version/command {:#x}", other)),
+    }
+
+    let (source, destination, ports) = match fixed[1] {
+        V2_TCP4 if length >= 12 => {
+            let source: [u8; 4] = body[0..4].try_into()?;
+            let destination: [u8; 4] = body[4..8].try_into()?;
+            (
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                &body[8..12],
+            )
+        }
+        V2_TCP6 if length >= 36 => {
+            let source: [u8; 16] = body[0..16].try_into()?;
+            let destination: [u8; 16] = body[16..32].try_into()?;
+            (
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                &body[32..36],
+            )
+        }
+        V2_TCP4 | V2_TCP6 => return Err(anyhow!("PROXY v2 address block is too short")),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(ProxyHeader {
+        version: ProxyProtocolVersion::V2,
+        source: SocketAddr::new(source, u16::from_be_bytes([ports[0], ports[1]])),
+        destination: SocketAddr::new(destination, u16::from_be_bytes([ports[2], ports[3]])),
+    }))
+}
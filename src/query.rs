@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Context, Result};
+use rand::RngExt;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const HANDSHAKE: u8 = 9;
+const STAT: u8 = 0;
+// Sent before the key/value section and the player list of a full stat
+const KEY_VALUE_PADDING: usize = 11;
+const PLAYER_PADDING: usize = 10;
+
+// The basic stat of the UDP query protocol
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BasicStat {
+    pub motd: String,
+    pub game_type: String,
+    pub map: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub host_port: u16,
+    pub host_ip: String,
+}
+
+// The full stat, with what the status ping doesn't tell: the world name,
+// plugins and every player's exact name instead of a sample
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FullStat {
+    pub motd: String,
+    pub game_type: String,
+    pub version: String,
+    // "Paper on 1.19" style, None for vanilla which lists no plugins
+    pub server_mod: Option<String>,
+    pub plugins: Vec<String>,
+    pub map: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub host_port: u16,
+    pub host_ip: String,
+    pub player_names: Vec<String>,
+    // Every key the server sent, including ones not listed above
+    pub values: BTreeMap<String, String>,
+}
+
+// Servers with enable-query=true answer on query.port, which is the game port
+// unless changed. Nothing answers at all otherwise, so the timeout is the
+// only sign it's off.
+pub fn query_basic(address: impl ToSocketAddrs, timeout: Duration) -> Result<BasicStat> {
+    let mut session = Session::open(address, timeout)?;
+    let response = session.stat(false)?;
+
+    let mut reader = Reader(&response);
+    let motd = reader.string()?;
+    let game_type = reader.string()?;
+    let map = reader.string()?;
+    let players = reader.number("numplayers")?;
+    let max_players = reader.number("maxplayers")?;
+    // The one little endian number in the protocol
+    let host_port = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+    let host_ip = reader.string()?;
+
+    Ok(BasicStat {
+        motd,
+        game_type,
+        map,
+        players,
+        max_players,
+        host_port,
+        host_ip,
+    })
+}
+
+pub fn query_full(address: impl ToSocketAddrs, timeout: Duration) -> Result<FullStat> {
+    let mut session = Session::open(address, timeout)?;
+    let response = session.stat(true)?;
+
+    let mut reader = Reader(&response);
+    reader.skip(KEY_VALUE_PADDING)?;
+    let mut values = BTreeMap::new();
+    loop {
+        let key = reader.string()?;
+        if key.is_empty() {
+            break;
+        }
+        values.insert(key, reader.string()?);
+    }
+    reader.skip(PLAYER_PADDING)?;
+    let mut player_names = Vec::new();
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        player_names.push(name);
+    }
+
+    let value = |key: &str| values.get(key).cloned().unwrap_or_default();
+    let number = |key: &str| -> Result<u32> {
+        value(key)
+            .parse()
+            .with_context(|| format!("Query response has a bad {}", key))
+    };
+    let (server_mod, plugins) = parse_plugins(&value("plugins"));
+    Ok(FullStat {
+        motd: value("hostname"),
+        game_type: value("gametype"),
+        version: value("version"),
+        server_mod,
+        plugins,
+        map: value("map"),
+        players: number("numplayers")?,
+        max_players: number("maxplayers")?,
+        host_port: value("hostport").parse().unwrap_or_default(),
+        host_ip: value("hostip"),
+        player_names,
+        values,
+    })
+}
+
+// "Paper on 1.19: WorldEdit 7.2.12; EssentialsX 2.19.7"
+fn parse_plugins(text: &str) -> (Option<String>, Vec<String>) {
+    if text.is_empty() {
+        return (None, Vec::new());
+    }
+    match text.split_once(": ") {
+        Some((server_mod, plugins)) => (
+            Some(String::from(server_mod)),
+            plugins
+                .split("; ")
+                .filter(|plugin| !plugin.is_empty())
+                .map(String::from)
+                .collect(),
+        ),
+        None => (Some(String::from(text)), Vec::new()),
+    }
+}
+
+struct Session {
+    socket: UdpSocket,
+    id: i32,
+    challenge: i32,
+}
+
+impl Session {
+    fn open(address: impl ToSocketAddrs, timeout: Duration) -> Result<Session> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("Query address resolved to nothing"))?;
+        let local: SocketAddr = match address {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+            SocketAddr::V6(_) => "[::]:0".parse()?,
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+        socket.set_read_timeout(Some(timeout))?;
+
+        // Servers only look at the low nibble of every byte
+        let id = rand::rng().random::<i32>() & 0x0F0F0F0F;
+        let mut session = Session {
+            socket,
+            id,
+            challenge: 0,
+        };
+        let token = session.request(HANDSHAKE, &[])?;
+        let token = Reader(&token).string()?;
+        session.challenge = token
+            .parse()
+            .with_context(|| format!("Bad query challenge token {:?}", token))?;
+        Ok(session)
+    }
+
+    fn stat(&mut self, full: bool) -> Result<Vec<u8>> {
+        let mut payload = self.challenge.to_be_bytes().to_vec();
+        if full {
+            payload.extend_from_slice(&[0; 4]);
+        }
+        self.request(STAT, &payload)
+    }
+
+    // The response after its type and session id
+    fn request(&self, kind: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut request = MAGIC.to_vec();
+        request.push(kind);
+        request.extend_from_slice(&self.id.to_be_bytes());
+        request.extend_from_slice(payload);
+        self.socket.send(&request)?;
+
+        let mut response = [0u8; 65536];
+        loop {
+            let length = self
+                .socket
+                .recv(&mut response)
+                .context("No query response, is enable-query on?")?;
+            // Anything else answers an earlier session
+            if length >= 5 && response[0] == kind && response[1..5] == self.id.to_be_bytes() {
+                return Ok(response[5..length].to_vec());
+            }
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8> {
+        let (&byte, rest) = self
+            .0
+            .split_first()
+            .ok_or_else(|| anyhow!("Query response ended early"))?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<()> {
+        if self.0.len() < count {
+            return Err(anyhow!("Query response ended early"));
+        }
+        self.0 = &self.0[count..];
+        Ok(())
+    }
+
+    // NUL terminated, in whatever encoding the server uses. Usually Latin-1
+    // or UTF-8, invalid bytes are replaced.
+    fn string(&mut self) -> Result<String> {
+        let end = self
+            .0
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| anyhow!("Query response has an unterminated string"))?;
+        let text = String::from_utf8_lossy(&self.0[..end]).into_owned();
+        self.0 = &self.0[end + 1..];
+        Ok(text)
+    }
+
+    fn number(&mut self, name: &str) -> Result<u32> {
+        let text = self.string()?;
+        text.parse()
+            .with_context(|| format!("Query response has a bad {} {:?}", name, text))
+    }
+}
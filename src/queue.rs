@@ -0,0 +1,68 @@
+use regex::Regex;
+use std::time::Duration;
+
+// Where we are in a server's join queue, as the queue plugin last told us
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuePosition {
+    // 1 is next in line
+    pub position: u32,
+    // Everyone waiting, when the plugin says
+    pub length: Option<u32>,
+    pub estimated_wait: Option<Duration>,
+}
+
+// Reads queue positions out of the chat and action bar lines of the common
+// queue plugins: 2b2t's "Position in queue: 12", ajQueue's "You are in
+// position 12 of 40", LeaveQueue's "Queue position: 12/40" and the like
+pub(crate) struct QueueDetector {
+    patterns: Vec<Regex>,
+    wait: Regex,
+}
+
+impl Default for QueueDetector {
+    fn default() -> QueueDetector {
+        let patterns = [
+            r"(?i)position in (?:the )?queue\W*(\d+)(?:\s*(?:/|of)\s*(\d+))?",
+            r"(?i)queue position\W*(\d+)(?:\s*(?:/|of)\s*(\d+))?",
+            r"(?i)\bin position #?(\d+)(?:\s*(?:/|of)\s*(\d+))?",
+            r"(?i)you are #?(\d+)(?:\s*(?:/|of)\s*(\d+))? in (?:the )?queue",
+        ];
+        QueueDetector {
+            patterns: patterns
+                .iter()
+                .map(|pattern| Regex::new(pattern).expect("Queue patterns are valid"))
+                .collect(),
+            wait: Regex::new(
+                r"(?i)(?:estimated|eta)[^:\d]*:?\s*(?:(\d+)\s*h\w*)?\s*(?:(\d+)\s*m\w*)?\s*(?:(\d+)\s*s\w*)?",
+            )
+            .expect("Queue wait pattern is valid"),
+        }
+    }
+}
+
+impl QueueDetector {
+    pub(crate) fn detect(&self, text: &str) -> Option<QueuePosition> {
+        let captures = self
+            .patterns
+            .iter()
+            .find_map(|pattern| pattern.captures(text))?;
+        let number = |index: usize| captures.get(index)?.as_str().parse::<u32>().ok();
+
+        Some(QueuePosition {
+            position: number(1)?,
+            length: number(2),
+            estimated_wait: self.estimated_wait(text),
+        })
+    }
+
+    fn estimated_wait(&self, text: &str) -> Option<Duration> {
+        let captures = self.wait.captures(text)?;
+        let number = |index: usize| captures.get(index)?.as_str().parse::<u64>().ok();
+        let (hours, minutes, seconds) = (number(1), number(2), number(3));
+        if hours.is_none() && minutes.is_none() && seconds.is_none() {
+            return None;
+        }
+        let seconds = hours.unwrap_or(0) * 3600 + minutes.unwrap_or(0) * 60 + seconds.unwrap_or(0);
+        Some(Duration::from_secs(seconds))
+    }
+}
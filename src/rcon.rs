@@ -0,0 +1,170 @@
+// Remote console, the Source RCON protocol servers expose with enable-rcon,
+// for running commands as the console:
+//
+//   let mut rcon = Rcon::connect("mc.example.com:25575", Duration::from_secs(5))?;
+//   rcon.authenticate(&password)?;
+//   println!("{}", rcon.command("list")?);
+use anyhow::{anyhow, Result};
+use std::{
+    fmt,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+pub const DEFAULT_RCON_PORT: u16 = 25575;
+// Longest command body servers accept in one packet
+pub const MAX_COMMAND_LENGTH: usize = 1446;
+// Responses are split into packets of at most this much body
+const MAX_RESPONSE_BODY: usize = 4096;
+
+const RESPONSE_VALUE: i32 = 0;
+const EXEC_COMMAND: i32 = 2;
+const AUTH_RESPONSE: i32 = 2;
+const LOGIN: i32 = 3;
+
+// The server turned the password down. The connection stays open, another
+// authenticate() may be tried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrongPassword;
+
+impl fmt::Display for WrongPassword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RCON password was rejected")
+    }
+}
+
+impl std::error::Error for WrongPassword {}
+
+struct RconPacket {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+pub struct Rcon {
+    stream: TcpStream,
+    next_id: i32,
+    authenticated: bool,
+}
+
+impl Rcon {
+    // `timeout` bounds connecting and every read and write after
+    pub fn connect(address: impl ToSocketAddrs, timeout: Duration) -> Result<Rcon> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("RCON address resolved to nothing"))?;
+        let stream = TcpStream::connect_timeout(&address, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(Rcon::from_stream(stream))
+    }
+
+    pub fn from_stream(stream: TcpStream) -> Rcon {
+        Rcon {
+            stream,
+            next_id: 1,
+            authenticated: false,
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    // Fails with WrongPassword when the server says no
+    pub fn authenticate(&mut self, password: &str) -> Result<()> {
+        let id = self.send(LOGIN, password)?;
+        loop {
+            let packet = self.read()?;
+            // Source servers send an empty response value first, Minecraft doesn't
+            if packet.kind != AUTH_RESPONSE {
+                continue;
+            }
+            if packet.id == -1 {
+                return Err(WrongPassword.into());
+            }
+            if packet.id != id {
+                return Err(anyhow!("RCON answered login {} with id {}", id, packet.id));
+            }
+            self.authenticated = true;
+            return Ok(());
+        }
+    }
+
+    // What the command printed, with § color codes as the server wrote them.
+    // Long output comes in several packets, which are put back together.
+    pub fn command(&mut self, command: &str) -> Result<String> {
+        if !self.authenticated {
+            return Err(anyhow!("RCON needs authenticate() before commands"));
+        }
+        if command.len() > MAX_COMMAND_LENGTH {
+            return Err(anyhow!(
+                "RCON commands can be at most {} bytes, not {}",
+                MAX_COMMAND_LENGTH,
+                command.len()
+            ));
+        }
+
+        let id = self.send(EXEC_COMMAND, command)?;
+        // Servers answer in order, so the answer to this one marks the end
+        // of the command's output however many packets it took
+        let end = self.send(RESPONSE_VALUE, "")?;
+        let mut output = String::new();
+        loop {
+            let packet = self.read()?;
+            if packet.id == id {
+                output.push_str(&packet.body);
+            } else if packet.id == end {
+                return Ok(output);
+            } else if packet.id == -1 {
+                return Err(anyhow!("RCON session is no longer authenticated"));
+            } else {
+                return Err(anyhow!("RCON answered with unexpected id {}", packet.id));
+            }
+        }
+    }
+
+    fn send(&mut self, kind: i32, body: &str) -> Result<i32> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        // Id, type, the body and two NULs, one ending the body and one padding
+        let length = 4 + 4 + body.len() + 2;
+        let mut packet = Vec::with_capacity(4 + length);
+        packet.extend_from_slice(&(length as i32).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        self.stream.write_all(&packet)?;
+        Ok(id)
+    }
+
+    fn read(&mut self) -> Result<RconPacket> {
+        let mut header = [0u8; 12];
+        self.stream.read_exact(&mut header)?;
+        let field = |index: usize| {
+            i32::from_le_bytes(header[index..index + 4].try_into().expect("Four bytes"))
+        };
+        let (length, id, kind) = (field(0), field(4), field(8));
+        let body_length = length
+            .checked_sub(8)
+            .and_then(|length| usize::try_from(length).ok())
+            .filter(|length| (1..=MAX_RESPONSE_BODY + 2).contains(length))
+            .ok_or_else(|| anyhow!("RCON packet has a bad length {}", length))?;
+
+        let mut body = vec![0; body_length];
+        self.stream.read_exact(&mut body)?;
+        let end = body
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(body.len());
+        Ok(RconPacket {
+            id,
+            kind,
+            body: String::from_utf8_lossy(&body[..end]).into_owned(),
+        })
+    }
+}
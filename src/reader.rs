@@ -0,0 +1,188 @@
+use crate::{Angle, BitSet, BlockPosition, Tag, VARINT_CONTINUE_BIT, VARINT_SEGMENT_BITS};
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+use uuid::Uuid;
+
+// Borrowing reader over a packet body. Everything it hands out points into
+// the original buffer, so decoding doesn't allocate unless asked to.
+#[derive(Debug, Clone)]
+pub struct PacketReader<'a> {
+    buffer: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(buffer: &'a [u8]) -> PacketReader<'a> {
+        PacketReader { buffer, cursor: 0 }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.buffer[self.cursor..]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cursor >= self.buffer.len()
+    }
+
+    pub fn read_bytes(&mut self, amount: usize) -> Result<&'a [u8]> {
+        let end = self
+            .cursor
+            .checked_add(amount)
+            .filter(|end| *end <= self.buffer.len())
+            .ok_or_else(|| anyhow!("Could not read slice past buffer."))?;
+
+        let result = &self.buffer[self.cursor..end];
+        self.cursor = end;
+        Ok(result)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16> {
+        Ok(self.read_u16()? as i16)
+    }
+
+    // Rotation in steps of 1/256 of a full turn, returned in degrees
+    pub fn read_angle(&mut self) -> Result<f32> {
+        Ok(Angle(self.read_u8()?).to_degrees())
+    }
+
+    pub fn read_position(&mut self) -> Result<BlockPosition> {
+        Ok(BlockPosition::from_packed(self.read_i64()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_be_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64> {
+        Ok(f64::from_be_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    pub fn read_uuid(&mut self) -> Result<Uuid> {
+        Ok(Uuid::from_slice(self.read_bytes(16)?)?)
+    }
+
+    pub fn read_varint(&mut self) -> Result<i32> {
+        let bytes = self.remaining();
+        // Ids and most lengths fit in one byte, worth skipping the loop for
+        if let Some(&byte) = bytes.first() {
+            if byte as i32 & VARINT_CONTINUE_BIT == 0 {
+                self.cursor += 1;
+                return Ok(byte as i32);
+            }
+        }
+
+        let mut value = 0u32;
+        for (position, &byte) in bytes.iter().take(5).enumerate() {
+            value |= ((byte as i32 & VARINT_SEGMENT_BITS) as u32) << (7 * position);
+            if byte as i32 & VARINT_CONTINUE_BIT == 0 {
+                self.cursor += position + 1;
+                return Ok(value as i32);
+            }
+        }
+
+        match bytes.len() >= 5 {
+            true => Err(anyhow!("Varint too large")),
+            false => Err(anyhow!("Buffer is too short to read a valid varint")),
+        }
+    }
+
+    pub fn read_varlong(&mut self) -> Result<i64> {
+        let mut value = 0i64;
+        let mut bit_position = 0i64;
+
+        loop {
+            if self.cursor >= self.buffer.len() {
+                return Err(anyhow!("Buffer is too short to read a valid varlong"));
+            }
+
+            let current_byte = self.buffer[self.cursor];
+            self.cursor += 1;
+
+            // Only the top bit of the 64 is left for the tenth byte
+            if bit_position == 63 && current_byte > 0x01 {
+                return Err(anyhow!("Varlong too large"));
+            }
+            value |= ((current_byte as i32 & VARINT_SEGMENT_BITS) as i64) << bit_position;
+
+            if (current_byte as i32 & VARINT_CONTINUE_BIT) == 0 {
+                break;
+            }
+
+            bit_position += 7;
+            if bit_position >= 64 {
+                return Err(anyhow!("Varlong too large"));
+            }
+        }
+
+        Ok(value)
+    }
+
+    pub fn read_bitset(&mut self) -> Result<BitSet> {
+        let length = self.read_varint()?;
+        let length = usize::try_from(length).map_err(|_| anyhow!("Negative length {}", length))?;
+        let bytes = self.read_bytes(
+            length
+                .checked_mul(8)
+                .ok_or_else(|| anyhow!("BitSet too long"))?,
+        )?;
+        Ok(BitSet::from_words(
+            bytes
+                .chunks_exact(8)
+                .map(|word| i64::from_be_bytes(word.try_into().unwrap()))
+                .collect(),
+        ))
+    }
+
+    // A BitSet of known size, sent without its length
+    pub fn read_fixed_bitset(&mut self, bits: usize) -> Result<BitSet> {
+        Ok(BitSet::from_fixed_bytes(self.read_bytes(bits.div_ceil(8))?))
+    }
+
+    // A varint length followed by that many bytes
+    pub fn read_byte_array(&mut self) -> Result<&'a [u8]> {
+        let length = self.read_varint()?;
+        let length = usize::try_from(length).map_err(|_| anyhow!("Negative length {}", length))?;
+        self.read_bytes(length)
+    }
+
+    pub fn read_str(&mut self) -> Result<&'a str> {
+        Ok(std::str::from_utf8(self.read_byte_array()?)?)
+    }
+
+    // Same as read_str, but swaps invalid UTF-8 for replacement characters
+    // instead of failing. Only allocates when something had to be replaced.
+    pub fn read_str_lossy(&mut self) -> Result<Cow<'a, str>> {
+        Ok(String::from_utf8_lossy(self.read_byte_array()?))
+    }
+
+    // NBT with a named root, as 1.19 sends it. The name is always empty on
+    // the network, so only the tag is returned, None where there's no NBT.
+    pub fn read_nbt(&mut self) -> Result<Option<Tag>> {
+        Ok(Tag::read_named(self)?.map(|(_, tag)| tag))
+    }
+}
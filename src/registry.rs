@@ -0,0 +1,255 @@
+use crate::{from_tag, ChatKind, Component, Packet, Tag};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+// Translation key and parameters
+type VanillaDecoration = (&'static str, &'static [ChatParameter]);
+
+// What 1.19 servers send when nothing changed the chat types: the name, then
+// the chat decoration if it has one
+const VANILLA_CHAT_TYPES: &[(&str, Option<VanillaDecoration>)] = &[
+    ("minecraft:chat", Some(("chat.type.text", SENDER_CONTENT))),
+    ("minecraft:system", None),
+    ("minecraft:game_info", None),
+    (
+        "minecraft:say_command",
+        Some(("chat.type.announcement", SENDER_CONTENT)),
+    ),
+    (
+        "minecraft:msg_command",
+        Some(("commands.message.display.incoming", SENDER_CONTENT)),
+    ),
+    (
+        "minecraft:team_msg_command",
+        Some((
+            "chat.type.team.text",
+            &[
+                ChatParameter::TeamName,
+                ChatParameter::Sender,
+                ChatParameter::Content,
+            ],
+        )),
+    ),
+    (
+        "minecraft:emote_command",
+        Some(("chat.type.emote", SENDER_CONTENT)),
+    ),
+    ("minecraft:tellraw_command", None),
+];
+
+const SENDER_CONTENT: &[ChatParameter] = &[ChatParameter::Sender, ChatParameter::Content];
+
+// What fills a slot of a chat decoration's translation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChatParameter {
+    Sender,
+    TeamName,
+    Content,
+    // Who a whisper went to, only used by chat types of later releases
+    Target,
+}
+
+impl ChatParameter {
+    pub fn from_name(name: &str) -> Option<ChatParameter> {
+        match name {
+            "sender" => Some(ChatParameter::Sender),
+            "team_name" => Some(ChatParameter::TeamName),
+            "content" => Some(ChatParameter::Content),
+            "target" => Some(ChatParameter::Target),
+            _ => None,
+        }
+    }
+}
+
+// How a chat type turns a message into the line shown in chat: a
+// translation filled with parts of the message, in the given style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatDecoration {
+    pub translation_key: String,
+    pub parameters: Vec<ChatParameter>,
+    // A component without text, only its color and formatting count
+    pub style: Component,
+}
+
+impl ChatDecoration {
+    pub fn new(translation_key: &str, parameters: &[ChatParameter]) -> ChatDecoration {
+        ChatDecoration {
+            translation_key: String::from(translation_key),
+            parameters: parameters.to_vec(),
+            style: Component::default(),
+        }
+    }
+
+    // The decoration of plain chat, <sender> content
+    pub fn plain_chat() -> ChatDecoration {
+        ChatDecoration::new("chat.type.text", SENDER_CONTENT)
+    }
+
+    // A parameter the message doesn't have, like the team of someone
+    // without one, is left empty
+    pub fn apply(
+        &self,
+        sender: &Component,
+        team_name: Option<&Component>,
+        content: &Component,
+    ) -> Component {
+        let with = self
+            .parameters
+            .iter()
+            .map(|parameter| match parameter {
+                ChatParameter::Sender => sender.clone(),
+                ChatParameter::TeamName => team_name.cloned().unwrap_or_default(),
+                ChatParameter::Content => content.clone(),
+                ChatParameter::Target => Component::default(),
+            })
+            .collect();
+
+        Component {
+            text: String::new(),
+            translate: Some(self.translation_key.clone()),
+            with,
+            extra: Vec::new(),
+            ..self.style.clone()
+        }
+    }
+
+    // The decoration compound of a chat type's chat element
+    fn from_nbt(decoration: &Tag) -> Result<ChatDecoration> {
+        let translation_key = decoration
+            .get("translation_key")
+            .and_then(Tag::as_str)
+            .ok_or_else(|| anyhow!("Chat decoration without a translation key"))?;
+        let parameters = match decoration.get("parameters").and_then(Tag::as_list) {
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    let name = name.as_str().unwrap_or_default();
+                    ChatParameter::from_name(name)
+                        .ok_or_else(|| anyhow!("Unknown chat parameter {:?}", name))
+                })
+                .collect::<Result<_>>()?,
+            None => Vec::new(),
+        };
+        let style = match decoration.get("style") {
+            Some(style) => from_tag(style)?,
+            None => Component::default(),
+        };
+
+        Ok(ChatDecoration {
+            translation_key: String::from(translation_key),
+            parameters,
+            style,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChatType {
+    name: String,
+    // None shows the content as it is, e.g. for /tellraw
+    decoration: Option<ChatDecoration>,
+}
+
+// The chat type registry, by id. Chat packets only carry the id, which one
+// it is depends on what the server sent in Login (play).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatTypes {
+    types: HashMap<i32, ChatType>,
+}
+
+impl Default for ChatTypes {
+    fn default() -> ChatTypes {
+        let mut types = HashMap::new();
+        for (id, (name, decoration)) in VANILLA_CHAT_TYPES.iter().enumerate() {
+            let decoration = decoration.map(|(key, parameters)| {
+                let decoration = ChatDecoration::new(key, parameters);
+                match *name {
+                    // Whispers are gray and italic in vanilla
+                    "minecraft:msg_command" => ChatDecoration {
+                        style: Component {
+                            italic: Some(true),
+                            ..Component::default().color("gray")
+                        },
+                        ..decoration
+                    },
+                    _ => decoration,
+                }
+            });
+            let name = String::from(*name);
+            types.insert(id as i32, ChatType { name, decoration });
+        }
+
+        ChatTypes { types }
+    }
+}
+
+impl ChatTypes {
+    // From the registry codec, the compound of every registry
+    pub fn from_codec(codec: &Tag) -> Result<ChatTypes> {
+        let entries = codec
+            .get("minecraft:chat_type")
+            .and_then(|registry| registry.get("value"))
+            .and_then(Tag::as_list)
+            .ok_or_else(|| anyhow!("Registry codec has no chat types"))?;
+
+        let mut types = HashMap::new();
+        for entry in entries {
+            let id = entry.get("id").and_then(Tag::as_i64);
+            let name = entry.get("name").and_then(Tag::as_str);
+            let (id, name) = match (id, name) {
+                (Some(id), Some(name)) => (id as i32, String::from(name)),
+                _ => return Err(anyhow!("Chat type without an id or name")),
+            };
+            let decoration = match entry
+                .get("element")
+                .and_then(|element| element.get("chat"))
+                .and_then(|chat| chat.get("decoration"))
+            {
+                Some(decoration) => Some(
+                    ChatDecoration::from_nbt(decoration)
+                        .map_err(|error| anyhow!("Chat type {}: {}", name, error))?,
+                ),
+                None => None,
+            };
+            types.insert(id, ChatType { name, decoration });
+        }
+
+        Ok(ChatTypes { types })
+    }
+
+    pub fn name(&self, id: i32) -> Option<&str> {
+        self.types.get(&id).map(|chat_type| chat_type.name.as_str())
+    }
+
+    // How messages of chat type `id` are shown. Ids the server never sent
+    // are shown like plain chat, None means the bare content.
+    pub fn decoration(&self, id: i32) -> Option<ChatDecoration> {
+        match self.types.get(&id) {
+            Some(chat_type) => chat_type.decoration.clone(),
+            None => Some(ChatDecoration::plain_chat()),
+        }
+    }
+
+    // Ids the server never sent count as public chat
+    pub fn kind(&self, id: i32) -> ChatKind {
+        self.name(id)
+            .map_or(ChatKind::Public, ChatKind::from_chat_type)
+    }
+}
+
+// The registry codec of a Login (play) packet, skipping the fields before it
+pub(crate) fn login_registry_codec(packet: &Packet) -> Result<Tag> {
+    let mut reader = packet.reader();
+    reader.read_i32()?; // Entity ID
+    reader.read_bool()?; // Is hardcore
+    reader.read_u8()?; // Gamemode
+    reader.read_u8()?; // Previous gamemode
+    let dimensions = reader.read_varint()?;
+    for _ in 0..dimensions {
+        reader.read_str()?; // Dimension name
+    }
+
+    reader
+        .read_nbt()?
+        .ok_or_else(|| anyhow!("Login (play) without a registry codec"))
+}
@@ -0,0 +1,402 @@
+use crate::{translate_fallback, Component};
+
+// Starts a legacy formatting code, like the red of "§cWarning". Plenty of
+// servers still put them in MOTDs and plugin messages, inside JSON text too.
+pub const SECTION_SIGN: char = '§';
+
+// Stands in for translation arguments while the pattern is filled, so the
+// literal parts and the arguments can be rendered separately
+const ARGUMENT_MARKER: char = '\u{0}';
+
+// Formatting in effect for a run of text, inherited down the component tree
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    pub color: Option<String>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub strikethrough: bool,
+    pub obfuscated: bool,
+}
+
+impl Style {
+    fn inherit(&self, component: &Component) -> Style {
+        Style {
+            color: component.color.clone().or_else(|| self.color.clone()),
+            bold: component.bold.unwrap_or(self.bold),
+            italic: component.italic.unwrap_or(self.italic),
+            underlined: component.underlined.unwrap_or(self.underlined),
+            strikethrough: component.strikethrough.unwrap_or(self.strikethrough),
+            obfuscated: component.obfuscated.unwrap_or(self.obfuscated),
+        }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    // What the legacy code `code` turns this style into, None for codes
+    // vanilla doesn't know. Like vanilla, colors also end bold and the rest,
+    // and §r goes back to `base`, the style of the component around it.
+    fn apply_legacy(&self, code: char, base: &Style) -> Option<Style> {
+        let mut style = self.clone();
+        match code.to_ascii_lowercase() {
+            'k' => style.obfuscated = true,
+            'l' => style.bold = true,
+            'm' => style.strikethrough = true,
+            'n' => style.underlined = true,
+            'o' => style.italic = true,
+            'r' => style = base.clone(),
+            code => {
+                style = Style {
+                    color: Some(String::from(legacy_color(code)?)),
+                    ..Style::default()
+                }
+            }
+        }
+        Some(style)
+    }
+}
+
+// The named colors of the legacy codes 0 to f
+fn legacy_color(code: char) -> Option<&'static str> {
+    let color = match code {
+        '0' => "black",
+        '1' => "dark_blue",
+        '2' => "dark_green",
+        '3' => "dark_aqua",
+        '4' => "dark_red",
+        '5' => "dark_purple",
+        '6' => "gold",
+        '7' => "gray",
+        '8' => "dark_gray",
+        '9' => "blue",
+        'a' => "green",
+        'b' => "aqua",
+        'c' => "red",
+        'd' => "light_purple",
+        'e' => "yellow",
+        'f' => "white",
+        _ => return None,
+    };
+
+    Some(color)
+}
+
+// Turns components into text for one kind of output. Implementations only
+// decide how a single styled run looks, walking the tree is shared.
+pub trait Renderer {
+    fn segment(&self, text: &str, style: &Style) -> String;
+
+    fn render(&self, component: &Component) -> String {
+        runs(component)
+            .iter()
+            .map(|(text, style)| self.segment(text, style))
+            .collect()
+    }
+}
+
+// The component as runs of text, with neighbouring runs of the same style merged
+pub fn runs(component: &Component) -> Vec<(String, Style)> {
+    let mut runs = Vec::new();
+    collect_runs(component, &Style::default(), &mut runs);
+    runs
+}
+
+fn collect_runs(component: &Component, parent: &Style, runs: &mut Vec<(String, Style)>) {
+    let style = parent.inherit(component);
+
+    match &component.translate {
+        Some(key) => {
+            let markers: Vec<String> = (0..component.with.len())
+                .map(|index| format!("{0}{1}{0}", ARGUMENT_MARKER, index))
+                .collect();
+            let pattern = translate_fallback(key, &markers);
+
+            // Odd pieces are argument indices, even pieces literal text
+            for (position, piece) in pattern.split(ARGUMENT_MARKER).enumerate() {
+                if position % 2 == 0 {
+                    push_legacy_runs(piece, &style, runs);
+                } else if let Some(argument) = piece
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| component.with.get(index))
+                {
+                    collect_runs(argument, &style, runs);
+                }
+            }
+        }
+        None => push_legacy_runs(&component.text, &style, runs),
+    }
+
+    for child in &component.extra {
+        collect_runs(child, &style, runs);
+    }
+}
+
+// Splits `text` on its legacy codes. Unknown codes are dropped with their
+// section sign, as vanilla does. BungeeCord's §x§r§r§g§g§b§b is #rrggbb.
+fn push_legacy_runs(text: &str, base: &Style, runs: &mut Vec<(String, Style)>) {
+    let mut style = base.clone();
+    let mut rest = text;
+
+    while let Some(index) = rest.find(SECTION_SIGN) {
+        push_run(&rest[..index], &style, runs);
+        rest = &rest[index + SECTION_SIGN.len_utf8()..];
+        let mut chars = rest.chars();
+        let Some(code) = chars.next() else {
+            break;
+        };
+        rest = chars.as_str();
+
+        if code.eq_ignore_ascii_case(&'x') {
+            let digits: Option<String> = (0..6)
+                .map(|position| {
+                    let pair = rest.get(position * 3..position * 3 + 3)?;
+                    let digit = pair.strip_prefix(SECTION_SIGN)?.chars().next()?;
+                    digit.is_ascii_hexdigit().then_some(digit)
+                })
+                .collect();
+            if let Some(digits) = digits {
+                rest = &rest[18..];
+                style = Style {
+                    color: Some(format!("#{}", digits.to_ascii_lowercase())),
+                    ..Style::default()
+                };
+                continue;
+            }
+        }
+        if let Some(next) = style.apply_legacy(code, base) {
+            style = next;
+        }
+    }
+    push_run(rest, &style, runs);
+}
+
+fn push_run(text: &str, style: &Style, runs: &mut Vec<(String, Style)>) {
+    if text.is_empty() {
+        return;
+    }
+    match runs.last_mut() {
+        Some((last, last_style)) if last_style == style => last.push_str(text),
+        _ => runs.push((String::from(text), style.clone())),
+    }
+}
+
+// Vanilla's palette for the named chat colors
+pub fn named_color_rgb(name: &str) -> Option<(u8, u8, u8)> {
+    let rgb = match name {
+        "black" => (0x00, 0x00, 0x00),
+        "dark_blue" => (0x00, 0x00, 0xAA),
+        "dark_green" => (0x00, 0xAA, 0x00),
+        "dark_aqua" => (0x00, 0xAA, 0xAA),
+        "dark_red" => (0xAA, 0x00, 0x00),
+        "dark_purple" => (0xAA, 0x00, 0xAA),
+        "gold" => (0xFF, 0xAA, 0x00),
+        "gray" => (0xAA, 0xAA, 0xAA),
+        "dark_gray" => (0x55, 0x55, 0x55),
+        "blue" => (0x55, 0x55, 0xFF),
+        "green" => (0x55, 0xFF, 0x55),
+        "aqua" => (0x55, 0xFF, 0xFF),
+        "red" => (0xFF, 0x55, 0x55),
+        "light_purple" => (0xFF, 0x55, 0xFF),
+        "yellow" => (0xFF, 0xFF, 0x55),
+        "white" => (0xFF, 0xFF, 0xFF),
+        _ => return None,
+    };
+
+    Some(rgb)
+}
+
+// Named colors plus the #rrggbb form servers may use since 1.16
+pub fn color_rgb(color: &str) -> Option<(u8, u8, u8)> {
+    match color.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => {
+            let value = u32::from_str_radix(hex, 16).ok()?;
+            Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+        }
+        Some(_) => None,
+        None => named_color_rgb(color),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainRenderer;
+
+impl Renderer for PlainRenderer {
+    fn segment(&self, text: &str, _style: &Style) -> String {
+        String::from(text)
+    }
+}
+
+// SGR escape codes, named colors map onto the 16 color palette
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnsiRenderer;
+
+impl Renderer for AnsiRenderer {
+    fn segment(&self, text: &str, style: &Style) -> String {
+        if style.is_plain() {
+            return String::from(text);
+        }
+
+        let mut codes = Vec::new();
+        if let Some(color) = &style.color {
+            match ansi_color(color) {
+                Some(code) => codes.push(code.to_string()),
+                None => {
+                    if let Some((r, g, b)) = color_rgb(color) {
+                        codes.push(format!("38;2;{};{};{}", r, g, b));
+                    }
+                }
+            }
+        }
+        let flags = [
+            (style.bold, "1"),
+            (style.italic, "3"),
+            (style.underlined, "4"),
+            (style.obfuscated, "5"),
+            (style.strikethrough, "9"),
+        ];
+        codes.extend(
+            flags
+                .iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, code)| code.to_string()),
+        );
+
+        format!("\x1b[{}m{}\x1b[0m", codes.join(";"), text)
+    }
+}
+
+fn ansi_color(name: &str) -> Option<u8> {
+    let code = match name {
+        "black" => 30,
+        "dark_red" => 31,
+        "dark_green" => 32,
+        "gold" => 33,
+        "dark_blue" => 34,
+        "dark_purple" => 35,
+        "dark_aqua" => 36,
+        "gray" => 37,
+        "dark_gray" => 90,
+        "red" => 91,
+        "green" => 92,
+        "yellow" => 93,
+        "blue" => 94,
+        "light_purple" => 95,
+        "aqua" => 96,
+        "white" => 97,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HtmlRenderer;
+
+impl Renderer for HtmlRenderer {
+    fn segment(&self, text: &str, style: &Style) -> String {
+        let text = escape_html(text);
+        if style.is_plain() {
+            return text;
+        }
+
+        let mut css = Vec::new();
+        if let Some((r, g, b)) = style.color.as_deref().and_then(color_rgb) {
+            css.push(format!("color:#{:02x}{:02x}{:02x}", r, g, b));
+        }
+        if style.bold {
+            css.push(String::from("font-weight:bold"));
+        }
+        if style.italic {
+            css.push(String::from("font-style:italic"));
+        }
+        let decorations: Vec<&str> = [
+            (style.underlined, "underline"),
+            (style.strikethrough, "line-through"),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, decoration)| *decoration)
+        .collect();
+        if !decorations.is_empty() {
+            css.push(format!("text-decoration:{}", decorations.join(" ")));
+        }
+
+        let class = if style.obfuscated {
+            " class=\"obfuscated\""
+        } else {
+            ""
+        };
+        format!("<span{} style=\"{}\">{}</span>", class, css.join(";"), text)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+// Discord flavoured markdown. Colors have no equivalent and are dropped,
+// obfuscated text becomes a spoiler.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkdownRenderer;
+
+impl Renderer for MarkdownRenderer {
+    // Runs that only differ in color look the same here and have to be
+    // merged, back to back markers like **a****b** don't parse
+    fn render(&self, component: &Component) -> String {
+        let mut merged: Vec<(String, Style)> = Vec::new();
+        for (text, mut style) in runs(component) {
+            style.color = None;
+            match merged.last_mut() {
+                Some((last, last_style)) if *last_style == style => last.push_str(&text),
+                _ => merged.push((text, style)),
+            }
+        }
+
+        merged
+            .iter()
+            .map(|(text, style)| self.segment(text, style))
+            .collect()
+    }
+
+    fn segment(&self, text: &str, style: &Style) -> String {
+        let mut text = escape_markdown(text);
+        let wrappers = [
+            (style.obfuscated, "||"),
+            (style.strikethrough, "~~"),
+            (style.underlined, "__"),
+            (style.italic, "*"),
+            (style.bold, "**"),
+        ];
+        for (enabled, marker) in wrappers {
+            if enabled {
+                text = format!("{}{}{}", marker, text, marker);
+            }
+        }
+        text
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if matches!(character, '\\' | '*' | '_' | '~' | '`' | '|' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
@@ -0,0 +1,127 @@
+use crate::{HttpClient, Packet};
+use anyhow::{anyhow, Context, Result};
+use sha1::{Digest, Sha1};
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+// Vanilla refuses packs above this size, so we do too
+const MAX_PACK_SIZE: u64 = 250 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourcePackPolicy {
+    // Claim the pack loaded without fetching it, enough to keep most servers happy
+    Accept,
+    Decline,
+    // Fetch the pack into the directory and verify its hash before reporting success
+    AcceptAndDownload(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourcePackStatus {
+    SuccessfullyLoaded = 0,
+    Declined = 1,
+    FailedDownload = 2,
+    Accepted = 3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourcePackRequest {
+    pub url: String,
+    // Lowercase hex SHA-1 of the pack, empty if the server didn't send one
+    pub hash: String,
+    pub forced: bool,
+    // Raw JSON chat component shown in the prompt
+    pub prompt: Option<String>,
+}
+
+impl ResourcePackRequest {
+    pub fn from_packet(packet: &Packet) -> Result<ResourcePackRequest> {
+        let mut reader = packet.reader();
+        let url = reader.read_str()?.to_owned();
+        let hash = reader.read_str()?.to_ascii_lowercase();
+        let forced = reader.read_bool()?;
+        let prompt = match reader.read_bool()? {
+            true => Some(reader.read_str()?.to_owned()),
+            false => None,
+        };
+
+        Ok(ResourcePackRequest {
+            url,
+            hash,
+            forced,
+            prompt,
+        })
+    }
+}
+
+// Downloads the pack into `directory`, named after its hash, and checks the
+// hash if the server provided one. Returns where the pack was written.
+pub fn download_resource_pack(
+    http: &HttpClient,
+    request: &ResourcePackRequest,
+    directory: &Path,
+) -> Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+
+    let name = match request.hash.is_empty() {
+        true => hex(&Sha1::digest(request.url.as_bytes())),
+        false => request.hash.clone(),
+    };
+    let path = directory.join(format!("{}.zip", name));
+    if !request.hash.is_empty() && path.exists() && hash_file(&path)? == request.hash {
+        return Ok(path);
+    }
+
+    let response = http
+        .get(&request.url)
+        .with_context(|| format!("Failed to download resource pack {}", request.url))?;
+    let mut body = response.into_body().into_reader().take(MAX_PACK_SIZE + 1);
+
+    let mut file = File::create(&path)?;
+    let mut hasher = Sha1::new();
+    let mut written = 0u64;
+    let mut chunk = [0u8; 8192];
+    loop {
+        let read = body.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        written += read as u64;
+        if written > MAX_PACK_SIZE {
+            drop(file);
+            fs::remove_file(&path)?;
+            return Err(anyhow!(
+                "Resource pack is larger than {} bytes",
+                MAX_PACK_SIZE
+            ));
+        }
+
+        hasher.update(&chunk[..read]);
+        file.write_all(&chunk[..read])?;
+    }
+
+    let hash = hex(&hasher.finalize());
+    if !request.hash.is_empty() && hash != request.hash {
+        fs::remove_file(&path)?;
+        return Err(anyhow!(
+            "Resource pack hash mismatch, expected {} got {}",
+            request.hash,
+            hash
+        ));
+    }
+
+    Ok(path)
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha1::new();
+    hasher.update(fs::read(path)?);
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
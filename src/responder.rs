@@ -0,0 +1,168 @@
+use crate::ChatMessage;
+use anyhow::{Context, Result};
+use regex::{Captures, Regex};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+// What a trigger's handler decided to do about a message
+pub enum Response {
+    Ignore,
+    // Sent right away, as a command when it starts with /
+    Reply(String),
+    // Runs on its own thread, e.g. to ask a web API, and its answer is sent
+    // the next time the client reads. poll_event picks it up within its
+    // timeout.
+    Spawn(Box<dyn FnOnce() -> Option<String> + Send>),
+}
+
+impl Response {
+    pub fn reply(text: &str) -> Response {
+        Response::Reply(String::from(text))
+    }
+
+    pub fn spawn(work: impl FnOnce() -> Option<String> + Send + 'static) -> Response {
+        Response::Spawn(Box::new(work))
+    }
+}
+
+// Gets the player chat that matched and the pattern's captures on its content
+pub type TriggerHandler = Box<dyn FnMut(&ChatMessage, &Captures) -> Response + Send>;
+
+// A trigger answers at most `count` times every `period`. Two bots answering
+// each other run into it quickly and go quiet instead of flooding the chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerRate {
+    pub count: usize,
+    pub period: Duration,
+}
+
+impl Default for TriggerRate {
+    // 3 answers every 10 seconds
+    fn default() -> TriggerRate {
+        TriggerRate {
+            count: 3,
+            period: Duration::from_secs(10),
+        }
+    }
+}
+
+struct Trigger {
+    pattern: Regex,
+    rate: TriggerRate,
+    // When it answered within the last period, oldest first
+    fired: VecDeque<Instant>,
+    handler: TriggerHandler,
+}
+
+impl Trigger {
+    fn is_limited(&mut self, now: Instant) -> bool {
+        while self
+            .fired
+            .front()
+            .is_some_and(|fired| now.duration_since(*fired) >= self.rate.period)
+        {
+            self.fired.pop_front();
+        }
+        self.fired.len() >= self.rate.count
+    }
+}
+
+// Keyword triggers for player chat: every trigger whose pattern matches the
+// content gets to reply, start work in the background or ignore it. Unlike
+// ChatRules a handler sees the whole ChatMessage and may decide not to
+// answer, which doesn't count against its rate. Our own messages never
+// match.
+pub struct Responder {
+    triggers: Vec<Trigger>,
+    // Answers of spawned work
+    finished: Receiver<String>,
+    sender: Sender<String>,
+}
+
+impl Default for Responder {
+    fn default() -> Responder {
+        let (sender, finished) = mpsc::channel();
+        Responder {
+            triggers: Vec::new(),
+            finished,
+            sender,
+        }
+    }
+}
+
+impl fmt::Debug for Responder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.triggers.iter().map(|trigger| trigger.pattern.as_str()))
+            .finish()
+    }
+}
+
+impl Responder {
+    pub fn new() -> Responder {
+        Responder::default()
+    }
+
+    pub fn trigger(
+        mut self,
+        pattern: &str,
+        rate: TriggerRate,
+        handler: impl FnMut(&ChatMessage, &Captures) -> Response + Send + 'static,
+    ) -> Result<Responder> {
+        let pattern = Regex::new(pattern).with_context(|| format!("Bad pattern {}", pattern))?;
+        self.triggers.push(Trigger {
+            pattern,
+            rate,
+            fired: VecDeque::new(),
+            handler: Box::new(handler),
+        });
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    // Runs every matching trigger that isn't rate limited, returns the
+    // replies to send now. Spawned work answers through finished().
+    pub fn respond(&mut self, message: &ChatMessage) -> Vec<String> {
+        let content = message.content.to_plain();
+        let now = Instant::now();
+        let mut replies = Vec::new();
+        for trigger in &mut self.triggers {
+            let captures = match trigger.pattern.captures(&content) {
+                Some(captures) => captures,
+                None => continue,
+            };
+            if trigger.is_limited(now) {
+                continue;
+            }
+
+            match (trigger.handler)(message, &captures) {
+                Response::Ignore => continue,
+                Response::Reply(reply) => replies.push(reply),
+                Response::Spawn(work) => {
+                    let sender = self.sender.clone();
+                    thread::spawn(move || {
+                        if let Some(answer) = work() {
+                            // The client is gone, nobody to answer to
+                            let _ = sender.send(answer);
+                        }
+                    });
+                }
+            }
+            trigger.fired.push_back(now);
+        }
+        replies
+    }
+
+    // Answers of spawned work that finished since the last call
+    pub fn finished(&self) -> Vec<String> {
+        self.finished.try_iter().collect()
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use regex::{Captures, Regex};
+use std::time::{Duration, Instant};
+
+// A chat line that matched a rule
+#[derive(Debug)]
+pub struct ChatMatch<'a> {
+    // None for system messages
+    pub sender: Option<&'a str>,
+    pub text: &'a str,
+    pub captures: Captures<'a>,
+}
+
+impl ChatMatch<'_> {
+    // Fills $1, $name and {sender} in `template` from this match
+    pub fn expand(&self, template: &str) -> String {
+        let mut expanded = String::new();
+        self.captures.expand(template, &mut expanded);
+        expanded.replace("{sender}", self.sender.unwrap_or_default())
+    }
+}
+
+// Returns what to answer with, if anything. Answers starting with / are sent
+// as commands.
+pub type ChatCallback = Box<dyn FnMut(&ChatMatch) -> Option<String> + Send>;
+
+struct Rule {
+    pattern: Regex,
+    cooldown: Duration,
+    last_fired: Option<Instant>,
+    callback: ChatCallback,
+}
+
+// Regex patterns run against every incoming chat and system message, for
+// auto replies and the like. Our own messages never match, so a rule can't
+// answer itself in a loop.
+#[derive(Default)]
+pub struct ChatRules {
+    rules: Vec<Rule>,
+}
+
+impl std::fmt::Debug for ChatRules {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.rules.iter().map(|rule| rule.pattern.as_str()))
+            .finish()
+    }
+}
+
+impl ChatRules {
+    pub fn new() -> ChatRules {
+        ChatRules::default()
+    }
+
+    // A rule fires at most once every `cooldown`, so spamming the trigger
+    // doesn't get us kicked for spamming the answer
+    pub fn rule(
+        mut self,
+        pattern: &str,
+        cooldown: Duration,
+        callback: impl FnMut(&ChatMatch) -> Option<String> + Send + 'static,
+    ) -> Result<ChatRules> {
+        let pattern = Regex::new(pattern).with_context(|| format!("Bad pattern {}", pattern))?;
+        self.rules.push(Rule {
+            pattern,
+            cooldown,
+            last_fired: None,
+            callback: Box::new(callback),
+        });
+        Ok(self)
+    }
+
+    // Answers a matching line with `template`, expanded like ChatMatch::expand
+    pub fn reply(self, pattern: &str, cooldown: Duration, template: &str) -> Result<ChatRules> {
+        let template = String::from(template);
+        self.rule(pattern, cooldown, move |matched| {
+            Some(matched.expand(&template))
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Runs every matching rule that isn't cooling down, returns their answers
+    pub fn evaluate(&mut self, sender: Option<&str>, text: &str) -> Vec<String> {
+        let mut answers = Vec::new();
+        for rule in &mut self.rules {
+            let captures = match rule.pattern.captures(text) {
+                Some(captures) => captures,
+                None => continue,
+            };
+            if rule
+                .last_fired
+                .is_some_and(|fired| fired.elapsed() < rule.cooldown)
+            {
+                continue;
+            }
+
+            rule.last_fired = Some(Instant::now());
+            let matched = ChatMatch {
+                sender,
+                text,
+                captures,
+            };
+            answers.extend((rule.callback)(&matched));
+        }
+        answers
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{lookup_srv, split_host_port, Client, ShutdownToken, StatusReport, DEFAULT_PORT};
+use anyhow::Result;
+use std::{
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+// What probing one server found out
+#[derive(Debug)]
+pub struct ScanResult {
+    // Position of the server in the list given to scan_servers
+    pub index: usize,
+    pub server: String,
+    // The status and, if the server answered the ping too, the latency
+    pub outcome: Result<(StatusReport, Option<Duration>)>,
+}
+
+// Probes `servers` with up to `concurrency` at once, on threads of the
+// token. Results come out of the receiver as they finish, so slow servers
+// don't hold up the rest, and it closes once every server is done.
+pub fn scan_servers(
+    servers: Vec<String>,
+    concurrency: usize,
+    timeout: Duration,
+    shutdown: &ShutdownToken,
+) -> Receiver<ScanResult> {
+    let (sender, results) = mpsc::channel();
+    let workers = concurrency.clamp(1, servers.len().max(1));
+    let queue = Arc::new(Mutex::new(servers.into_iter().enumerate()));
+
+    for _ in 0..workers {
+        let queue = Arc::clone(&queue);
+        let sender = sender.clone();
+        shutdown.spawn(move |token| {
+            while !token.is_cancelled() {
+                let (index, server) = match queue.lock().unwrap().next() {
+                    Some(next) => next,
+                    None => return,
+                };
+                let outcome = probe_server(&server, timeout, &token);
+                let result = ScanResult {
+                    index,
+                    server,
+                    outcome,
+                };
+                if sender.send(result).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    results
+}
+
+// Status first, then a ping on a second connection for the latency. A
+// server that answers status but not pings still counts as up. Servers
+// given without a port are looked up through SRV like vanilla does.
+pub fn probe_server(
+    server: &str,
+    timeout: Duration,
+    shutdown: &ShutdownToken,
+) -> Result<(StatusReport, Option<Duration>)> {
+    let (host, port) = split_host_port(server)?;
+    let (host, port) = match port {
+        Some(port) => (host, port),
+        None => lookup_srv(&host, timeout)
+            .ok()
+            .flatten()
+            .unwrap_or((host, DEFAULT_PORT)),
+    };
+    let connect = || {
+        Client::builder(&host, port)
+            .connect_timeout(timeout)
+            .read_timeout(timeout)
+            .lenient_status(true)
+            .shutdown_token(shutdown.clone())
+            .connect()
+    };
+
+    let report = connect()?.server_status_report()?;
+    let latency = connect().and_then(|mut client| client.ping()).ok();
+
+    Ok((report, latency))
+}
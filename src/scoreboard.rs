@@ -0,0 +1,166 @@
+use crate::{Component, Event, Packet};
+use anyhow::Result;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DisplaySlot {
+    List,
+    Sidebar,
+    BelowName,
+    // Sidebar only shown to members of the team with this color index
+    TeamSidebar(u8),
+}
+
+impl DisplaySlot {
+    fn from_byte(byte: u8) -> DisplaySlot {
+        match byte {
+            0 => DisplaySlot::List,
+            1 => DisplaySlot::Sidebar,
+            2 => DisplaySlot::BelowName,
+            other => DisplaySlot::TeamSidebar(other - 3),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Objective {
+    pub name: String,
+    pub display_name: Component,
+    // Shown as hearts instead of a number in the tab list
+    pub hearts: bool,
+    // Keyed by entry, a player name or any fake name the server made up
+    pub scores: HashMap<String, i32>,
+}
+
+impl Objective {
+    // Highest score first, the order the sidebar shows them in
+    pub fn sorted_scores(&self) -> Vec<(&str, i32)> {
+        let mut scores: Vec<(&str, i32)> = self
+            .scores
+            .iter()
+            .map(|(entry, score)| (entry.as_str(), *score))
+            .collect();
+        scores.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        scores
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Scoreboard {
+    objectives: HashMap<String, Objective>,
+    displayed: HashMap<DisplaySlot, String>,
+}
+
+impl Scoreboard {
+    pub fn objectives(&self) -> &HashMap<String, Objective> {
+        &self.objectives
+    }
+
+    pub fn objective(&self, name: &str) -> Option<&Objective> {
+        self.objectives.get(name)
+    }
+
+    pub fn displayed(&self, slot: DisplaySlot) -> Option<&Objective> {
+        self.displayed
+            .get(&slot)
+            .and_then(|name| self.objectives.get(name))
+    }
+
+    pub fn sidebar(&self) -> Option<&Objective> {
+        self.displayed(DisplaySlot::Sidebar)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.objectives.clear();
+        self.displayed.clear();
+    }
+
+    // Display Objective
+    pub(crate) fn handle_display(&mut self, packet: &Packet) -> Result<()> {
+        let mut reader = packet.reader();
+        let slot = DisplaySlot::from_byte(reader.read_u8()?);
+        let name = reader.read_str()?;
+
+        // An empty name clears the slot
+        if name.is_empty() {
+            self.displayed.remove(&slot);
+        } else {
+            self.displayed.insert(slot, String::from(name));
+        }
+
+        Ok(())
+    }
+
+    // Update Objectives
+    pub(crate) fn handle_objective(&mut self, packet: &Packet) -> Result<()> {
+        let mut reader = packet.reader();
+        let name = String::from(reader.read_str()?);
+        match reader.read_u8()? {
+            1 => {
+                self.objectives.remove(&name);
+                self.displayed.retain(|_, displayed| *displayed != name);
+            }
+            mode => {
+                let display_name = Component::from_json(reader.read_str()?)?;
+                let hearts = reader.read_varint()? == 1;
+                match (mode, self.objectives.get_mut(&name)) {
+                    (2, Some(objective)) => {
+                        objective.display_name = display_name;
+                        objective.hearts = hearts;
+                    }
+                    _ => {
+                        self.objectives.insert(
+                            name.clone(),
+                            Objective {
+                                name,
+                                display_name,
+                                hearts,
+                                scores: HashMap::new(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Update Score, returns a ScoreChanged event per affected objective
+    pub(crate) fn handle_score(&mut self, packet: &Packet) -> Result<Vec<Event>> {
+        let mut reader = packet.reader();
+        let entry = String::from(reader.read_str()?);
+        let action = reader.read_varint()?;
+        let objective = reader.read_str()?;
+
+        let mut events = Vec::new();
+        if action == 1 {
+            // Removal from an unnamed objective removes the entry everywhere
+            for (name, target) in self.objectives.iter_mut() {
+                if (objective.is_empty() || name == objective)
+                    && target.scores.remove(&entry).is_some()
+                {
+                    events.push(Event::ScoreChanged {
+                        objective: name.clone(),
+                        entry: entry.clone(),
+                        score: None,
+                    });
+                }
+            }
+            return Ok(events);
+        }
+
+        let score = reader.read_varint()?;
+        if let Some(target) = self.objectives.get_mut(objective) {
+            if target.scores.insert(entry.clone(), score) != Some(score) {
+                events.push(Event::ScoreChanged {
+                    objective: String::from(objective),
+                    entry,
+                    score: Some(score),
+                });
+            }
+        }
+
+        Ok(events)
+    }
+}
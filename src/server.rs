@@ -0,0 +1,175 @@
+use crate::{Connection, Packet, ProxyHeader, Transport};
+use anyhow::{anyhow, Result};
+use std::{net::SocketAddr, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NextState {
+    Status,
+    Login,
+    // Logging in after the previous server sent a Transfer (1.20.5+)
+    Transfer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub protocol_version: i32,
+    pub hostname: String,
+    pub port: u16,
+    pub next_state: NextState,
+}
+
+impl Handshake {
+    pub fn to_packet(&self) -> Result<Packet> {
+        let mut packet = Packet::new();
+        packet.write_varint(0x00)?; // protocol id
+        packet.write_varint(self.protocol_version)?; // protocol version
+        packet.write_string(&self.hostname)?; // hostname
+        packet.write_slice(&self.port.to_be_bytes()); // port
+        packet.write_varint(match self.next_state {
+            NextState::Status => 1,
+            NextState::Login => 2,
+            NextState::Transfer => 3,
+        })?; // next state
+
+        Ok(packet)
+    }
+
+    pub fn from_packet(packet: &mut Packet) -> Result<Handshake> {
+        if packet.get_protocol_id() != Some(0x00) {
+            return Err(anyhow!(
+                "Expected a handshake, got packet {:?}",
+                packet.get_protocol_id()
+            ));
+        }
+
+        let protocol_version = packet.read_varint()?;
+        let hostname = packet.read_string()?;
+        let port = packet.read_slice(2)?;
+        let port = u16::from_be_bytes([port[0], port[1]]);
+        let next_state = match packet.read_varint()? {
+            1 => NextState::Status,
+            2 => NextState::Login,
+            3 => NextState::Transfer,
+            other => return Err(anyhow!("Unknown handshake next state {}", other)),
+        };
+
+        Ok(Handshake {
+            protocol_version,
+            hostname,
+            port,
+            next_state,
+        })
+    }
+}
+
+// The server side of a connection: an accepted client that already sent its handshake
+pub struct ServerConnection {
+    connection: Connection,
+    peer: SocketAddr,
+    handshake: Handshake,
+}
+
+impl ServerConnection {
+    // Transports without a network address, like unix sockets and memory
+    // pipes, get 0.0.0.0:0 as the peer
+    pub fn accept(stream: impl Transport) -> Result<ServerConnection> {
+        let peer = stream
+            .peer_addr()
+            .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+        let mut connection = Connection::new(Box::new(stream))?;
+
+        let mut packet = connection.read_packet()?;
+        let handshake = Handshake::from_packet(&mut packet)?;
+
+        Ok(ServerConnection {
+            connection,
+            peer,
+            handshake,
+        })
+    }
+
+    // For listeners behind a load balancer: reads the PROXY protocol header
+    // first and reports the address it carries as the peer
+    pub fn accept_proxied(mut stream: impl Transport) -> Result<ServerConnection> {
+        let header = ProxyHeader::read_from(&mut stream)?;
+        let mut connection = ServerConnection::accept(stream)?;
+        if let Some(header) = header {
+            connection.peer = header.source;
+        }
+
+        Ok(connection)
+    }
+
+    pub fn handshake(&self) -> &Handshake {
+        &self.handshake
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.connection.set_read_timeout(timeout)
+    }
+
+    // Waits up to `timeout` for the next packet to start arriving
+    pub fn wait_readable(&mut self, timeout: Duration) -> Result<bool> {
+        self.connection.wait_readable(timeout)
+    }
+
+    pub fn set_compression(&mut self, threshold: Option<usize>) {
+        self.connection.set_compression(threshold);
+    }
+
+    pub fn send_packet(&mut self, packet: &Packet) -> Result<()> {
+        self.connection.send_packet(packet)
+    }
+
+    pub fn read_packet(&mut self) -> Result<Packet> {
+        self.connection.read_packet()
+    }
+
+    pub fn read_packet_into(&mut self, packet: &mut Packet) -> Result<()> {
+        self.connection.read_packet_into(packet)
+    }
+
+    pub fn into_inner(self) -> Result<(Box<dyn Transport>, Vec<u8>)> {
+        self.connection.into_inner()
+    }
+
+    // Answers a status request with `status` and echoes the ping that follows
+    pub fn respond_status(&mut self, status: &str) -> Result<()> {
+        let request = self.read_packet()?;
+        if request.get_protocol_id() != Some(0x00) {
+            return Err(anyhow!(
+                "Expected a status request, got packet {:?}",
+                request.get_protocol_id()
+            ));
+        }
+
+        let mut packet = Packet::new();
+        packet.write_varint(0x00)?; // Protocol ID
+        packet.write_string(status)?; // JSON response
+        self.send_packet(&packet)?;
+
+        // Clients that only want the MOTD close the connection here
+        let ping = match self.read_packet() {
+            Ok(ping) => ping,
+            Err(_) => return Ok(()),
+        };
+        if ping.get_protocol_id() == Some(0x01) {
+            self.send_packet(&ping)?; // Pong carries the same payload back
+        }
+
+        Ok(())
+    }
+
+    // Kicks a client that is still in the login state
+    pub fn disconnect_login(&mut self, reason: &str) -> Result<()> {
+        let mut packet = Packet::new();
+        packet.write_varint(0x00)?; // Protocol ID
+        packet.write_string(&serde_json::json!({ "text": reason }).to_string())?; // Reason
+
+        self.send_packet(&packet)
+    }
+}
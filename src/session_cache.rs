@@ -0,0 +1,322 @@
+use crate::{
+    auth::{self, MicrosoftTokens},
+    HttpClient, Profile,
+};
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::*;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{ErrorKind, Write},
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+// Tokens this close to expiring are refreshed before being handed out, so a
+// connection started with one doesn't outlive it. Seconds.
+pub const REFRESH_MARGIN: u64 = 300;
+// PBKDF2-HMAC-SHA256 rounds turning a passphrase into the file key
+const KDF_ITERATIONS: u32 = 200_000;
+const SALT_LENGTH: usize = 16;
+const FILE_VERSION: u32 = 1;
+
+// One account's tokens, as kept between runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedSession {
+    pub uuid: Uuid,
+    pub name: String,
+    // Minecraft's, for the session server
+    pub access_token: String,
+    // Seconds since the epoch
+    pub expires_at: u64,
+    // Microsoft's, for getting the next access token unattended
+    pub refresh_token: String,
+}
+
+impl CachedSession {
+    // Signs the Microsoft account in to Minecraft
+    pub fn sign_in(http: &HttpClient, tokens: &MicrosoftTokens) -> Result<CachedSession> {
+        let minecraft = auth::minecraft_login(http, &tokens.access_token)?;
+        Ok(CachedSession {
+            uuid: minecraft.profile.uuid,
+            name: minecraft.profile.name,
+            access_token: minecraft.access_token,
+            expires_at: minecraft.expires_at,
+            refresh_token: tokens.refresh_token.clone(),
+        })
+    }
+
+    // Whether the access token is gone or about to be
+    pub fn needs_refresh(&self) -> bool {
+        auth::unix_time() + REFRESH_MARGIN >= self.expires_at
+    }
+
+    pub fn profile(&self) -> Profile {
+        Profile {
+            uuid: self.uuid,
+            name: self.name.clone(),
+            properties: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Sessions {
+    version: u32,
+    sessions: BTreeMap<String, CachedSession>,
+}
+
+// Sessions encrypted with ChaCha20-Poly1305 under a key derived from the
+// passphrase, so a copied file is no use without it
+#[derive(Serialize, Deserialize)]
+struct EncryptedSessions {
+    version: u32,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+// Keeps Microsoft refresh tokens and Minecraft access tokens in a file,
+// keyed by an account name of our choosing, so a bot signed in once can
+// start unattended from then on. The file is only readable by its owner,
+// and encrypted with a passphrase when one is given.
+#[derive(Debug, Clone)]
+pub struct SessionCache {
+    path: PathBuf,
+    passphrase: Option<String>,
+    http: HttpClient,
+}
+
+impl SessionCache {
+    pub fn new(path: impl Into<PathBuf>) -> SessionCache {
+        SessionCache {
+            path: path.into(),
+            passphrase: None,
+            http: HttpClient::default(),
+        }
+    }
+
+    // sessions.json in data_directory()
+    pub fn default_path() -> Option<PathBuf> {
+        Some(data_directory()?.join("sessions.json"))
+    }
+
+    // Encrypts what's written and is needed to read an encrypted file
+    pub fn passphrase(mut self, passphrase: Option<String>) -> SessionCache {
+        self.passphrase = passphrase;
+        self
+    }
+
+    // Used for refreshing
+    pub fn http(mut self, http: HttpClient) -> SessionCache {
+        self.http = http;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Whether the file on disk is encrypted, false when there is none
+    pub fn is_encrypted(&self) -> Result<bool> {
+        Ok(match self.read()? {
+            Some(text) => serde_json::from_str::<EncryptedSessions>(&text).is_ok(),
+            None => false,
+        })
+    }
+
+    pub fn accounts(&self) -> Result<Vec<String>> {
+        Ok(self.load()?.sessions.into_keys().collect())
+    }
+
+    // As stored, however stale
+    pub fn get(&self, account: &str) -> Result<Option<CachedSession>> {
+        Ok(self.load()?.sessions.remove(account))
+    }
+
+    pub fn insert(&self, account: &str, session: CachedSession) -> Result<()> {
+        let mut sessions = self.load()?;
+        sessions.sessions.insert(String::from(account), session);
+        self.save(&sessions)
+    }
+
+    // Returns whether there was such an account
+    pub fn remove(&self, account: &str) -> Result<bool> {
+        let mut sessions = self.load()?;
+        let removed = sessions.sessions.remove(account).is_some();
+        if removed {
+            self.save(&sessions)?;
+        }
+        Ok(removed)
+    }
+
+    // The account's session, refreshed with `client_id` first if its access
+    // token is about to expire, which needs the Azure app it was signed in
+    // with. Refreshing replaces the refresh token too, so the new one is
+    // saved right away.
+    pub fn session(&self, account: &str, client_id: Option<&str>) -> Result<CachedSession> {
+        let session = self
+            .get(account)?
+            .ok_or_else(|| anyhow!("No saved session for {}, log in first", account))?;
+        if !session.needs_refresh() {
+            return Ok(session);
+        }
+        let client_id = client_id.ok_or_else(|| {
+            anyhow!(
+                "The session of {} expired, refreshing it needs a client id",
+                account
+            )
+        })?;
+
+        let tokens = auth::refresh_microsoft(&self.http, client_id, &session.refresh_token)?;
+        let session = CachedSession::sign_in(&self.http, &tokens)?;
+        self.insert(account, session.clone())?;
+        Ok(session)
+    }
+
+    fn read(&self) -> Result<Option<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => Ok(Some(text)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => {
+                Err(error).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        }
+    }
+
+    fn load(&self) -> Result<Sessions> {
+        let Some(text) = self.read()? else {
+            return Ok(Sessions::default());
+        };
+        let invalid = || format!("Invalid session cache {}", self.path.display());
+
+        let text = match serde_json::from_str::<EncryptedSessions>(&text) {
+            Ok(encrypted) => {
+                let passphrase = self.passphrase.as_deref().ok_or_else(|| {
+                    anyhow!(
+                        "{} is encrypted, a passphrase is needed",
+                        self.path.display()
+                    )
+                })?;
+                decrypt(&encrypted, passphrase).with_context(invalid)?
+            }
+            Err(_) => text,
+        };
+        let sessions: Sessions = serde_json::from_str(&text).with_context(invalid)?;
+        if sessions.version > FILE_VERSION {
+            return Err(anyhow!(
+                "{} was written by a newer mchat",
+                self.path.display()
+            ));
+        }
+        Ok(sessions)
+    }
+
+    // Written next to the file and renamed over it, so a crash can't leave
+    // half of it behind
+    fn save(&self, sessions: &Sessions) -> Result<()> {
+        let sessions = Sessions {
+            version: FILE_VERSION,
+            sessions: sessions.sessions.clone(),
+        };
+        let mut text = serde_json::to_string_pretty(&sessions)?;
+        if let Some(passphrase) = &self.passphrase {
+            text = serde_json::to_string_pretty(&encrypt(&text, passphrase)?)?;
+        }
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)
+                .with_context(|| format!("Failed to create {}", directory.display()))?;
+        }
+        let temporary = self.path.with_extension("json.tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(&temporary)
+            .with_context(|| format!("Failed to write {}", temporary.display()))?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+// Where mchat keeps what it saves per user: $XDG_DATA_HOME/mchat,
+// ~/Library/Application Support/mchat or %APPDATA%\mchat
+pub fn data_directory() -> Option<PathBuf> {
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        PathBuf::from(non_empty("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(non_empty("HOME")?).join("Library/Application Support")
+    } else {
+        match non_empty("XDG_DATA_HOME") {
+            Some(base) => PathBuf::from(base),
+            None => PathBuf::from(non_empty("HOME")?).join(".local/share"),
+        }
+    };
+
+    Some(base.join("mchat"))
+}
+
+fn file_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<LessSafeKey> {
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| anyhow!("Key derivation needs rounds"))?;
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| anyhow!("Invalid key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn encrypt(text: &str, passphrase: &str) -> Result<EncryptedSessions> {
+    let random = SystemRandom::new();
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LEN];
+    random
+        .fill(&mut salt)
+        .and_then(|_| random.fill(&mut nonce))
+        .map_err(|_| anyhow!("No randomness to encrypt with"))?;
+
+    let mut data = text.as_bytes().to_vec();
+    file_key(passphrase, &salt, KDF_ITERATIONS)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Failed to encrypt the session cache"))?;
+
+    Ok(EncryptedSessions {
+        version: FILE_VERSION,
+        iterations: KDF_ITERATIONS,
+        salt: BASE64_STANDARD.encode(salt),
+        nonce: BASE64_STANDARD.encode(nonce),
+        ciphertext: BASE64_STANDARD.encode(data),
+    })
+}
+
+fn decrypt(encrypted: &EncryptedSessions, passphrase: &str) -> Result<String> {
+    let salt = BASE64_STANDARD.decode(&encrypted.salt)?;
+    let nonce = Nonce::try_assume_unique_for_key(&BASE64_STANDARD.decode(&encrypted.nonce)?)
+        .map_err(|_| anyhow!("Invalid nonce"))?;
+    let mut data = BASE64_STANDARD.decode(&encrypted.ciphertext)?;
+
+    // Poly1305 catches a wrong passphrase as surely as tampering
+    let text = file_key(passphrase, &salt, encrypted.iterations)?
+        .open_in_place(nonce, Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Wrong passphrase, or the file was changed"))?;
+    Ok(String::from_utf8(text.to_vec())?)
+}
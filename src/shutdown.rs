@@ -0,0 +1,198 @@
+use std::{
+    mem,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    sync::{Arc, Condvar, Mutex, Weak},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+type CancelCallback = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    // Numbered so a CancelRegistration can take its own back out
+    callbacks: Vec<(u64, CancelCallback)>,
+    next_callback: u64,
+    threads: Vec<JoinHandle<()>>,
+}
+
+type Inner = (Mutex<State>, Condvar);
+
+// Shared by every subsystem of a process. Cancelling it stops loops that
+// poll it, runs the registered callbacks (used to unblock sockets) and lets
+// `shutdown` join every thread that was spawned through it.
+#[derive(Clone, Default)]
+pub struct ShutdownToken {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for ShutdownToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ShutdownToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl ShutdownToken {
+    pub fn new() -> ShutdownToken {
+        ShutdownToken::default()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.0.lock().unwrap().cancelled
+    }
+
+    // Safe to call any number of times from any thread
+    pub fn cancel(&self) {
+        let callbacks = {
+            let mut state = self.inner.0.lock().unwrap();
+            if state.cancelled {
+                return;
+            }
+            state.cancelled = true;
+            mem::take(&mut state.callbacks)
+        };
+        self.inner.1.notify_all();
+
+        // Outside the lock, callbacks may well touch the token themselves
+        for (_, callback) in callbacks {
+            callback();
+        }
+    }
+
+    // Runs `callback` on cancellation, right away if that already happened
+    pub fn on_cancel(&self, callback: impl FnOnce() + Send + 'static) {
+        self.add_callback(Box::new(callback));
+    }
+
+    // Like on_cancel, but only as long as the returned registration lives.
+    // For callbacks tied to something shorter lived than the token, e.g. a
+    // connection, which would otherwise pile up in it.
+    pub fn register_on_cancel(
+        &self,
+        callback: impl FnOnce() + Send + 'static,
+    ) -> CancelRegistration {
+        CancelRegistration {
+            inner: Arc::downgrade(&self.inner),
+            id: self.add_callback(Box::new(callback)),
+        }
+    }
+
+    // accept() only returns for a connection, so on cancellation this makes
+    // one to the listener bound at `address`. One on all interfaces (0.0.0.0
+    // or ::) is dialed on localhost, the unspecified address isn't
+    // connectable everywhere.
+    pub fn wake_on_cancel(&self, address: SocketAddr) {
+        let mut address = address;
+        if address.ip().is_unspecified() {
+            let localhost = match address {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            };
+            address.set_ip(localhost);
+        }
+        self.on_cancel(move || {
+            let _ = TcpStream::connect(address);
+        });
+    }
+
+    // Returns the callback's number, None if it already ran
+    fn add_callback(&self, callback: CancelCallback) -> Option<u64> {
+        let mut state = self.inner.0.lock().unwrap();
+        if state.cancelled {
+            drop(state);
+            callback();
+            return None;
+        }
+        let id = state.next_callback;
+        state.next_callback += 1;
+        state.callbacks.push((id, callback));
+        Some(id)
+    }
+
+    // Sleeps for `timeout` unless cancelled first. Returns true if cancelled.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let state = self.inner.0.lock().unwrap();
+        let (state, _) = self
+            .inner
+            .1
+            .wait_timeout_while(state, timeout, |state| !state.cancelled)
+            .unwrap();
+        state.cancelled
+    }
+
+    // Blocks until cancelled
+    pub fn wait(&self) {
+        let state = self.inner.0.lock().unwrap();
+        drop(
+            self.inner
+                .1
+                .wait_while(state, |state| !state.cancelled)
+                .unwrap(),
+        );
+    }
+
+    // Spawns a thread that `shutdown` will wait for. The thread gets its own
+    // handle to the token and is expected to return soon after cancellation.
+    pub fn spawn(&self, task: impl FnOnce(ShutdownToken) + Send + 'static) {
+        let token = self.clone();
+        let handle = thread::spawn(move || task(token));
+
+        let mut state = self.inner.0.lock().unwrap();
+        // Drop the handles of threads that are already done so long running
+        // servers don't accumulate them
+        state.threads.retain(|thread| !thread.is_finished());
+        state.threads.push(handle);
+    }
+
+    // Cancels and waits for every spawned thread, including ones spawned
+    // while shutting down
+    pub fn shutdown(&self) {
+        self.cancel();
+        loop {
+            let threads = mem::take(&mut self.inner.0.lock().unwrap().threads);
+            if threads.is_empty() {
+                return;
+            }
+            for thread in threads {
+                if thread.join().is_err() {
+                    eprintln!("A thread panicked while shutting down");
+                }
+            }
+        }
+    }
+}
+
+// Takes its callback out of the token when dropped, see
+// ShutdownToken::register_on_cancel
+#[must_use = "dropping the registration removes the callback right away"]
+pub struct CancelRegistration {
+    inner: Weak<Inner>,
+    id: Option<u64>,
+}
+
+impl std::fmt::Debug for CancelRegistration {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CancelRegistration")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl Drop for CancelRegistration {
+    fn drop(&mut self) {
+        if let (Some(inner), Some(id)) = (self.inner.upgrade(), self.id) {
+            let mut state = inner.0.lock().unwrap();
+            let index = state
+                .callbacks
+                .iter()
+                .position(|(callback, _)| *callback == id);
+            // Dropped outside the lock, it may own anything
+            let removed = index.map(|index| state.callbacks.remove(index));
+            drop(state);
+            drop(removed);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{
+    inspect, ConnectionState, DecodedPacket, Direction, Frame, Handshake, NextState,
+    ProtocolFeatures, Transport,
+};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+// One packet on its way through a Sniffer
+#[derive(Debug)]
+pub struct SniffedPacket {
+    pub direction: Direction,
+    // What the connection was in when the packet was sent
+    pub state: ConnectionState,
+    // Bytes the frame took on the wire
+    pub size: usize,
+    // Or why it couldn't be decoded
+    pub decoded: Result<DecodedPacket, String>,
+}
+
+// Sits between a vanilla client and a server for debugging the protocol,
+// passing every packet on as it came while decoding what it can. The one
+// change is the handshake, rewritten to name the target so virtual hosts
+// route it like a direct connection. Online mode servers encrypt what
+// follows the login's Encryption Response, from there it only relays.
+pub struct Sniffer {
+    hostname: String,
+    port: u16,
+    features: Option<ProtocolFeatures>,
+}
+
+// What both directions have to agree on, each changes it for the other
+struct Session {
+    state: ConnectionState,
+    compression: Option<usize>,
+    features: ProtocolFeatures,
+    // Encrypted, or out of step after a framing error
+    opaque: bool,
+}
+
+impl Sniffer {
+    pub fn new(hostname: &str, port: u16) -> Sniffer {
+        Sniffer {
+            hostname: String::from(hostname),
+            port,
+            features: None,
+        }
+    }
+
+    // Decodes with these packet ids instead of the ones for the protocol
+    // version in the client's handshake
+    pub fn protocol_features(mut self, features: ProtocolFeatures) -> Sniffer {
+        self.features = Some(features);
+        self
+    }
+
+    // Relays one player until either side hangs up, handing every packet to
+    // `log` on the thread that forwards it
+    pub fn relay(
+        &self,
+        client: impl Transport,
+        log: impl Fn(&SniffedPacket) + Send + Sync + 'static,
+    ) -> Result<()> {
+        let address = format!("{}:{}", self.hostname, self.port);
+        let server = TcpStream::connect(&address)
+            .with_context(|| format!("Failed to connect to {}", address))?;
+        let server: Box<dyn Transport> = Box::new(server);
+
+        let session = Arc::new(Mutex::new(Session {
+            state: ConnectionState::Handshaking,
+            compression: None,
+            features: self.features.unwrap_or_default(),
+            opaque: false,
+        }));
+        let log = Arc::new(log);
+
+        let upstream = Pump {
+            direction: Direction::Serverbound,
+            session: session.clone(),
+            target: Some((self.hostname.clone(), self.port)),
+            pick_features: self.features.is_none(),
+        };
+        let (reader, writer) = (client.try_clone()?, server.try_clone()?);
+        let upstream_log = log.clone();
+        let upstream = thread::spawn(move || upstream.run(reader, writer, &*upstream_log));
+
+        let downstream = Pump {
+            direction: Direction::Clientbound,
+            session,
+            target: None,
+            pick_features: false,
+        };
+        downstream.run(server, Box::new(client), &*log);
+        let _ = upstream.join();
+
+        Ok(())
+    }
+}
+
+struct Pump {
+    direction: Direction,
+    session: Arc<Mutex<Session>>,
+    // Where the handshake gets pointed, only for the client's side
+    target: Option<(String, u16)>,
+    pick_features: bool,
+}
+
+impl Pump {
+    // Like a splice, a side hanging up tears down the other
+    fn run(
+        &self,
+        mut reader: Box<dyn Transport>,
+        mut writer: Box<dyn Transport>,
+        log: &dyn Fn(&SniffedPacket),
+    ) {
+        let _ = self.forward(&mut reader, &mut writer, log);
+        let _ = writer.shutdown();
+        let _ = reader.shutdown();
+    }
+
+    fn forward(
+        &self,
+        reader: &mut Box<dyn Transport>,
+        writer: &mut Box<dyn Transport>,
+        log: &dyn Fn(&SniffedPacket),
+    ) -> Result<()> {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 8192];
+        loop {
+            let length = reader.read(&mut chunk)?;
+            if length == 0 {
+                return Ok(());
+            }
+            buffer.extend_from_slice(&chunk[..length]);
+
+            while let Some(forwarded) = self.next_frame(&mut buffer, log)? {
+                writer.write_all(&forwarded)?;
+            }
+            writer.flush()?;
+        }
+    }
+
+    // Takes the next whole frame off `buffer`, returning what to send on.
+    // Once the session is opaque that's everything buffered, undecoded.
+    fn next_frame(
+        &self,
+        buffer: &mut Vec<u8>,
+        log: &dyn Fn(&SniffedPacket),
+    ) -> Result<Option<Vec<u8>>> {
+        let mut session = self
+            .session
+            .lock()
+            .map_err(|_| anyhow!("A sniffer thread panicked"))?;
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+        if session.opaque {
+            return Ok(Some(std::mem::take(buffer)));
+        }
+
+        let state = session.state;
+        let frame = match Frame::parse(buffer, session.compression) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(None),
+            Err(error) => {
+                // Can't know where the next frame starts, so give up decoding
+                session.opaque = true;
+                log(&SniffedPacket {
+                    direction: self.direction,
+                    state,
+                    size: buffer.len(),
+                    decoded: Err(format!("{:#}", error)),
+                });
+                return Ok(Some(std::mem::take(buffer)));
+            }
+        };
+        let mut forwarded: Vec<u8> = buffer.drain(..frame.size).collect();
+
+        let packet = frame.packet;
+        let decoded = inspect::decode(&session.features, self.direction, state, &packet)
+            .map_err(|error| format!("{:#}", error));
+        let id = packet.get_protocol_id();
+        match (self.direction, state, id) {
+            (Direction::Serverbound, ConnectionState::Handshaking, Some(0x00)) => {
+                let mut handshake = Handshake::from_packet(&mut packet.clone())?;
+                if self.pick_features {
+                    if let Some(features) =
+                        ProtocolFeatures::for_version(handshake.protocol_version)
+                    {
+                        session.features = *features;
+                    }
+                }
+                session.state = match handshake.next_state {
+                    NextState::Status => ConnectionState::Status,
+                    NextState::Login | NextState::Transfer => ConnectionState::Login,
+                };
+                if let Some((hostname, port)) = &self.target {
+                    handshake.hostname = hostname.clone();
+                    handshake.port = *port;
+                    forwarded = handshake.to_packet()?.into_frame(None);
+                }
+            }
+            (Direction::Clientbound, ConnectionState::Login, Some(id))
+                if id == session.features.compression_packet_id =>
+            {
+                let threshold = packet.reader().read_varint()?;
+                session.compression = usize::try_from(threshold).ok();
+            }
+            (Direction::Clientbound, ConnectionState::Login, Some(id))
+                if id == session.features.login_success_packet_id =>
+            {
+                session.state = ConnectionState::Play;
+            }
+            // The Encryption Response, everything after it is encrypted
+            (Direction::Serverbound, ConnectionState::Login, Some(0x01)) => session.opaque = true,
+            _ => {}
+        }
+
+        log(&SniffedPacket {
+            direction: self.direction,
+            state,
+            size: frame.size,
+            decoded,
+        });
+        Ok(Some(forwarded))
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{
+    chat_command, chat_message, connection::ConnectionWriter, Client, ConnectionState, Event,
+    Packet, ProtocolFeatures, ShutdownToken,
+};
+use anyhow::Result;
+use rand::{rngs::StdRng, RngExt};
+use std::time::Duration;
+
+// The receiving half of a split client. It owns the client, so everything
+// tracked from incoming packets stays readable through client(). Dropping
+// it closes the connection, for the writers too.
+pub struct ClientReader {
+    client: Client,
+}
+
+impl ClientReader {
+    pub(crate) fn new(client: Client) -> ClientReader {
+        ClientReader { client }
+    }
+
+    pub fn next_event(&mut self) -> Result<Event> {
+        self.client.next_event()
+    }
+
+    pub fn poll_event(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        self.client.poll_event(timeout)
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.client.state()
+    }
+
+    // Players, entities, scoreboard and the rest, as of the last event
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+// The sending half of a split client. Clones share the connection and can
+// each live on their own thread, packets never interleave.
+pub struct ClientWriter {
+    connection: ConnectionWriter,
+    features: ProtocolFeatures,
+    shutdown: ShutdownToken,
+    rng: StdRng,
+}
+
+impl ClientWriter {
+    pub(crate) fn new(
+        connection: ConnectionWriter,
+        features: ProtocolFeatures,
+        shutdown: ShutdownToken,
+    ) -> ClientWriter {
+        ClientWriter {
+            connection,
+            features,
+            shutdown,
+            rng: rand::make_rng(),
+        }
+    }
+
+    pub fn send_packet(&self, packet: &Packet) -> Result<()> {
+        self.connection.send_packet(packet)
+    }
+
+    // Unsigned like Client::send_chat_message, but not held to its chat rate
+    pub fn send_chat_message(&mut self, message: &str) -> Result<()> {
+        let packet = chat_message(&self.features, message, self.rng.random())?;
+        self.send_packet(&packet)
+    }
+
+    // `command` without the leading slash
+    pub fn send_command(&mut self, command: &str) -> Result<()> {
+        let packet = chat_command(&self.features, command, self.rng.random())?;
+        self.send_packet(&packet)
+    }
+
+    // Stops the reader too, its next_event returns an error
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+}
+
+// Every clone gets its own salts
+impl Clone for ClientWriter {
+    fn clone(&self) -> ClientWriter {
+        ClientWriter::new(
+            self.connection.clone(),
+            self.features,
+            self.shutdown.clone(),
+        )
+    }
+}
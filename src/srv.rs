@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use rand::RngExt;
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+const SRV: u16 = 33;
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+// Where the _minecraft._tcp SRV record of `host` points, the way vanilla
+// looks up servers given without a port. None for IP addresses, hosts
+// without a record and systems without a nameserver configured.
+pub fn lookup_srv(host: &str, timeout: Duration) -> Result<Option<(String, u16)>> {
+    if host.parse::<IpAddr>().is_ok() {
+        return Ok(None);
+    }
+    let Some(nameserver) = system_nameserver() else {
+        return Ok(None);
+    };
+
+    lookup_srv_with(SocketAddr::new(nameserver, 53), host, timeout)
+}
+
+// Same as lookup_srv, asking `nameserver` instead of the system's
+pub fn lookup_srv_with(
+    nameserver: SocketAddr,
+    host: &str,
+    timeout: Duration,
+) -> Result<Option<(String, u16)>> {
+    let id: u16 = rand::rng().random();
+    let name = format!("_minecraft._tcp.{}", host.trim_end_matches('.'));
+
+    let mut query = Vec::new();
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&[0x01, 0x00]); // Recursion desired
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // One question
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(anyhow!("{} isn't a valid hostname", host));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&SRV.to_be_bytes());
+    query.extend_from_slice(&[0, 1]); // Class IN
+
+    let local: SocketAddr = match nameserver {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+        SocketAddr::V6(_) => "[::]:0".parse()?,
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(nameserver)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send(&query)?;
+
+    let mut response = [0u8; 4096];
+    loop {
+        let length = socket.recv(&mut response)?;
+        // Anything else is a late answer to someone else's query
+        if length >= 2 && response[..2] == id.to_be_bytes() {
+            return parse_response(&response[..length]);
+        }
+    }
+}
+
+fn system_nameserver() -> Option<IpAddr> {
+    let conf = fs::read_to_string(RESOLV_CONF).ok()?;
+    conf.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => words.next()?.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+// The record with the lowest priority wins, the highest weight among equals
+fn parse_response(message: &[u8]) -> Result<Option<(String, u16)>> {
+    let truncated = || anyhow!("Truncated DNS response");
+    let u16_at = |offset: usize| -> Result<u16> {
+        let bytes = message.get(offset..offset + 2).ok_or_else(truncated)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    match u16_at(2)? & 0x000F {
+        0 => {}
+        3 => return Ok(None), // No such name
+        code => return Err(anyhow!("DNS lookup failed with code {}", code)),
+    }
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(message, offset)?.1 + 4; // Type and class
+    }
+
+    let mut best: Option<(u16, u16, String, u16)> = None;
+    for _ in 0..answers {
+        offset = read_name(message, offset)?.1;
+        let kind = u16_at(offset)?;
+        let data_length = u16_at(offset + 8)? as usize;
+        let data = offset + 10;
+        offset = data + data_length;
+        if offset > message.len() {
+            return Err(truncated());
+        }
+        if kind != SRV {
+            continue;
+        }
+
+        let (priority, weight, port) = (u16_at(data)?, u16_at(data + 2)?, u16_at(data + 4)?);
+        let target = read_name(message, data + 6)?.0;
+        let better = match &best {
+            Some((best_priority, best_weight, ..)) => {
+                priority < *best_priority || (priority == *best_priority && weight > *best_weight)
+            }
+            None => true,
+        };
+        if better && !target.is_empty() {
+            best = Some((priority, weight, target, port));
+        }
+    }
+
+    Ok(best.map(|(_, _, target, port)| (target, port)))
+}
+
+// A possibly compressed name at `offset`, and the offset right after it
+fn read_name(message: &[u8], mut offset: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Every jump has to go backwards, so pointer loops end
+    let mut limit = offset;
+    loop {
+        let length = *message
+            .get(offset)
+            .ok_or_else(|| anyhow!("Truncated DNS name"))? as usize;
+        match length {
+            0 => break,
+            _ if length & 0xC0 == 0xC0 => {
+                let low = *message
+                    .get(offset + 1)
+                    .ok_or_else(|| anyhow!("Truncated DNS name"))?
+                    as usize;
+                let target = (length & 0x3F) << 8 | low;
+                if target >= limit {
+                    return Err(anyhow!("DNS name pointer doesn't point back"));
+                }
+                end.get_or_insert(offset + 2);
+                offset = target;
+                limit = target;
+            }
+            _ => {
+                let label = message
+                    .get(offset + 1..offset + 1 + length)
+                    .ok_or_else(|| anyhow!("Truncated DNS name"))?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + length;
+            }
+        }
+    }
+
+    Ok((labels.join("."), end.unwrap_or(offset + 1)))
+}
@@ -0,0 +1,61 @@
+use crate::Packet;
+use anyhow::Result;
+
+// Below this the player can no longer sprint, vanilla's own threshold
+pub const SPRINT_FOOD_LEVEL: i32 = 6;
+
+// What the hotbar HUD shows: health, hunger and experience
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerStats {
+    pub health: f32,
+    pub food: i32,
+    pub saturation: f32,
+    // Progress towards the next level, 0 to 1
+    pub experience_bar: f32,
+    pub level: i32,
+    pub total_experience: i32,
+}
+
+impl Default for PlayerStats {
+    // A freshly spawned player, until the server says otherwise
+    fn default() -> PlayerStats {
+        PlayerStats {
+            health: 20.0,
+            food: 20,
+            saturation: 5.0,
+            experience_bar: 0.0,
+            level: 0,
+            total_experience: 0,
+        }
+    }
+}
+
+impl PlayerStats {
+    pub fn is_starving(&self) -> bool {
+        self.food <= 0
+    }
+
+    pub fn can_sprint(&self) -> bool {
+        self.food >= SPRINT_FOOD_LEVEL
+    }
+
+    // Set Health
+    pub(crate) fn apply_health(&mut self, packet: &Packet) -> Result<()> {
+        let mut reader = packet.reader();
+        self.health = reader.read_f32()?;
+        self.food = reader.read_varint()?;
+        self.saturation = reader.read_f32()?;
+
+        Ok(())
+    }
+
+    // Set Experience
+    pub(crate) fn apply_experience(&mut self, packet: &Packet) -> Result<()> {
+        let mut reader = packet.reader();
+        self.experience_bar = reader.read_f32()?;
+        self.level = reader.read_varint()?;
+        self.total_experience = reader.read_varint()?;
+
+        Ok(())
+    }
+}
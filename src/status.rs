@@ -0,0 +1,222 @@
+use crate::{favicon_from_file, Component};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, path::Path};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    pub name: String,
+    pub protocol: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Players {
+    pub max: i32,
+    pub online: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<Vec<PlayerSample>>,
+}
+
+// The JSON a server answers status requests with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub version: Version,
+    pub players: Players,
+    #[serde(default)]
+    pub description: Component,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    #[serde(
+        rename = "enforcesSecureChat",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub enforces_secure_chat: Option<bool>,
+}
+
+impl ServerStatus {
+    pub fn parse(json: &str) -> Result<ServerStatus> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    // Accepts the broken responses some servers send: invalid UTF-8,
+    // trailing commas, raw control characters inside strings and junk after
+    // the object. Everything that had to be fixed is listed in the report.
+    pub fn parse_lenient(bytes: &[u8]) -> Result<StatusReport> {
+        let mut fixes = Vec::new();
+
+        let json = String::from_utf8_lossy(bytes);
+        if let Cow::Owned(_) = json {
+            fixes.push(StatusFix::InvalidUtf8);
+        }
+        let json = repair_json(&json, &mut fixes);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        let status = ServerStatus::deserialize(&mut deserializer)?;
+        if deserializer.end().is_err() {
+            fixes.push(StatusFix::TrailingData);
+        }
+
+        Ok(StatusReport { status, fixes })
+    }
+
+    pub fn to_json(&self) -> String {
+        // Every field is a plain string, number or bool
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+// Something parse_lenient had to repair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFix {
+    InvalidUtf8,
+    TrailingCommas(usize),
+    ControlCharacters(usize),
+    TrailingData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusReport {
+    pub status: ServerStatus,
+    // Empty for well formed responses
+    pub fixes: Vec<StatusFix>,
+}
+
+// Drops commas right before a closing bracket and escapes control
+// characters inside strings, leaving everything else untouched
+fn repair_json(json: &str, fixes: &mut Vec<StatusFix>) -> String {
+    let mut repaired = String::with_capacity(json.len());
+    let (mut commas, mut controls) = (0, 0);
+    let (mut in_string, mut escaped) = (false, false);
+
+    for (index, character) in json.char_indices() {
+        if in_string {
+            match character {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                control if (control as u32) < 0x20 => {
+                    repaired.push_str(&format!("\\u{:04x}", control as u32));
+                    controls += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        } else if character == '"' {
+            in_string = true;
+        } else if character == ',' {
+            let next = json[index + 1..].trim_start().chars().next();
+            if matches!(next, Some('}') | Some(']')) {
+                commas += 1;
+                continue;
+            }
+        }
+        repaired.push(character);
+    }
+
+    if commas > 0 {
+        fixes.push(StatusFix::TrailingCommas(commas));
+    }
+    if controls > 0 {
+        fixes.push(StatusFix::ControlCharacters(controls));
+    }
+    repaired
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusBuilder {
+    status: ServerStatus,
+}
+
+impl Default for StatusBuilder {
+    fn default() -> StatusBuilder {
+        StatusBuilder::new()
+    }
+}
+
+impl StatusBuilder {
+    pub fn new() -> StatusBuilder {
+        StatusBuilder {
+            status: ServerStatus {
+                version: Version {
+                    name: String::from("1.19"),
+                    protocol: 759,
+                },
+                players: Players {
+                    max: 20,
+                    online: 0,
+                    sample: None,
+                },
+                description: Component::text("A Minecraft Server"),
+                favicon: None,
+                enforces_secure_chat: None,
+            },
+        }
+    }
+
+    pub fn version(mut self, name: &str, protocol: i32) -> StatusBuilder {
+        self.status.version = Version {
+            name: String::from(name),
+            protocol,
+        };
+        self
+    }
+
+    pub fn players(mut self, online: i32, max: i32) -> StatusBuilder {
+        self.status.players.online = online;
+        self.status.players.max = max;
+        self
+    }
+
+    pub fn sample(mut self, name: &str, id: Uuid) -> StatusBuilder {
+        self.status
+            .players
+            .sample
+            .get_or_insert_with(Vec::new)
+            .push(PlayerSample {
+                name: String::from(name),
+                id: id.hyphenated().to_string(),
+            });
+        self
+    }
+
+    pub fn description(mut self, description: Component) -> StatusBuilder {
+        self.status.description = description;
+        self
+    }
+
+    pub fn motd(self, text: &str) -> StatusBuilder {
+        self.description(Component::text(text))
+    }
+
+    // Expects an already encoded "data:image/png;base64,..." string
+    pub fn favicon(mut self, favicon: &str) -> StatusBuilder {
+        self.status.favicon = Some(String::from(favicon));
+        self
+    }
+
+    pub fn favicon_file(self, path: impl AsRef<Path>) -> Result<StatusBuilder> {
+        let favicon = favicon_from_file(path)?;
+        Ok(self.favicon(&favicon))
+    }
+
+    pub fn enforces_secure_chat(mut self, enforces: bool) -> StatusBuilder {
+        self.status.enforces_secure_chat = Some(enforces);
+        self
+    }
+
+    pub fn build(self) -> ServerStatus {
+        self.status
+    }
+
+    pub fn to_json(&self) -> String {
+        self.status.to_json()
+    }
+}
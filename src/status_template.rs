@@ -0,0 +1,210 @@
+use crate::favicon_from_file;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Fake player count that drifts between min and max over `period_secs`
+#[derive(Debug, Clone, Deserialize)]
+pub struct DynamicPlayers {
+    pub min: u32,
+    pub max: u32,
+    #[serde(default = "default_period")]
+    pub period_secs: u64,
+}
+
+fn default_period() -> u64 {
+    600
+}
+
+fn default_rotate() -> u64 {
+    10
+}
+
+// The template file itself. `status` is a regular status response where any
+// string may use {time}, {date}, {online}, {max} and {message} placeholders.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateFile {
+    pub status: Value,
+    pub players: Option<DynamicPlayers>,
+    #[serde(default)]
+    pub messages: Vec<String>,
+    #[serde(default = "default_rotate")]
+    pub rotate_secs: u64,
+    // Image of any size or format, relative to the template file
+    pub favicon_file: Option<PathBuf>,
+}
+
+// A status response rendered from a template file, reloaded whenever the file changes
+#[derive(Debug)]
+pub struct StatusTemplate {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    template: TemplateFile,
+    favicon: Option<String>,
+}
+
+impl StatusTemplate {
+    pub fn load(path: impl AsRef<Path>) -> Result<StatusTemplate> {
+        let path = path.as_ref().to_path_buf();
+        let modified = fs::metadata(&path)?.modified().ok();
+        let template = read_template(&path)?;
+        let favicon = load_favicon(&path, &template)?;
+
+        Ok(StatusTemplate {
+            path,
+            modified,
+            template,
+            favicon,
+        })
+    }
+
+    pub fn template(&self) -> &TemplateFile {
+        &self.template
+    }
+
+    // Picks up edits to the file. A broken edit keeps the previous template
+    // in place and returns the error so the caller can report it.
+    pub fn reload_if_changed(&mut self) -> Result<bool> {
+        let modified = fs::metadata(&self.path)?.modified().ok();
+        if modified == self.modified {
+            return Ok(false);
+        }
+
+        self.modified = modified;
+        let template = read_template(&self.path)?;
+        self.favicon = load_favicon(&self.path, &template)?;
+        self.template = template;
+        Ok(true)
+    }
+
+    pub fn render(&mut self) -> Result<String> {
+        if let Err(error) = self.reload_if_changed() {
+            eprintln!("Keeping the previous status template: {:#}", error);
+        }
+
+        self.render_at(SystemTime::now())
+    }
+
+    pub fn render_at(&self, now: SystemTime) -> Result<String> {
+        let seconds = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let template = &self.template;
+
+        let (online, max) = match &template.players {
+            Some(players) => (dynamic_count(players, seconds), players.max),
+            None => (0, 0),
+        };
+        let message = match template.messages.len() {
+            0 => String::new(),
+            count => {
+                let index = (seconds / template.rotate_secs.max(1)) as usize % count;
+                template.messages[index].clone()
+            }
+        };
+
+        let placeholders = [
+            ("{time}", format_time(seconds)),
+            ("{date}", format_date(seconds)),
+            ("{online}", online.to_string()),
+            ("{max}", max.to_string()),
+        ];
+
+        let mut status = template.status.clone();
+        // Messages can use placeholders too, so they go in before the rest
+        substitute(&mut status, &[("{message}", message)]);
+        substitute(&mut status, &placeholders);
+
+        if let (Some(favicon), Some(status)) = (&self.favicon, status.as_object_mut()) {
+            status.insert(String::from("favicon"), Value::from(favicon.as_str()));
+        }
+
+        if template.players.is_some() {
+            if let Some(players) = status.get_mut("players").and_then(Value::as_object_mut) {
+                players.insert(String::from("online"), Value::from(online));
+                players.insert(String::from("max"), Value::from(max));
+            }
+        }
+
+        Ok(serde_json::to_string(&status)?)
+    }
+}
+
+fn read_template(path: &Path) -> Result<TemplateFile> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read status template {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse status template {}", path.display()))
+}
+
+fn load_favicon(path: &Path, template: &TemplateFile) -> Result<Option<String>> {
+    let file = match &template.favicon_file {
+        None => return Ok(None),
+        Some(val) => val,
+    };
+
+    let file = path.parent().unwrap_or(Path::new(".")).join(file);
+    Ok(Some(favicon_from_file(file)?))
+}
+
+fn substitute(value: &mut Value, placeholders: &[(&str, String)]) {
+    match value {
+        Value::String(text) => {
+            for (placeholder, replacement) in placeholders {
+                if text.contains(placeholder) {
+                    *text = text.replace(placeholder, replacement);
+                }
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| substitute(value, placeholders)),
+        Value::Object(values) => values
+            .values_mut()
+            .for_each(|value| substitute(value, placeholders)),
+        _ => {}
+    }
+}
+
+// Follows a sine wave so the count moves smoothly instead of jumping around
+fn dynamic_count(players: &DynamicPlayers, seconds: u64) -> u32 {
+    let low = players.min.min(players.max) as f64;
+    let high = players.min.max(players.max) as f64;
+    let phase = (seconds % players.period_secs.max(1)) as f64 / players.period_secs.max(1) as f64;
+    let wave = (1.0 + (phase * std::f64::consts::TAU).sin()) / 2.0;
+
+    (low + (high - low) * wave).round() as u32
+}
+
+fn format_time(seconds: u64) -> String {
+    let of_day = seconds % 86400;
+    format!(
+        "{:02}:{:02}:{:02}",
+        of_day / 3600,
+        of_day % 3600 / 60,
+        of_day % 60
+    )
+}
+
+// Civil date from days since the epoch (Howard Hinnant's algorithm)
+fn format_date(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
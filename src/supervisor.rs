@@ -0,0 +1,111 @@
+use crate::ShutdownToken;
+use anyhow::Result;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    // Restart even after the task returned successfully
+    Always,
+    // Restart after an error or a panic
+    OnError,
+    Never,
+}
+
+// Runs long lived tasks on their own threads and restarts them according to
+// their policy, so one failing bridge doesn't silently take the rest down.
+// Tasks get the shutdown token and should return once it's cancelled.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    token: ShutdownToken,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+enum Outcome {
+    Finished,
+    Failed(String),
+}
+
+impl Supervisor {
+    pub fn new(token: ShutdownToken) -> Supervisor {
+        Supervisor {
+            token,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    // Delay before a restart, doubled for every failure in a row. A task
+    // that stayed up for longer than `max` starts over from `initial`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Supervisor {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    pub fn token(&self) -> &ShutdownToken {
+        &self.token
+    }
+
+    pub fn supervise(
+        &self,
+        name: &str,
+        policy: RestartPolicy,
+        mut task: impl FnMut(ShutdownToken) -> Result<()> + Send + 'static,
+    ) {
+        let name = String::from(name);
+        let (initial_backoff, max_backoff) = (self.initial_backoff, self.max_backoff);
+
+        self.token.spawn(move |token| {
+            let mut backoff = initial_backoff;
+            loop {
+                let started = Instant::now();
+                let outcome = match panic::catch_unwind(AssertUnwindSafe(|| task(token.clone()))) {
+                    Ok(Ok(())) => Outcome::Finished,
+                    Ok(Err(error)) => Outcome::Failed(format!("{:#}", error)),
+                    Err(payload) => Outcome::Failed(panic_message(payload.as_ref())),
+                };
+
+                if token.is_cancelled() {
+                    return;
+                }
+
+                let restart = matches!(
+                    (&outcome, policy),
+                    (_, RestartPolicy::Always) | (Outcome::Failed(_), RestartPolicy::OnError)
+                );
+                match &outcome {
+                    Outcome::Finished if !restart => return,
+                    Outcome::Finished => eprintln!("Task {} finished", name),
+                    Outcome::Failed(error) => eprintln!("Task {} failed: {}", name, error),
+                }
+                if !restart {
+                    eprintln!("Task {} will not be restarted", name);
+                    return;
+                }
+
+                if started.elapsed() > max_backoff {
+                    backoff = initial_backoff;
+                }
+                eprintln!("Restarting task {} in {:?}", name, backoff);
+                if token.wait_timeout(backoff) {
+                    return;
+                }
+                backoff = (backoff * 2).min(max_backoff);
+            }
+        });
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => format!("panicked: {}", message),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => format!("panicked: {}", message),
+            None => String::from("panicked"),
+        },
+    }
+}
@@ -0,0 +1,360 @@
+// Scripted stand-in for a Minecraft server, so client flows can be tested
+// without a real one:
+//
+//   let server = MockServer::start(vec![Script::new()
+//       .expect_handshake(NextState::Login)
+//       .expect_login_start("alice")
+//       .login_success("alice")
+//       .expect_chat("hello")])?;
+//   let mut client = Client::builder("127.0.0.1", server.port()).username("alice").connect()?;
+//   client.login()?;
+//   client.send_chat_message("hello")?;
+//   server.finish()?;
+//
+// Every step runs in order and the first one that doesn't go as scripted
+// fails the whole server, which finish() reports. MockServer::in_memory
+// skips the port, clients reach it through its connector():
+//
+//   let server = MockServer::in_memory(scripts)?;
+//   let client = Client::builder("127.0.0.1", 25565).connector(server.connector()).connect()?;
+use crate::{
+    listener, memory_pipe, offline_uuid, Component, NextState, Packet, PlayerInfo,
+    ServerConnection, StreamTransport, Transport,
+};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use uuid::Uuid;
+
+// How long a step waits for the client before failing the script
+pub const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Check = Box<dyn FnMut(&Packet) -> Result<()> + Send>;
+
+enum Step {
+    Handshake(NextState),
+    Expect { id: u8, check: Check },
+    Send(Packet),
+    Compression(usize),
+    Status(String),
+    Close,
+}
+
+// Sent by every client right after logging in, skipped unless expected
+const CLIENT_INFORMATION: u8 = 0x07;
+const PLUGIN_MESSAGE: u8 = 0x0C;
+
+// What happens on one connection, in order
+pub struct Script {
+    steps: Vec<Step>,
+    ignored: Vec<u8>,
+    timeout: Option<Duration>,
+}
+
+impl Default for Script {
+    fn default() -> Script {
+        Script::new()
+    }
+}
+
+impl Script {
+    pub fn new() -> Script {
+        Script {
+            steps: Vec::new(),
+            ignored: vec![CLIENT_INFORMATION, PLUGIN_MESSAGE],
+            timeout: None,
+        }
+    }
+
+    // Packets with these ids are skipped wherever an expected one is read,
+    // for things the client sends on its own like position updates
+    pub fn ignore(mut self, id: u8) -> Script {
+        self.ignored.push(id);
+        self
+    }
+
+    pub fn step_timeout(mut self, timeout: Duration) -> Script {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn expect_handshake(mut self, next_state: NextState) -> Script {
+        self.steps.push(Step::Handshake(next_state));
+        self
+    }
+
+    // The next packet has to have `id` and pass `check`, which gets it with
+    // the cursor after the id
+    pub fn expect(
+        mut self,
+        id: u8,
+        check: impl FnMut(&Packet) -> Result<()> + Send + 'static,
+    ) -> Script {
+        self.steps.push(Step::Expect {
+            id,
+            check: Box::new(check),
+        });
+        self
+    }
+
+    pub fn expect_id(self, id: u8) -> Script {
+        self.expect(id, |_| Ok(()))
+    }
+
+    pub fn expect_login_start(self, name: &str) -> Script {
+        let name = String::from(name);
+        self.expect(0x00, move |packet| expect_str(packet, &name))
+    }
+
+    pub fn expect_chat(self, message: &str) -> Script {
+        let message = String::from(message);
+        self.expect(0x04, move |packet| expect_str(packet, &message))
+    }
+
+    // `command` without the leading slash
+    pub fn expect_command(self, command: &str) -> Script {
+        let command = String::from(command);
+        self.expect(0x03, move |packet| expect_str(packet, &command))
+    }
+
+    pub fn expect_keep_alive(self, id: i64) -> Script {
+        self.expect(0x11, move |packet| {
+            let received = packet.reader().read_i64()?;
+            match received == id {
+                true => Ok(()),
+                false => Err(anyhow!("Expected keep alive {}, got {}", id, received)),
+            }
+        })
+    }
+
+    pub fn send(mut self, packet: Packet) -> Script {
+        self.steps.push(Step::Send(packet));
+        self
+    }
+
+    // Answers the status request with `status` and the ping after it, if any
+    pub fn status(mut self, status: &str) -> Script {
+        self.steps.push(Step::Status(String::from(status)));
+        self
+    }
+
+    // Sends Set Compression and compresses from then on
+    pub fn compression(mut self, threshold: usize) -> Script {
+        self.steps.push(Step::Compression(threshold));
+        self
+    }
+
+    // Login Success with the offline UUID of `name`
+    pub fn login_success(self, name: &str) -> Script {
+        let packet = listener::login_success(offline_uuid(name), name);
+        self.send_built(packet)
+    }
+
+    pub fn system_message(self, message: &Component, overlay: bool) -> Script {
+        let packet = listener::system_message(message, overlay);
+        self.send_built(packet)
+    }
+
+    pub fn player_chat(self, sender: Uuid, sender_name: &str, message: &str) -> Script {
+        let packet = listener::player_chat(sender, sender_name, message);
+        self.send_built(packet)
+    }
+
+    pub fn player_added(self, player: &PlayerInfo) -> Script {
+        let packet = listener::player_added(player);
+        self.send_built(packet)
+    }
+
+    pub fn player_removed(self, uuid: Uuid) -> Script {
+        let packet = listener::player_removed(uuid);
+        self.send_built(packet)
+    }
+
+    pub fn keep_alive(self, id: i64) -> Script {
+        let packet = listener::keep_alive(id);
+        self.send_built(packet)
+    }
+
+    // Disconnect in the play state
+    pub fn disconnect(self, reason: &Component) -> Script {
+        let packet = listener::disconnect(reason);
+        self.send_built(packet)
+    }
+
+    // Closes the connection right away, without a word
+    pub fn close(mut self) -> Script {
+        self.steps.push(Step::Close);
+        self
+    }
+
+    // The packet builders only fail for values that can't be encoded, which a
+    // test has no business sending
+    fn send_built(self, packet: Result<Packet>) -> Script {
+        self.send(packet.expect("Scripted packet can't be encoded"))
+    }
+
+    fn run(mut self, mut connection: ServerConnection) -> Result<()> {
+        for (index, step) in std::mem::take(&mut self.steps).into_iter().enumerate() {
+            if let Step::Close = step {
+                break;
+            }
+            self.run_step(&mut connection, step)
+                .with_context(|| format!("Script step {} failed", index + 1))?;
+        }
+        Ok(())
+    }
+
+    fn run_step(&self, connection: &mut ServerConnection, step: Step) -> Result<()> {
+        match step {
+            Step::Handshake(next_state) => {
+                let handshake = connection.handshake();
+                if handshake.next_state != next_state {
+                    return Err(anyhow!(
+                        "Expected a handshake for {:?}, got {:?}",
+                        next_state,
+                        handshake
+                    ));
+                }
+            }
+            Step::Expect { id, mut check } => {
+                let packet = loop {
+                    let packet = connection.read_packet()?;
+                    match packet.get_protocol_id() {
+                        Some(received) if received != id && self.ignored.contains(&received) => {
+                            continue
+                        }
+                        _ => break packet,
+                    }
+                };
+                if packet.get_protocol_id() != Some(id) {
+                    return Err(anyhow!(
+                        "Expected packet {:#04x}, got {:?}",
+                        id,
+                        packet.get_protocol_id()
+                    ));
+                }
+                check(&packet)?;
+            }
+            Step::Send(packet) => connection.send_packet(&packet)?,
+            Step::Compression(threshold) => {
+                connection.send_packet(&listener::set_compression(threshold)?)?;
+                connection.set_compression(Some(threshold));
+            }
+            Step::Status(status) => connection.respond_status(&status)?,
+            Step::Close => {}
+        }
+        Ok(())
+    }
+}
+
+fn expect_str(packet: &Packet, expected: &str) -> Result<()> {
+    let received = packet.reader().read_str()?;
+    match received == expected {
+        true => Ok(()),
+        false => Err(anyhow!("Expected {:?}, got {:?}", expected, received)),
+    }
+}
+
+// Plays one script per accepted connection, in order, on a background thread
+pub struct MockServer {
+    endpoint: Endpoint,
+    thread: JoinHandle<Result<()>>,
+}
+
+enum Endpoint {
+    Tcp(SocketAddr),
+    // Takes the server's end of each memory pipe
+    Memory(Sender<StreamTransport>),
+}
+
+impl MockServer {
+    // Listens on a free port on 127.0.0.1
+    pub fn start(scripts: Vec<Script>) -> Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+
+        let thread = thread::spawn(move || serve(scripts, || Ok(Box::new(listener.accept()?.0))));
+
+        Ok(MockServer {
+            endpoint: Endpoint::Tcp(address),
+            thread,
+        })
+    }
+
+    // Binds nothing, connections come from connector() over memory pipes
+    pub fn in_memory(scripts: Vec<Script>) -> Result<MockServer> {
+        let (sender, receiver) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            serve(scripts, || {
+                let stream = receiver
+                    .recv()
+                    .map_err(|_| anyhow!("Every connector was dropped"))?;
+                Ok(Box::new(stream))
+            })
+        });
+
+        Ok(MockServer {
+            endpoint: Endpoint::Memory(sender),
+            thread,
+        })
+    }
+
+    // Panics for one in memory, which has no address
+    pub fn address(&self) -> SocketAddr {
+        match &self.endpoint {
+            Endpoint::Tcp(address) => *address,
+            Endpoint::Memory(_) => panic!("An in-memory MockServer has no address"),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.address().port()
+    }
+
+    // For ClientBuilder::connector, reaches the server however it listens
+    pub fn connector(&self) -> impl FnMut() -> Result<Box<dyn Transport>> + Send + 'static {
+        let endpoint = match &self.endpoint {
+            Endpoint::Tcp(address) => Endpoint::Tcp(*address),
+            Endpoint::Memory(sender) => Endpoint::Memory(sender.clone()),
+        };
+        move || match &endpoint {
+            Endpoint::Tcp(address) => Ok(Box::new(TcpStream::connect(address)?)),
+            Endpoint::Memory(sender) => {
+                let (client, server) = memory_pipe();
+                sender
+                    .send(server)
+                    .map_err(|_| anyhow!("The mock server is done"))?;
+                Ok(Box::new(client))
+            }
+        }
+    }
+
+    // Waits for every script to finish, returns the first step that failed
+    pub fn finish(self) -> Result<()> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("Mock server panicked")),
+        }
+    }
+}
+
+fn serve(
+    scripts: Vec<Script>,
+    mut accept: impl FnMut() -> Result<Box<dyn Transport>>,
+) -> Result<()> {
+    for (index, script) in scripts.into_iter().enumerate() {
+        let stream = accept()?;
+        stream.set_read_timeout(Some(script.timeout.unwrap_or(DEFAULT_STEP_TIMEOUT)))?;
+        let connection = ServerConnection::accept(stream)
+            .with_context(|| format!("Connection {} sent no handshake", index + 1))?;
+        script
+            .run(connection)
+            .with_context(|| format!("Connection {}", index + 1))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,383 @@
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, IoSlice, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::{Arc, Condvar, Mutex, MutexGuard, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+// What a Connection runs over. TcpStream is what the client dials by
+// default; anything else that carries bytes both ways can be made into one
+// with StreamTransport. Async streams can go through a sync bridge such as
+// tokio_util's SyncIoBridge.
+pub trait Transport: Read + Write + Send + Sync + 'static {
+    // Another handle on the same stream, so one thread can read while
+    // another writes
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+
+    // Ends both directions for every handle, waking up blocked reads
+    fn shutdown(&self) -> io::Result<()>;
+
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+
+    // A read that times out fails with WouldBlock or TimedOut
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    // Reads fail with WouldBlock instead of waiting while set
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+
+    // None when there's no network address on the other end
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+impl Transport for TcpStream {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        TcpStream::peer_addr(self).ok()
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(UnixStream::try_clone(self)?))
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        UnixStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+// So boxed transports, like the ones connectors return, can go anywhere an
+// unboxed one can
+impl Transport for Box<dyn Transport> {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        (**self).try_clone()
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        (**self).shutdown()
+    }
+
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        (**self).read_timeout()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        (**self).set_write_timeout(timeout)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        (**self).set_nonblocking(nonblocking)
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        (**self).peer_addr()
+    }
+}
+
+// A Transport over any pair of read and write halves, e.g. a WebSocket's or
+// the stdout and stdin of an `ssh -W host:port` child. Plain Read can't time
+// out or be interrupted, so a thread reads ahead into a buffer and reads are
+// served from that. Write timeouts aren't supported, the halves block as
+// they do.
+pub struct StreamTransport {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    incoming: Mutex<Incoming>,
+    arrived: Condvar,
+    // None once shut down, which drops the write half and so tells the
+    // other end we're done
+    writer: Mutex<Option<Box<dyn Write + Send>>>,
+    // False for memory pipes, whose writes never wait so any timeout is met
+    writes_block: bool,
+}
+
+struct Incoming {
+    buffer: VecDeque<u8>,
+    // The read half hit its end or an error, or we were shut down
+    closed: bool,
+    read_timeout: Option<Duration>,
+    nonblocking: bool,
+}
+
+impl StreamTransport {
+    pub fn new(
+        mut reader: impl Read + Send + 'static,
+        writer: impl Write + Send + 'static,
+    ) -> StreamTransport {
+        let transport = StreamTransport::with_writer(Box::new(writer), true);
+
+        // Weakly, so dropping every handle drops the write half even while
+        // the read half is still blocked
+        let pumped = Arc::downgrade(&transport.shared);
+        thread::spawn(move || {
+            let mut chunk = [0u8; 8192];
+            loop {
+                let read = reader.read(&mut chunk);
+                let Some(pumped) = pumped.upgrade() else {
+                    return;
+                };
+                let bytes = match read {
+                    Ok(length) => &chunk[..length],
+                    Err(_) => &[],
+                };
+                if pumped.arrive(bytes).is_err() || bytes.is_empty() {
+                    return;
+                }
+            }
+        });
+
+        transport
+    }
+
+    fn with_writer(writer: Box<dyn Write + Send>, writes_block: bool) -> StreamTransport {
+        StreamTransport {
+            shared: Arc::new(Shared {
+                incoming: Mutex::new(Incoming {
+                    buffer: VecDeque::new(),
+                    closed: false,
+                    read_timeout: None,
+                    nonblocking: false,
+                }),
+                arrived: Condvar::new(),
+                writer: Mutex::new(Some(writer)),
+                writes_block,
+            }),
+        }
+    }
+
+    fn incoming(&self) -> io::Result<MutexGuard<'_, Incoming>> {
+        self.shared.incoming.lock().map_err(|_| poisoned())
+    }
+}
+
+impl Read for StreamTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut incoming = self.incoming()?;
+        let deadline = incoming
+            .read_timeout
+            .map(|timeout| Instant::now() + timeout);
+        loop {
+            if !incoming.buffer.is_empty() {
+                let length = buf.len().min(incoming.buffer.len());
+                for (byte, read) in buf.iter_mut().zip(incoming.buffer.drain(..length)) {
+                    *byte = read;
+                }
+                return Ok(length);
+            }
+            if incoming.closed {
+                return Ok(0);
+            }
+            if incoming.nonblocking {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+
+            incoming = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(ErrorKind::WouldBlock.into());
+                    }
+                    self.shared
+                        .arrived
+                        .wait_timeout(incoming, left)
+                        .map_err(|_| poisoned())?
+                        .0
+                }
+                None => self.shared.arrived.wait(incoming).map_err(|_| poisoned())?,
+            };
+        }
+    }
+}
+
+impl Write for StreamTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut writer = self.shared.writer.lock().map_err(|_| poisoned())?;
+        match writer.as_mut() {
+            Some(writer) => writer.write(buf),
+            None => Err(ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    // Passed on so a child's stdin or a tunnel gets frames in one piece too
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut writer = self.shared.writer.lock().map_err(|_| poisoned())?;
+        match writer.as_mut() {
+            Some(writer) => writer.write_vectored(bufs),
+            None => Err(ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut writer = self.shared.writer.lock().map_err(|_| poisoned())?;
+        match writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Transport for StreamTransport {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(StreamTransport {
+            shared: self.shared.clone(),
+        }))
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        self.incoming()?.closed = true;
+        self.shared.arrived.notify_all();
+        self.shared.writer.lock().map_err(|_| poisoned())?.take();
+        Ok(())
+    }
+
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.incoming()?.read_timeout)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        // Same as TcpStream, which can't wait for nothing either
+        if timeout == Some(Duration::ZERO) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "A zero read timeout is not allowed",
+            ));
+        }
+        self.incoming()?.read_timeout = timeout;
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match timeout {
+            Some(_) if self.shared.writes_block => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Write timeouts aren't supported over a StreamTransport",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.incoming()?.nonblocking = nonblocking;
+        Ok(())
+    }
+}
+
+impl Shared {
+    // Adds what the read half got, nothing meaning it ended
+    fn arrive(&self, bytes: &[u8]) -> io::Result<()> {
+        let mut incoming = self.incoming.lock().map_err(|_| poisoned())?;
+        if bytes.is_empty() {
+            incoming.closed = true;
+        } else {
+            incoming.buffer.extend(bytes);
+        }
+        drop(incoming);
+        self.arrived.notify_all();
+        Ok(())
+    }
+}
+
+// Two transports wired to each other, what's written to one is read from
+// the other. No socket and no thread, so a client and a server can share a
+// process without binding a port, as MockServer::in_memory does. Nothing
+// holds a writer back, every write lands in the other end's buffer.
+pub fn memory_pipe() -> (StreamTransport, StreamTransport) {
+    let first = StreamTransport::with_writer(Box::new(io::sink()), false);
+    let second =
+        StreamTransport::with_writer(Box::new(PipeWriter(Arc::downgrade(&first.shared))), false);
+    if let Ok(mut writer) = first.shared.writer.lock() {
+        *writer = Some(Box::new(PipeWriter(Arc::downgrade(&second.shared))));
+    }
+
+    (first, second)
+}
+
+// One end of a memory pipe writing into the other. Dropping it, which
+// shutting its end down does, is the other end's EOF.
+struct PipeWriter(Weak<Shared>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(peer) = self.0.upgrade() else {
+            return Err(ErrorKind::BrokenPipe.into());
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if peer.incoming.lock().map_err(|_| poisoned())?.closed {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+        peer.arrive(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        if let Some(peer) = self.0.upgrade() {
+            let _ = peer.arrive(&[]);
+        }
+    }
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("A thread panicked while using the stream")
+}
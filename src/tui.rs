@@ -0,0 +1,743 @@
+use crate::{
+    alias::Aliases,
+    config::Reconnect,
+    editor::{Edit, LineEditor},
+};
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Color, Print, SetAttribute, SetForegroundColor},
+    terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use mchat::{
+    color_rgb, runs, ChatLogger, Client, Component, Event, Kicked, Locale, MessageFilter, Packet,
+    ShutdownToken, Style, Suggestion,
+};
+use regex::Regex;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Stdout, Write},
+    panic,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// Lines kept for scrolling back
+const SCROLLBACK: usize = 1000;
+// How often each side checks for work from the other
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long quitting waits for the farewell to go out and the connection
+// to close before tearing it down anyway
+const QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Line = Vec<(String, Style)>;
+
+// A line as received, drawn or not depending on the mutes and highlights
+// in effect, which can be toggled after the fact
+struct ChatLine {
+    runs: Line,
+    muted: bool,
+    // Color of the first highlight that matched
+    highlight: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Status {
+    state: String,
+    ping: Option<i32>,
+    players: usize,
+}
+
+// Network thread to UI
+enum Update {
+    Line(Component),
+    Status(Status),
+    ActionBar(Component),
+    Suggestions(String, Vec<Suggestion>),
+}
+
+// UI to network thread
+enum Command {
+    Send(String),
+    Complete(String),
+}
+
+// Both ends of a webhook chat bridge, see mchat::WebhookBridge
+pub struct Bridge {
+    // Every event goes here, the bridge picks what to post
+    pub events: Sender<Event>,
+    // Why posting failed
+    pub errors: Receiver<String>,
+    // Lines to say in the chat, never run as commands
+    pub relay: Option<Receiver<String>>,
+}
+
+// Where events go to be published, see mchat::EventPublisher
+pub struct Publisher {
+    pub events: Sender<Event>,
+    // Why publishing failed
+    pub errors: Receiver<String>,
+}
+
+// Where events go besides the screen, kept across reconnects
+struct Sinks {
+    chat_logger: Option<ChatLogger>,
+    bridge: Option<Bridge>,
+    publisher: Option<Publisher>,
+}
+
+// How the UI thread tells the network thread the user is quitting
+struct Quit {
+    requested: ShutdownToken,
+    // Said in chat before disconnecting
+    farewell: Option<String>,
+}
+
+// Chat lines drawn in another color, see config::HighlightConfig
+pub struct Highlight {
+    pub pattern: Regex,
+    pub color: String,
+    pub bell: bool,
+}
+
+// Restores the terminal however the UI exits
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> Result<TerminalGuard> {
+        // The panic message would go to the alternate screen and vanish with
+        // it, so the terminal is restored before it's printed
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            hook(info);
+        }));
+
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let _ = execute!(io::stdout(), LeaveAlternateScreen, cursor::Show);
+    let _ = terminal::disable_raw_mode();
+}
+
+pub struct Settings {
+    // Shown in the status bar
+    pub address: String,
+    pub display: MessageFilter,
+    pub mutes: Vec<Regex>,
+    pub highlights: Vec<Highlight>,
+    pub aliases: Aliases,
+    pub locale: Locale,
+    pub reconnect: Reconnect,
+    pub chat_log: Option<File>,
+    // Where sent lines are remembered, see LineEditor
+    pub history: Option<PathBuf>,
+    pub chat_logger: Option<ChatLogger>,
+    pub bridge: Option<Bridge>,
+    pub publisher: Option<Publisher>,
+    // Said in chat before disconnecting when quitting
+    pub farewell: Option<String>,
+}
+
+// Takes over the terminal until the user quits, `shutdown` is cancelled
+// (Ctrl-C) or the connection drops without reconnecting. The client must
+// already be logged in, `connect` makes a new logged in one for reconnects.
+// Both have to use `session` rather than `shutdown` as their token, so the
+// connection outlives Ctrl-C long enough to leave cleanly.
+pub fn run(
+    client: Client,
+    connect: impl Fn() -> Result<Client> + Send + 'static,
+    settings: Settings,
+    shutdown: &ShutdownToken,
+    session: &ShutdownToken,
+) -> Result<()> {
+    let (update_sender, updates) = mpsc::channel();
+    let (command_sender, commands) = mpsc::channel();
+    // Nothing is ever sent, the network thread dropping its end is the signal
+    let (finished, network_finished) = mpsc::channel::<()>();
+    let quit_requested = ShutdownToken::new();
+
+    let Settings {
+        address,
+        display,
+        mutes,
+        highlights,
+        aliases,
+        locale,
+        reconnect,
+        chat_log,
+        history,
+        chat_logger,
+        bridge,
+        publisher,
+        farewell,
+    } = settings;
+    let mut sinks = Sinks {
+        chat_logger,
+        bridge,
+        publisher,
+    };
+    let network_locale = locale.clone();
+    let quit = Quit {
+        requested: quit_requested.clone(),
+        farewell,
+    };
+    session.spawn(move |token| {
+        let _finished = finished;
+        let mut client = Some(client);
+        let mut delay = reconnect.delay();
+        let mut attempts = 0;
+
+        loop {
+            let result = match client.take() {
+                Some(client) => Ok(client),
+                None => connect(),
+            }
+            .and_then(|client| {
+                attempts = 0;
+                delay = reconnect.delay();
+                network(
+                    client,
+                    &display,
+                    &network_locale,
+                    &mut sinks,
+                    &update_sender,
+                    &commands,
+                    &quit,
+                )
+            });
+            if token.is_cancelled() || quit.requested.is_cancelled() {
+                return;
+            }
+
+            // Kick reasons keep the colors the server gave them
+            let line = match result
+                .as_ref()
+                .err()
+                .and_then(|error| error.downcast_ref::<Kicked>())
+            {
+                Some(kicked) => kicked.to_component(),
+                None => Component::text(&format!(
+                    "Disconnected: {}",
+                    result
+                        .err()
+                        .map(|error| format!("{:#}", error))
+                        .unwrap_or_default()
+                )),
+            };
+            let _ = update_sender.send(Update::Line(line));
+
+            attempts += 1;
+            let give_up = reconnect.max_attempts != 0 && attempts > reconnect.max_attempts;
+            if !reconnect.enabled || give_up {
+                let _ = update_sender.send(Update::Status(Status {
+                    state: String::from("disconnected"),
+                    ping: None,
+                    players: 0,
+                }));
+                return;
+            }
+
+            let _ = update_sender.send(Update::Status(Status {
+                state: format!("reconnecting in {}s", delay.as_secs()),
+                ping: None,
+                players: 0,
+            }));
+            // Quitting is the only reason to stop waiting, the session token
+            // isn't cancelled before this thread is done
+            if quit.requested.wait_timeout(delay) {
+                return;
+            }
+            delay = (delay * 2).min(reconnect.max_delay());
+        }
+    });
+
+    let _guard = TerminalGuard::new()?;
+    let mut stdout = io::stdout();
+
+    let mut ui = Ui {
+        address,
+        locale,
+        chat_log,
+        lines: VecDeque::new(),
+        input: LineEditor::new(history),
+        scroll: 0,
+        status: Status {
+            state: String::from("online"),
+            ping: None,
+            players: 0,
+        },
+        action_bar: None,
+        mutes,
+        highlights,
+        aliases,
+        muting: true,
+        highlighting: true,
+        bell: false,
+    };
+    let mut dirty = true;
+
+    while !shutdown.is_cancelled() {
+        for update in updates.try_iter() {
+            ui.apply(update);
+            dirty = true;
+        }
+        if dirty {
+            ui.draw(&mut stdout)?;
+            dirty = false;
+        }
+
+        if !event::poll(POLL_INTERVAL)? {
+            continue;
+        }
+        match event::read()? {
+            TermEvent::Key(key) if key.kind == KeyEventKind::Press => {
+                if !ui.handle_key(key, &command_sender) {
+                    break;
+                }
+                dirty = true;
+            }
+            TermEvent::Resize(_, _) => dirty = true,
+            _ => {}
+        }
+    }
+
+    // Say goodbye while the connection is still up, then take it down
+    quit_requested.cancel();
+    let _ = network_finished.recv_timeout(QUIT_TIMEOUT);
+    session.shutdown();
+    shutdown.cancel();
+    if let Some(log) = &ui.chat_log {
+        log.sync_data()?;
+    }
+    Ok(())
+}
+
+fn network(
+    mut client: Client,
+    display: &MessageFilter,
+    locale: &Locale,
+    sinks: &mut Sinks,
+    updates: &Sender<Update>,
+    commands: &Receiver<Command>,
+    quit: &Quit,
+) -> Result<()> {
+    let mut last_status = None;
+
+    loop {
+        if quit.requested.is_cancelled() {
+            return leave(client, quit.farewell.as_deref(), sinks);
+        }
+
+        for command in commands.try_iter() {
+            match command {
+                Command::Send(text) => {
+                    // Over the chat rate it goes out a little later, in order
+                    match text.strip_prefix('/') {
+                        Some(command) => client.send_command(command)?,
+                        None => client.send_chat_message(&text)?,
+                    };
+                }
+                Command::Complete(text) => {
+                    let suggestions = client.tab_complete(&text)?;
+                    updates.send(Update::Suggestions(text, suggestions))?;
+                }
+            }
+        }
+
+        if let Some(bridge) = &sinks.bridge {
+            for error in bridge.errors.try_iter() {
+                updates.send(Update::Line(
+                    Component::text(&format!("Webhook failed: {}", error)).color("red"),
+                ))?;
+            }
+            if let Some(relay) = &bridge.relay {
+                for line in relay.try_iter() {
+                    client.send_chat_message(&line)?;
+                }
+            }
+        }
+
+        if let Some(publisher) = &sinks.publisher {
+            for error in publisher.errors.try_iter() {
+                updates.send(Update::Line(
+                    Component::text(&format!("Publishing failed: {}", error)).color("red"),
+                ))?;
+            }
+        }
+
+        if let Some(event) = client.poll_event(POLL_INTERVAL)? {
+            if let Some(bridge) = &sinks.bridge {
+                // The bridge outlives connections, it only stops on shutdown
+                let _ = bridge.events.send(event.clone());
+            }
+            if let Some(publisher) = &sinks.publisher {
+                let _ = publisher.events.send(event.clone());
+            }
+            if let Some(logger) = &mut sinks.chat_logger {
+                // A full disk shouldn't end the session, only the logging
+                if let Err(error) = logger.log(&event) {
+                    updates.send(Update::Line(
+                        Component::text(&format!("Chat logging stopped: {:#}", error)).color("red"),
+                    ))?;
+                    sinks.chat_logger = None;
+                }
+            }
+            let update = match event {
+                Event::ChatMessage(message) => Some(Update::Line(
+                    Component::text(&format!("[{}] ", locale.format_time(message.timestamp)))
+                        .color("dark_gray")
+                        .push(message.to_component()),
+                )),
+                Event::SystemMessage {
+                    message, overlay, ..
+                } if overlay => Some(Update::ActionBar(message)),
+                Event::SystemMessage {
+                    message, category, ..
+                } if display.allows(category) => Some(Update::Line(message)),
+                Event::ActionBar(text) => Some(Update::ActionBar(text)),
+                Event::Title(text) | Event::Subtitle(text) => Some(Update::Line(text.bold())),
+                Event::Died { message } => Some(Update::Line(
+                    Component::text("You died! ").color("red").push(message),
+                )),
+                Event::ResourcePack {
+                    error: Some(error), ..
+                } => Some(Update::Line(
+                    Component::text(&format!("Resource pack download failed: {}", error))
+                        .color("red"),
+                )),
+                Event::Packet(packet) => handle_packet(&mut client, &packet)?,
+                _ => None,
+            };
+            if let Some(update) = update {
+                updates.send(update)?;
+            }
+        }
+
+        let state = match client.queue_position() {
+            Some(queue) => match queue.length {
+                Some(length) => format!("queued {}/{}", queue.position, length),
+                None => format!("queued {}", queue.position),
+            },
+            None => String::from("online"),
+        };
+        let status = Status {
+            state,
+            ping: client.latency().map(|latency| latency.as_millis() as i32),
+            players: client.players().len(),
+        };
+        if last_status.as_ref() != Some(&status) {
+            updates.send(Update::Status(status.clone()))?;
+            last_status = Some(status);
+        }
+    }
+}
+
+// Ends a session the user quit. The farewell may have to wait for the
+// chat rate, what's still queued after a while is dropped.
+fn leave(mut client: Client, farewell: Option<&str>, sinks: &mut Sinks) -> Result<()> {
+    if let Some(farewell) = farewell {
+        client.send_chat_message(farewell)?;
+        let deadline = Instant::now() + QUIT_TIMEOUT / 2;
+        while client.queued_chat() > 0 && Instant::now() < deadline {
+            client.poll_event(POLL_INTERVAL)?;
+        }
+    }
+    client.disconnect("Quit")?;
+
+    if let Some(logger) = &mut sinks.chat_logger {
+        logger.flush()?;
+    }
+    Ok(())
+}
+
+// Packets the library leaves to us
+fn handle_packet(client: &mut Client, packet: &Packet) -> Result<Option<Update>> {
+    client.answer_keep_alive(packet)?;
+    Ok(None)
+}
+
+struct Ui {
+    address: String,
+    locale: Locale,
+    chat_log: Option<File>,
+    lines: VecDeque<ChatLine>,
+    input: LineEditor,
+    // Rows scrolled up from the bottom
+    scroll: usize,
+    status: Status,
+    action_bar: Option<Component>,
+    mutes: Vec<Regex>,
+    highlights: Vec<Highlight>,
+    aliases: Aliases,
+    // Toggled with F2 and F3
+    muting: bool,
+    highlighting: bool,
+    // Rung on the next draw
+    bell: bool,
+}
+
+impl Ui {
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Line(component) => self.push_line(&component),
+            Update::Status(status) => self.status = status,
+            Update::ActionBar(text) => self.action_bar = Some(text),
+            Update::Suggestions(text, suggestions) => self.complete(&text, suggestions),
+        }
+    }
+
+    fn push_line(&mut self, component: &Component) {
+        if let Some(log) = &mut self.chat_log {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_millis() as i64);
+            let line = format!(
+                "{} {}",
+                self.locale.format_date_time(now),
+                component.to_plain()
+            );
+            // A full disk shouldn't take the chat down with it
+            if writeln!(log, "{}", line).is_err() {
+                self.chat_log = None;
+            }
+        }
+
+        let plain = component.to_plain();
+        let muted = self.mutes.iter().any(|mute| mute.is_match(&plain));
+        let highlight = self
+            .highlights
+            .iter()
+            .find(|highlight| highlight.pattern.is_match(&plain));
+        if let Some(highlight) = highlight {
+            self.bell |= highlight.bell && self.highlighting && !(muted && self.muting);
+        }
+
+        if self.lines.len() == SCROLLBACK {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(ChatLine {
+            runs: runs(component),
+            muted,
+            highlight: highlight.map(|highlight| highlight.color.clone()),
+        });
+    }
+
+    fn complete(&mut self, text: &str, suggestions: Vec<Suggestion>) {
+        // The user kept typing while we waited, the answer is stale
+        if text != self.input.line() {
+            return;
+        }
+
+        match suggestions.as_slice() {
+            [] => {}
+            [suggestion] => self.input.replace(
+                suggestion.start,
+                suggestion.start + suggestion.length,
+                &suggestion.text,
+            ),
+            _ => {
+                let options: Vec<&str> = suggestions.iter().map(|s| s.text.as_str()).collect();
+                self.push_line(&Component::text(&options.join("  ")).color("gray"));
+            }
+        }
+    }
+
+    // The history keeps the alias as typed, only its expansion is sent
+    fn send(&mut self, line: &str, commands: &Sender<Command>) {
+        match self.aliases.expand(line) {
+            None => {
+                let _ = commands.send(Command::Send(line.to_owned()));
+            }
+            Some(Ok(lines)) => {
+                for line in lines {
+                    let _ = commands.send(Command::Send(line));
+                }
+            }
+            Some(Err(error)) => {
+                self.push_line(&Component::text(&error.to_string()).color("red"));
+            }
+        }
+    }
+
+    // Returns false when the user wants to quit
+    fn handle_key(&mut self, key: KeyEvent, commands: &Sender<Command>) -> bool {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => return false,
+            // Cancels a history search first
+            KeyCode::Esc if !self.input.is_searching() => return false,
+            KeyCode::Tab if !self.input.is_empty() && !self.input.is_searching() => {
+                let _ = commands.send(Command::Complete(self.input.line().to_owned()));
+            }
+            KeyCode::F(2) => self.muting = !self.muting,
+            KeyCode::F(3) => self.highlighting = !self.highlighting,
+            KeyCode::PageUp => self.scroll += 10,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            _ => match self.input.handle_key(key) {
+                Edit::Submit(line) => {
+                    self.send(&line, commands);
+                    self.scroll = 0;
+                }
+                Edit::Eof => return false,
+                Edit::Changed | Edit::Ignored => {}
+            },
+        }
+
+        true
+    }
+
+    fn draw(&mut self, stdout: &mut Stdout) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        if width == 0 || height < 3 {
+            return Ok(());
+        }
+
+        let rows: Vec<Line> = self
+            .lines
+            .iter()
+            .filter(|line| !(self.muting && line.muted))
+            .flat_map(|line| match (&line.highlight, self.highlighting) {
+                (Some(color), true) => wrap(&highlighted(&line.runs, color), width),
+                _ => wrap(&line.runs, width),
+            })
+            .collect();
+        let body = height - 2;
+        self.scroll = self.scroll.min(rows.len().saturating_sub(body));
+        let end = rows.len() - self.scroll;
+        let visible = &rows[end.saturating_sub(body)..end];
+
+        queue!(stdout, terminal::Clear(ClearType::All))?;
+        for (index, row) in visible.iter().enumerate() {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, (body - visible.len() + index) as u16)
+            )?;
+            for (text, style) in row {
+                print_styled(stdout, text, style)?;
+            }
+        }
+
+        let mut status = format!(" {} | {}", self.address, self.status.state);
+        if let Some(ping) = self.status.ping {
+            status.push_str(&format!(" | {}", self.locale.format_latency(ping)));
+        }
+        status.push_str(&format!(
+            " | {} players",
+            self.locale.format_integer(self.status.players as i64)
+        ));
+        if self.scroll > 0 {
+            status.push_str(" | scrolled");
+        }
+        if !self.muting && !self.mutes.is_empty() {
+            status.push_str(" | mutes off");
+        }
+        if !self.highlighting && !self.highlights.is_empty() {
+            status.push_str(" | highlights off");
+        }
+        if let Some(action_bar) = &self.action_bar {
+            status.push_str(&format!(" | {}", action_bar.to_plain()));
+        }
+        let status: String = status.chars().take(width).collect();
+        queue!(
+            stdout,
+            cursor::MoveTo(0, (height - 2) as u16),
+            SetAttribute(Attribute::Reverse),
+            Print(format!("{:<width$}", status, width = width)),
+            SetAttribute(Attribute::Reset),
+        )?;
+
+        // Scrolled sideways when the cursor would be off the screen
+        let (prompt, column) = self.input.prompt();
+        let skip = column.saturating_sub(width - 1);
+        let prompt: String = prompt.chars().skip(skip).take(width).collect();
+        queue!(
+            stdout,
+            cursor::MoveTo(0, (height - 1) as u16),
+            Print(&prompt),
+            cursor::MoveTo((column - skip) as u16, (height - 1) as u16),
+            cursor::Show,
+        )?;
+        if std::mem::take(&mut self.bell) {
+            queue!(stdout, Print('\x07'))?;
+        }
+
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+// The line in the highlight's color, bold so it stands out from chat that's
+// that color anyway
+fn highlighted(line: &Line, color: &str) -> Line {
+    line.iter()
+        .map(|(text, style)| {
+            let mut style = style.clone();
+            style.color = Some(String::from(color));
+            style.bold = true;
+            (text.clone(), style)
+        })
+        .collect()
+}
+
+// Splits a line into rows of at most `width` characters, breaking on newlines too
+fn wrap(line: &Line, width: usize) -> Vec<Line> {
+    let mut rows = vec![Vec::new()];
+    let mut used = 0;
+
+    for (text, style) in line {
+        let mut current = String::new();
+        for character in text.chars() {
+            if character == '\n' || used == width {
+                let row = rows.last_mut().unwrap();
+                row.push((std::mem::take(&mut current), style.clone()));
+                rows.push(Vec::new());
+                used = 0;
+                if character == '\n' {
+                    continue;
+                }
+            }
+            current.push(character);
+            used += 1;
+        }
+        rows.last_mut().unwrap().push((current, style.clone()));
+    }
+
+    rows
+}
+
+fn print_styled(stdout: &mut Stdout, text: &str, style: &Style) -> Result<()> {
+    if let Some((r, g, b)) = style.color.as_deref().and_then(color_rgb) {
+        queue!(stdout, SetForegroundColor(Color::Rgb { r, g, b }))?;
+    }
+    let attributes = [
+        (style.bold, Attribute::Bold),
+        (style.italic, Attribute::Italic),
+        (style.underlined, Attribute::Underlined),
+        (style.strikethrough, Attribute::CrossedOut),
+        (style.obfuscated, Attribute::SlowBlink),
+    ];
+    for (enabled, attribute) in attributes {
+        if enabled {
+            queue!(stdout, SetAttribute(attribute))?;
+        }
+    }
+
+    queue!(stdout, Print(text), SetAttribute(Attribute::Reset))?;
+    Ok(())
+}
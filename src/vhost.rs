@@ -0,0 +1,113 @@
+use crate::{NextState, ServerConnection, Transport};
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    net::{Shutdown, TcpStream},
+    thread,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    // Forward the whole connection to another server
+    Backend(String),
+    // Answer status pings ourselves and refuse logins
+    Status(String),
+}
+
+// Picks a route by the hostname the client typed, like SNI does for TLS
+#[derive(Debug, Clone, Default)]
+pub struct VirtualHosts {
+    routes: HashMap<String, Route>,
+    fallback: Option<Route>,
+}
+
+impl VirtualHosts {
+    pub fn new() -> VirtualHosts {
+        VirtualHosts::default()
+    }
+
+    // Hostnames may start with "*." to match every subdomain
+    pub fn route(mut self, hostname: &str, route: Route) -> VirtualHosts {
+        self.routes.insert(normalize_hostname(hostname), route);
+        self
+    }
+
+    pub fn fallback(mut self, route: Route) -> VirtualHosts {
+        self.fallback = Some(route);
+        self
+    }
+
+    pub fn resolve(&self, hostname: &str) -> Option<&Route> {
+        let hostname = normalize_hostname(hostname);
+        if let Some(route) = self.routes.get(&hostname) {
+            return Some(route);
+        }
+
+        // Try the wildcards from the most to the least specific
+        let mut rest = hostname.as_str();
+        while let Some((_, parent)) = rest.split_once('.') {
+            if let Some(route) = self.routes.get(&format!("*.{}", parent)) {
+                return Some(route);
+            }
+            rest = parent;
+        }
+
+        self.fallback.as_ref()
+    }
+
+    pub fn serve(&self, mut connection: ServerConnection) -> Result<()> {
+        let handshake = connection.handshake().clone();
+        match self.resolve(&handshake.hostname) {
+            Some(Route::Backend(address)) => {
+                let mut backend = TcpStream::connect(address)
+                    .with_context(|| format!("Failed to connect to backend {}", address))?;
+                // The backend never saw the handshake, so replay it before splicing
+                backend.write_all(&handshake.to_packet()?.into_frame(None))?;
+
+                let (client, leftover) = connection.into_inner()?;
+                splice(client, leftover, backend)
+            }
+            Some(Route::Status(status)) => match handshake.next_state {
+                NextState::Status => connection.respond_status(status),
+                NextState::Login | NextState::Transfer => {
+                    connection.disconnect_login("This server only answers pings")
+                }
+            },
+            None => match handshake.next_state {
+                NextState::Status => Ok(()),
+                NextState::Login | NextState::Transfer => {
+                    connection.disconnect_login(&format!("Unknown host {}", handshake.hostname))
+                }
+            },
+        }
+    }
+}
+
+// Strips what Forge and BungeeCord append after a NUL, plus the trailing
+// dot some clients keep from DNS names
+fn normalize_hostname(hostname: &str) -> String {
+    let hostname = hostname.split('\0').next().unwrap_or_default();
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// Pumps bytes both ways until either side hangs up, then tears down the other
+fn splice(client: Box<dyn Transport>, leftover: Vec<u8>, backend: TcpStream) -> Result<()> {
+    let mut upstream_reader = client.try_clone()?;
+    let mut upstream_writer = backend.try_clone()?;
+    upstream_writer.write_all(&leftover)?;
+
+    let upstream = thread::spawn(move || {
+        let _ = io::copy(&mut upstream_reader, &mut upstream_writer);
+        let _ = upstream_writer.shutdown(Shutdown::Both);
+    });
+
+    let mut downstream_reader = backend;
+    let mut downstream_writer = client;
+    let _ = io::copy(&mut downstream_reader, &mut downstream_writer);
+    let _ = downstream_writer.shutdown();
+
+    let _ = upstream.join();
+
+    Ok(())
+}
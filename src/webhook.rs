@@ -0,0 +1,266 @@
+use crate::{multiline::one_line, Event, HttpClient, ShutdownToken};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+    time::Duration,
+};
+
+// Relayed bodies beyond this are refused, chat lines are short anyway
+const MAX_RELAY_BODY: usize = 64 * 1024;
+
+// What the receiving end expects to be posted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    // {"content", "username"}, the sender shows up as the webhook's name
+    Discord,
+    // {"text"}, also what Mattermost and Rocket.Chat incoming webhooks take
+    Slack,
+}
+
+// Posts chat from the server to a Discord or Slack webhook, the outgoing
+// half of a chat bridge. listen_relay is the other half.
+#[derive(Debug, Clone)]
+pub struct WebhookBridge {
+    http: HttpClient,
+    url: String,
+    format: WebhookFormat,
+    template: String,
+    system_template: Option<String>,
+}
+
+impl WebhookBridge {
+    pub fn new(url: &str, format: WebhookFormat) -> WebhookBridge {
+        WebhookBridge {
+            http: HttpClient::default(),
+            url: String::from(url),
+            format,
+            template: String::from("{content}"),
+            system_template: None,
+        }
+    }
+
+    // For a proxy or different timeouts
+    pub fn http_client(mut self, http: HttpClient) -> WebhookBridge {
+        self.http = http;
+        self
+    }
+
+    // How player chat is posted, with {sender}, {content} and {message}, the
+    // whole line as the server decorates it. "{content}" by default, as
+    // Discord already shows the sender as the name of the post.
+    pub fn template(mut self, template: &str) -> WebhookBridge {
+        self.template = String::from(template);
+        self
+    }
+
+    // System messages (joins, deaths, ...) are only posted with a template,
+    // where {message} and {content} are the message and {sender} is empty
+    pub fn system_template(mut self, template: Option<&str>) -> WebhookBridge {
+        self.system_template = template.map(String::from);
+        self
+    }
+
+    // The JSON posted for `event`, None for events that aren't forwarded
+    pub fn payload(&self, event: &Event) -> Option<Value> {
+        let (sender, content, message, template) = match event {
+            Event::ChatMessage(chat) => (
+                chat.sender_name.to_plain(),
+                chat.content.to_plain(),
+                chat.to_component().to_plain(),
+                &self.template,
+            ),
+            Event::SystemMessage {
+                message, overlay, ..
+            } if !overlay => (
+                String::new(),
+                message.to_plain(),
+                message.to_plain(),
+                self.system_template.as_ref()?,
+            ),
+            _ => return None,
+        };
+
+        let text = template
+            .replace("{sender}", &sender)
+            .replace("{content}", &content)
+            .replace("{message}", &message);
+        Some(match self.format {
+            WebhookFormat::Discord => {
+                let mut payload = json!({
+                    "content": text,
+                    // Players mustn't be able to ping @everyone through us
+                    "allowed_mentions": { "parse": [] },
+                });
+                if !sender.is_empty() {
+                    payload["username"] = json!(sender);
+                }
+                payload
+            }
+            WebhookFormat::Slack => json!({ "text": text }),
+        })
+    }
+
+    // Posts `event` right away. Returns whether it was one to forward.
+    pub fn forward(&self, event: &Event) -> Result<bool> {
+        let Some(payload) = self.payload(event) else {
+            return Ok(false);
+        };
+        self.http.post_json(&self.url, &payload)?;
+        Ok(true)
+    }
+
+    // Posts from a thread of the token, in order, so a slow webhook doesn't
+    // hold up the connection. Failed posts are handed to `on_error` and
+    // dropped, the bridge keeps going.
+    pub fn spawn(
+        self,
+        token: &ShutdownToken,
+        mut on_error: impl FnMut(anyhow::Error) + Send + 'static,
+    ) -> Sender<Event> {
+        let (sender, events) = mpsc::channel::<Event>();
+        token.spawn(move |token| {
+            for event in events {
+                if token.is_cancelled() {
+                    return;
+                }
+                if let Err(error) = self.forward(&event) {
+                    on_error(error);
+                }
+            }
+        });
+        sender
+    }
+}
+
+// A message someone posted to the relay, to be said in the server's chat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayedMessage {
+    pub sender: Option<String>,
+    pub content: String,
+}
+
+impl RelayedMessage {
+    // "<sender> content", or only the content for anonymous ones
+    pub fn to_chat(&self) -> String {
+        match &self.sender {
+            Some(sender) => format!("<{}> {}", sender, self.content),
+            None => self.content.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RelayBody {
+    #[serde(alias = "username", alias = "user_name")]
+    sender: Option<String>,
+    #[serde(alias = "text")]
+    content: String,
+}
+
+// Accepts POSTs on `address` from a thread of the token, until it's
+// cancelled, and hands what was posted out of the receiver. The body is
+// JSON with "content" (or "text") and optionally "sender" (or "username"),
+// or else plain text. With a secret, requests have to carry it as
+// "Authorization: Bearer <secret>". Returns the bound address too, useful
+// with port 0.
+pub fn listen_relay(
+    address: &str,
+    secret: Option<String>,
+    token: &ShutdownToken,
+) -> Result<(SocketAddr, Receiver<RelayedMessage>)> {
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+    let (sender, messages) = mpsc::channel();
+
+    token.wake_on_cancel(address);
+
+    token.spawn(move |token| {
+        for stream in listener.incoming() {
+            if token.is_cancelled() {
+                return;
+            }
+            // Errors only end that one request
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if let Ok(message) = answer_relay(stream, secret.as_deref()) {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok((address, messages))
+}
+
+fn answer_relay(mut stream: TcpStream, secret: Option<&str>) -> Result<RelayedMessage> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut length = 0;
+    let mut authorized = secret.is_none();
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => length = value.parse()?,
+                "authorization" => {
+                    authorized |=
+                        secret.is_some_and(|secret| value.strip_prefix("Bearer ") == Some(secret))
+                }
+                _ => {}
+            }
+        }
+        header.clear();
+    }
+
+    let result = match request_line.split_whitespace().next() {
+        Some("POST") if !authorized => Err(("401 Unauthorized", "Wrong or missing secret")),
+        Some("POST") if length > MAX_RELAY_BODY => Err(("413 Payload Too Large", "Too long")),
+        Some("POST") => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            parse_relay_body(&body).ok_or(("400 Bad Request", "Nothing to say"))
+        }
+        _ => Err(("405 Method Not Allowed", "Only POST is here")),
+    };
+
+    let (status, text) = match &result {
+        Ok(_) => ("204 No Content", ""),
+        Err(error) => *error,
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        text.len(),
+        text
+    )?;
+
+    result.map_err(|(status, _)| anyhow!("Refused a relay request: {}", status))
+}
+
+fn parse_relay_body(body: &[u8]) -> Option<RelayedMessage> {
+    let (sender, content) = match serde_json::from_slice::<RelayBody>(body) {
+        Ok(body) => (body.sender, body.content),
+        Err(_) => (None, String::from_utf8_lossy(body).into_owned()),
+    };
+    let sender = sender
+        .map(|sender| one_line(&sender))
+        .filter(|sender| !sender.is_empty());
+    let content = one_line(&content);
+
+    match content.is_empty() {
+        true => None,
+        false => Some(RelayedMessage { sender, content }),
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::Result;
+use mchat::bedrock;
+use std::{net::UdpSocket, thread, time::Duration};
+
+const MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+
+// Answers one unconnected ping with `server_id`, after a stale pong that
+// has to be ignored
+fn bedrock_server(server_id: &'static str) -> Result<(u16, thread::JoinHandle<Result<()>>)> {
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let port = socket.local_addr()?.port();
+
+    let thread = thread::spawn(move || -> Result<()> {
+        let mut buffer = [0u8; 1500];
+        let (length, from) = socket.recv_from(&mut buffer)?;
+        let ping = &buffer[..length];
+        assert_eq!(ping[0], 0x01);
+        assert_eq!(ping[9..25], MAGIC);
+
+        let pong = |time: &[u8]| {
+            let mut pong = vec![0x1C];
+            pong.extend_from_slice(time);
+            pong.extend_from_slice(&0x1234_5678_i64.to_be_bytes());
+            pong.extend_from_slice(&MAGIC);
+            pong.extend_from_slice(&(server_id.len() as u16).to_be_bytes());
+            pong.extend_from_slice(server_id.as_bytes());
+            pong
+        };
+        socket.send_to(&pong(&[0; 8]), from)?;
+        socket.send_to(&pong(&ping[1..9]), from)?;
+        Ok(())
+    });
+
+    Ok((port, thread))
+}
+
+#[test]
+fn pong_is_parsed() -> Result<()> {
+    let (port, server) = bedrock_server(
+        "MCPE;§aDedicated Server;594;1.20.10;3;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;",
+    )?;
+
+    let status = bedrock::ping("127.0.0.1", port, Duration::from_secs(5))?;
+    assert_eq!(status.edition, "MCPE");
+    assert_eq!(status.motd, "§aDedicated Server");
+    assert_eq!(status.sub_motd.as_deref(), Some("Bedrock level"));
+    assert_eq!((status.protocol, status.version.as_str()), (594, "1.20.10"));
+    assert_eq!((status.players, status.max_players), (3, 10));
+    assert_eq!(status.game_mode.as_deref(), Some("Survival"));
+    assert_eq!((status.port_v4, status.port_v6), (Some(19132), Some(19133)));
+
+    server.join().unwrap()
+}
+
+#[test]
+fn short_server_ids_are_enough() -> Result<()> {
+    // Older servers stop after the player counts
+    let (port, server) = bedrock_server("MCPE;Old Server;390;1.14.60;0;20")?;
+
+    let status = bedrock::ping("127.0.0.1", port, Duration::from_secs(5))?;
+    assert_eq!(status.max_players, 20);
+    assert_eq!(status.sub_motd, None);
+    assert_eq!(status.port_v4, None);
+
+    server.join().unwrap()
+}
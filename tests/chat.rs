@@ -0,0 +1,263 @@
+use mchat::{
+    runs, split_chat_message, to_tag, AnsiRenderer, ChatLogger, ChatParameter, ChatTypes,
+    Component, Event, MessageCategory, Renderer, Rotation, Style, MAX_CHAT_LENGTH,
+};
+use proptest::prelude::*;
+use serde_json::json;
+use std::{env, fs};
+
+#[test]
+fn short_lines_are_kept() {
+    assert_eq!(
+        split_chat_message("hello  world\n\nsecond line", "... "),
+        ["hello world", "second line"]
+    );
+}
+
+#[test]
+fn long_lines_wrap_between_words() {
+    let text = vec!["word"; 100].join(" ");
+    let messages = split_chat_message(&text, "... ");
+
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].len(), 254); // 51 words, another one wouldn't fit
+    assert!(messages[1].starts_with("... word"));
+    assert_eq!(messages[1].matches("word").count(), 49);
+}
+
+#[test]
+fn long_words_are_cut() {
+    let word = "é".repeat(600);
+    let messages = split_chat_message(&word, ">");
+
+    let lengths: Vec<usize> = messages.iter().map(|m| m.chars().count()).collect();
+    assert_eq!(lengths, [256, 256, 90]);
+    assert_eq!(messages.concat().replace('>', ""), word);
+}
+
+proptest! {
+    #[test]
+    fn messages_fit_and_keep_every_word(text in "[a-z ]{0,2000}", continuation in "[>.]{0,8}") {
+        let messages = split_chat_message(&text, &continuation);
+        for message in &messages {
+            prop_assert!(message.chars().count() <= MAX_CHAT_LENGTH);
+        }
+
+        let original: Vec<&str> = text.split_whitespace().collect();
+        let joined = messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| match index {
+                0 => message.as_str(),
+                _ => message.strip_prefix(continuation.as_str()).unwrap_or(message),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        // Word for word the same, as long as none had to be cut
+        if original.iter().all(|word| word.len() <= MAX_CHAT_LENGTH - continuation.len()) {
+            prop_assert_eq!(joined.split_whitespace().collect::<Vec<_>>(), original);
+        }
+    }
+}
+
+#[test]
+fn vanilla_chat_types_are_decorated() {
+    let types = ChatTypes::default();
+    let bob = Component::text("bob");
+    let content = Component::text("hi");
+
+    let chat = types.decoration(0).unwrap();
+    assert_eq!(chat.apply(&bob, None, &content).to_plain(), "<bob> hi");
+
+    let whisper = types.decoration(4).unwrap();
+    let line = whisper.apply(&bob, None, &content);
+    assert_eq!(line.to_plain(), "bob whispers to you: hi");
+    assert_eq!(line.color.as_deref(), Some("gray"));
+    assert_eq!(line.italic, Some(true));
+
+    // /tellraw shows the content alone, unknown ids like plain chat
+    assert!(types.decoration(7).is_none());
+    assert_eq!(types.decoration(99), types.decoration(0));
+}
+
+fn styled(color: Option<&str>, bold: bool) -> Style {
+    Style {
+        color: color.map(String::from),
+        bold,
+        ..Style::default()
+    }
+}
+
+#[test]
+fn legacy_motds_are_styled() {
+    // As a bare string description, the way Bukkit servers send them
+    let motd = Component::from_json(r#""§6§lHypixel§r §7- §aSkyBlock\n§cMaintenance""#).unwrap();
+    assert_eq!(motd.to_plain(), "Hypixel - SkyBlock\nMaintenance");
+    assert_eq!(
+        runs(&motd),
+        vec![
+            (String::from("Hypixel"), styled(Some("gold"), true)),
+            (String::from(" "), Style::default()),
+            (String::from("- "), styled(Some("gray"), false)),
+            (String::from("SkyBlock\n"), styled(Some("green"), false)),
+            (String::from("Maintenance"), styled(Some("red"), false)),
+        ]
+    );
+    assert_eq!(
+        AnsiRenderer.render(&motd),
+        "\x1b[33;1mHypixel\x1b[0m \x1b[37m- \x1b[0m\x1b[92mSkyBlock\n\x1b[0m\x1b[91mMaintenance\x1b[0m"
+    );
+}
+
+#[test]
+fn legacy_codes_inside_json_text_are_styled() {
+    // Colors end formatting, §r goes back to the style of the component
+    let description = Component::from_json(
+        r#"{"text":"","extra":[{"text":"A §ksecret§r §x§f§f§8§8§0§0orange","bold":true},"§zodd§"]}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        runs(&description),
+        vec![
+            (String::from("A "), styled(None, true)),
+            (
+                String::from("secret"),
+                Style {
+                    bold: true,
+                    obfuscated: true,
+                    ..Style::default()
+                }
+            ),
+            (String::from(" "), styled(None, true)),
+            (String::from("orange"), styled(Some("#ff8800"), false)),
+            (String::from("odd"), Style::default()),
+        ]
+    );
+}
+
+#[test]
+fn legacy_text_becomes_components() {
+    let component = Component::from_legacy("§cred §nunderlined§r plain");
+    assert_eq!(component.extra.len(), 3);
+    assert_eq!(component.extra[0], Component::text("red ").color("red"));
+    assert_eq!(component.extra[1].underlined, Some(true));
+    assert_eq!(component.extra[1].color.as_deref(), Some("red"));
+    assert_eq!(component.extra[2], Component::text(" plain"));
+    assert_eq!(component.to_plain(), "red underlined plain");
+}
+
+#[test]
+fn chat_types_are_decoded_from_the_codec() {
+    let codec = to_tag(&json!({
+        "minecraft:chat_type": {
+            "type": "minecraft:chat_type",
+            "value": [{
+                "name": "example:shout",
+                "id": 3,
+                "element": {
+                    "chat": {
+                        "decoration": {
+                            "translation_key": "chat.type.team.text",
+                            "parameters": ["team_name", "sender", "content"],
+                            "style": { "color": "red", "bold": true },
+                        },
+                    },
+                },
+            }, {
+                "name": "example:raw",
+                "id": 4,
+                "element": { "chat": {} },
+            }],
+        },
+    }))
+    .unwrap();
+    let types = ChatTypes::from_codec(&codec).unwrap();
+    assert_eq!(types.name(3), Some("example:shout"));
+
+    let shout = types.decoration(3).unwrap();
+    assert_eq!(
+        shout.parameters,
+        [
+            ChatParameter::TeamName,
+            ChatParameter::Sender,
+            ChatParameter::Content
+        ]
+    );
+    let line = shout.apply(
+        &Component::text("bob"),
+        Some(&Component::text("Red")),
+        &Component::text("charge"),
+    );
+    assert_eq!(line.to_plain(), "Red <bob> charge");
+    assert_eq!(line.bold, Some(true));
+    assert!(types.decoration(4).is_none());
+}
+
+fn system_message(text: &str, overlay: bool) -> Event {
+    let message = Component::text(text);
+    Event::SystemMessage {
+        category: MessageCategory::classify(&message),
+        message,
+        overlay,
+    }
+}
+
+#[test]
+fn chat_logs_rotate_by_time_and_size() {
+    let directory = env::temp_dir().join(format!("mchat-chat-log-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    let mut logger = ChatLogger::new(&directory)
+        .rotation(Rotation::Hourly)
+        .max_size(Some(100));
+
+    // 2026-10-16 12:00:00 UTC
+    let noon = 1_792_152_000_000;
+    assert!(logger
+        .log_at(noon, &system_message("Server restarts soon", false))
+        .unwrap());
+    assert!(!logger
+        .log_at(noon, &system_message("action bar", true))
+        .unwrap());
+    assert!(!logger.log_at(noon, &Event::Idle).unwrap());
+
+    let text = fs::read_to_string(directory.join("chat-2026-10-16-12.log")).unwrap();
+    assert_eq!(text, "2026-10-16 12:00:00 Server restarts soon\n");
+    let line = fs::read_to_string(directory.join("chat-2026-10-16-12.jsonl")).unwrap();
+    let value: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+    assert_eq!(value["type"], "system");
+    assert_eq!(value["logged_at"], noon);
+
+    // The JSON line is over 100 bytes already, so the next one starts a part
+    logger
+        .log_at(noon + 1000, &system_message("second", false))
+        .unwrap();
+    assert!(directory.join("chat-2026-10-16-12.1.log").exists());
+    // And the next hour starts over
+    logger
+        .log_at(noon + 3_600_000, &system_message("third", false))
+        .unwrap();
+    assert_eq!(
+        logger.current_files(),
+        [
+            directory.join("chat-2026-10-16-13.log"),
+            directory.join("chat-2026-10-16-13.jsonl")
+        ]
+    );
+
+    // A new logger skips the parts that are already full
+    let mut logger = ChatLogger::new(&directory)
+        .rotation(Rotation::Hourly)
+        .max_size(Some(100));
+    logger
+        .log_at(noon, &system_message("again", false))
+        .unwrap();
+    assert_eq!(
+        logger.current_files(),
+        [
+            directory.join("chat-2026-10-16-12.2.log"),
+            directory.join("chat-2026-10-16-12.2.jsonl")
+        ]
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
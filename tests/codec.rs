@@ -0,0 +1,470 @@
+use mchat::{
+    auth::minecraft_hex_digest, f64_to_fixed, fixed_to_f64, memory_pipe, Angle, BitSet,
+    BlockPosition, Connection, Frame, Handshake, NextState, Packet, PacketReader,
+    MAX_DECOMPRESSED_LENGTH, MAX_PACKET_LENGTH,
+};
+use proptest::prelude::*;
+use std::io::{self, IoSlice, Read, Write};
+
+fn compression() -> impl Strategy<Value = Option<usize>> {
+    prop_oneof![Just(None), (0usize..512).prop_map(Some)]
+}
+
+// Takes at most `limit` bytes per write, like a busy socket
+struct ShortWriter {
+    written: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for ShortWriter {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let length = buffer.len().min(self.limit);
+        self.written.extend_from_slice(&buffer[..length]);
+        Ok(length)
+    }
+
+    fn write_vectored(&mut self, buffers: &[IoSlice]) -> io::Result<usize> {
+        let mut written = 0;
+        for buffer in buffers {
+            let length = buffer.len().min(self.limit - written);
+            self.written.extend_from_slice(&buffer[..length]);
+            written += length;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn next_state() -> impl Strategy<Value = NextState> {
+    prop_oneof![Just(NextState::Status), Just(NextState::Login)]
+}
+
+proptest! {
+    #[test]
+    fn frames_roundtrip(
+        body in prop::collection::vec(any::<u8>(), 1..4096),
+        compression in compression(),
+    ) {
+        let frame = Packet::from_bytes(&body).to_frame(compression);
+        let parsed = Frame::parse(&frame, compression).unwrap().unwrap();
+
+        prop_assert_eq!(parsed.size, frame.len());
+        prop_assert_eq!(&parsed.packet.buffer, &body);
+        prop_assert_eq!(parsed.packet.get_protocol_id(), Some(body[0]));
+    }
+
+    #[test]
+    fn frames_survive_short_writes(
+        body in prop::collection::vec(any::<u8>(), 1..4096),
+        compression in compression(),
+        limit in 1usize..16,
+    ) {
+        let packet = Packet::from_bytes(&body);
+        let mut writer = ShortWriter { written: Vec::new(), limit };
+        let size = packet.write_frame_to(&mut writer, compression, 6).unwrap();
+
+        prop_assert_eq!(size, writer.written.len());
+        prop_assert_eq!(writer.written, packet.to_frame(compression));
+    }
+
+    #[test]
+    fn truncated_frames_wait_for_more(
+        body in prop::collection::vec(any::<u8>(), 1..1024),
+        compression in compression(),
+        cut in any::<prop::sample::Index>(),
+    ) {
+        let frame = Packet::from_bytes(&body).to_frame(compression);
+        let cut = cut.index(frame.len());
+        prop_assert!(Frame::parse(&frame[..cut], compression).unwrap().is_none());
+    }
+
+    #[test]
+    fn handshakes_roundtrip(
+        protocol_version in any::<i32>(),
+        hostname in ".{0,255}",
+        port in any::<u16>(),
+        next_state in next_state(),
+        compression in compression(),
+    ) {
+        let handshake = Handshake { protocol_version, hostname, port, next_state };
+        let frame = handshake.to_packet().unwrap().to_frame(compression);
+        let mut parsed = Frame::parse(&frame, compression).unwrap().unwrap();
+
+        prop_assert_eq!(Handshake::from_packet(&mut parsed.packet).unwrap(), handshake);
+    }
+
+    #[test]
+    fn frame_parsing_never_panics(
+        bytes in prop::collection::vec(any::<u8>(), 0..512),
+        compression in compression(),
+    ) {
+        if let Ok(Some(frame)) = Frame::parse(&bytes, compression) {
+            prop_assert!(frame.size <= bytes.len());
+            prop_assert!(frame.size <= MAX_PACKET_LENGTH + 3);
+        }
+    }
+
+    #[test]
+    fn varlongs_roundtrip(value in any::<i64>()) {
+        let mut packet = Packet::new();
+        packet.write_varlong(value).unwrap();
+        prop_assert!(packet.buffer.len() <= 10);
+
+        let mut reader = PacketReader::new(&packet.buffer);
+        prop_assert_eq!(reader.read_varlong().unwrap(), value);
+        prop_assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn positions_roundtrip(
+        x in -(1 << 25)..(1i32 << 25),
+        y in -2048..2048i32,
+        z in -(1 << 25)..(1i32 << 25),
+    ) {
+        let position = BlockPosition::new(x, y, z);
+        let mut packet = Packet::new();
+        packet.write_position(&position).unwrap();
+        prop_assert_eq!(packet.buffer.len(), 8);
+        prop_assert_eq!(PacketReader::new(&packet.buffer).read_position().unwrap(), position);
+    }
+
+    #[test]
+    fn angles_roundtrip(step in any::<u8>()) {
+        prop_assert_eq!(Angle::from_degrees(Angle(step).to_degrees()), Angle(step));
+    }
+
+    #[test]
+    fn bitsets_roundtrip(words in prop::collection::vec(any::<i64>(), 0..8)) {
+        let set = BitSet::from_words(words);
+        let mut packet = Packet::new();
+        packet.write_bitset(&set).unwrap();
+
+        let mut reader = PacketReader::new(&packet.buffer);
+        prop_assert_eq!(reader.read_bitset().unwrap(), set);
+        prop_assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn fixed_bitsets_roundtrip(indices in prop::collection::btree_set(0usize..20, 0..20)) {
+        let mut set = BitSet::new();
+        for index in &indices {
+            set.set(*index, true);
+        }
+        let mut packet = Packet::new();
+        packet.write_fixed_bitset(&set, 20);
+        prop_assert_eq!(packet.buffer.len(), 3);
+
+        let read = PacketReader::new(&packet.buffer).read_fixed_bitset(20).unwrap();
+        prop_assert!(read.ones().eq(indices.into_iter()));
+    }
+
+    #[test]
+    fn fixed_point_roundtrips(value in any::<i16>()) {
+        let value = value as i64;
+        prop_assert_eq!(f64_to_fixed(fixed_to_f64(value, 12), 12), value);
+    }
+
+    // Every read either fails or stays inside the buffer
+    #[test]
+    fn reads_stay_in_bounds(
+        bytes in prop::collection::vec(any::<u8>(), 0..256),
+        reads in prop::collection::vec(0u8..8, 0..32),
+    ) {
+        let mut reader = PacketReader::new(&bytes);
+        for read in reads {
+            let result = match read {
+                0 => reader.read_varint().map(drop),
+                1 => reader.read_str().map(drop),
+                2 => reader.read_str_lossy().map(drop),
+                3 => reader.read_byte_array().map(drop),
+                4 => reader.read_uuid().map(drop),
+                5 => reader.read_i64().map(drop),
+                6 => reader.read_u16().map(drop),
+                _ => reader.read_bool().map(drop),
+            };
+            prop_assert!(reader.cursor() <= bytes.len());
+            if result.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn negative_decompressed_length_is_rejected() {
+    // Frame length 6, then a data length of -1 and some bytes
+    let frame = [6, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x00];
+    assert!(Frame::parse(&frame, Some(256)).is_err());
+}
+
+// System chat (0x5F) of 291 bytes compressed at a threshold of 256, as a
+// vanilla 1.19 server sends it, zlib stream and all
+const COMPRESSED_FRAME: &str = "32a302789c8b9fcf54ad54925a51a264a5149e9a939c9f9baa5092af509291aa509c5a54965aa4a830a285956a1901a2d86661";
+// Keep alive (0x1E) under the threshold, a data length of 0 and the bare packet
+const UNCOMPRESSED_FRAME: &str = "0a001e0123456789abcdef";
+
+fn hex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn captured_compressed_frames_are_decoded() {
+    let frame = hex(COMPRESSED_FRAME);
+    let parsed = Frame::parse(&frame, Some(256)).unwrap().unwrap();
+    assert_eq!(parsed.size, frame.len());
+    assert_eq!(parsed.packet.get_protocol_id(), Some(0x5F));
+    let mut reader = parsed.packet.reader();
+    let text = reader.read_str().unwrap();
+    assert!(text.starts_with(r#"{"text":"Welcome to the server! "#));
+    assert_eq!(text.len(), 287);
+    assert_eq!(reader.read_varint().unwrap(), 1);
+    assert!(reader.remaining().is_empty());
+}
+
+#[test]
+fn captured_uncompressed_frames_are_decoded() {
+    let frame = hex(UNCOMPRESSED_FRAME);
+    let parsed = Frame::parse(&frame, Some(256)).unwrap().unwrap();
+    assert_eq!(parsed.packet.get_protocol_id(), Some(0x1E));
+    assert_eq!(
+        parsed.packet.reader().read_i64().unwrap(),
+        0x0123456789abcdef
+    );
+
+    // And built the same way, packets under the threshold aren't compressed
+    let packet = Packet::from_bytes(&frame[2..]);
+    assert_eq!(packet.to_frame(Some(256)), frame);
+    assert_eq!(Packet::from_bytes(&frame[2..]).to_frame(None), {
+        let mut plain = vec![9];
+        plain.extend_from_slice(&frame[2..]);
+        plain
+    });
+}
+
+#[test]
+fn compression_levels_trade_size() {
+    let packet = "Welcome to the server! ".repeat(12).into_bytes();
+    let stored = Packet::from_bytes(&packet).to_frame_at(Some(0), 0);
+    let smallest = Packet::from_bytes(&packet).to_frame_at(Some(0), 9);
+    assert!(stored.len() > packet.len());
+    assert!(smallest.len() < packet.len() / 4);
+    for frame in [stored, smallest] {
+        let parsed = Frame::parse(&frame, Some(0)).unwrap().unwrap();
+        assert_eq!(parsed.size, frame.len());
+    }
+}
+
+#[test]
+fn compressed_frames_under_the_threshold_are_rejected() {
+    // Compressed at 256, but read by a client told the threshold is 512
+    let frame = hex(COMPRESSED_FRAME);
+    let error = Frame::parse(&frame, Some(512)).unwrap_err();
+    assert!(error.to_string().contains("under the threshold of 512"));
+}
+
+#[test]
+fn decompressed_lengths_over_the_limit_are_rejected() {
+    // A data length of 2^23 + 1, one over what vanilla accepts
+    let mut body = Vec::new();
+    let mut length = MAX_DECOMPRESSED_LENGTH as u32 + 1;
+    while length >= 0x80 {
+        body.push((length & 0x7F) as u8 | 0x80);
+        length >>= 7;
+    }
+    body.push(length as u8);
+    body.extend_from_slice(&hex("789c03000000000001"));
+    let mut frame = vec![body.len() as u8];
+    frame.extend_from_slice(&body);
+    assert!(Frame::parse(&frame, Some(256))
+        .unwrap_err()
+        .to_string()
+        .contains("out of range"));
+}
+
+#[test]
+fn compressed_data_must_match_its_length() {
+    // Claims 300 bytes, but the zlib stream holds the 291 of the captured frame
+    let mut frame = hex(COMPRESSED_FRAME);
+    frame[1..3].copy_from_slice(&[0xAC, 0x02]);
+    assert!(Frame::parse(&frame, Some(256)).is_err());
+}
+
+#[test]
+fn oversized_varint_is_rejected() {
+    let mut reader = PacketReader::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+    assert!(reader.read_varint().is_err());
+
+    let mut reader = PacketReader::new(&[0xFF; 11]);
+    assert!(reader.read_varlong().is_err());
+}
+
+#[test]
+fn tenth_varlong_byte_only_holds_the_top_bit() {
+    let mut largest = vec![0xFF; 9];
+    largest.push(0x01);
+    assert_eq!(PacketReader::new(&largest).read_varlong().unwrap(), -1);
+
+    let mut overflowing = vec![0xFF; 9];
+    overflowing.push(0x7F);
+    assert!(PacketReader::new(&overflowing).read_varlong().is_err());
+
+    let mut overflowing = vec![0x80; 9];
+    overflowing.push(0x02);
+    assert!(PacketReader::new(&overflowing).read_varlong().is_err());
+}
+
+#[test]
+fn varlongs_match_the_protocol() {
+    let cases: &[(i64, &[u8])] = &[
+        (0, &[0x00]),
+        (127, &[0x7F]),
+        (128, &[0x80, 0x01]),
+        (2147483647, &[0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+        (
+            i64::MAX,
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F],
+        ),
+        (
+            -1,
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+        ),
+        (
+            i64::MIN,
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+        ),
+    ];
+    for (value, bytes) in cases {
+        let mut packet = Packet::new();
+        packet.write_varlong(*value).unwrap();
+        assert_eq!(&packet.buffer, bytes);
+        assert_eq!(PacketReader::new(bytes).read_varlong().unwrap(), *value);
+    }
+}
+
+#[test]
+fn positions_match_the_protocol() {
+    let position = BlockPosition::new(18357644, 831, -20882616);
+    assert_eq!(position.to_packed().unwrap(), 0x4607632C15B4833F);
+    assert_eq!(BlockPosition::from_packed(0x4607632C15B4833F), position);
+
+    assert!(BlockPosition::new(1 << 25, 0, 0).to_packed().is_err());
+    assert!(BlockPosition::new(0, -2049, 0).to_packed().is_err());
+}
+
+#[test]
+fn angles_wrap_around() {
+    assert_eq!(Angle::from_degrees(90.0), Angle(64));
+    assert_eq!(Angle::from_degrees(-90.0), Angle(192));
+    assert_eq!(Angle::from_degrees(450.0), Angle(64));
+    assert_eq!(Angle(128).to_degrees(), 180.0);
+}
+
+#[test]
+fn bitsets_match_the_protocol() {
+    let mut set = BitSet::new();
+    set.set(0, true);
+    set.set(65, true);
+    assert!(set.get(65) && !set.get(64) && !set.get(1000));
+
+    let mut packet = Packet::new();
+    packet.write_bitset(&set).unwrap();
+    assert_eq!(
+        packet.buffer,
+        [2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2]
+    );
+
+    // A count of longs the packet doesn't have
+    assert!(PacketReader::new(&[0x7F, 0, 0]).read_bitset().is_err());
+}
+
+#[test]
+fn fixed_point_values() {
+    assert_eq!(fixed_to_f64(4096, 12), 1.0);
+    assert_eq!(fixed_to_f64(-2048, 12), -0.5);
+    assert_eq!(f64_to_fixed(2.5, 5), 80);
+}
+
+#[test]
+fn minecraft_hex_digests() {
+    // The examples from wiki.vg, Java's BigInteger formatting included
+    assert_eq!(
+        minecraft_hex_digest(b"Notch"),
+        "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+    );
+    assert_eq!(
+        minecraft_hex_digest(b"jeb_"),
+        "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+    );
+    assert_eq!(
+        minecraft_hex_digest(b"simon"),
+        "88e16a1019277b15d58faf0541e11910eb756f6"
+    );
+}
+
+#[test]
+fn skipped_packets_keep_frames_in_sync() {
+    // A chunk's worth of compressible data, a small compressed packet and
+    // one under the threshold
+    let mut chunk = vec![0x21];
+    chunk.extend((0..1 << 20).map(|index: u32| (index / 4096) as u8));
+    let mut chat = vec![0x5F];
+    chat.extend_from_slice(&[b'!'; 300]);
+    let frames = [chunk.as_slice(), &chat, &[0x1E, 1, 2, 3, 4, 5, 6, 7, 8]]
+        .iter()
+        .flat_map(|packet| Packet::from_bytes(packet).to_frame(Some(256)))
+        .collect::<Vec<u8>>();
+
+    let (mut server, client) = memory_pipe();
+    server.write_all(&frames).unwrap();
+    server.write_all(&frames).unwrap();
+    let mut connection = Connection::new(Box::new(client)).unwrap();
+    connection.set_compression(Some(256));
+
+    // Skipped without reading, read in full, and streamed part way
+    let packet = connection.next_packet().unwrap();
+    assert_eq!((packet.id(), packet.length()), (0x21, chunk.len()));
+    assert!(packet.frame_length() < chunk.len() / 100);
+    packet.skip().unwrap();
+    assert_eq!(
+        connection.next_packet().unwrap().read().unwrap().buffer,
+        chat
+    );
+    let mut packet = connection.next_packet().unwrap();
+    assert_eq!((packet.id(), packet.length()), (0x1E, 9));
+    let mut start = [0u8; 3];
+    packet.read_exact(&mut start).unwrap();
+    assert_eq!(start, [1, 2, 3]);
+    drop(packet);
+
+    // The next round comes out whole from wherever the skips left off
+    for expected in [&chunk, &chat] {
+        assert_eq!(&connection.read_packet().unwrap().buffer, expected);
+    }
+    let mut packet = connection.next_packet().unwrap();
+    let mut rest = Vec::new();
+    packet.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, [1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn frames_bigger_than_the_write_buffer_stay_in_order() {
+    let (server, client) = memory_pipe();
+    let mut sender = Connection::new(Box::new(client)).unwrap();
+    let mut receiver = Connection::new(Box::new(server)).unwrap();
+
+    let mut big = vec![0x21];
+    big.extend((0..1 << 16).map(|index: u32| index as u8));
+    let packets = [vec![0x1E, 1], big, vec![0x5F, 2]];
+    for packet in &packets {
+        sender.send_packet(&Packet::from_bytes(packet)).unwrap();
+    }
+    for packet in &packets {
+        assert_eq!(&receiver.read_packet().unwrap().buffer, packet);
+    }
+}
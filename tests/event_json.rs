@@ -0,0 +1,162 @@
+use mchat::{
+    event_to_json, offline_uuid, BossBar, ChatKind, ChatMessage, Component, Event, LoginPhase,
+    MessageCategory, NextState, Packet, PlayerInfo, PlayerPosition, PlayerStats, QueuePosition,
+    ResourcePackRequest, ResourcePackStatus, EVENT_SCHEMA_VERSION,
+};
+use serde_json::json;
+use std::time::Duration;
+
+// The "type" each variant goes by. No catch-all, so a new variant doesn't
+// compile until it's listed here and in every_event below.
+fn event_type(event: &Event) -> &'static str {
+    match event {
+        Event::LoginPhase(LoginPhase::HandshakeSent { .. }) => "handshake_sent",
+        Event::LoginPhase(LoginPhase::CompressionEnabled { .. }) => "compression_enabled",
+        Event::LoginPhase(LoginPhase::LoginSuccess { .. }) => "login_success",
+        Event::PlayerJoined(_) => "player_joined",
+        Event::PlayerLeft(_) => "player_left",
+        Event::Idle => "idle",
+        Event::Resumed => "resumed",
+        Event::Teleported(_) => "teleported",
+        Event::Died { .. } => "died",
+        Event::HealthChanged { .. } => "health_changed",
+        Event::Starving => "starving",
+        Event::ScoreChanged { .. } => "score_changed",
+        Event::BossBarUpdated(_) => "boss_bar_updated",
+        Event::BossBarRemoved(_) => "boss_bar_removed",
+        Event::Title(_) => "title",
+        Event::Subtitle(_) => "subtitle",
+        Event::ActionBar(_) => "action_bar",
+        Event::TitleTimes { .. } => "title_times",
+        Event::TitlesCleared { .. } => "titles_cleared",
+        Event::ResourcePack { .. } => "resource_pack",
+        Event::ChatMessage(_) => "chat",
+        Event::SystemMessage { .. } => "system",
+        Event::QueuePosition(_) => "queue_position",
+        Event::Joined => "joined",
+        Event::Transferred { .. } => "transferred",
+        Event::Disconnected { .. } => "disconnected",
+        Event::Packet(_) => "packet",
+        Event::UnknownPacket(_) => "unknown_packet",
+    }
+}
+
+fn every_event() -> Vec<Event> {
+    let text = Component::text("Hello");
+    let player = PlayerInfo {
+        uuid: offline_uuid("alice"),
+        name: String::from("alice"),
+        properties: Vec::new(),
+        gamemode: 0,
+        latency: 40,
+        display_name: None,
+    };
+    let bar = BossBar {
+        uuid: offline_uuid("bar"),
+        title: text.clone(),
+        health: 0.5,
+        color: 0,
+        division: 0,
+        flags: 0,
+    };
+
+    vec![
+        Event::LoginPhase(LoginPhase::HandshakeSent {
+            next_state: NextState::Login,
+        }),
+        Event::LoginPhase(LoginPhase::CompressionEnabled { threshold: 256 }),
+        Event::LoginPhase(LoginPhase::LoginSuccess {
+            uuid: offline_uuid("alice"),
+            name: String::from("alice"),
+        }),
+        Event::PlayerJoined(player.clone()),
+        Event::PlayerLeft(player),
+        Event::Idle,
+        Event::Resumed,
+        Event::Teleported(PlayerPosition::default()),
+        Event::Died {
+            message: text.clone(),
+        },
+        Event::HealthChanged {
+            previous: PlayerStats::default(),
+            current: PlayerStats::default(),
+        },
+        Event::Starving,
+        Event::ScoreChanged {
+            objective: String::from("kills"),
+            entry: String::from("alice"),
+            score: Some(3),
+        },
+        Event::BossBarUpdated(bar.clone()),
+        Event::BossBarRemoved(bar),
+        Event::Title(text.clone()),
+        Event::Subtitle(text.clone()),
+        Event::ActionBar(text.clone()),
+        Event::TitleTimes {
+            fade_in: 10,
+            stay: 70,
+            fade_out: 20,
+        },
+        Event::TitlesCleared { reset: true },
+        Event::ResourcePack {
+            request: ResourcePackRequest {
+                url: String::from("https://example.com/pack.zip"),
+                hash: String::new(),
+                forced: false,
+                prompt: None,
+            },
+            status: ResourcePackStatus::FailedDownload,
+            error: Some(String::from("404")),
+        },
+        Event::ChatMessage(Box::new(ChatMessage {
+            sender: offline_uuid("bob"),
+            sender_name: Component::text("bob"),
+            team_name: None,
+            content: text.clone(),
+            signed_content: text.clone(),
+            chat_type: 0,
+            kind: ChatKind::Public,
+            decoration: None,
+            timestamp: 0,
+        })),
+        Event::SystemMessage {
+            message: text.clone(),
+            category: MessageCategory::Other,
+            overlay: false,
+        },
+        Event::QueuePosition(QueuePosition {
+            position: 12,
+            length: Some(40),
+            estimated_wait: Some(Duration::from_secs(60)),
+        }),
+        Event::Joined,
+        Event::Transferred {
+            host: String::from("example.com"),
+            port: 25565,
+        },
+        Event::Disconnected { reason: text },
+        Event::Packet(Packet::from_bytes(&[0x7A])),
+        Event::UnknownPacket(Packet::from_bytes(&[0x7B])),
+    ]
+}
+
+#[test]
+fn every_event_carries_its_type_and_the_schema_version() {
+    let events = every_event();
+    for event in &events {
+        let json = event_to_json(event);
+        assert_eq!(json["type"], json!(event_type(event)), "{:?}", event);
+        assert_eq!(
+            json["schema_version"],
+            json!(EVENT_SCHEMA_VERSION),
+            "{:?}",
+            event
+        );
+    }
+
+    // And no two variants share a type
+    let mut types: Vec<_> = events.iter().map(event_type).collect();
+    types.sort_unstable();
+    types.dedup();
+    assert_eq!(types.len(), events.len());
+}
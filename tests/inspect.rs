@@ -0,0 +1,106 @@
+use mchat::{
+    decode_frame, decode_packet, parse_blob, ConnectionState, Direction, Handshake, NextState,
+    ProtocolFeatures,
+};
+
+fn field<'a>(fields: &'a [(&'static str, String)], name: &str) -> &'a str {
+    &fields
+        .iter()
+        .find(|(field, _)| *field == name)
+        .unwrap_or_else(|| panic!("no {} field", name))
+        .1
+}
+
+#[test]
+fn blobs_are_hex_or_base64() {
+    let keep_alive = vec![0x1E, 0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+    assert_eq!(parse_blob("1e0123456789abcdef").unwrap(), keep_alive);
+    assert_eq!(
+        parse_blob("0x1E 01 23 45 67 89 AB CD EF\n").unwrap(),
+        keep_alive
+    );
+    assert_eq!(
+        parse_blob("1e:01:23:45:67:89:ab:cd:ef").unwrap(),
+        keep_alive
+    );
+    assert_eq!(parse_blob("HgEjRWeJq83v").unwrap(), keep_alive);
+    assert!(parse_blob("not a packet!").is_err());
+}
+
+#[test]
+fn handshakes_are_decoded() {
+    let handshake = Handshake {
+        protocol_version: 759,
+        hostname: String::from("mc.example.com"),
+        port: 25565,
+        next_state: NextState::Login,
+    };
+    let decoded = decode_packet(
+        &ProtocolFeatures::default(),
+        Direction::Serverbound,
+        ConnectionState::Handshaking,
+        &handshake.to_packet().unwrap().buffer,
+    )
+    .unwrap();
+    assert_eq!(decoded.name, "Handshake");
+    assert_eq!(field(&decoded.fields, "protocol version"), "759");
+    assert_eq!(field(&decoded.fields, "hostname"), "\"mc.example.com\"");
+    assert_eq!(field(&decoded.fields, "next state"), "Login");
+    assert!(decoded.unread.is_empty());
+}
+
+#[test]
+fn compressed_frames_are_decoded() {
+    let frame = parse_blob("32a302789c8b9fcf54ad54925a51a264a5149e9a939c9f9baa5092af509291aa509c5a54965aa4a830a285956a1901a2d86661").unwrap();
+    let decoded = decode_frame(
+        &ProtocolFeatures::default(),
+        Direction::Clientbound,
+        ConnectionState::Play,
+        &frame,
+        Some(256),
+    )
+    .unwrap();
+    assert_eq!(decoded.id, 0x5F);
+    assert_eq!(decoded.name, "System Chat Message");
+    assert!(field(&decoded.fields, "content").starts_with("\"Welcome to the server! "));
+    assert_eq!(field(&decoded.fields, "type"), "1");
+    assert!(decoded.unread.is_empty());
+}
+
+#[test]
+fn mismatched_layouts_show_up() {
+    let features = ProtocolFeatures::default();
+
+    // A keep alive with a byte too many, as a different version might send
+    let keep_alive = parse_blob("1e0123456789abcdef00").unwrap();
+    let decoded = decode_packet(
+        &features,
+        Direction::Clientbound,
+        ConnectionState::Play,
+        &keep_alive,
+    )
+    .unwrap();
+    assert_eq!(decoded.unread, vec![0x00]);
+    assert!(decoded.to_string().contains("1 bytes left unread: 00"));
+
+    // And one too short to be one at all
+    let error = decode_packet(
+        &features,
+        Direction::Clientbound,
+        ConnectionState::Play,
+        &keep_alive[..4],
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("clientbound packet 0x1E"));
+
+    // Ids nothing decodes keep their payload
+    let decoded = decode_packet(
+        &features,
+        Direction::Serverbound,
+        ConnectionState::Play,
+        &[0x7F, 0xAA],
+    )
+    .unwrap();
+    assert_eq!(decoded.name, "Unknown");
+    assert_eq!(field(&decoded.fields, "payload"), "aa");
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use mchat::{
+    BridgedMessage, Component, Event, IrcServer, LineProtocol, MessageCategory, ShutdownToken,
+};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+struct IrcClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl IrcClient {
+    fn connect(server: &IrcServer) -> Result<IrcClient> {
+        let stream = TcpStream::connect(server.local_addr())?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        Ok(IrcClient {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        })
+    }
+
+    fn send(&mut self, line: &str) -> Result<()> {
+        write!(self.stream, "{}\r\n", line)?;
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        Ok(String::from(line.trim_end()))
+    }
+
+    // Reads until a line contains `needle` and returns that line
+    fn expect(&mut self, needle: &str) -> Result<String> {
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                panic!("Connection closed while waiting for {:?}", needle);
+            }
+            if line.contains(needle) {
+                return Ok(line);
+            }
+        }
+    }
+
+    fn join(server: &IrcServer, nick: &str) -> Result<IrcClient> {
+        let mut client = IrcClient::connect(server)?;
+        client.send(&format!("NICK {}", nick))?;
+        client.send(&format!("USER {} 0 * :{}", nick, nick))?;
+        client.expect(" 001 ")?;
+        client.send("JOIN #minecraft")?;
+        client.expect(" 366 ")?;
+        Ok(client)
+    }
+}
+
+fn system_message(text: &str) -> Event {
+    Event::SystemMessage {
+        message: Component::text(text),
+        category: MessageCategory::Other,
+        overlay: false,
+    }
+}
+
+#[test]
+fn irc_clients_join_and_chat_both_ways() -> Result<()> {
+    let token = ShutdownToken::new();
+    let (server, messages) =
+        IrcServer::bind("127.0.0.1:0", LineProtocol::Irc, "#minecraft", None, &token)?;
+
+    let mut alice = IrcClient::join(&server, "alice")?;
+    let mut bob = IrcClient::join(&server, "bob")?;
+    assert!(alice.expect("JOIN #minecraft")?.starts_with(":bob!"));
+    assert_eq!(server.client_count(), 2);
+
+    alice.send("PING :check")?;
+    alice.expect("PONG")?;
+
+    alice.send("PRIVMSG #minecraft :hello   there")?;
+    let said = messages.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(
+        said,
+        BridgedMessage {
+            nick: Some(String::from("alice")),
+            text: String::from("hello there"),
+        }
+    );
+    assert_eq!(said.to_chat(), "<alice> hello there");
+    // The other client sees it without a round trip through the server
+    assert_eq!(
+        bob.expect("PRIVMSG")?,
+        ":alice!alice@minecraft PRIVMSG #minecraft :hello there"
+    );
+
+    assert!(server.forward(&system_message("Steve joined the game")));
+    assert!(!server.forward(&Event::Idle));
+    assert_eq!(
+        alice.expect("NOTICE")?,
+        ":mchat NOTICE #minecraft :Steve joined the game"
+    );
+
+    server.say(Some("Steve"), "hi irc");
+    assert_eq!(
+        bob.expect("PRIVMSG")?,
+        ":Steve!Steve@minecraft PRIVMSG #minecraft :hi irc"
+    );
+
+    token.shutdown();
+    Ok(())
+}
+
+#[test]
+fn irc_clients_need_the_password_and_a_free_nick() -> Result<()> {
+    let token = ShutdownToken::new();
+    let (server, _messages) = IrcServer::bind(
+        "127.0.0.1:0",
+        LineProtocol::Irc,
+        "#minecraft",
+        Some(String::from("hunter2")),
+        &token,
+    )?;
+
+    let mut stranger = IrcClient::connect(&server)?;
+    stranger.send("NICK stranger")?;
+    stranger.send("USER stranger 0 * :stranger")?;
+    stranger.expect(" 464 ")?;
+
+    let mut alice = IrcClient::connect(&server)?;
+    alice.send("PASS hunter2")?;
+    alice.send("NICK alice")?;
+    alice.send("USER alice 0 * :alice")?;
+    alice.expect(" 001 ")?;
+    alice.send("JOIN #elsewhere")?;
+    alice.expect(" 403 ")?;
+
+    let mut copycat = IrcClient::connect(&server)?;
+    copycat.send("PASS hunter2")?;
+    copycat.send("NICK ALICE")?;
+    copycat.expect(" 433 ")?;
+
+    token.shutdown();
+    Ok(())
+}
+
+#[test]
+fn plain_clients_get_and_send_bare_lines() -> Result<()> {
+    let token = ShutdownToken::new();
+    let (server, messages) = IrcServer::bind(
+        "127.0.0.1:0",
+        LineProtocol::Plain,
+        "#minecraft",
+        None,
+        &token,
+    )?;
+
+    let mut client = IrcClient::connect(&server)?;
+    client.send("say this")?;
+    let said = messages.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(said.nick, None);
+    assert_eq!(said.to_chat(), "say this");
+
+    server.say(Some("Steve"), "one\ntwo");
+    assert_eq!(client.read_line()?, "<Steve> one");
+    assert_eq!(client.read_line()?, "<Steve> two");
+
+    token.shutdown();
+    Ok(())
+}
+
+#[test]
+fn channel_names_are_checked() {
+    let token = ShutdownToken::new();
+    for channel in ["minecraft", "#mine craft"] {
+        assert!(IrcServer::bind("127.0.0.1:0", LineProtocol::Irc, channel, None, &token).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "metrics")]
+
+use anyhow::Result;
+use mchat::{
+    offline_uuid,
+    testing::{MockServer, Script},
+    Client, Event, Metrics, NextState, ShutdownToken,
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+fn value(text: &str, name: &str) -> Option<i64> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[test]
+fn logins_and_chat_are_counted() -> Result<()> {
+    let script = |name: &str| {
+        Script::new()
+            .expect_handshake(NextState::Login)
+            .expect_login_start(name)
+            .login_success(name)
+    };
+    let server = MockServer::start(vec![
+        script("alice")
+            .player_chat(offline_uuid("bob"), "bob", "hi")
+            .expect_chat("hello"),
+        script("alice"),
+    ])?;
+
+    let metrics = Metrics::new();
+    for round in 0..2 {
+        let mut client = Client::builder("127.0.0.1", server.port())
+            .username("alice")
+            .metrics(metrics.clone())
+            .connect()?;
+        client.login()?;
+        if round == 0 {
+            while !matches!(
+                client.poll_event(Duration::from_secs(5))?,
+                Some(Event::ChatMessage(_)) | None
+            ) {}
+            client.send_chat_message("hello")?;
+        }
+    }
+    server.finish()?;
+
+    let text = metrics.render();
+    assert_eq!(value(&text, "mchat_logins_total"), Some(2));
+    assert_eq!(value(&text, "mchat_reconnects_total"), Some(1));
+    assert_eq!(value(&text, "mchat_chat_received_total"), Some(1));
+    assert_eq!(value(&text, "mchat_chat_sent_total"), Some(1));
+    // Two handshakes and login starts, plus the chat message
+    assert_eq!(value(&text, "mchat_packets_sent_total"), Some(5));
+    assert!(value(&text, "mchat_bytes_received_total").unwrap() > 0);
+    assert!(text.contains("# TYPE mchat_online_players gauge"));
+
+    Ok(())
+}
+
+#[test]
+fn metrics_are_served_over_http() -> Result<()> {
+    let token = ShutdownToken::new();
+    let address = Metrics::new().serve("127.0.0.1:0", &token)?;
+
+    let mut stream = TcpStream::connect(address)?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(value(&response, "mchat_packets_sent_total"), Some(0));
+
+    let mut stream = TcpStream::connect(address)?;
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.starts_with("HTTP/1.1 404"));
+
+    token.cancel();
+    token.shutdown();
+    Ok(())
+}
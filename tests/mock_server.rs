@@ -0,0 +1,1139 @@
+use anyhow::Result;
+use mchat::{
+    lookup_srv_with, offline_uuid, scan_servers,
+    testing::{MockServer, Script},
+    AlreadyClosed, ChatKind, ChatLogger, ChatMode, ChatRate, ChatRules, Client, ClientInformation,
+    Component, ConnectionState, DecodeMode, Event, ForwardedPlayer, Forwarding, Kicked, NextState,
+    Packet, PlayerInfo, Profile, ProtocolFeatures, Responder, Response, SendResult, ShutdownToken,
+    StatusMonitor, Tag, TriggerRate, UnexpectedPacket, SKIN_CAPE, SKIN_HAT, VELOCITY_CHANNEL,
+};
+use std::{
+    env, fs,
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const STATUS: &str = r#"{"version":{"name":"1.19","protocol":759},"players":{"max":20,"online":3},"description":{"text":"Mock"}}"#;
+
+fn client(server: &MockServer, username: &str) -> Result<Client> {
+    Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username(username)
+        .connect()
+}
+
+fn login_script(name: &str) -> Script {
+    Script::new()
+        .expect_handshake(NextState::Login)
+        .expect_login_start(name)
+        .compression(64)
+        .login_success(name)
+}
+
+// Skips the login phase events queued while logging in
+fn next_event(client: &mut Client) -> Result<Event> {
+    loop {
+        match client.poll_event(Duration::from_secs(5))? {
+            Some(Event::LoginPhase(_)) => continue,
+            Some(event) => return Ok(event),
+            None => anyhow::bail!("No event within 5 seconds"),
+        }
+    }
+}
+
+#[test]
+fn status_is_parsed() -> Result<()> {
+    let server = MockServer::in_memory(vec![Script::new()
+        .expect_handshake(NextState::Status)
+        .status(STATUS)])?;
+
+    let status = client(&server, "alice")?.server_status()?;
+    assert_eq!(status.version.protocol, 759);
+    assert_eq!(status.players.online, 3);
+    assert_eq!(status.description.to_plain(), "Mock");
+
+    server.finish()
+}
+
+#[test]
+fn ping_reconnects_for_each_status() -> Result<()> {
+    let server = MockServer::in_memory(vec![
+        Script::new().status(STATUS),
+        Script::new().status(STATUS),
+    ])?;
+
+    let mut client = client(&server, "alice")?;
+    client.status()?;
+    client.ping()?;
+
+    server.finish()
+}
+
+#[test]
+fn login_with_compression() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+    assert_eq!(client.uuid(), Some(offline_uuid("alice")));
+    let profile = client.profile().unwrap();
+    assert_eq!(profile, &Profile::offline("alice"));
+    assert!(profile.is_offline());
+    assert!(profile.textures().is_none());
+
+    server.finish()
+}
+
+#[test]
+fn chat_both_ways() -> Result<()> {
+    let bob = offline_uuid("bob");
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .system_message(&Component::text("Welcome"), false)
+        .player_chat(bob, "bob", "hi alice")
+        .expect_chat("hi bob")
+        .expect_command("spawn")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+
+    match next_event(&mut client)? {
+        Event::SystemMessage {
+            message, overlay, ..
+        } => {
+            assert_eq!(message.to_plain(), "Welcome");
+            assert!(!overlay);
+        }
+        other => panic!("Expected a system message, got {:?}", other),
+    }
+    match next_event(&mut client)? {
+        Event::ChatMessage(message) => {
+            assert_eq!(message.sender, bob);
+            assert_eq!(message.sender_name.to_plain(), "bob");
+            assert_eq!(message.content.to_plain(), "hi alice");
+        }
+        other => panic!("Expected a chat message, got {:?}", other),
+    }
+
+    client.send_chat_message("hi bob")?;
+    client.send_command("spawn")?;
+
+    server.finish()
+}
+
+#[test]
+fn tab_list_follows_player_info() -> Result<()> {
+    let bob = PlayerInfo {
+        uuid: offline_uuid("bob"),
+        name: String::from("bob"),
+        properties: Vec::new(),
+        gamemode: 1,
+        latency: 42,
+        display_name: None,
+    };
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .player_added(&bob)
+        .player_removed(bob.uuid)])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+
+    match next_event(&mut client)? {
+        Event::PlayerJoined(player) => assert_eq!(player.name, "bob"),
+        other => panic!("Expected bob to join, got {:?}", other),
+    }
+    assert_eq!(client.players().get(&bob.uuid).map(|p| p.latency), Some(42));
+    match next_event(&mut client)? {
+        Event::PlayerLeft(player) => assert_eq!(player.uuid, bob.uuid),
+        other => panic!("Expected bob to leave, got {:?}", other),
+    }
+    assert!(client.players().is_empty());
+
+    server.finish()
+}
+
+#[test]
+fn chat_rules_answer() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .player_chat(offline_uuid("bob"), "bob", "!discord")
+        .expect_chat("discord.gg/example for you, bob")])?;
+
+    let rules = ChatRules::new().reply(
+        "^!discord$",
+        Duration::from_secs(30),
+        "discord.gg/example for you, {sender}",
+    )?;
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .chat_rules(rules)
+        .connect()?;
+    client.login()?;
+    next_event(&mut client)?;
+
+    server.finish()
+}
+
+#[test]
+fn responder_spawns_and_rate_limits() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .player_chat(offline_uuid("bob"), "bob", "!roll")
+        .expect_chat("bob rolled 4")
+        .player_chat(offline_uuid("bob"), "bob", "!roll")
+        .player_chat(offline_uuid("bob"), "bob", "!ping")
+        // The second roll went over the trigger's rate and wasn't answered
+        .expect_chat("pong")])?;
+
+    let once = TriggerRate {
+        count: 1,
+        period: Duration::from_secs(60),
+    };
+    let responder = Responder::new()
+        .trigger("^!roll$", once, |message, _| {
+            let name = message.sender_name.to_plain();
+            Response::spawn(move || Some(format!("{} rolled 4", name)))
+        })?
+        .trigger("^!ping$", once, |_, _| Response::reply("pong"))?;
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .responder(responder)
+        .connect()?;
+    client.login()?;
+
+    // Until the script is done and the server hangs up
+    for _ in 0..100 {
+        if client.poll_event(Duration::from_millis(50)).is_err() {
+            break;
+        }
+    }
+
+    server.finish()
+}
+
+#[test]
+fn queue_position_until_joined() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .system_message(&Component::text("Position in queue: 12"), false)
+        .system_message(
+            &Component::text("You are in position 3 of 40. Estimated time: 2m 30s"),
+            true,
+        )
+        // Respawn, the proxy moving us to the main server
+        .send(Packet::from_bytes(&[0x3B]))])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+
+    let mut queued = Vec::new();
+    loop {
+        match next_event(&mut client)? {
+            Event::QueuePosition(position) => queued.push(position),
+            Event::Joined => break,
+            _ => {}
+        }
+    }
+    assert_eq!(queued.len(), 2);
+    assert_eq!((queued[0].position, queued[0].length), (12, None));
+    assert_eq!((queued[1].position, queued[1].length), (3, Some(40)));
+    assert_eq!(queued[1].estimated_wait, Some(Duration::from_secs(150)));
+    assert!(client.queue_position().is_none());
+
+    server.finish()
+}
+
+#[test]
+fn mismatch_fails_the_script() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice").expect_chat("hello")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+    client.send_chat_message("goodbye")?;
+
+    let error = server.finish().unwrap_err();
+    assert!(format!("{:#}", error).contains("Expected \"hello\", got \"goodbye\""));
+    Ok(())
+}
+
+#[test]
+fn state_follows_the_protocol() -> Result<()> {
+    let server = MockServer::in_memory(vec![
+        Script::new().status(STATUS),
+        login_script("alice").disconnect(&Component::text("Bye")),
+    ])?;
+
+    let mut client = client(&server, "alice")?;
+    assert_eq!(client.state(), ConnectionState::Handshaking);
+    assert!(client.send_chat_message("too early").is_err());
+
+    client.status()?;
+    assert_eq!(client.state(), ConnectionState::Status);
+
+    // A new connection for the login, the status one can't be reused
+    client.login()?;
+    assert_eq!(client.state(), ConnectionState::Play);
+
+    loop {
+        if let Event::Disconnected { reason } = next_event(&mut client)? {
+            assert_eq!(reason.to_plain(), "Bye");
+            break;
+        }
+    }
+    assert_eq!(client.state(), ConnectionState::Closed);
+
+    // The reason, not an end of file, is what reads fail with from here on
+    let error = next_event(&mut client).unwrap_err();
+    let kicked = error.downcast_ref::<Kicked>().unwrap();
+    assert!(!kicked.during_login);
+    assert_eq!(error.to_string(), "Kicked by the server: Bye");
+
+    server.finish()
+}
+
+#[test]
+fn login_disconnect_is_an_error() -> Result<()> {
+    let reason = br#"{"text":"You are not whitelisted"}"#;
+    let mut body = vec![0x00, reason.len() as u8];
+    body.extend_from_slice(reason);
+    let server = MockServer::in_memory(vec![Script::new()
+        .expect_handshake(NextState::Login)
+        .expect_login_start("alice")
+        .send(Packet::from_bytes(&body))])?;
+
+    let mut client = client(&server, "alice")?;
+    let error = client.login().unwrap_err();
+    assert!(error.to_string().contains("You are not whitelisted"));
+    assert!(error.downcast_ref::<Kicked>().unwrap().during_login);
+    assert_eq!(client.state(), ConnectionState::Closed);
+
+    server.finish()
+}
+
+#[test]
+fn reads_with_timeouts() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .expect_chat("ready")
+        .keep_alive(1)
+        .system_message(&Component::text("Hello"), false)
+        .keep_alive(2)
+        .expect_chat("done")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+    assert!(client.try_read_packet()?.is_none());
+    assert!(client
+        .read_packet_timeout(Duration::from_millis(50))?
+        .is_none());
+
+    client.send_chat_message("ready")?;
+    let packet = client.read_packet_timeout(Duration::from_secs(5))?.unwrap();
+    assert_eq!(packet.get_protocol_id(), Some(0x1E));
+
+    // The system message in between is kept for next_event
+    let packet = client
+        .block_until_packet(Some(Duration::from_secs(5)), |packet| {
+            packet.get_protocol_id() == Some(0x1E)
+        })?
+        .unwrap();
+    assert_eq!(packet.reader().read_i64()?, 2);
+    match next_event(&mut client)? {
+        Event::SystemMessage { message, .. } => assert_eq!(message.to_plain(), "Hello"),
+        other => panic!("Expected the skipped system message, got {:?}", other),
+    }
+
+    assert!(client
+        .block_until_packet_id(0x1E, Some(Duration::from_millis(50)))
+        .is_err());
+
+    client.send_chat_message("done")?;
+    server.finish()
+}
+
+#[test]
+fn split_halves_work_across_threads() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .expect_chat("from another thread")
+        .expect_command("spawn")
+        .system_message(&Component::text("Both arrived"), false)
+        .expect_chat("done")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+    let (mut reader, mut writer) = client.split()?;
+
+    let mut other = writer.clone();
+    std::thread::spawn(move || -> Result<()> {
+        other.send_chat_message("from another thread")?;
+        other.send_command("spawn")
+    })
+    .join()
+    .unwrap()?;
+
+    loop {
+        match reader.next_event()? {
+            Event::SystemMessage { message, .. } => {
+                assert_eq!(message.to_plain(), "Both arrived");
+                break;
+            }
+            _ => continue,
+        }
+    }
+    assert_eq!(reader.client().uuid(), Some(offline_uuid("alice")));
+    writer.send_chat_message("done")?;
+
+    server.finish()
+}
+
+#[test]
+fn background_client_answers_keep_alives() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .keep_alive(7)
+        .expect_keep_alive(7)
+        .system_message(&Component::text("Still here"), false)
+        .expect_chat("from the background")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+    let background = client.spawn_background()?;
+
+    loop {
+        match background.events.recv_timeout(Duration::from_secs(5))? {
+            Event::SystemMessage { message, .. } => {
+                assert_eq!(message.to_plain(), "Still here");
+                break;
+            }
+            _ => continue,
+        }
+    }
+    background
+        .commands
+        .send_chat_message("from the background")?;
+    server.finish()?;
+
+    // The server hung up after its script, which ends the thread
+    assert!(background.join().is_err());
+    Ok(())
+}
+
+// Login (play) whose registry codec only has the given chat types
+fn login_play(chat_types: &[(i32, &str)]) -> Result<Packet> {
+    let entry = |id: i32, name: &str| {
+        Tag::Compound(
+            [
+                (String::from("id"), Tag::Int(id)),
+                (String::from("name"), Tag::String(String::from(name))),
+                (String::from("element"), Tag::Compound(Default::default())),
+            ]
+            .into(),
+        )
+    };
+    let registry = Tag::Compound(
+        [
+            (
+                String::from("type"),
+                Tag::String(String::from("minecraft:chat_type")),
+            ),
+            (
+                String::from("value"),
+                Tag::List(
+                    chat_types
+                        .iter()
+                        .map(|(id, name)| entry(*id, name))
+                        .collect(),
+                ),
+            ),
+        ]
+        .into(),
+    );
+    let codec = Tag::Compound([(String::from("minecraft:chat_type"), registry)].into());
+
+    let mut body = vec![0x23, 0, 0, 0, 42, 0, 1, 0xFF, 0];
+    codec.write_named("", &mut body)?;
+    Ok(Packet::from_bytes(&body))
+}
+
+#[test]
+fn whispers_use_the_chat_type_registry() -> Result<()> {
+    let bob = offline_uuid("bob");
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .player_chat(bob, "bob", "before the registry")
+        .send(login_play(&[(0, "minecraft:msg_command")])?)
+        .player_chat(bob, "bob", "psst")
+        .expect_command("msg bob hi back")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+
+    let mut kinds = Vec::new();
+    while kinds.len() < 2 {
+        if let Event::ChatMessage(message) = next_event(&mut client)? {
+            kinds.push(message.kind);
+        }
+    }
+    assert_eq!(kinds, [ChatKind::Public, ChatKind::Whisper]);
+    assert_eq!(client.chat_types().name(0), Some("minecraft:msg_command"));
+
+    assert!(client.send_whisper("not a name", "hi").is_err());
+    client.send_whisper("bob", "hi back")?;
+
+    server.finish()
+}
+
+#[test]
+fn client_information_follows_login_play() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .send(login_play(&[])?)
+        .expect(0x0C, |packet| {
+            let mut reader = packet.reader();
+            assert_eq!(reader.read_str()?, "minecraft:brand");
+            assert_eq!(reader.read_str()?, "mchat");
+            Ok(())
+        })
+        .expect(0x07, |packet| {
+            let mut reader = packet.reader();
+            assert_eq!(reader.read_str()?, "de_de");
+            assert_eq!(reader.read_u8()?, 4); // View distance
+            assert_eq!(reader.read_varint()?, 1); // Commands only
+            assert!(reader.read_bool()?);
+            assert_eq!(reader.read_u8()?, SKIN_HAT | SKIN_CAPE);
+            assert_eq!(reader.read_varint()?, 1); // Right hand
+            assert!(!reader.read_bool()?);
+            assert!(reader.read_bool()?);
+            Ok(())
+        })])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .brand(Some("mchat"))
+        .client_information(Some(ClientInformation {
+            locale: String::from("de_de"),
+            view_distance: 4,
+            chat_mode: ChatMode::CommandsOnly,
+            skin_parts: SKIN_HAT | SKIN_CAPE,
+            ..ClientInformation::default()
+        }))
+        .connect()?;
+    client.login()?;
+    next_event(&mut client)?;
+
+    server.finish()
+}
+
+#[test]
+fn chat_over_the_rate_is_queued() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .expect_chat("one")
+        .expect_chat("two")
+        .expect_command("three")
+        .system_message(&Component::text("Got them"), false)])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .chat_rate(Some(ChatRate {
+            per_second: 20.0,
+            burst: 1,
+        }))
+        .connect()?;
+    client.login()?;
+
+    assert_eq!(client.send_chat_message("one")?, SendResult::Sent);
+    assert_eq!(client.send_chat_message("two")?, SendResult::Queued);
+    assert_eq!(client.send_command("three")?, SendResult::Queued);
+    assert_eq!(client.queued_chat(), 2);
+
+    // Reading events sends the queue as the rate allows
+    loop {
+        if let Event::SystemMessage { .. } = next_event(&mut client)? {
+            break;
+        }
+    }
+    assert_eq!(client.queued_chat(), 0);
+
+    server.finish()
+}
+
+#[test]
+fn protocol_features_pick_the_packets() -> Result<()> {
+    assert_eq!(
+        ProtocolFeatures::for_version(759),
+        Some(&ProtocolFeatures::default())
+    );
+    assert!(ProtocolFeatures::for_version(1).is_none());
+
+    // A made up snapshot that moved chat, titles and respawning and
+    // dropped chat signing
+    let snapshot = ProtocolFeatures {
+        version: 0x40000001,
+        name: "snapshot",
+        has_chat_signing: false,
+        chat_message_packet_id: 0x05,
+        title_packet_id: 0x70,
+        client_command_packet_id: 0x09,
+        ..ProtocolFeatures::default()
+    };
+    let title = r#"{"text":"Welcome"}"#;
+    let mut title_packet = vec![0x70, title.len() as u8];
+    title_packet.extend_from_slice(title.as_bytes());
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .send(Packet::from_bytes(&title_packet))
+        .expect(0x05, |packet| {
+            let mut reader = packet.reader();
+            assert_eq!(reader.read_str()?, "hi");
+            assert!(reader.remaining().is_empty());
+            Ok(())
+        })
+        .expect(0x09, |packet| {
+            assert_eq!(packet.reader().read_varint()?, 0);
+            Ok(())
+        })])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .protocol_features(snapshot)
+        .connect()?;
+    assert_eq!(client.features().name, "snapshot");
+    client.login()?;
+    match next_event(&mut client)? {
+        Event::Title(text) => assert_eq!(text.to_plain(), "Welcome"),
+        event => panic!("Expected the moved title, got {:?}", event),
+    }
+    client.send_chat_message("hi")?;
+    client.respawn()?;
+
+    server.finish()
+}
+
+#[test]
+fn packet_hooks_see_and_drop_packets() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .system_message(&Component::text("Secret"), false)
+        .system_message(&Component::text("Public"), false)
+        .expect_chat("kept")])?;
+
+    let received = Arc::new(AtomicUsize::new(0));
+    let counter = received.clone();
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .receive_hook(move |packet| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            // System chat, hidden when it's a secret
+            packet.get_protocol_id() != Some(0x5F)
+                || !packet.reader().read_str().unwrap().contains("Secret")
+        })
+        // Chat messages are 0x04, the first one never goes out
+        .send_hook({
+            let mut dropped = false;
+            move |packet| {
+                if packet.buffer[0] != 0x04 || dropped {
+                    return true;
+                }
+                dropped = true;
+                false
+            }
+        })
+        .connect()?;
+    client.login()?;
+    match next_event(&mut client)? {
+        Event::SystemMessage { message, .. } => assert_eq!(message.to_plain(), "Public"),
+        event => panic!("Expected the public message, got {:?}", event),
+    }
+    // Set compression, login success and both messages
+    assert_eq!(received.load(Ordering::SeqCst), 4);
+    client.send_chat_message("dropped")?;
+    client.send_chat_message("kept")?;
+
+    server.finish()
+}
+
+#[test]
+fn disconnect_closes_for_good() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_script("alice").expect_chat("bye")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+    client.send_chat_message("bye")?;
+    client.disconnect("done for today")?;
+    assert_eq!(client.state(), ConnectionState::Closed);
+    assert!(client.is_disconnected());
+
+    let error = next_event(&mut client).unwrap_err();
+    let closed = error.downcast_ref::<AlreadyClosed>().unwrap();
+    assert_eq!(closed.reason, "done for today");
+    assert!(client.send_chat_message("still there?").is_err());
+    assert!(client.disconnect("again").is_err());
+    assert!(client.login().is_err());
+
+    server.finish()
+}
+
+#[test]
+fn unknown_packets_follow_the_decode_mode() -> Result<()> {
+    // 0x70 is a plugin's, 0x7A nobody's
+    let script = || {
+        login_script("alice")
+            .send(Packet::from_bytes(&[0x70, 1]))
+            .send(Packet::from_bytes(&[0x7A, 1, 2, 3]))
+    };
+    let server = MockServer::in_memory(vec![script(), script()])?;
+
+    let mut lenient = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .expect_packets(&[0x70])
+        .connect()?;
+    lenient.login()?;
+    match next_event(&mut lenient)? {
+        Event::Packet(packet) => assert_eq!(packet.get_protocol_id(), Some(0x70)),
+        event => panic!("Expected the plugin's packet, got {:?}", event),
+    }
+    match next_event(&mut lenient)? {
+        Event::UnknownPacket(packet) => assert_eq!(packet.get_protocol_id(), Some(0x7A)),
+        event => panic!("Expected an unknown packet, got {:?}", event),
+    }
+
+    let mut strict = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .decode_mode(DecodeMode::Strict)
+        .expect_packets(&[0x70])
+        .connect()?;
+    strict.login()?;
+    assert!(matches!(next_event(&mut strict)?, Event::Packet(_)));
+    let error = next_event(&mut strict).unwrap_err();
+    let unexpected = error.downcast_ref::<UnexpectedPacket>().unwrap();
+    assert_eq!((unexpected.id, unexpected.length), (Some(0x7A), 3));
+
+    server.finish()
+}
+
+#[test]
+fn filtered_packets_are_skipped_unread() -> Result<()> {
+    // Over the compression threshold, so skipping passes over deflated bytes
+    let mut chunk = vec![0x21];
+    chunk.extend_from_slice(&[7; 4096]);
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .send(Packet::from_bytes(&chunk))
+        .send(Packet::from_bytes(&[0x70, 1]))
+        .send(Packet::from_bytes(&[0x7A, 1, 2, 3]))
+        .system_message(&Component::text("Still here"), false)])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .expect_packets(&[0x70])
+        .filter_packets(&[0x70])
+        .connect()?;
+    client.login()?;
+    match next_event(&mut client)? {
+        Event::Packet(packet) => assert_eq!(packet.get_protocol_id(), Some(0x70)),
+        event => panic!("Expected the wanted packet, got {:?}", event),
+    }
+    match next_event(&mut client)? {
+        Event::SystemMessage { message, .. } => assert_eq!(message.to_plain(), "Still here"),
+        event => panic!("Expected the chat to get through, got {:?}", event),
+    }
+    assert_eq!(client.filtered_packets(), 2);
+
+    server.finish()
+}
+
+#[test]
+fn packet_filter_follows_the_features() -> Result<()> {
+    // A release that moved Set Health, the client still needs it
+    let features = ProtocolFeatures {
+        set_health_packet_id: 0x6A,
+        ..ProtocolFeatures::default()
+    };
+    let mut health = vec![0x6A];
+    health.extend_from_slice(&20f32.to_be_bytes());
+    health.push(20); // Food
+    health.extend_from_slice(&5f32.to_be_bytes());
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .send(Packet::from_bytes(&[0x52, 0, 0, 0, 0]))
+        .send(Packet::from_bytes(&health))])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .protocol_features(features)
+        .filter_packets(&[])
+        .connect()?;
+    client.login()?;
+    match next_event(&mut client)? {
+        Event::HealthChanged { current, .. } => assert_eq!(current.health, 20.0),
+        event => panic!("Expected the moved health packet, got {:?}", event),
+    }
+    assert_eq!(client.filtered_packets(), 1);
+
+    server.finish()
+}
+
+fn transfer_packet(host: &str, port: u16) -> Result<Packet> {
+    let mut packet = Packet::new();
+    packet.write_varint(0x73)?;
+    packet.write_varint(host.len() as i32)?;
+    packet.buffer.extend_from_slice(host.as_bytes());
+    packet.write_varint(port as i32)?;
+    Ok(packet)
+}
+
+fn transfer_features() -> ProtocolFeatures {
+    ProtocolFeatures {
+        transfer_packet_id: Some(0x73),
+        ..ProtocolFeatures::default()
+    }
+}
+
+#[test]
+fn transfer_logs_in_at_the_new_host() -> Result<()> {
+    let lobby = MockServer::start(vec![Script::new()
+        .expect_handshake(NextState::Transfer)
+        .expect_login_start("alice")
+        .login_success("alice")
+        .expect_chat("made it")])?;
+    let origin = MockServer::start(vec![
+        login_script("alice").send(transfer_packet("127.0.0.1", lobby.port())?)
+    ])?;
+
+    let mut client = Client::builder("127.0.0.1", origin.port())
+        .username("alice")
+        .protocol_features(transfer_features())
+        .connect()?;
+    client.login()?;
+    loop {
+        if let Event::Transferred { host, port } = next_event(&mut client)? {
+            assert_eq!((host.as_str(), port), ("127.0.0.1", lobby.port()));
+            break;
+        }
+    }
+    assert_eq!(client.state(), ConnectionState::Play);
+    client.send_chat_message("made it")?;
+
+    origin.finish()?;
+    lobby.finish()
+}
+
+#[test]
+fn transfers_fail_with_a_connector() -> Result<()> {
+    let server = MockServer::in_memory(vec![
+        login_script("alice").send(transfer_packet("lobby.example.com", 25566)?)
+    ])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .protocol_features(transfer_features())
+        .connect()?;
+    client.login()?;
+    let error = loop {
+        match next_event(&mut client) {
+            Ok(_) => continue,
+            Err(error) => break error,
+        }
+    };
+    assert!(
+        format!("{:#}", error).contains("only dials the original server"),
+        "{:#}",
+        error
+    );
+
+    server.finish()
+}
+
+#[test]
+fn status_monitor_reports_changes() -> Result<()> {
+    let busier = STATUS.replace(r#""online":3"#, r#""online":5"#);
+    let server = MockServer::in_memory(vec![
+        Script::new().status(STATUS),
+        Script::new().status(&busier),
+    ])?;
+
+    let token = ShutdownToken::new();
+    let mut monitor = StatusMonitor::new(token.clone(), Duration::from_millis(10));
+    let mut samples = Vec::new();
+    monitor.run(
+        || {
+            Client::builder("127.0.0.1", 25565)
+                .connector(server.connector())
+                .shutdown_token(token.clone())
+                .connect()
+        },
+        |sample| {
+            samples.push(sample.clone());
+            if samples.len() == 3 {
+                token.cancel();
+            }
+        },
+    );
+
+    assert_eq!(samples[0].online(), Some(3));
+    assert_eq!(samples[0].online_change, None);
+    assert!(samples[0].latency.is_some());
+    assert_eq!(samples[1].online(), Some(5));
+    assert_eq!(samples[1].online_change, Some(2));
+    // The server only had two connections scripted
+    assert!(samples[2].error.is_some() && samples[2].status.is_none());
+
+    server.finish()
+}
+
+#[test]
+fn srv_records_are_resolved() -> Result<()> {
+    let nameserver = UdpSocket::bind("127.0.0.1:0")?;
+    let address = nameserver.local_addr()?;
+    let answer = std::thread::spawn(move || -> Result<()> {
+        let mut query = [0u8; 512];
+        let (length, client) = nameserver.recv_from(&mut query)?;
+
+        // Same id and question, two answers pointing back at the question's name
+        let mut response = query[..length].to_vec();
+        response[2..4].copy_from_slice(&[0x81, 0x80]);
+        response[6..8].copy_from_slice(&[0, 2]);
+        for (priority, port, target) in [(20, 25570, "backup"), (10, 25566, "mc")] {
+            let mut data = Vec::new();
+            data.extend_from_slice(&[0, priority, 0, 5]);
+            data.extend_from_slice(&u16::to_be_bytes(port));
+            data.push(target.len() as u8);
+            data.extend_from_slice(target.as_bytes());
+            data.extend_from_slice(&[7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0]);
+
+            response.extend_from_slice(&[0xC0, 12, 0, 33, 0, 1, 0, 0, 1, 0]);
+            response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            response.extend_from_slice(&data);
+        }
+        nameserver.send_to(&response, client)?;
+        Ok(())
+    });
+
+    let resolved = lookup_srv_with(address, "example", Duration::from_secs(5))?;
+    answer.join().unwrap()?;
+    assert_eq!(resolved, Some((String::from("mc.example"), 25566)));
+
+    Ok(())
+}
+
+#[test]
+fn scans_tolerate_dead_servers() -> Result<()> {
+    // One connection for the status, one for the ping
+    let server = MockServer::start(vec![
+        Script::new().status(STATUS),
+        Script::new().status(STATUS),
+    ])?;
+    let servers = vec![
+        String::from("127.0.0.1:1"),
+        format!("127.0.0.1:{}", server.port()),
+    ];
+
+    let mut results: Vec<_> =
+        scan_servers(servers, 4, Duration::from_secs(5), &ShutdownToken::new())
+            .iter()
+            .collect();
+    results.sort_by_key(|result| result.index);
+    assert_eq!(results.len(), 2);
+    assert!(results[0].outcome.is_err());
+    let (report, latency) = results[1].outcome.as_ref().unwrap();
+    assert_eq!(report.status.players.online, 3);
+    assert!(latency.is_some());
+
+    server.finish()
+}
+
+// Handles what arrives until the server goes quiet for half a second,
+// scripts end waiting for a chat message to keep the connection open
+fn drain_events(client: &mut Client) -> Result<()> {
+    while let Some(event) = client.poll_event(Duration::from_millis(500))? {
+        if let Event::Packet(packet) = &event {
+            client.answer_keep_alive(packet)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn latency_comes_from_the_tab_list() -> Result<()> {
+    let alice = |latency| PlayerInfo {
+        uuid: offline_uuid("alice"),
+        name: String::from("alice"),
+        properties: Vec::new(),
+        gamemode: 0,
+        latency,
+        display_name: None,
+    };
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .player_added(&alice(0))
+        .player_added(&alice(40))
+        .player_added(&alice(60))
+        .expect_chat("done")])?;
+
+    let mut client = client(&server, "alice")?;
+    client.login()?;
+    assert_eq!(client.latency(), None);
+    drain_events(&mut client)?;
+
+    // The 0 before the server measured anything isn't a sample
+    assert_eq!(client.latency(), Some(Duration::from_millis(50)));
+    assert_eq!(client.last_latency(), Some(Duration::from_millis(60)));
+    client.send_chat_message("done")?;
+
+    server.finish()
+}
+
+#[test]
+fn builder_options_apply_to_the_connection() -> Result<()> {
+    let directory = env::temp_dir().join(format!("mchat-builder-log-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    let long = "a message long enough to go over the compression threshold of 64 bytes";
+    let server = MockServer::in_memory(vec![login_script("alice")
+        .system_message(&Component::text("Welcome"), false)
+        .expect_chat(long)
+        .expect_chat("done")])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .compression_level(0)
+        .read_timeout(Duration::from_millis(300))
+        .write_timeout(Duration::from_secs(5))
+        .chat_logger(ChatLogger::new(&directory).jsonl(false))
+        .connect()?;
+    client.login()?;
+
+    match next_event(&mut client)? {
+        Event::SystemMessage { message, .. } => assert_eq!(message.to_plain(), "Welcome"),
+        other => panic!("Expected a system message, got {:?}", other),
+    }
+    // Stored, not deflated, but a valid zlib stream all the same
+    client.send_chat_message(long)?;
+
+    // The server has nothing more to say until it hears "done"
+    assert!(client.next_event().is_err());
+    client.send_chat_message("done")?;
+    server.finish()?;
+
+    let files: Vec<_> = fs::read_dir(&directory)?.collect::<Result<_, _>>()?;
+    assert_eq!(files.len(), 1);
+    assert!(fs::read_to_string(files[0].path())?.contains("Welcome"));
+    Ok(())
+}
+
+fn login_plugin_request(message_id: u8, channel: &str, data: &[u8]) -> Packet {
+    let mut body = vec![0x04, message_id, channel.len() as u8];
+    body.extend_from_slice(channel.as_bytes());
+    body.extend_from_slice(data);
+    Packet::from_bytes(&body)
+}
+
+// A login where the server asks on `channel` and checks the answer
+fn login_plugin_script(
+    channel: &str,
+    data: &[u8],
+    check: impl Fn(Option<&[u8]>) + Send + 'static,
+) -> Script {
+    Script::new()
+        .expect_handshake(NextState::Login)
+        .expect_login_start("alice")
+        .send(login_plugin_request(7, channel, data))
+        .expect(0x02, move |packet| {
+            let mut reader = packet.reader();
+            assert_eq!(reader.read_varint()?, 7);
+            match reader.read_bool()? {
+                true => check(Some(reader.remaining())),
+                false => {
+                    assert!(reader.remaining().is_empty());
+                    check(None);
+                }
+            }
+            Ok(())
+        })
+        .login_success("alice")
+        .expect_chat("done")
+}
+
+#[test]
+fn login_plugin_requests_go_to_the_handler() -> Result<()> {
+    let server = MockServer::in_memory(vec![login_plugin_script(
+        "example:hello",
+        &[1, 2, 3],
+        |answer| assert_eq!(answer, Some(&b"hi back"[..])),
+    )])?;
+
+    let asked = Arc::new(AtomicUsize::new(0));
+    let counter = asked.clone();
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .login_plugin_handler(move |channel, data| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(channel, "example:hello");
+            assert_eq!(data, [1, 2, 3]);
+            Some(b"hi back".to_vec())
+        })
+        .connect()?;
+    client.login()?;
+    assert_eq!(asked.load(Ordering::SeqCst), 1);
+    client.send_chat_message("done")?;
+
+    server.finish()
+}
+
+#[test]
+fn unanswered_login_plugin_requests_still_get_a_response() -> Result<()> {
+    // Once without a handler, once with one that doesn't know the channel
+    let server = MockServer::in_memory(vec![
+        login_plugin_script("example:hello", &[1], |answer| assert_eq!(answer, None)),
+        login_plugin_script("example:other", &[1], |answer| assert_eq!(answer, None)),
+    ])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .connect()?;
+    client.login()?;
+    client.send_chat_message("done")?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .login_plugin_handler(|channel, _| (channel == "example:hello").then(Vec::new))
+        .connect()?;
+    client.login()?;
+    client.send_chat_message("done")?;
+
+    server.finish()
+}
+
+#[test]
+fn velocity_forwarding_answers_before_the_handler() -> Result<()> {
+    let forwarding = Forwarding::Velocity {
+        secret: b"secret".to_vec(),
+        player: ForwardedPlayer {
+            address: "203.0.113.7".parse()?,
+            uuid: offline_uuid("alice"),
+            properties: Vec::new(),
+        },
+    };
+    let expected = forwarding.velocity_response("alice", &[1])?.unwrap();
+    let server = MockServer::in_memory(vec![login_plugin_script(
+        VELOCITY_CHANNEL,
+        &[1],
+        move |answer| assert_eq!(answer, Some(&expected[..])),
+    )])?;
+
+    let mut client = Client::builder("127.0.0.1", 25565)
+        .connector(server.connector())
+        .username("alice")
+        .forwarding(forwarding)
+        .login_plugin_handler(|_, _| panic!("Velocity's request reached the handler"))
+        .connect()?;
+    client.login()?;
+    client.send_chat_message("done")?;
+
+    server.finish()
+}
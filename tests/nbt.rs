@@ -0,0 +1,168 @@
+use mchat::{from_tag, to_tag, PacketReader, Tag, MAX_NBT_DEPTH};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// hello_world.nbt from the original specification
+const HELLO_WORLD: &[u8] = &[
+    0x0A, 0x00, 0x0B, b'h', b'e', b'l', b'l', b'o', b' ', b'w', b'o', b'r', b'l', b'd', 0x08, 0x00,
+    0x04, b'n', b'a', b'm', b'e', 0x00, 0x09, b'B', b'a', b'n', b'a', b'n', b'r', b'a', b'm', b'a',
+    0x00,
+];
+
+fn compound(entries: Vec<(&str, Tag)>) -> Tag {
+    Tag::Compound(
+        entries
+            .into_iter()
+            .map(|(name, tag)| (String::from(name), tag))
+            .collect(),
+    )
+}
+
+#[test]
+fn hello_world() {
+    let (name, tag) = Tag::read_named(&mut PacketReader::new(HELLO_WORLD))
+        .unwrap()
+        .unwrap();
+    assert_eq!(name, "hello world");
+    assert_eq!(tag.get("name").and_then(Tag::as_str), Some("Bananrama"));
+
+    let mut written = Vec::new();
+    tag.write_named(&name, &mut written).unwrap();
+    assert_eq!(written, HELLO_WORLD);
+}
+
+#[test]
+fn every_type_roundtrips() {
+    let tag = compound(vec![
+        ("byte", Tag::Byte(-3)),
+        ("short", Tag::Short(-300)),
+        ("int", Tag::Int(70000)),
+        ("long", Tag::Long(i64::MIN)),
+        ("float", Tag::Float(0.5)),
+        ("double", Tag::Double(-1.25)),
+        ("bytes", Tag::ByteArray(vec![-1, 0, 1])),
+        ("string", Tag::String(String::from("nul \0 and 🎉"))),
+        ("list", Tag::List(vec![Tag::Int(1), Tag::Int(2)])),
+        ("empty", Tag::List(Vec::new())),
+        ("nested", compound(vec![("inner", Tag::Byte(1))])),
+        ("ints", Tag::IntArray(vec![i32::MIN, 0, i32::MAX])),
+        ("longs", Tag::LongArray(vec![i64::MAX])),
+    ]);
+
+    let mut named = Vec::new();
+    tag.write_named("", &mut named).unwrap();
+    let mut reader = PacketReader::new(&named);
+    assert_eq!(reader.read_nbt().unwrap(), Some(tag.clone()));
+    assert!(reader.is_empty());
+
+    // Network NBT is the same minus the root name's length
+    let mut network = Vec::new();
+    tag.write_network(&mut network).unwrap();
+    assert_eq!(network.len(), named.len() - 2);
+    assert_eq!(
+        Tag::read_network(&mut PacketReader::new(&network)).unwrap(),
+        Some(tag)
+    );
+}
+
+#[test]
+fn strings_are_modified_utf8() {
+    let mut written = Vec::new();
+    Tag::String(String::from("\0🎉"))
+        .write_network(&mut written)
+        .unwrap();
+    // NUL as two bytes, the emoji as two 3 byte surrogates
+    assert_eq!(
+        written,
+        [0x08, 0x00, 0x08, 0xC0, 0x80, 0xED, 0xA0, 0xBC, 0xED, 0xBE, 0x89]
+    );
+}
+
+#[test]
+fn no_nbt_is_a_single_end() {
+    let mut reader = PacketReader::new(&[0x00]);
+    assert_eq!(reader.read_nbt().unwrap(), None);
+    assert!(reader.is_empty());
+}
+
+#[test]
+fn malformed_nbt_is_rejected() {
+    // Negative array length
+    assert!(Tag::read_network(&mut PacketReader::new(&[0x07, 0xFF, 0xFF, 0xFF, 0xFF])).is_err());
+    // A list claiming billions of ints with nothing behind it
+    assert!(Tag::read_network(&mut PacketReader::new(&[
+        0x09, 0x03, 0x7F, 0xFF, 0xFF, 0xFF
+    ]))
+    .is_err());
+    // Unknown tag type
+    assert!(Tag::read_network(&mut PacketReader::new(&[0x0D])).is_err());
+
+    // Lists of lists, one level deeper than allowed
+    let mut bytes = vec![0x09];
+    for _ in 0..=MAX_NBT_DEPTH {
+        bytes.extend_from_slice(&[0x09, 0x00, 0x00, 0x00, 0x01]);
+    }
+    bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00]);
+    assert!(Tag::read_network(&mut PacketReader::new(&bytes)).is_err());
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct DimensionType {
+    height: i32,
+    ambient_light: f32,
+    has_skylight: bool,
+    effects: String,
+}
+
+#[test]
+fn serde_both_ways() {
+    let tag = compound(vec![
+        ("height", Tag::Int(384)),
+        ("ambient_light", Tag::Float(0.0)),
+        ("has_skylight", Tag::Byte(1)),
+        ("effects", Tag::String(String::from("minecraft:overworld"))),
+    ]);
+    let dimension: DimensionType = from_tag(&tag).unwrap();
+    assert_eq!(
+        dimension,
+        DimensionType {
+            height: 384,
+            ambient_light: 0.0,
+            has_skylight: true,
+            effects: String::from("minecraft:overworld"),
+        }
+    );
+
+    let back = to_tag(&dimension).unwrap();
+    assert_eq!(back.get("height"), Some(&Tag::Int(384)));
+    assert_eq!(back.get("has_skylight"), Some(&Tag::Byte(1)));
+    let entries: &BTreeMap<String, Tag> = back.as_compound().unwrap();
+    assert_eq!(entries.len(), 4);
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Biome {
+    has_precipitation: bool,
+    // Bytes of 0 and 1 that stay numbers
+    grass_color: u8,
+    fog_levels: Vec<i32>,
+}
+
+#[test]
+fn only_bools_are_read_from_bytes() {
+    let tag = compound(vec![
+        ("has_precipitation", Tag::Byte(0)),
+        ("grass_color", Tag::Byte(1)),
+        ("fog_levels", Tag::List(vec![Tag::Int(0), Tag::Int(1)])),
+    ]);
+    let biome: Biome = from_tag(&tag).unwrap();
+    assert_eq!(
+        biome,
+        Biome {
+            has_precipitation: false,
+            grass_color: 1,
+            fog_levels: vec![0, 1],
+        }
+    );
+    assert!(from_tag::<Biome>(&Tag::Int(1)).is_err());
+}
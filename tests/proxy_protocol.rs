@@ -0,0 +1,88 @@
+use anyhow::Result;
+use mchat::{ProxyHeader, ProxyProtocolVersion};
+use std::io::Read;
+
+fn header(version: ProxyProtocolVersion, source: &str, destination: &str) -> ProxyHeader {
+    ProxyHeader {
+        version,
+        source: source.parse().unwrap(),
+        destination: destination.parse().unwrap(),
+    }
+}
+
+#[test]
+fn headers_read_back_as_encoded() -> Result<()> {
+    for version in [ProxyProtocolVersion::V1, ProxyProtocolVersion::V2] {
+        for (source, destination) in [
+            ("203.0.113.7:51234", "10.0.0.2:25565"),
+            ("[2001:db8::7]:51234", "[::1]:25565"),
+        ] {
+            let header = header(version, source, destination);
+            let mut bytes = header.encode()?;
+            bytes.extend_from_slice(b"next");
+
+            // Nothing past the header is read
+            let mut reader = &bytes[..];
+            assert_eq!(ProxyHeader::read_from(&mut reader)?, Some(header));
+            assert_eq!(reader, b"next");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn mixed_families_are_rejected() {
+    let mixed = header(ProxyProtocolVersion::V1, "203.0.113.7:51234", "[::1]:25565");
+    assert!(mixed.encode().is_err());
+
+    let mut reader = &b"PROXY TCP4 2001:db8::7 ::1 51234 25565\r\n"[..];
+    assert!(ProxyHeader::read_from(&mut reader).is_err());
+    let mut reader = &b"PROXY TCP6 203.0.113.7 10.0.0.2 51234 25565\r\n"[..];
+    assert!(ProxyHeader::read_from(&mut reader).is_err());
+}
+
+#[test]
+fn v1_headers_end_within_107_bytes() {
+    let mut line = b"PROXY TCP4 ".to_vec();
+    line.resize(200, b'1');
+    let mut reader = &line[..];
+    let error = ProxyHeader::read_from(&mut reader).unwrap_err();
+    assert!(error.to_string().contains("too long"));
+    // It gave up at the limit instead of reading the rest
+    assert_eq!(reader.len(), 200 - 107);
+}
+
+#[test]
+fn short_v2_address_blocks_are_rejected() -> Result<()> {
+    let mut bytes = header(
+        ProxyProtocolVersion::V2,
+        "203.0.113.7:51234",
+        "10.0.0.2:25565",
+    )
+    .encode()?;
+    // Claim 8 bytes of addresses, TCP4 needs 12
+    bytes.truncate(bytes.len() - 12);
+    bytes.extend_from_slice(&[0; 8]);
+    let length = bytes.len() - 16;
+    bytes[14..16].copy_from_slice(&(length as u16).to_be_bytes());
+
+    let error = ProxyHeader::read_from(&mut &bytes[..]).unwrap_err();
+    assert!(error.to_string().contains("too short"));
+    Ok(())
+}
+
+#[test]
+fn v2_local_headers_carry_no_addresses() -> Result<()> {
+    // LOCAL with an address block anyway, which still has to be skipped
+    let mut bytes = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    bytes.extend_from_slice(&[0x20, 0x11, 0x00, 0x0C]);
+    bytes.extend_from_slice(&[0; 12]);
+    bytes.extend_from_slice(b"next");
+
+    let mut reader = &bytes[..];
+    assert_eq!(ProxyHeader::read_from(&mut reader)?, None);
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest)?;
+    assert_eq!(rest, "next");
+    Ok(())
+}
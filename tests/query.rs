@@ -0,0 +1,110 @@
+use anyhow::Result;
+use mchat::{query_basic, query_full};
+use std::{net::UdpSocket, thread, time::Duration};
+
+const CHALLENGE: i32 = 9513307;
+
+// Answers `requests` query packets the way a server with enable-query does
+fn query_server(requests: usize) -> Result<(u16, thread::JoinHandle<Result<()>>)> {
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let port = socket.local_addr()?.port();
+
+    let thread = thread::spawn(move || -> Result<()> {
+        let mut buffer = [0u8; 1500];
+        for _ in 0..requests {
+            let (length, from) = socket.recv_from(&mut buffer)?;
+            let request = &buffer[..length];
+            assert_eq!(request[..2], [0xFE, 0xFD]);
+            let session = &request[3..7];
+
+            let mut response = vec![request[2]];
+            response.extend_from_slice(session);
+            match (request[2], length) {
+                (9, _) => response.extend_from_slice(format!("{}\0", CHALLENGE).as_bytes()),
+                (0, 11) => response.extend_from_slice(&basic_stat_body(request)),
+                (0, _) => response.extend_from_slice(&full_stat_body(request)),
+                (other, _) => panic!("Unexpected query type {}", other),
+            }
+            socket.send_to(&response, from)?;
+        }
+        Ok(())
+    });
+
+    Ok((port, thread))
+}
+
+fn basic_stat_body(request: &[u8]) -> Vec<u8> {
+    assert_eq!(request[7..11], CHALLENGE.to_be_bytes());
+    let mut body = b"A Minecraft Server\0SMP\0world\0".to_vec();
+    body.extend_from_slice(b"2\x0020\0");
+    body.extend_from_slice(&25565u16.to_le_bytes());
+    body.extend_from_slice(b"127.0.0.1\0");
+    body
+}
+
+fn full_stat_body(request: &[u8]) -> Vec<u8> {
+    assert_eq!(
+        request[7..15],
+        [&CHALLENGE.to_be_bytes()[..], &[0; 4]].concat()
+    );
+    let mut body = b"splitnum\0\x80\0".to_vec();
+    for (key, value) in [
+        ("hostname", "A Minecraft Server"),
+        ("gametype", "SMP"),
+        ("game_id", "MINECRAFT"),
+        ("version", "1.19"),
+        (
+            "plugins",
+            "Paper on 1.19: WorldEdit 7.2.12; EssentialsX 2.19.7",
+        ),
+        ("map", "world"),
+        ("numplayers", "2"),
+        ("maxplayers", "20"),
+        ("hostport", "25565"),
+        ("hostip", "127.0.0.1"),
+    ] {
+        body.extend_from_slice(format!("{}\0{}\0", key, value).as_bytes());
+    }
+    body.extend_from_slice(b"\0\x01player_\0\0");
+    body.extend_from_slice(b"alice\0bob\0\0");
+    body
+}
+
+#[test]
+fn basic_stat() -> Result<()> {
+    let (port, server) = query_server(2)?;
+
+    let stat = query_basic(("127.0.0.1", port), Duration::from_secs(5))?;
+    assert_eq!(stat.motd, "A Minecraft Server");
+    assert_eq!(stat.map, "world");
+    assert_eq!((stat.players, stat.max_players), (2, 20));
+    assert_eq!(stat.host_port, 25565);
+
+    server.join().unwrap()
+}
+
+#[test]
+fn full_stat_has_plugins_and_players() -> Result<()> {
+    let (port, server) = query_server(2)?;
+
+    let stat = query_full(("127.0.0.1", port), Duration::from_secs(5))?;
+    assert_eq!(stat.version, "1.19");
+    assert_eq!(stat.server_mod.as_deref(), Some("Paper on 1.19"));
+    assert_eq!(stat.plugins, ["WorldEdit 7.2.12", "EssentialsX 2.19.7"]);
+    assert_eq!(stat.player_names, ["alice", "bob"]);
+    assert_eq!(stat.values["game_id"], "MINECRAFT");
+
+    server.join().unwrap()
+}
+
+#[test]
+fn silent_server_times_out() -> Result<()> {
+    // Bound but never answering, like a server without enable-query
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    let port = socket.local_addr()?.port();
+
+    let error = query_basic(("127.0.0.1", port), Duration::from_millis(100)).unwrap_err();
+    assert!(format!("{:#}", error).contains("enable-query"));
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use mchat::rcon::{Rcon, WrongPassword};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+const PASSWORD: &str = "hunter2";
+
+fn read_packet(stream: &mut TcpStream) -> Result<(i32, i32, String)> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let mut packet = vec![0u8; i32::from_le_bytes(length) as usize];
+    stream.read_exact(&mut packet)?;
+    let id = i32::from_le_bytes(packet[0..4].try_into()?);
+    let kind = i32::from_le_bytes(packet[4..8].try_into()?);
+    let body = String::from_utf8(packet[8..packet.len() - 2].to_vec())?;
+    Ok((id, kind, body))
+}
+
+fn write_packet(stream: &mut TcpStream, id: i32, kind: i32, body: &str) -> Result<()> {
+    let mut packet = ((body.len() + 10) as i32).to_le_bytes().to_vec();
+    packet.extend_from_slice(&id.to_le_bytes());
+    packet.extend_from_slice(&kind.to_le_bytes());
+    packet.extend_from_slice(body.as_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    Ok(stream.write_all(&packet)?)
+}
+
+// Answers like a Minecraft server: output over 4096 bytes is split, and
+// anything that isn't a login or command gets "Unknown request"
+fn rcon_server(output: String) -> Result<(u16, thread::JoinHandle<Result<()>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    let thread = thread::spawn(move || -> Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        loop {
+            let (id, kind, body) = match read_packet(&mut stream) {
+                Ok(packet) => packet,
+                // The client hung up
+                Err(_) => return Ok(()),
+            };
+            match kind {
+                3 => {
+                    let id = if body == PASSWORD { id } else { -1 };
+                    write_packet(&mut stream, id, 2, "")?;
+                }
+                2 => {
+                    for chunk in output.as_bytes().chunks(4096) {
+                        write_packet(&mut stream, id, 0, std::str::from_utf8(chunk)?)?;
+                    }
+                }
+                other => write_packet(&mut stream, id, 0, &format!("Unknown request {:x}", other))?,
+            }
+        }
+    });
+
+    Ok((port, thread))
+}
+
+#[test]
+fn long_output_is_reassembled() -> Result<()> {
+    let output = "There are 3 of a max of 20 players online: alice, bob, carol".repeat(100);
+    let (port, server) = rcon_server(output.clone())?;
+
+    let mut rcon = Rcon::connect(("127.0.0.1", port), Duration::from_secs(5))?;
+    assert!(rcon.command("list").is_err());
+    rcon.authenticate(PASSWORD)?;
+    assert_eq!(rcon.command("list")?, output);
+    assert_eq!(rcon.command("list")?, output);
+    drop(rcon);
+
+    server.join().unwrap()
+}
+
+#[test]
+fn wrong_password_is_rejected() -> Result<()> {
+    let (port, server) = rcon_server(String::new())?;
+
+    let mut rcon = Rcon::connect(("127.0.0.1", port), Duration::from_secs(5))?;
+    let error = rcon.authenticate("hunter3").unwrap_err();
+    assert!(error.downcast_ref::<WrongPassword>().is_some());
+    assert!(!rcon.is_authenticated());
+
+    // The connection is still good for another try
+    rcon.authenticate(PASSWORD)?;
+    assert_eq!(rcon.command("save-all")?, "");
+    drop(rcon);
+
+    server.join().unwrap()
+}
+
+#[test]
+fn negative_lengths_are_rejected() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    let server = thread::spawn(move || -> Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        read_packet(&mut stream)?;
+        let mut header = (i32::MIN + 1).to_le_bytes().to_vec();
+        header.extend_from_slice(&[0; 8]);
+        Ok(stream.write_all(&header)?)
+    });
+
+    let mut rcon = Rcon::connect(("127.0.0.1", port), Duration::from_secs(5))?;
+    let error = rcon.authenticate(PASSWORD).unwrap_err();
+    assert!(error.to_string().contains("bad length"));
+
+    server.join().unwrap()
+}
@@ -0,0 +1,82 @@
+use mchat::{auth::unix_time, CachedSession, SessionCache, REFRESH_MARGIN};
+use std::{env, fs, path::PathBuf};
+use uuid::Uuid;
+
+fn cache_path(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("mchat-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    directory.join("mchat").join("sessions.json")
+}
+
+fn session(expires_at: u64) -> CachedSession {
+    CachedSession {
+        uuid: Uuid::from_u128(0x069a79f444e94726a5befca90e38aaf5),
+        name: String::from("Notch"),
+        access_token: String::from("eyJhbGciOiJIUzI1NiJ9.minecraft"),
+        expires_at,
+        refresh_token: String::from("M.C507_BAY.0.U.-refresh"),
+    }
+}
+
+#[test]
+fn sessions_are_kept_per_account() {
+    let path = cache_path("sessions");
+    let cache = SessionCache::new(&path);
+    assert_eq!(cache.accounts().unwrap(), Vec::<String>::new());
+    assert_eq!(cache.get("default").unwrap(), None);
+
+    let fresh = session(unix_time() + 86_400);
+    cache.insert("default", fresh.clone()).unwrap();
+    cache.insert("alt", session(0)).unwrap();
+    assert_eq!(cache.accounts().unwrap(), vec!["alt", "default"]);
+    assert!(!cache.is_encrypted().unwrap());
+
+    // A valid session comes back without a refresh, so without a client id
+    assert_eq!(cache.session("default", None).unwrap(), fresh);
+    let error = cache.session("alt", None).unwrap_err();
+    assert!(error.to_string().contains("needs a client id"));
+    assert!(cache.session("missing", None).is_err());
+
+    assert!(cache.remove("alt").unwrap());
+    assert!(!cache.remove("alt").unwrap());
+    assert_eq!(cache.accounts().unwrap(), vec!["default"]);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn encrypted_sessions_need_the_passphrase() {
+    let path = cache_path("encrypted-sessions");
+    let passphrase = Some(String::from("correct horse battery staple"));
+    let cache = SessionCache::new(&path).passphrase(passphrase.clone());
+    cache.insert("default", session(1)).unwrap();
+
+    // Nothing of the tokens is readable on disk
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.contains("ciphertext"));
+    assert!(!text.contains("minecraft") && !text.contains("refresh"));
+    assert!(cache.is_encrypted().unwrap());
+    assert_eq!(cache.get("default").unwrap(), Some(session(1)));
+
+    let error = SessionCache::new(&path).get("default").unwrap_err();
+    assert!(error.to_string().contains("passphrase is needed"));
+    let wrong = SessionCache::new(&path).passphrase(Some(String::from("hunter2")));
+    assert!(format!("{:#}", wrong.get("default").unwrap_err()).contains("Wrong passphrase"));
+
+    // Each save gets a fresh nonce and salt
+    cache.insert("default", session(1)).unwrap();
+    assert_ne!(fs::read_to_string(&path).unwrap(), text);
+}
+
+#[test]
+fn sessions_are_refreshed_ahead_of_expiry() {
+    assert!(session(0).needs_refresh());
+    assert!(session(unix_time() + REFRESH_MARGIN - 1).needs_refresh());
+    assert!(!session(unix_time() + REFRESH_MARGIN + 60).needs_refresh());
+    assert_eq!(session(0).profile().name, "Notch");
+}
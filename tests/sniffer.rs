@@ -0,0 +1,95 @@
+use anyhow::Result;
+use mchat::{
+    memory_pipe,
+    testing::{MockServer, Script},
+    Client, Component, ConnectionState, Direction, Event, NextState, SniffedPacket, Sniffer,
+    Transport,
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+#[test]
+fn sniffed_traffic_is_relayed_and_decoded() -> Result<()> {
+    let server = MockServer::start(vec![Script::new()
+        .expect_handshake(NextState::Login)
+        .expect_login_start("alice")
+        .compression(64)
+        .login_success("alice")
+        .system_message(&Component::text("Welcome"), false)
+        .expect_chat("hello through the sniffer")])?;
+
+    let sniffed = Arc::new(Mutex::new(
+        Vec::<(Direction, ConnectionState, String)>::new(),
+    ));
+    let log = sniffed.clone();
+    let (client_end, sniffer_end) = memory_pipe();
+    let sniffer = Sniffer::new("127.0.0.1", server.port());
+    let relay = thread::spawn(move || {
+        sniffer.relay(sniffer_end, move |packet: &SniffedPacket| {
+            let decoded = packet.decoded.as_ref().expect("Undecodable packet");
+            let name = match decoded.fields.first() {
+                Some((field, value)) => format!("{} {}={}", decoded.name, field, value),
+                None => decoded.name.to_string(),
+            };
+            log.lock()
+                .unwrap()
+                .push((packet.direction, packet.state, name));
+        })
+    });
+
+    let mut client = Client::builder("mc.example.com", 25565)
+        .username("alice")
+        .transport(Box::new(client_end) as Box<dyn Transport>)
+        .connect()?;
+    client.login()?;
+    loop {
+        match client.poll_event(Duration::from_secs(5))? {
+            Some(Event::SystemMessage { message, .. }) => {
+                assert_eq!(message.to_plain(), "Welcome");
+                break;
+            }
+            Some(_) => continue,
+            None => panic!("No message within 5 seconds"),
+        }
+    }
+    client.send_chat_message("hello through the sniffer")?;
+    server.finish()?;
+    drop(client);
+    relay.join().unwrap()?;
+
+    let sniffed = sniffed.lock().unwrap();
+    let seen = |direction, state, name: &str| {
+        sniffed
+            .iter()
+            .any(|packet| packet.0 == direction && packet.1 == state && packet.2.starts_with(name))
+    };
+    use ConnectionState::*;
+    use Direction::*;
+    assert_eq!(
+        sniffed[0],
+        (
+            Serverbound,
+            Handshaking,
+            String::from("Handshake protocol version=759")
+        )
+    );
+    assert!(seen(Serverbound, Login, "Login Start username=\"alice\""));
+    assert!(seen(Clientbound, Login, "Set Compression threshold=64"));
+    assert!(seen(Clientbound, Login, "Login Success"));
+    // Both compressed by then
+    assert!(seen(
+        Clientbound,
+        Play,
+        "System Chat Message content=\"Welcome\""
+    ));
+    assert!(seen(
+        Serverbound,
+        Play,
+        "Chat Message message=\"hello through the sniffer\""
+    ));
+
+    Ok(())
+}
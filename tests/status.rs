@@ -0,0 +1,88 @@
+use anyhow::Result;
+use mchat::{ServerStatus, StatusFix};
+
+const WELL_FORMED: &str = r#"{"version":{"name":"1.19","protocol":759},"players":{"max":20,"online":3},"description":"Hi"}"#;
+
+#[test]
+fn well_formed_status_needs_no_fixes() -> Result<()> {
+    let report = ServerStatus::parse_lenient(WELL_FORMED.as_bytes())?;
+    assert_eq!(report.status, ServerStatus::parse(WELL_FORMED)?);
+    assert!(report.fixes.is_empty());
+    Ok(())
+}
+
+#[test]
+fn trailing_commas_are_dropped() -> Result<()> {
+    let json = r#"{"version":{"name":"1.19","protocol":759,},"players":{"max":20,"online":3,"sample":[],},
+        "description":"a, b",}"#;
+    assert!(ServerStatus::parse(json).is_err());
+
+    let report = ServerStatus::parse_lenient(json.as_bytes())?;
+    assert_eq!(report.fixes, [StatusFix::TrailingCommas(3)]);
+    // Commas inside strings are left alone
+    assert_eq!(report.status.description.to_plain(), "a, b");
+    assert_eq!(report.status.players.sample, Some(Vec::new()));
+    Ok(())
+}
+
+#[test]
+fn control_characters_in_strings_are_escaped() -> Result<()> {
+    let json = "{\"version\":{\"name\":\"1.19\",\"protocol\":759},\n\
+        \"players\":{\"max\":20,\"online\":3},\"description\":\"line\none\ttab\"}";
+    assert!(ServerStatus::parse(json).is_err());
+
+    // The newline between fields is whitespace, not a repair
+    let report = ServerStatus::parse_lenient(json.as_bytes())?;
+    assert_eq!(report.fixes, [StatusFix::ControlCharacters(2)]);
+    assert_eq!(report.status.description.to_plain(), "line\none\ttab");
+    Ok(())
+}
+
+#[test]
+fn invalid_utf8_is_replaced() -> Result<()> {
+    let mut bytes = WELL_FORMED.replace("Hi", "H\u{1}i").into_bytes();
+    let at = bytes.iter().position(|byte| *byte == 1).unwrap();
+    bytes[at] = 0xFF;
+
+    let report = ServerStatus::parse_lenient(&bytes)?;
+    assert_eq!(report.fixes, [StatusFix::InvalidUtf8]);
+    assert_eq!(report.status.description.to_plain(), "H\u{FFFD}i");
+    Ok(())
+}
+
+#[test]
+fn trailing_data_is_ignored() -> Result<()> {
+    let json = format!("{}\0\0garbage", WELL_FORMED);
+    assert!(ServerStatus::parse(&json).is_err());
+
+    let report = ServerStatus::parse_lenient(json.as_bytes())?;
+    assert_eq!(report.fixes, [StatusFix::TrailingData]);
+    assert_eq!(report.status, ServerStatus::parse(WELL_FORMED)?);
+    Ok(())
+}
+
+#[test]
+fn every_repair_is_reported() -> Result<()> {
+    let mut bytes = br#"{"version":{"name":"1.19","protocol":759},"players":{"max":20,"online":3,},"description":"a"#
+        .to_vec();
+    bytes.extend_from_slice(b"\xFF\x07\"}  trailing");
+
+    let report = ServerStatus::parse_lenient(&bytes)?;
+    assert_eq!(
+        report.fixes,
+        [
+            StatusFix::InvalidUtf8,
+            StatusFix::TrailingCommas(1),
+            StatusFix::ControlCharacters(1),
+            StatusFix::TrailingData,
+        ]
+    );
+    assert_eq!(report.status.players.online, 3);
+    Ok(())
+}
+
+#[test]
+fn unrepairable_status_still_fails() {
+    assert!(ServerStatus::parse_lenient(b"{\"version\":").is_err());
+    assert!(ServerStatus::parse_lenient(b"not json").is_err());
+}
@@ -0,0 +1,382 @@
+use anyhow::Result;
+use mchat::{
+    memory_pipe,
+    testing::{MockServer, Script},
+    Client, Component, Event, HttpClient, HttpConfig, NextState, ProxyConfig, ServerConnection,
+    ShutdownToken, StreamTransport, Transport,
+};
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const STATUS: &str = r#"{"version":{"name":"1.19","protocol":759},"players":{"max":20,"online":3},"description":{"text":"Mock"}}"#;
+
+// The write half of a tunnel, which hangs up when dropped like a child's
+// stdin would
+struct WriteHalf(TcpStream);
+
+impl Write for WriteHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Drop for WriteHalf {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown(Shutdown::Both);
+    }
+}
+
+// The halves of a TCP connection, standing in for a tunnel that only hands
+// out a reader and a writer
+fn split_stream(address: SocketAddr) -> io::Result<Box<dyn Transport>> {
+    let stream = TcpStream::connect(address)?;
+    let reader = stream.try_clone()?;
+    Ok(Box::new(StreamTransport::new(reader, WriteHalf(stream))))
+}
+
+fn login_script(name: &str) -> Script {
+    Script::new()
+        .expect_handshake(NextState::Login)
+        .expect_login_start(name)
+        .compression(64)
+        .login_success(name)
+}
+
+#[test]
+fn clients_run_over_a_connector() -> Result<()> {
+    let server = MockServer::start(vec![
+        Script::new()
+            .expect_handshake(NextState::Status)
+            .status(STATUS),
+        login_script("alice").system_message(&Component::text("Welcome"), false),
+    ])?;
+
+    let opened = Arc::new(AtomicUsize::new(0));
+    let counter = opened.clone();
+    let address = server.address();
+    let mut client = Client::builder("mc.example.com", 25565)
+        .username("alice")
+        .connector(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(split_stream(address)?)
+        })
+        .connect()?;
+
+    assert_eq!(client.server_status()?.description.to_plain(), "Mock");
+    client.login()?;
+    // One for the status, one for the login after it
+    assert_eq!(opened.load(Ordering::SeqCst), 2);
+
+    loop {
+        match client.poll_event(Duration::from_secs(5))? {
+            Some(Event::SystemMessage { message, .. }) => {
+                assert_eq!(message.to_plain(), "Welcome");
+                break;
+            }
+            Some(_) => continue,
+            None => panic!("No message within 5 seconds"),
+        }
+    }
+
+    server.finish()
+}
+
+#[test]
+fn a_transport_is_good_for_one_connection() -> Result<()> {
+    let server = MockServer::start(vec![Script::new()
+        .expect_handshake(NextState::Status)
+        .status(STATUS)])?;
+
+    let mut client = Client::builder("127.0.0.1", server.port())
+        .transport(split_stream(server.address())?)
+        .connect()?;
+    client.server_status()?;
+    let error = client.login().unwrap_err();
+    assert!(error.to_string().contains("needs a connector"));
+    // Hangs up the status connection the server is still waiting on
+    drop(client);
+
+    server.finish()
+}
+
+#[test]
+fn stream_transports_time_out_and_shut_down() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let stream = TcpStream::connect(listener.local_addr()?)?;
+    let (mut remote, _) = listener.accept()?;
+    let mut transport = StreamTransport::new(stream.try_clone()?, stream);
+    assert_eq!(transport.peer_addr(), None);
+
+    transport.set_read_timeout(Some(Duration::from_millis(50)))?;
+    let mut buffer = [0u8; 16];
+    let error = transport.read(&mut buffer).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::WouldBlock);
+
+    remote.write_all(b"ping")?;
+    transport.set_read_timeout(None)?;
+    assert_eq!(transport.read(&mut buffer)?, 4);
+    assert_eq!(&buffer[..4], b"ping");
+
+    transport.set_nonblocking(true)?;
+    let error = transport.read(&mut buffer).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::WouldBlock);
+    transport.set_nonblocking(false)?;
+
+    transport.write_all(b"pong")?;
+    remote.read_exact(&mut buffer[..4])?;
+    assert_eq!(&buffer[..4], b"pong");
+
+    // A shutdown from another handle wakes up a blocked read, like it would
+    // for a ShutdownToken
+    let closer = transport.try_clone()?;
+    let token = ShutdownToken::new();
+    token.on_cancel(move || {
+        let _ = closer.shutdown();
+    });
+    let started = Instant::now();
+    let waker = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        token.cancel();
+    });
+    assert_eq!(transport.read(&mut buffer)?, 0);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(
+        transport.write(b"late").unwrap_err().kind(),
+        ErrorKind::BrokenPipe
+    );
+    waker.join().unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn memory_pipes_connect_both_ends() -> Result<()> {
+    let (mut first, mut second) = memory_pipe();
+    assert_eq!(first.peer_addr(), None);
+
+    first.write_all(b"ping")?;
+    let mut buffer = [0u8; 16];
+    assert_eq!(second.read(&mut buffer)?, 4);
+    assert_eq!(&buffer[..4], b"ping");
+
+    // Writes never wait, so a write timeout is as good as met
+    second.set_write_timeout(Some(Duration::from_secs(1)))?;
+    second.write_all(b"pong")?;
+    assert_eq!(first.read(&mut buffer)?, 4);
+    assert_eq!(&buffer[..4], b"pong");
+
+    first.set_read_timeout(Some(Duration::from_millis(20)))?;
+    assert_eq!(
+        first.read(&mut buffer).unwrap_err().kind(),
+        ErrorKind::WouldBlock
+    );
+
+    // Shutting one end down is EOF for the other, and for itself
+    second.write_all(b"last")?;
+    Transport::shutdown(&second)?;
+    assert_eq!(first.read(&mut buffer)?, 4);
+    assert_eq!(first.read(&mut buffer)?, 0);
+    assert_eq!(second.read(&mut buffer)?, 0);
+    assert_eq!(
+        first.write(b"late").unwrap_err().kind(),
+        ErrorKind::BrokenPipe
+    );
+
+    // And so is dropping every handle on it
+    let (mut first, second) = memory_pipe();
+    let clone = second.try_clone()?;
+    drop(second);
+    first.write_all(b"still there")?;
+    drop(clone);
+    assert_eq!(first.read(&mut buffer)?, 0);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn clients_connect_over_unix_sockets() -> Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    let path = std::env::temp_dir().join(format!("mchat-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    let server = thread::spawn(move || -> Result<()> {
+        let (stream, _) = listener.accept()?;
+        let mut connection = ServerConnection::accept(stream)?;
+        assert_eq!(connection.handshake().hostname, "mc.example.com");
+        assert!(connection.peer_addr().ip().is_unspecified());
+        connection.respond_status(STATUS)
+    });
+
+    let status = Client::builder("mc.example.com", 25565)
+        .unix_socket(&path)
+        .connect()?
+        .server_status()?;
+    assert_eq!(status.players.online, 3);
+    server.join().unwrap()?;
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
+
+#[test]
+fn http_proxies_get_bracketed_ipv6_targets() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let proxy = ProxyConfig::http(&listener.local_addr()?.to_string());
+    let server = thread::spawn(move || -> Result<String> {
+        let (stream, _) = listener.accept()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            if reader.read_line(&mut head)? == 0 {
+                break;
+            }
+        }
+        (&stream).write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+        Ok(head)
+    });
+
+    proxy.connect("::1", 25565)?;
+    let head = server.join().unwrap()?;
+    assert!(head.starts_with("CONNECT [::1]:25565 HTTP/1.1\r\n"));
+    assert!(head.contains("Host: [::1]:25565\r\n"));
+    Ok(())
+}
+
+#[test]
+fn socks_proxies_get_ip_targets_as_addresses() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let proxy = ProxyConfig::socks5(&listener.local_addr()?.to_string());
+    let server = thread::spawn(move || -> Result<Vec<u8>> {
+        let (mut stream, _) = listener.accept()?;
+        let mut greeting = [0; 3];
+        stream.read_exact(&mut greeting)?;
+        stream.write_all(&[0x05, 0x00])?;
+        let mut request = [0; 10];
+        stream.read_exact(&mut request)?;
+        stream.write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])?;
+        Ok(request.to_vec())
+    });
+
+    proxy.connect("10.0.0.7", 25565)?;
+    let request = server.join().unwrap()?;
+    assert_eq!(request, [0x05, 0x01, 0x00, 0x01, 10, 0, 0, 7, 0x63, 0xDD]);
+    Ok(())
+}
+
+#[test]
+fn silent_proxies_time_out() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let proxy = ProxyConfig::socks5(&listener.local_addr()?.to_string());
+
+    let started = Instant::now();
+    let result = proxy.connect_timeout("example.com", 25565, Some(Duration::from_millis(200)));
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    drop(listener);
+    Ok(())
+}
+
+#[test]
+fn cancel_registrations_end_with_their_owner() {
+    let token = ShutdownToken::new();
+    let ran = Arc::new(AtomicUsize::new(0));
+    let counter = |ran: &Arc<AtomicUsize>| {
+        let ran = ran.clone();
+        move || {
+            ran.fetch_add(1, Ordering::SeqCst);
+        }
+    };
+
+    // Like a connection per reconnect, only the live one is closed
+    for _ in 0..100 {
+        drop(token.register_on_cancel(counter(&ran)));
+    }
+    let live = token.register_on_cancel(counter(&ran));
+    token.cancel();
+    assert_eq!(ran.load(Ordering::SeqCst), 1);
+
+    // Once cancelled, registering runs the callback right away
+    drop(token.register_on_cancel(counter(&ran)));
+    assert_eq!(ran.load(Ordering::SeqCst), 2);
+    drop(live);
+}
+
+#[test]
+fn query_values_are_percent_encoded() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let server = thread::spawn(move || -> Result<String> {
+        let (stream, _) = listener.accept()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+        (&stream).write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")?;
+        Ok(request_line)
+    });
+
+    let url = format!("http://{}/hasJoined", addr);
+    HttpClient::default().get_with_query(&url, &[("username", "a&b=c"), ("serverId", "-1f #")])?;
+
+    let request_line = server.join().unwrap()?;
+    assert!(request_line.starts_with("GET /hasJoined?username=a%26b%3Dc&serverId=-1f%20%23 "));
+    Ok(())
+}
+
+#[test]
+fn posts_are_not_retried_once_sent() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = requests.clone();
+    thread::spawn(move || -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line)? > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse()?;
+                }
+                line.clear();
+            }
+            reader.read_exact(&mut vec![0; length])?;
+            counted.fetch_add(1, Ordering::SeqCst);
+            (&stream).write_all(
+                b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )?;
+        }
+        Ok(())
+    });
+
+    let http = HttpClient::new(HttpConfig {
+        retries: 2,
+        retry_delay: Duration::from_millis(1),
+        ..HttpConfig::default()
+    })?;
+    let url = format!("http://{}/", addr);
+    assert!(http.post_json(&url, &"hello").is_err());
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    assert!(http.get(&url).is_err());
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use mchat::{Route, VirtualHosts};
+
+fn backend(address: &str) -> Route {
+    Route::Backend(String::from(address))
+}
+
+#[test]
+fn hostnames_are_normalized() {
+    let hosts = VirtualHosts::new().route("play.example.com", backend("10.0.0.2:25565"));
+    let expected = Some(&backend("10.0.0.2:25565"));
+
+    // Forge's FML marker and BungeeCord's forwarded data come after a NUL
+    assert_eq!(hosts.resolve("play.example.com\0FML\0"), expected);
+    assert_eq!(hosts.resolve("play.example.com\0FML2\0"), expected);
+    assert_eq!(
+        hosts.resolve("play.example.com\x00203.0.113.7\x00uuid"),
+        expected
+    );
+    // The trailing dot of a fully qualified name
+    assert_eq!(hosts.resolve("play.example.com."), expected);
+    // And case, both in what's looked up and in the route itself
+    assert_eq!(hosts.resolve("Play.EXAMPLE.com"), expected);
+    let hosts = VirtualHosts::new().route("PLAY.example.com.", backend("10.0.0.2:25565"));
+    assert_eq!(hosts.resolve("play.example.com"), expected);
+}
+
+#[test]
+fn wildcards_match_the_most_specific_first() {
+    let hosts = VirtualHosts::new()
+        .route("*.example.com", backend("10.0.0.2:25565"))
+        .route("*.eu.example.com", backend("10.0.0.3:25565"))
+        .route("lobby.eu.example.com", backend("10.0.0.4:25565"));
+
+    assert_eq!(
+        hosts.resolve("lobby.eu.example.com"),
+        Some(&backend("10.0.0.4:25565"))
+    );
+    assert_eq!(
+        hosts.resolve("pvp.eu.example.com"),
+        Some(&backend("10.0.0.3:25565"))
+    );
+    assert_eq!(
+        hosts.resolve("a.b.example.com"),
+        Some(&backend("10.0.0.2:25565"))
+    );
+    assert_eq!(
+        hosts.resolve("PVP.EU.example.com.\0FML\0"),
+        Some(&backend("10.0.0.3:25565"))
+    );
+    // A wildcard only covers subdomains, not the name itself
+    assert_eq!(hosts.resolve("example.com"), None);
+}
+
+#[test]
+fn unknown_hosts_take_the_fallback() {
+    let status = Route::Status(String::from(r#"{"description":"Wrong address"}"#));
+    let hosts = VirtualHosts::new()
+        .route("play.example.com", backend("10.0.0.2:25565"))
+        .fallback(status.clone());
+
+    assert_eq!(hosts.resolve("other.example.com"), Some(&status));
+    assert_eq!(hosts.resolve("203.0.113.7"), Some(&status));
+    assert_eq!(hosts.resolve(""), Some(&status));
+    assert_eq!(
+        hosts.resolve("play.example.com"),
+        Some(&backend("10.0.0.2:25565"))
+    );
+
+    assert_eq!(VirtualHosts::new().resolve("play.example.com"), None);
+}
@@ -0,0 +1,150 @@
+#![cfg(feature = "http")]
+
+use anyhow::Result;
+use mchat::{
+    listen_relay, offline_uuid,
+    testing::{MockServer, Script},
+    Client, Component, Event, MessageCategory, NextState, RelayedMessage, ShutdownToken,
+    WebhookBridge, WebhookFormat,
+};
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+fn post(address: &str, headers: &str, body: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(address)?;
+    write!(
+        stream,
+        "POST /relay HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: {}\r\n\r\n{}",
+        headers,
+        body.len(),
+        body
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[test]
+fn chat_is_posted_to_webhooks() -> Result<()> {
+    let server = MockServer::start(vec![Script::new()
+        .expect_handshake(NextState::Login)
+        .expect_login_start("alice")
+        .login_success("alice")
+        .player_chat(offline_uuid("bob"), "bob", "hi @everyone")])?;
+    let mut client = Client::builder("127.0.0.1", server.port())
+        .username("alice")
+        .connect()?;
+    client.login()?;
+    let chat = loop {
+        match client.poll_event(Duration::from_secs(5))? {
+            Some(event @ Event::ChatMessage(_)) => break event,
+            Some(_) => {}
+            None => panic!("No chat message arrived"),
+        }
+    };
+    server.finish()?;
+
+    // A webhook that keeps what was posted
+    let webhook = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/hook", webhook.local_addr()?);
+    let received = thread::spawn(move || -> Result<Value> {
+        let (mut stream, _) = webhook.accept()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut length = 0;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse()?;
+            }
+            line.clear();
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")?;
+        Ok(serde_json::from_slice(&body)?)
+    });
+
+    let bridge = WebhookBridge::new(&url, WebhookFormat::Discord);
+    assert!(bridge.forward(&chat)?);
+    assert_eq!(
+        received.join().unwrap()?,
+        json!({
+            "content": "hi @everyone",
+            "username": "bob",
+            "allowed_mentions": { "parse": [] },
+        })
+    );
+
+    let slack = WebhookBridge::new(&url, WebhookFormat::Slack).template("<{sender}> {content}");
+    assert_eq!(
+        slack.payload(&chat),
+        Some(json!({ "text": "<bob> hi @everyone" }))
+    );
+
+    // System messages only go out with a template of their own
+    let message = Component::text("bob joined the game");
+    let joined = Event::SystemMessage {
+        category: MessageCategory::classify(&message),
+        message,
+        overlay: false,
+    };
+    assert_eq!(slack.payload(&joined), None);
+    let slack = slack.system_template(Some("*{message}*"));
+    assert_eq!(
+        slack.payload(&joined),
+        Some(json!({ "text": "*bob joined the game*" }))
+    );
+    Ok(())
+}
+
+#[test]
+fn relayed_messages_need_the_secret() -> Result<()> {
+    let token = ShutdownToken::new();
+    let (address, messages) = listen_relay("127.0.0.1:0", Some(String::from("hunter2")), &token)?;
+    let address = address.to_string();
+
+    let refused = post(&address, "", r#"{"content": "sneaky"}"#)?;
+    assert!(refused.starts_with("HTTP/1.1 401"));
+
+    let bearer = "Authorization: Bearer hunter2\r\n";
+    let accepted = post(
+        &address,
+        bearer,
+        r#"{"username": "carol", "content": "hello\n/op carol"}"#,
+    )?;
+    assert!(accepted.starts_with("HTTP/1.1 204"));
+    post(&address, bearer, "plain text")?;
+
+    let message = messages.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(
+        message,
+        RelayedMessage {
+            sender: Some(String::from("carol")),
+            content: String::from("hello /op carol"),
+        }
+    );
+    assert_eq!(message.to_chat(), "<carol> hello /op carol");
+    assert_eq!(
+        messages.recv_timeout(Duration::from_secs(5))?.to_chat(),
+        "plain text"
+    );
+
+    token.cancel();
+    Ok(())
+}
+
+#[test]
+fn relay_on_all_interfaces_shuts_down() -> Result<()> {
+    let token = ShutdownToken::new();
+    let (address, _messages) = listen_relay("0.0.0.0:0", None, &token)?;
+    assert!(address.ip().is_unspecified());
+
+    // Joins the accepting thread, which only returns once woken
+    token.shutdown();
+    Ok(())
+}